@@ -0,0 +1,226 @@
+use crate::client::AwsClient;
+use crate::s3::S3;
+use cloud_conveyor_core::build::{ArtifactManifest, ProvideArtifactLocation};
+use cloud_conveyor_core::credentials::CredentialProvider;
+use cloud_conveyor_core::{Account, Application};
+use failure::Error;
+use log::info;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The longest name s3 allows for a bucket.
+const MAX_BUCKET_LENGTH: usize = 63;
+
+/// The region buckets are created in when the account of an application has none.
+const DEFAULT_REGION: &str = "us-east-1";
+
+/// Gets the files underneath the directory, relative to it.
+fn files(dir: &Path, relative: &Path, found: &mut Vec<PathBuf>) -> Result<(), Error> {
+    for entry in fs::read_dir(dir.join(relative))? {
+        let entry = entry?;
+        let path = relative.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            files(dir, &path, found)?;
+        } else {
+            found.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Stores artifacts in s3 or any store that is compatible with it, such as MinIO. Every application has a bucket
+/// in its default account, see [get_bucket](#method.get_bucket), and the artifacts of a build are stored under
+/// `<sha>/` in it. The manifest of each recorded build is stored next to them as `<sha>.yaml`.
+///
+/// Every object is tagged with `cloud-conveyor:app` and `cloud-conveyor:sha` along with any tags that are
+/// [added](#method.with_tag), so that the lifecycle rules of the bucket can expire artifacts by their tags.
+/// Buckets are created when the first build of an application is uploaded. Requests are signed with the
+/// credentials of the account that the bucket is in.
+#[derive(Debug)]
+pub struct S3Artifacts {
+    client: AwsClient,
+    credentials: Box<dyn CredentialProvider>,
+    prefix: String,
+    tags: Vec<(String, String)>,
+}
+
+impl S3Artifacts {
+    /// Creates a provider that signs requests with the credentials that the provider has for the
+    /// account of the bucket.
+    pub fn new(credentials: Box<dyn CredentialProvider>) -> Self {
+        Self {
+            client: AwsClient::new(),
+            credentials,
+            prefix: "cloud-conveyor".to_string(),
+            tags: Vec::new(),
+        }
+    }
+
+    /// Sends every request to the endpoint rather than the public endpoint of s3 in the region of the
+    /// bucket, for example `http://localhost:9000` for MinIO.
+    pub fn with_endpoint(mut self, endpoint: String) -> Self {
+        self.client = self.client.with_endpoint(endpoint);
+        self
+    }
+
+    /// Sets the prefix of the names of buckets. Defaults to `cloud-conveyor`.
+    pub fn with_prefix(mut self, prefix: String) -> Self {
+        self.prefix = prefix;
+        self
+    }
+
+    /// Adds a tag that every stored object is tagged with.
+    pub fn with_tag(mut self, key: String, value: String) -> Self {
+        self.tags.push((key, value));
+        self
+    }
+
+    /// Gets the account that stores the artifacts of the application, which is its default account or
+    /// its first account when it has no default.
+    fn account<'a>(&self, app: &'a Application) -> Result<&'a Account, Error> {
+        app.default_account()
+            .or_else(|| app.accounts.first())
+            .ok_or_else(|| format_err!("App {:?} has no account to store artifacts in", app.app))
+    }
+
+    /// Gets the client of s3 in the account that stores the artifacts of the application.
+    fn s3<'a>(&'a self, app: &'a Application) -> Result<S3<'a>, Error> {
+        let account = self.account(app)?;
+        let region = account
+            .regions
+            .first()
+            .map(String::as_str)
+            .unwrap_or(DEFAULT_REGION);
+        let credentials = self.credentials.credentials(account)?;
+        Ok(S3::new(&self.client, credentials, region))
+    }
+
+    fn tags(&self, app: &Application, git_sha: &str) -> Vec<(String, String)> {
+        let mut tags = vec![
+            ("cloud-conveyor:app".to_string(), app.app.clone()),
+            ("cloud-conveyor:sha".to_string(), git_sha.to_string()),
+        ];
+        tags.extend(self.tags.iter().cloned());
+        tags
+    }
+
+    fn manifest_key(git_sha: &str) -> String {
+        format!("{}.yaml", git_sha)
+    }
+}
+
+impl ProvideArtifactLocation for S3Artifacts {
+    /// Gets the name of the bucket of the application, which is `<prefix>-<org>-<app>-<account id>`
+    /// converted into a valid bucket name. Names that are too long are cut short in the
+    /// `<prefix>-<org>-<app>` part, so that buckets of different accounts never get the same name.
+    fn get_bucket(&self, app: &Application) -> Result<String, Error> {
+        let account = self.account(app)?;
+        let account_id = account.id.to_string();
+        let owner: String = format!("{}-{}-{}", self.prefix, app.org, app.app)
+            .to_ascii_lowercase()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .skip_while(|c| *c == '-')
+            .take(MAX_BUCKET_LENGTH.saturating_sub(account_id.len() + 1))
+            .collect();
+        Ok(format!("{}-{}", owner.trim_end_matches('-'), account_id))
+    }
+
+    fn get_folder(&self, _app: &Application, git_sha: &str) -> Result<String, Error> {
+        Ok(git_sha.to_string())
+    }
+
+    fn find_build(
+        &self,
+        app: &Application,
+        git_sha: &str,
+    ) -> Result<Option<ArtifactManifest>, Error> {
+        let s3 = self.s3(app)?;
+        match s3.get_object(&self.get_bucket(app)?, &Self::manifest_key(git_sha))? {
+            Some(body) => Ok(Some(serde_yaml::from_slice(&body)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn record_build(&self, app: &Application, manifest: &ArtifactManifest) -> Result<(), Error> {
+        let s3 = self.s3(app)?;
+        let bucket = self.get_bucket(app)?;
+        s3.create_bucket(&bucket)?;
+        s3.put_object(
+            &bucket,
+            &Self::manifest_key(&manifest.git_sha),
+            serde_yaml::to_string(manifest)?.as_bytes(),
+            &self.tags(app, &manifest.git_sha),
+        )?;
+        Ok(())
+    }
+
+    fn upload(&self, app: &Application, git_sha: &str, source: &Path) -> Result<(), Error> {
+        let s3 = self.s3(app)?;
+        let bucket = self.get_bucket(app)?;
+        let folder = self.get_folder(app, git_sha)?;
+        s3.create_bucket(&bucket)?;
+
+        let mut found = Vec::new();
+        files(source, Path::new(""), &mut found)?;
+        let tags = self.tags(app, git_sha);
+        for file in &found {
+            let parts: Vec<_> = file.iter().map(|part| part.to_string_lossy()).collect();
+            let key = format!("{}/{}", folder, parts.join("/"));
+            s3.put_object(&bucket, &key, &fs::read(source.join(file))?, &tags)?;
+        }
+        info!(
+            "Uploaded {} artifacts of sha {:?} to bucket {:?}",
+            found.len(),
+            git_sha,
+            bucket
+        );
+        Ok(())
+    }
+
+    fn download(&self, app: &Application, git_sha: &str, destination: &Path) -> Result<(), Error> {
+        let s3 = self.s3(app)?;
+        let bucket = self.get_bucket(app)?;
+        let prefix = format!("{}/", self.get_folder(app, git_sha)?);
+        for key in s3.list(&bucket, &prefix, None)? {
+            let body = s3
+                .get_object(&bucket, &key)?
+                .ok_or_else(|| format_err!("Artifact {:?} disappeared while downloading", key))?;
+            let target = destination.join(&key[prefix.len()..]);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(target, body)?;
+        }
+        Ok(())
+    }
+
+    fn list(&self, app: &Application) -> Result<Vec<ArtifactManifest>, Error> {
+        let s3 = self.s3(app)?;
+        let bucket = self.get_bucket(app)?;
+        let mut builds = Vec::new();
+        for key in s3.list(&bucket, "", Some("/"))? {
+            if !key.ends_with(".yaml") {
+                continue;
+            }
+            if let Some(body) = s3.get_object(&bucket, &key)? {
+                let manifest: ArtifactManifest = serde_yaml::from_slice(&body)?;
+                builds.push(manifest);
+            }
+        }
+        builds.sort_by_key(|build| build.built_at);
+        Ok(builds)
+    }
+
+    fn delete(&self, app: &Application, git_sha: &str) -> Result<(), Error> {
+        let s3 = self.s3(app)?;
+        let bucket = self.get_bucket(app)?;
+        let prefix = format!("{}/", self.get_folder(app, git_sha)?);
+        for key in s3.list(&bucket, &prefix, None)? {
+            s3.delete_object(&bucket, &key)?;
+        }
+        s3.delete_object(&bucket, &Self::manifest_key(git_sha))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+use crate::sigv4::{self, Request};
+use chrono::Utc;
+use cloud_conveyor_core::credentials::Credentials;
+use cloud_conveyor_core::deploy::DeployPollError;
+use cloud_conveyor_core::teardown::TeardownPollError;
+use roxmltree::{Document, Node};
+
+use std::io::Read;
+use std::time::Duration;
+
+/// The error codes aws uses for credentials that are missing, invalid, expired or not allowed.
+const CREDENTIAL_ERRORS: &[&str] = &[
+    "AccessDenied",
+    "AccessDeniedException",
+    "AuthFailure",
+    "ExpiredToken",
+    "InvalidAccessKeyId",
+    "InvalidClientTokenId",
+    "MissingAuthenticationToken",
+    "SignatureDoesNotMatch",
+    "UnrecognizedClientException",
+];
+
+/// An error of a request to aws.
+#[derive(Debug, Fail)]
+pub(crate) enum AwsError {
+    /// Aws rejected the credentials.
+    #[fail(display = "The aws credentials were rejected.")]
+    Credentials,
+    /// Aws answered with an error.
+    #[fail(display = "Aws answered with {}: {}", code, message)]
+    Api {
+        /// The code of the error, such as `ValidationError`.
+        code: String,
+        /// The message of the error.
+        message: String,
+    },
+    /// Any other failure.
+    #[fail(display = "Request to aws failed: {}", _0)]
+    Other(String),
+}
+
+impl AwsError {
+    /// Determines if aws answered with the error code.
+    pub(crate) fn is(&self, expected: &str) -> bool {
+        matches!(self, AwsError::Api { code, .. } if code == expected)
+    }
+
+    /// Gets the message that aws answered with.
+    pub(crate) fn message(&self) -> &str {
+        match self {
+            AwsError::Api { message, .. } | AwsError::Other(message) => message,
+            AwsError::Credentials => "",
+        }
+    }
+}
+
+impl From<AwsError> for DeployPollError {
+    fn from(error: AwsError) -> Self {
+        match error {
+            AwsError::Credentials => DeployPollError::Credentials,
+            AwsError::Api { code, message } => DeployPollError::Other {
+                info: format!("{}: {}", code, message),
+            },
+            AwsError::Other(info) => DeployPollError::Other { info },
+        }
+    }
+}
+
+impl From<AwsError> for TeardownPollError {
+    fn from(error: AwsError) -> Self {
+        match error {
+            AwsError::Credentials => TeardownPollError::Credentials,
+            AwsError::Api { code, message } => TeardownPollError::Other {
+                info: format!("{}: {}", code, message),
+            },
+            AwsError::Other(info) => TeardownPollError::Other { info },
+        }
+    }
+}
+
+/// Gets the text of the first element with the name underneath the node.
+pub(crate) fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.descendants()
+        .find(|n| n.tag_name().name() == name)
+        .and_then(|n| n.text())
+}
+
+/// Parses the error out of the xml body of a failed response.
+fn parse_error(status: u16, body: &str) -> AwsError {
+    let (code, message) = match Document::parse(body) {
+        Ok(document) => (
+            child_text(document.root(), "Code")
+                .unwrap_or_default()
+                .to_string(),
+            child_text(document.root(), "Message")
+                .unwrap_or_default()
+                .to_string(),
+        ),
+        Err(_) => (String::new(), body.to_string()),
+    };
+    if status == 401 || CREDENTIAL_ERRORS.contains(&code.as_str()) {
+        AwsError::Credentials
+    } else if code.is_empty() {
+        AwsError::Other(format!(
+            "Request failed with status {}: {}",
+            status, message
+        ))
+    } else {
+        AwsError::Api { code, message }
+    }
+}
+
+/// Sends requests to aws that are signed with the credentials of the account they are sent on behalf of.
+/// Requests go to the public endpoints of the services unless an endpoint, such as the one of LocalStack, is set.
+#[derive(Debug)]
+pub(crate) struct AwsClient {
+    endpoint: Option<String>,
+    agent: ureq::Agent,
+}
+
+impl AwsClient {
+    pub(crate) fn new() -> Self {
+        Self {
+            endpoint: None,
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(30))
+                .build(),
+        }
+    }
+
+    pub(crate) fn with_endpoint(mut self, endpoint: String) -> Self {
+        self.endpoint = Some(endpoint.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Gets the url that requests to the service in the region are sent to.
+    fn endpoint(&self, service: &str, region: &str) -> String {
+        match &self.endpoint {
+            Some(endpoint) => endpoint.clone(),
+            None => format!("https://{}.{}.amazonaws.com", service, region),
+        }
+    }
+
+    /// Sends a request that is signed with the credentials to the service in the region and returns the
+    /// body of the response. The path must already be encoded.
+    pub(crate) fn send(
+        &self,
+        credentials: &Credentials,
+        service: &str,
+        region: &str,
+        request: &Request<'_>,
+    ) -> Result<Vec<u8>, AwsError> {
+        let endpoint = self.endpoint(service, region);
+        let host = endpoint
+            .split("://")
+            .nth(1)
+            .unwrap_or(&endpoint)
+            .to_string();
+        let mut headers = request.headers.to_vec();
+        headers.push(("Host".to_string(), host));
+        let signed = Request {
+            headers: &headers,
+            ..*request
+        };
+        let headers = sigv4::sign(&signed, credentials, region, service, Utc::now());
+
+        let mut url = format!("{}{}", endpoint, request.path);
+        if !request.query.is_empty() {
+            url = format!("{}?{}", url, sigv4::encode_pairs(request.query));
+        }
+        let http = headers.iter().fold(
+            self.agent.request(request.method, &url),
+            |http, (name, value)| http.set(name, value),
+        );
+        match http.send_bytes(request.payload) {
+            Ok(response) => {
+                let mut body = Vec::new();
+                response
+                    .into_reader()
+                    .read_to_end(&mut body)
+                    .map_err(|e| AwsError::Other(e.to_string()))?;
+                Ok(body)
+            }
+            Err(ureq::Error::Status(status, response)) => Err(parse_error(
+                status,
+                &response.into_string().unwrap_or_default(),
+            )),
+            Err(e) => Err(AwsError::Other(e.to_string())),
+        }
+    }
+}
@@ -0,0 +1,210 @@
+//! A minimal client for the parts of the cloud formation api that deployments need.
+use crate::client::{child_text, AwsClient, AwsError};
+use crate::sigv4::{encode_pairs, Request};
+use cloud_conveyor_core::credentials::Credentials;
+use roxmltree::Document;
+
+const SERVICE: &str = "cloudformation";
+const VERSION: &str = "2010-05-15";
+
+/// The capabilities every stack is created and updated with, so that templates may manage iam
+/// resources and use macros.
+const CAPABILITIES: &[&str] = &[
+    "CAPABILITY_IAM",
+    "CAPABILITY_NAMED_IAM",
+    "CAPABILITY_AUTO_EXPAND",
+];
+
+/// The state of a stack.
+#[derive(Debug)]
+pub(crate) struct Stack {
+    pub(crate) status: String,
+    pub(crate) reason: Option<String>,
+}
+
+/// An event of a stack or one of its resources.
+#[derive(Debug)]
+pub(crate) struct StackEvent {
+    pub(crate) logical_id: String,
+    pub(crate) resource_type: String,
+    pub(crate) status: String,
+    pub(crate) reason: Option<String>,
+}
+
+/// The template, parameters and tags of a stack that is created or updated.
+#[derive(Debug)]
+pub(crate) struct StackSpec<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) template: &'a str,
+    pub(crate) parameters: &'a [(String, String)],
+    pub(crate) tags: &'a [(String, String)],
+}
+
+impl StackSpec<'_> {
+    fn params(&self) -> Vec<(String, String)> {
+        let mut params = vec![
+            ("StackName".to_string(), self.name.to_string()),
+            ("TemplateBody".to_string(), self.template.to_string()),
+        ];
+        for (i, capability) in CAPABILITIES.iter().enumerate() {
+            params.push((
+                format!("Capabilities.member.{}", i + 1),
+                capability.to_string(),
+            ));
+        }
+        for (i, (key, value)) in self.parameters.iter().enumerate() {
+            params.push((
+                format!("Parameters.member.{}.ParameterKey", i + 1),
+                key.clone(),
+            ));
+            params.push((
+                format!("Parameters.member.{}.ParameterValue", i + 1),
+                value.clone(),
+            ));
+        }
+        for (i, (key, value)) in self.tags.iter().enumerate() {
+            params.push((format!("Tags.member.{}.Key", i + 1), key.clone()));
+            params.push((format!("Tags.member.{}.Value", i + 1), value.clone()));
+        }
+        params
+    }
+}
+
+/// Sends requests to the cloud formation api on behalf of the account of the credentials.
+#[derive(Debug)]
+pub(crate) struct CloudFormation<'a> {
+    client: &'a AwsClient,
+    credentials: &'a Credentials,
+}
+
+impl<'a> CloudFormation<'a> {
+    pub(crate) fn new(client: &'a AwsClient, credentials: &'a Credentials) -> Self {
+        Self {
+            client,
+            credentials,
+        }
+    }
+
+    /// Calls the action in the region and returns the xml of the response.
+    fn call(
+        &self,
+        region: &str,
+        action: &str,
+        mut params: Vec<(String, String)>,
+    ) -> Result<String, AwsError> {
+        params.insert(0, ("Action".to_string(), action.to_string()));
+        params.insert(1, ("Version".to_string(), VERSION.to_string()));
+        let body = encode_pairs(&params);
+        let headers = [(
+            "Content-Type".to_string(),
+            "application/x-www-form-urlencoded; charset=utf-8".to_string(),
+        )];
+        let request = Request {
+            method: "POST",
+            path: "/",
+            query: &[],
+            headers: &headers,
+            payload: body.as_bytes(),
+        };
+        let body = self
+            .client
+            .send(self.credentials, SERVICE, region, &request)?;
+        String::from_utf8(body).map_err(|e| AwsError::Other(format!("Invalid response: {}", e)))
+    }
+
+    fn parse(xml: &str) -> Result<Document<'_>, AwsError> {
+        Document::parse(xml).map_err(|e| AwsError::Other(format!("Invalid response: {}", e)))
+    }
+
+    /// Gets the stack with the name. Returns None when it does not exist.
+    pub(crate) fn describe_stack(
+        &self,
+        region: &str,
+        name: &str,
+    ) -> Result<Option<Stack>, AwsError> {
+        let params = vec![("StackName".to_string(), name.to_string())];
+        let xml = match self.call(region, "DescribeStacks", params) {
+            Ok(xml) => xml,
+            Err(e) if e.is("ValidationError") && e.message().contains("does not exist") => {
+                return Ok(None)
+            }
+            Err(e) => return Err(e),
+        };
+        let document = Self::parse(&xml)?;
+        let stack = document
+            .descendants()
+            .find(|n| n.tag_name().name() == "member" && child_text(*n, "StackStatus").is_some());
+        Ok(stack.map(|stack| Stack {
+            status: child_text(stack, "StackStatus")
+                .unwrap_or_default()
+                .to_string(),
+            reason: child_text(stack, "StackStatusReason").map(str::to_string),
+        }))
+    }
+
+    /// Gets the names of the parameters that the template declares.
+    pub(crate) fn template_parameters(
+        &self,
+        region: &str,
+        template: &str,
+    ) -> Result<Vec<String>, AwsError> {
+        let params = vec![("TemplateBody".to_string(), template.to_string())];
+        let xml = self.call(region, "ValidateTemplate", params)?;
+        let document = Self::parse(&xml)?;
+        Ok(document
+            .descendants()
+            .filter(|n| n.tag_name().name() == "ParameterKey")
+            .filter_map(|n| n.text())
+            .map(str::to_string)
+            .collect())
+    }
+
+    pub(crate) fn create_stack(&self, region: &str, spec: &StackSpec<'_>) -> Result<(), AwsError> {
+        self.call(region, "CreateStack", spec.params()).map(|_| ())
+    }
+
+    /// Updates the stack. Returns false when the stack is already up to date.
+    pub(crate) fn update_stack(
+        &self,
+        region: &str,
+        spec: &StackSpec<'_>,
+    ) -> Result<bool, AwsError> {
+        match self.call(region, "UpdateStack", spec.params()) {
+            Ok(_) => Ok(true),
+            Err(e) if e.is("ValidationError") && e.message().contains("No updates") => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub(crate) fn delete_stack(&self, region: &str, name: &str) -> Result<(), AwsError> {
+        let params = vec![("StackName".to_string(), name.to_string())];
+        self.call(region, "DeleteStack", params).map(|_| ())
+    }
+
+    /// Gets the events of the stack, newest first.
+    pub(crate) fn stack_events(
+        &self,
+        region: &str,
+        name: &str,
+    ) -> Result<Vec<StackEvent>, AwsError> {
+        let params = vec![("StackName".to_string(), name.to_string())];
+        let xml = self.call(region, "DescribeStackEvents", params)?;
+        let document = Self::parse(&xml)?;
+        Ok(document
+            .descendants()
+            .filter(|n| n.tag_name().name() == "member")
+            .map(|event| StackEvent {
+                logical_id: child_text(event, "LogicalResourceId")
+                    .unwrap_or_default()
+                    .to_string(),
+                resource_type: child_text(event, "ResourceType")
+                    .unwrap_or_default()
+                    .to_string(),
+                status: child_text(event, "ResourceStatus")
+                    .unwrap_or_default()
+                    .to_string(),
+                reason: child_text(event, "ResourceStatusReason").map(str::to_string),
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,165 @@
+use crate::client::AwsClient;
+use crate::sts::Sts;
+use cloud_conveyor_core::credentials::{CredentialError, CredentialProvider, Credentials};
+use cloud_conveyor_core::Account;
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// The name of the sessions of assumed roles, which shows up in the cloud trail of the accounts.
+const SESSION_NAME: &str = "cloud-conveyor";
+
+/// Provides the credentials in the standard `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and
+/// `AWS_SESSION_TOKEN` environment variables for every account. This is mostly useful as the source
+/// of [AssumeRoleCredentials](struct.AssumeRoleCredentials.html).
+#[derive(Debug, Default)]
+pub struct EnvironmentCredentials;
+
+impl CredentialProvider for EnvironmentCredentials {
+    fn credentials(&self, account: &Account) -> Result<Credentials, CredentialError> {
+        let missing = || CredentialError::Missing {
+            account: account.name.clone(),
+        };
+        let access_key_id = env::var("AWS_ACCESS_KEY_ID").map_err(|_| missing())?;
+        let secret_access_key = env::var("AWS_SECRET_ACCESS_KEY").map_err(|_| missing())?;
+        let credentials = Credentials::new(access_key_id, secret_access_key);
+        match env::var("AWS_SESSION_TOKEN") {
+            Ok(token) if !token.is_empty() => Ok(credentials.with_session_token(token)),
+            _ => Ok(credentials),
+        }
+    }
+}
+
+/// Provides credentials from the profiles of a shared credentials file such as `~/.aws/credentials`.
+/// The credentials of an account are those of the profile with the name of the account unless the
+/// account is given [a profile](#method.with_profile) of its own.
+#[derive(Debug)]
+pub struct ProfileCredentials {
+    path: PathBuf,
+    profiles: HashMap<String, String>,
+}
+
+impl ProfileCredentials {
+    /// Creates a provider that reads the credentials file at the path whenever credentials are needed.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            profiles: HashMap::new(),
+        }
+    }
+
+    /// Uses the profile for the credentials of the account with the name.
+    pub fn with_profile(mut self, account: String, profile: String) -> Self {
+        self.profiles.insert(account, profile);
+        self
+    }
+}
+
+/// Gets the keys of the profile with the name in the content of a credentials file.
+fn parse_profile(content: &str, name: &str) -> Option<HashMap<String, String>> {
+    let mut profile = None;
+    let mut current = None;
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            current = Some(line[1..line.len() - 1].trim().to_string());
+            if current.as_deref() == Some(name) {
+                profile = Some(HashMap::new());
+            }
+        } else if current.as_deref() == Some(name) {
+            if let (Some(keys), Some((key, value))) = (profile.as_mut(), line.split_once('=')) {
+                keys.insert(key.trim().to_string(), value.trim().to_string());
+            }
+        }
+    }
+    profile
+}
+
+impl CredentialProvider for ProfileCredentials {
+    fn credentials(&self, account: &Account) -> Result<Credentials, CredentialError> {
+        let name = self.profiles.get(&account.name).unwrap_or(&account.name);
+        let content = fs::read_to_string(&self.path).map_err(|e| CredentialError::Other {
+            info: format!("Could not read credentials file {:?}: {}", self.path, e),
+        })?;
+        let mut profile =
+            parse_profile(&content, name).ok_or_else(|| CredentialError::Missing {
+                account: account.name.clone(),
+            })?;
+        let mut key = |key: &str| {
+            profile.remove(key).ok_or_else(|| CredentialError::Other {
+                info: format!("Profile {:?} has no {}", name, key),
+            })
+        };
+        let credentials =
+            Credentials::new(key("aws_access_key_id")?, key("aws_secret_access_key")?);
+        match key("aws_session_token") {
+            Ok(token) => Ok(credentials.with_session_token(token)),
+            Err(_) => Ok(credentials),
+        }
+    }
+}
+
+/// Provides credentials by assuming a role in every account. The credentials that the source provider
+/// has for an account are used to assume the roles of the [chain](#method.with_chain) one after the other,
+/// and then the role with the name in the account itself, which is `arn:aws:iam::<account id>:role/<name>`.
+///
+/// The credentials of assumed roles expire after an hour, so this provider is best wrapped in
+/// [CachedCredentials](../cloud_conveyor_core/credentials/struct.CachedCredentials.html).
+#[derive(Debug)]
+pub struct AssumeRoleCredentials {
+    client: AwsClient,
+    source: Box<dyn CredentialProvider>,
+    chain: Vec<String>,
+    role_name: String,
+}
+
+impl AssumeRoleCredentials {
+    /// Creates a provider that assumes the role with the name in every account with the credentials of
+    /// the source.
+    pub fn new(source: Box<dyn CredentialProvider>, role_name: String) -> Self {
+        Self {
+            client: AwsClient::new(),
+            source,
+            chain: Vec::new(),
+            role_name,
+        }
+    }
+
+    /// Assumes the role with the arn before the role in the account. Roles that are added this way
+    /// are assumed in the order they are added.
+    pub fn with_chain(mut self, role_arn: String) -> Self {
+        self.chain.push(role_arn);
+        self
+    }
+
+    /// Sends every request to the endpoint rather than the public endpoint of sts, for example
+    /// `http://localhost:4566` for LocalStack.
+    pub fn with_endpoint(mut self, endpoint: String) -> Self {
+        self.client = self.client.with_endpoint(endpoint);
+        self
+    }
+
+    /// Gets the arn of the role in the account.
+    fn role_arn(&self, account: &Account) -> String {
+        format!("arn:aws:iam::{:012}:role/{}", account.id, self.role_name)
+    }
+}
+
+impl CredentialProvider for AssumeRoleCredentials {
+    fn credentials(&self, account: &Account) -> Result<Credentials, CredentialError> {
+        let mut credentials = self.source.credentials(account)?;
+        let target = self.role_arn(account);
+        for role_arn in self.chain.iter().chain(std::iter::once(&target)) {
+            credentials = Sts::new(&self.client, &credentials)
+                .assume_role(role_arn, SESSION_NAME)
+                .map_err(|e| CredentialError::Other {
+                    info: format!("Could not assume role {}: {}", role_arn, e),
+                })?;
+        }
+        Ok(credentials)
+    }
+}
@@ -0,0 +1,365 @@
+use crate::client::AwsClient;
+use crate::cloudformation::{CloudFormation, StackSpec};
+use cloud_conveyor_core::credentials::Credentials;
+use cloud_conveyor_core::deploy::{DeployInfrastructure, DeployPollError, DeployStatus};
+use cloud_conveyor_core::pipelining::{Deploy, Teardown};
+use cloud_conveyor_core::runtime::RuntimeContext;
+use cloud_conveyor_core::secrets::Secrets;
+use cloud_conveyor_core::teardown::{TeardownInfrastructure, TeardownPollError, TeardownStatus};
+use cloud_conveyor_core::{qualified_stage_name, short_hash, Application, Stage};
+use log::{info, warn};
+
+use std::fs;
+use std::path::PathBuf;
+
+/// The longest name cloud formation allows for a stack.
+const MAX_STACK_NAME_LENGTH: usize = 128;
+
+/// The type of the events of a stack itself, as opposed to the events of its resources.
+const STACK_TYPE: &str = "AWS::CloudFormation::Stack";
+
+/// Gets the name of the stack of the stage of the application. The name is the
+/// [qualified name](../cloud_conveyor_core/fn.qualified_stage_name.html) of the stage with every character
+/// that cloud formation does not allow replaced, so the stages `pr-42` and `api-pr-42` of the application
+/// `api` are deployed to the stack `api-pr-42`. Stack names must start with a letter, so names that start
+/// with anything else are prefixed with `stack-`. Names that are too long for a stack are cut short and end
+/// with a [hash](../cloud_conveyor_core/fn.short_hash.html) of the qualified name, so that stages whose names
+/// only differ past the cut still get stacks of their own.
+pub fn stack_name(app: &Application, stage: &Stage) -> String {
+    let qualified = qualified_stage_name(app, stage);
+    let name: String = qualified
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let name = name.trim_matches('-');
+    let name = if name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        name.to_string()
+    } else {
+        format!("stack-{}", name)
+    };
+    if name.len() <= MAX_STACK_NAME_LENGTH {
+        return name;
+    }
+    let hash = short_hash(&qualified);
+    let prefix = name[..MAX_STACK_NAME_LENGTH - hash.len() - 1].trim_end_matches('-');
+    format!("{}-{}", prefix, hash)
+}
+
+/// Gets the name of the parameter that a secret is passed as. Parameter names may only hold letters and
+/// digits, so every other byte of the name, and the letter `x` itself, is written as `x` and its two hex
+/// digits. `DATABASE_URL` is passed as `DATABASEx5FURL`. Different secrets never get the same parameter.
+fn parameter_name(secret: &str) -> String {
+    let mut name = String::new();
+    for byte in secret.bytes() {
+        if byte.is_ascii_alphanumeric() && byte != b'x' {
+            name.push(char::from(byte));
+        } else {
+            name.push_str(&format!("x{:02X}", byte));
+        }
+    }
+    name
+}
+
+fn other<E: ToString>(e: E) -> DeployPollError {
+    DeployPollError::Other {
+        info: e.to_string(),
+    }
+}
+
+/// Maps the status of a stack that is being created or updated to the status of the deployment.
+fn deploy_status(status: &str) -> DeployStatus {
+    match status {
+        "CREATE_COMPLETE" | "UPDATE_COMPLETE" | "IMPORT_COMPLETE" => DeployStatus::Complete,
+        // A stack that rolls back has already failed, but it cannot be deployed again until it is done.
+        status if status.ends_with("_IN_PROGRESS") => DeployStatus::Pending,
+        _ => DeployStatus::Failed,
+    }
+}
+
+/// Maps the status of a stack that is being deleted to the status of the teardown. A stack that no longer
+/// exists is torn down.
+fn teardown_status(status: Option<&str>) -> TeardownStatus {
+    match status {
+        None | Some("DELETE_COMPLETE") => TeardownStatus::Complete,
+        Some("DELETE_FAILED") => TeardownStatus::Failed,
+        Some(_) => TeardownStatus::Pending,
+    }
+}
+
+/// Deploys applications with cloud formation with a stack for every stage in every region of its account.
+///
+/// The artifacts of the build that is deployed are expected to contain the template of the stack, which is
+/// `cloudformation/template.yaml` by default. Stacks are named after the application and stage, see
+/// [stack_name](fn.stack_name.html), and are created when they do not exist yet and updated otherwise. The
+/// template is passed the `App`, `Stage` and `GitRef` parameters along with the secrets of the stage, but only
+/// the parameters that the template declares are passed. Secrets should be declared with `NoEcho`.
+///
+/// A deployment is complete when the stacks in every region are. When a stack fails or rolls back, the
+/// events of the resources that failed are logged. Tearing a stage down deletes its stacks.
+///
+/// Requests are signed with the credentials that the [CredentialProvider](../cloud_conveyor_core/credentials/trait.CredentialProvider.html)
+/// of the runtime has for the account of the stage, so the stages of an application can be deployed to different
+/// accounts. Requests go to the public endpoints of aws unless an endpoint is set, which makes it possible to
+/// deploy to a stand-in such as LocalStack.
+#[derive(Debug)]
+pub struct CloudFormationDeployer {
+    client: AwsClient,
+    workspace: PathBuf,
+    template: PathBuf,
+}
+
+impl CloudFormationDeployer {
+    /// Creates a deployer that downloads the artifacts of builds into the workspace directory.
+    pub fn new<P: Into<PathBuf>>(workspace: P) -> Self {
+        Self {
+            client: AwsClient::new(),
+            workspace: workspace.into(),
+            template: PathBuf::from("cloudformation/template.yaml"),
+        }
+    }
+
+    /// Sends every request to the endpoint rather than the public endpoint of cloud formation in
+    /// the region of the stack, for example `http://localhost:4566` for LocalStack.
+    pub fn with_endpoint(mut self, endpoint: String) -> Self {
+        self.client = self.client.with_endpoint(endpoint);
+        self
+    }
+
+    /// Sets the path of the template, relative to the root of the build artifacts. Defaults to
+    /// `cloudformation/template.yaml`.
+    pub fn with_template<P: Into<PathBuf>>(mut self, template: P) -> Self {
+        self.template = template.into();
+        self
+    }
+
+    fn cloudformation<'a>(&'a self, credentials: &'a Credentials) -> CloudFormation<'a> {
+        CloudFormation::new(&self.client, credentials)
+    }
+
+    fn load_template(
+        &self,
+        app: &Application,
+        deploy: &Deploy,
+        name: &str,
+        ctx: &RuntimeContext,
+    ) -> Result<String, DeployPollError> {
+        let artifacts = self.workspace.join(name);
+        if artifacts.exists() {
+            fs::remove_dir_all(&artifacts).map_err(other)?;
+        }
+        fs::create_dir_all(&artifacts).map_err(other)?;
+        ctx.artifact_provider
+            .download(app, &deploy.git_ref, &artifacts)
+            .map_err(other)?;
+        let template = fs::read_to_string(artifacts.join(&self.template)).map_err(|e| {
+            other(format!(
+                "Could not read template {:?} of git_ref {}: {}",
+                self.template, deploy.git_ref, e
+            ))
+        })?;
+        fs::remove_dir_all(&artifacts).map_err(other)?;
+        Ok(template)
+    }
+
+    /// Logs the events of the resources that failed in the latest operation on the stack.
+    fn log_failures(&self, cloudformation: &CloudFormation<'_>, region: &str, name: &str) {
+        let events = match cloudformation.stack_events(region, name) {
+            Ok(events) => events,
+            Err(e) => {
+                warn!("Could not get the events of stack {:?}: {:?}", name, e);
+                return;
+            }
+        };
+        for event in events {
+            if event.resource_type == STACK_TYPE
+                && event.logical_id == name
+                && matches!(
+                    event.status.as_str(),
+                    "CREATE_IN_PROGRESS" | "UPDATE_IN_PROGRESS" | "DELETE_IN_PROGRESS"
+                )
+            {
+                break;
+            }
+            if event.status.ends_with("_FAILED") {
+                warn!(
+                    "Stack {:?} in {}: {} {} is {}: {}",
+                    name,
+                    region,
+                    event.resource_type,
+                    event.logical_id,
+                    event.status,
+                    event.reason.unwrap_or_default()
+                );
+            }
+        }
+    }
+}
+
+impl DeployInfrastructure for CloudFormationDeployer {
+    fn start_deployment(
+        &self,
+        deploy: &Deploy,
+        secrets: &Secrets,
+        ctx: &RuntimeContext,
+    ) -> Result<(), DeployPollError> {
+        let app = deploy
+            .application(ctx)
+            .ok_or_else(|| DeployPollError::Other {
+                info: format!("No application is configured for repo {}", deploy.repo),
+            })?;
+        let name = stack_name(app, &deploy.stage);
+        let template = self.load_template(app, deploy, &name, ctx)?;
+
+        let mut values = vec![
+            ("App".to_string(), app.app.clone()),
+            ("Stage".to_string(), deploy.stage.name.clone()),
+            ("GitRef".to_string(), deploy.git_ref.clone()),
+        ];
+        values.extend(
+            secrets
+                .iter()
+                .map(|(secret, value)| (parameter_name(secret), value.to_string())),
+        );
+        let tags = [
+            ("cloud-conveyor:app".to_string(), app.app.clone()),
+            (
+                "cloud-conveyor:stage".to_string(),
+                deploy.stage.name.clone(),
+            ),
+        ];
+
+        let credentials = ctx.credentials.credentials(&deploy.stage.account)?;
+        let cloudformation = self.cloudformation(&credentials);
+        for region in &deploy.stage.account.regions {
+            let declared = cloudformation.template_parameters(region, &template)?;
+            let parameters: Vec<_> = values
+                .iter()
+                .filter(|(key, _)| declared.contains(key))
+                .cloned()
+                .collect();
+            let spec = StackSpec {
+                name: &name,
+                template: &template,
+                parameters: &parameters,
+                tags: &tags,
+            };
+            match cloudformation.describe_stack(region, &name)? {
+                None => {
+                    cloudformation.create_stack(region, &spec)?;
+                    info!("Creating stack {:?} in {}", name, region);
+                }
+                Some(stack) if stack.status == "ROLLBACK_COMPLETE" => {
+                    return Err(DeployPollError::Other {
+                        info: format!(
+                            "Stack {:?} in {} failed to be created and must be torn down before it is deployed again",
+                            name, region
+                        ),
+                    });
+                }
+                Some(_) => {
+                    if cloudformation.update_stack(region, &spec)? {
+                        info!("Updating stack {:?} in {}", name, region);
+                    } else {
+                        info!("Stack {:?} in {} is already up to date", name, region);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_deployment(
+        &self,
+        deploy: &Deploy,
+        ctx: &RuntimeContext,
+    ) -> Result<DeployStatus, DeployPollError> {
+        let app = deploy
+            .application(ctx)
+            .ok_or_else(|| DeployPollError::Other {
+                info: format!("No application is configured for repo {}", deploy.repo),
+            })?;
+        let name = stack_name(app, &deploy.stage);
+        let credentials = ctx.credentials.credentials(&deploy.stage.account)?;
+        let cloudformation = self.cloudformation(&credentials);
+
+        let mut status = DeployStatus::Complete;
+        for region in &deploy.stage.account.regions {
+            let stack = cloudformation
+                .describe_stack(region, &name)?
+                .ok_or_else(|| DeployPollError::Other {
+                    info: format!("Stack {:?} in {} does not exist", name, region),
+                })?;
+            match deploy_status(&stack.status) {
+                DeployStatus::Complete => {}
+                DeployStatus::Pending => status = DeployStatus::Pending,
+                DeployStatus::Failed => {
+                    warn!(
+                        "Stack {:?} in {} is {}: {}",
+                        name,
+                        region,
+                        stack.status,
+                        stack.reason.unwrap_or_default()
+                    );
+                    self.log_failures(&cloudformation, region, &name);
+                    return Ok(DeployStatus::Failed);
+                }
+            }
+        }
+        if status == DeployStatus::Complete {
+            info!("Stack {:?} is deployed", name);
+        }
+        Ok(status)
+    }
+}
+
+impl TeardownInfrastructure for CloudFormationDeployer {
+    fn start_teardown(
+        &self,
+        teardown: &Teardown,
+        ctx: &RuntimeContext,
+    ) -> Result<(), TeardownPollError> {
+        let app = teardown
+            .application(ctx)
+            .ok_or_else(|| TeardownPollError::Other {
+                info: format!("No application is configured for repo {}", teardown.repo),
+            })?;
+        let name = stack_name(app, &teardown.stage);
+        let credentials = ctx.credentials.credentials(&teardown.stage.account)?;
+        let cloudformation = self.cloudformation(&credentials);
+        for region in &teardown.stage.account.regions {
+            cloudformation.delete_stack(region, &name)?;
+            info!("Deleting stack {:?} in {}", name, region);
+        }
+        Ok(())
+    }
+
+    fn check_teardown(
+        &self,
+        teardown: &Teardown,
+        ctx: &RuntimeContext,
+    ) -> Result<TeardownStatus, TeardownPollError> {
+        let app = teardown
+            .application(ctx)
+            .ok_or_else(|| TeardownPollError::Other {
+                info: format!("No application is configured for repo {}", teardown.repo),
+            })?;
+        let name = stack_name(app, &teardown.stage);
+        let credentials = ctx.credentials.credentials(&teardown.stage.account)?;
+        let cloudformation = self.cloudformation(&credentials);
+
+        let mut status = TeardownStatus::Complete;
+        for region in &teardown.stage.account.regions {
+            let stack = cloudformation.describe_stack(region, &name)?;
+            match teardown_status(stack.as_ref().map(|s| s.status.as_str())) {
+                TeardownStatus::Complete => {}
+                TeardownStatus::Pending => status = TeardownStatus::Pending,
+                TeardownStatus::Failed => {
+                    warn!("Stack {:?} in {} could not be deleted", name, region);
+                    self.log_failures(&cloudformation, region, &name);
+                    return Ok(TeardownStatus::Failed);
+                }
+            }
+        }
+        Ok(status)
+    }
+}
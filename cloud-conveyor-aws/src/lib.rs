@@ -0,0 +1,35 @@
+//! This crate defines runtime implementations that deploy applications to aws. Infrastructure is deployed
+//! with cloud formation, where every stage of an application is a stack of its own, and artifacts are stored
+//! in s3.
+//!
+//! Requests are signed with [version 4](https://docs.aws.amazon.com/general/latest/gr/signature-version-4.html)
+//! of the aws signature process and can be sent to a stand-in for aws, such as LocalStack or MinIO, instead.
+//! The credentials of every account come from the
+//! [CredentialProvider](../cloud_conveyor_core/credentials/trait.CredentialProvider.html) of the runtime, for which
+//! this crate provides implementations that read the environment or a credentials file, or assume roles.
+//!
+//! This crate also uses the standard logging pattern that the core crate uses.
+#![warn(
+    missing_docs,
+    rust_2018_idioms,
+    missing_debug_implementations,
+    rustdoc::broken_intra_doc_links
+)]
+// The derive of failure expands to impls nested in anonymous constants.
+#![allow(non_local_definitions)]
+
+#[macro_use]
+extern crate failure;
+
+mod artifacts;
+mod client;
+mod cloudformation;
+mod credentials;
+mod deployer;
+mod s3;
+mod sigv4;
+mod sts;
+
+pub use artifacts::S3Artifacts;
+pub use credentials::{AssumeRoleCredentials, EnvironmentCredentials, ProfileCredentials};
+pub use deployer::{stack_name, CloudFormationDeployer};
@@ -0,0 +1,156 @@
+//! A minimal client for the parts of the s3 api that storing artifacts needs. Buckets are addressed by path
+//! rather than by host name so that the same requests work with s3 compatible stores such as MinIO.
+use crate::client::{child_text, AwsClient, AwsError};
+use crate::sigv4::{encode, encode_pairs, sha256_hex, Request};
+use cloud_conveyor_core::credentials::Credentials;
+use roxmltree::Document;
+
+const SERVICE: &str = "s3";
+
+/// Sends requests to the s3 api on behalf of the account of the credentials.
+#[derive(Debug)]
+pub(crate) struct S3<'a> {
+    client: &'a AwsClient,
+    credentials: Credentials,
+    region: &'a str,
+}
+
+impl<'a> S3<'a> {
+    pub(crate) fn new(client: &'a AwsClient, credentials: Credentials, region: &'a str) -> Self {
+        Self {
+            client,
+            credentials,
+            region,
+        }
+    }
+
+    /// Gets the encoded path of the key in the bucket.
+    fn path(bucket: &str, key: &str) -> String {
+        let key: Vec<String> = key.split('/').map(encode).collect();
+        format!("/{}/{}", bucket, key.join("/"))
+    }
+
+    fn send(
+        &self,
+        method: &str,
+        path: &str,
+        query: &[(String, String)],
+        mut headers: Vec<(String, String)>,
+        payload: &[u8],
+    ) -> Result<Vec<u8>, AwsError> {
+        headers.push(("x-amz-content-sha256".to_string(), sha256_hex(payload)));
+        let request = Request {
+            method,
+            path,
+            query,
+            headers: &headers,
+            payload,
+        };
+        self.client
+            .send(&self.credentials, SERVICE, self.region, &request)
+    }
+
+    /// Creates the bucket in the region. Creating a bucket that is already owned is not an error.
+    pub(crate) fn create_bucket(&self, bucket: &str) -> Result<(), AwsError> {
+        // Buckets in us-east-1 are created without a location.
+        let body = if self.region == "us-east-1" {
+            String::new()
+        } else {
+            format!(
+                "<CreateBucketConfiguration><LocationConstraint>{}</LocationConstraint></CreateBucketConfiguration>",
+                self.region
+            )
+        };
+        match self.send(
+            "PUT",
+            &format!("/{}", bucket),
+            &[],
+            Vec::new(),
+            body.as_bytes(),
+        ) {
+            Ok(_) => Ok(()),
+            Err(e) if e.is("BucketAlreadyOwnedByYou") => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Stores the object at the key with the tags.
+    pub(crate) fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        body: &[u8],
+        tags: &[(String, String)],
+    ) -> Result<(), AwsError> {
+        let mut headers = Vec::new();
+        if !tags.is_empty() {
+            headers.push(("x-amz-tagging".to_string(), encode_pairs(tags)));
+        }
+        self.send("PUT", &Self::path(bucket, key), &[], headers, body)
+            .map(|_| ())
+    }
+
+    /// Gets the object at the key. Returns None when it or its bucket does not exist.
+    pub(crate) fn get_object(&self, bucket: &str, key: &str) -> Result<Option<Vec<u8>>, AwsError> {
+        match self.send("GET", &Self::path(bucket, key), &[], Vec::new(), b"") {
+            Ok(body) => Ok(Some(body)),
+            Err(e) if e.is("NoSuchKey") || e.is("NoSuchBucket") => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub(crate) fn delete_object(&self, bucket: &str, key: &str) -> Result<(), AwsError> {
+        self.send("DELETE", &Self::path(bucket, key), &[], Vec::new(), b"")
+            .map(|_| ())
+    }
+
+    /// Lists the keys of the objects that start with the prefix. When a delimiter is given, keys that have it
+    /// after the prefix are left out. A bucket that does not exist is empty.
+    pub(crate) fn list(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        delimiter: Option<&str>,
+    ) -> Result<Vec<String>, AwsError> {
+        let mut keys = Vec::new();
+        let mut continuation = None;
+        loop {
+            let mut query = vec![
+                ("list-type".to_string(), "2".to_string()),
+                ("prefix".to_string(), prefix.to_string()),
+            ];
+            if let Some(delimiter) = delimiter {
+                query.push(("delimiter".to_string(), delimiter.to_string()));
+            }
+            if let Some(token) = continuation.take() {
+                query.push(("continuation-token".to_string(), token));
+            }
+            let body = match self.send("GET", &format!("/{}", bucket), &query, Vec::new(), b"") {
+                Ok(body) => body,
+                Err(e) if e.is("NoSuchBucket") => return Ok(keys),
+                Err(e) => return Err(e),
+            };
+            let xml = String::from_utf8(body)
+                .map_err(|e| AwsError::Other(format!("Invalid response: {}", e)))?;
+            let document = Document::parse(&xml)
+                .map_err(|e| AwsError::Other(format!("Invalid response: {}", e)))?;
+            keys.extend(
+                document
+                    .root_element()
+                    .children()
+                    .filter(|node| node.tag_name().name() == "Contents")
+                    .filter_map(|node| child_text(node, "Key"))
+                    .map(str::to_string),
+            );
+            continuation = match child_text(document.root(), "IsTruncated") {
+                Some("true") => {
+                    child_text(document.root(), "NextContinuationToken").map(str::to_string)
+                }
+                _ => None,
+            };
+            if continuation.is_none() {
+                return Ok(keys);
+            }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! Signs requests to aws with [version 4](https://docs.aws.amazon.com/general/latest/gr/signature-version-4.html)
+//! of the aws signature process.
+use chrono::{DateTime, Utc};
+use cloud_conveyor_core::credentials::Credentials;
+use hex::ToHex;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+/// Gets the hex encoded sha256 hash of the data.
+pub(crate) fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data).as_slice().to_hex()
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("Hmac takes keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent encodes the value the way aws expects, which leaves only the unreserved characters
+/// of RFC 3986 as they are.
+pub(crate) fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Encodes the pairs as a query string or form body.
+pub(crate) fn encode_pairs(pairs: &[(String, String)]) -> String {
+    pairs
+        .iter()
+        .map(|(name, value)| format!("{}={}", encode(name), encode(value)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// The parts of a request to aws that are signed.
+#[derive(Debug)]
+pub(crate) struct Request<'a> {
+    /// The http method of the request.
+    pub(crate) method: &'a str,
+    /// The path of the request, which must already be encoded.
+    pub(crate) path: &'a str,
+    /// The parameters of the query string of the request.
+    pub(crate) query: &'a [(String, String)],
+    /// The headers of the request that are signed. The `host` header is required.
+    pub(crate) headers: &'a [(String, String)],
+    /// The body of the request.
+    pub(crate) payload: &'a [u8],
+}
+
+/// Signs the request to the service in the region at the time. Returns every header that has to be sent
+/// with the request, which are the headers of the request along with the date, session token and
+/// authorization headers that the signature adds.
+pub(crate) fn sign(
+    request: &Request<'_>,
+    credentials: &Credentials,
+    region: &str,
+    service: &str,
+    time: DateTime<Utc>,
+) -> Vec<(String, String)> {
+    let amz_date = time.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = time.format("%Y%m%d").to_string();
+
+    let mut headers: Vec<(String, String)> = request.headers.to_vec();
+    headers.push(("x-amz-date".to_string(), amz_date.clone()));
+    if let Some(token) = &credentials.session_token {
+        headers.push(("x-amz-security-token".to_string(), token.expose().clone()));
+    }
+    let mut canonical_headers: Vec<(String, String)> = headers
+        .iter()
+        .map(|(name, value)| (name.to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+    canonical_headers.sort();
+    let signed_headers = canonical_headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+
+    let mut query: Vec<(String, String)> = request
+        .query
+        .iter()
+        .map(|(name, value)| (encode(name), encode(value)))
+        .collect();
+    query.sort();
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        request.method,
+        request.path,
+        query
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("&"),
+        canonical_headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value))
+            .collect::<String>(),
+        signed_headers,
+        sha256_hex(request.payload)
+    );
+
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "{}\n{}\n{}\n{}",
+        ALGORITHM,
+        amz_date,
+        scope,
+        sha256_hex(canonical_request.as_bytes())
+    );
+    let key = format!("AWS4{}", credentials.secret_access_key.expose());
+    let key = hmac(key.as_bytes(), &date);
+    let key = hmac(&key, region);
+    let key = hmac(&key, service);
+    let key = hmac(&key, "aws4_request");
+    let signature = hmac(&key, &string_to_sign).to_hex();
+
+    headers.push((
+        "Authorization".to_string(),
+        format!(
+            "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+            ALGORITHM, credentials.access_key_id, scope, signed_headers, signature
+        ),
+    ));
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn credentials() -> Credentials {
+        Credentials::new(
+            "AKIDEXAMPLE".to_string(),
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+        )
+    }
+
+    fn authorization(headers: Vec<(String, String)>) -> String {
+        headers
+            .into_iter()
+            .find(|(name, _)| name == "Authorization")
+            .unwrap()
+            .1
+    }
+
+    #[test]
+    fn signs_the_vanilla_get_of_the_test_suite() {
+        let request = Request {
+            method: "GET",
+            path: "/",
+            query: &[],
+            headers: &[("Host".to_string(), "example.amazonaws.com".to_string())],
+            payload: b"",
+        };
+        let time = Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap();
+        let headers = sign(&request, &credentials(), "us-east-1", "service", time);
+        assert_eq!(
+            authorization(headers),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+    }
+
+    #[test]
+    fn signs_the_iam_example_of_the_documentation() {
+        let request = Request {
+            method: "GET",
+            path: "/",
+            query: &[
+                ("Version".to_string(), "2010-05-08".to_string()),
+                ("Action".to_string(), "ListUsers".to_string()),
+            ],
+            headers: &[
+                (
+                    "Content-Type".to_string(),
+                    "application/x-www-form-urlencoded; charset=utf-8".to_string(),
+                ),
+                ("Host".to_string(), "iam.amazonaws.com".to_string()),
+            ],
+            payload: b"",
+        };
+        let time = Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap();
+        let headers = sign(&request, &credentials(), "us-east-1", "iam", time);
+        assert_eq!(
+            authorization(headers),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/iam/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date, \
+             Signature=5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+        );
+    }
+
+    #[test]
+    fn encodes_everything_but_unreserved_characters() {
+        assert_eq!(
+            encode("a-b_c.d~e f/g+h=é"),
+            "a-b_c.d~e%20f%2Fg%2Bh%3D%C3%A9"
+        );
+    }
+}
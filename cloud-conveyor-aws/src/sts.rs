@@ -0,0 +1,75 @@
+//! A minimal client for the part of the sts api that assuming roles needs.
+use crate::client::{child_text, AwsClient, AwsError};
+use crate::sigv4::{encode_pairs, Request};
+use chrono::{DateTime, Utc};
+use cloud_conveyor_core::credentials::Credentials;
+use roxmltree::Document;
+
+const SERVICE: &str = "sts";
+const VERSION: &str = "2011-06-15";
+
+/// Sts is a global service, so every request is signed for the region of its global endpoint.
+const REGION: &str = "us-east-1";
+
+/// Sends requests to the sts api on behalf of the account of the credentials.
+#[derive(Debug)]
+pub(crate) struct Sts<'a> {
+    client: &'a AwsClient,
+    credentials: &'a Credentials,
+}
+
+impl<'a> Sts<'a> {
+    pub(crate) fn new(client: &'a AwsClient, credentials: &'a Credentials) -> Self {
+        Self {
+            client,
+            credentials,
+        }
+    }
+
+    /// Assumes the role and returns the temporary credentials of the session.
+    pub(crate) fn assume_role(
+        &self,
+        role_arn: &str,
+        session_name: &str,
+    ) -> Result<Credentials, AwsError> {
+        let params = [
+            ("Action".to_string(), "AssumeRole".to_string()),
+            ("Version".to_string(), VERSION.to_string()),
+            ("RoleArn".to_string(), role_arn.to_string()),
+            ("RoleSessionName".to_string(), session_name.to_string()),
+        ];
+        let body = encode_pairs(&params);
+        let headers = [(
+            "Content-Type".to_string(),
+            "application/x-www-form-urlencoded; charset=utf-8".to_string(),
+        )];
+        let request = Request {
+            method: "POST",
+            path: "/",
+            query: &[],
+            headers: &headers,
+            payload: body.as_bytes(),
+        };
+        let body = self
+            .client
+            .send(self.credentials, SERVICE, REGION, &request)?;
+        let xml = String::from_utf8(body)
+            .map_err(|e| AwsError::Other(format!("Invalid response: {}", e)))?;
+        let document = Document::parse(&xml)
+            .map_err(|e| AwsError::Other(format!("Invalid response: {}", e)))?;
+        let root = document.root();
+
+        let field = |name: &str| {
+            child_text(root, name)
+                .map(str::to_string)
+                .ok_or_else(|| AwsError::Other(format!("Response has no {}", name)))
+        };
+        let expiration = DateTime::parse_from_rfc3339(&field("Expiration")?)
+            .map_err(|e| AwsError::Other(format!("Invalid expiration: {}", e)))?;
+        Ok(
+            Credentials::new(field("AccessKeyId")?, field("SecretAccessKey")?)
+                .with_session_token(field("SessionToken")?)
+                .with_expiry(expiration.with_timezone(&Utc)),
+        )
+    }
+}
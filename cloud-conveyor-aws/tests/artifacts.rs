@@ -0,0 +1,176 @@
+mod common;
+
+use chrono::{TimeZone, Utc};
+use cloud_conveyor_aws::S3Artifacts;
+use cloud_conveyor_core::build::{ArtifactManifest, ProvideArtifactLocation, RetentionPolicy};
+use cloud_conveyor_core::credentials::{Credentials, StaticCredentials};
+use common::MockS3;
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+const BUCKET: &str = "cloud-conveyor-zprobst-cloud-conveyor-123456789";
+
+fn artifacts(server: &MockS3) -> S3Artifacts {
+    S3Artifacts::new(common::static_credentials()).with_endpoint(server.url.clone())
+}
+
+fn manifest(sha: &str, minute: u32) -> ArtifactManifest {
+    ArtifactManifest {
+        git_sha: sha.to_string(),
+        bucket: BUCKET.to_string(),
+        folder: sha.to_string(),
+        logs: format!("https://ci.example.com/{}", sha),
+        branch: Some("master".to_string()),
+        built_at: Utc.with_ymd_and_hms(2020, 1, 1, 0, minute, 0).unwrap(),
+    }
+}
+
+fn write_build(dir: &Path) {
+    fs::create_dir_all(dir.join("bin")).unwrap();
+    fs::write(dir.join("bin/app"), b"\x7fELF binary").unwrap();
+    fs::write(dir.join("template file.yaml"), "Resources: {}").unwrap();
+}
+
+#[test]
+fn bucket_is_derived_from_the_application_and_account() {
+    let server = MockS3::start();
+    let app = common::application();
+    assert_eq!(artifacts(&server).get_bucket(&app).unwrap(), BUCKET);
+    assert_eq!(artifacts(&server).get_folder(&app, "abc").unwrap(), "abc");
+    assert_eq!(
+        artifacts(&server)
+            .with_prefix("Builds".to_string())
+            .get_bucket(&app)
+            .unwrap(),
+        "builds-zprobst-cloud-conveyor-123456789"
+    );
+
+    let mut app = common::application();
+    app.app = "a".repeat(80);
+    let bucket = artifacts(&server).get_bucket(&app).unwrap();
+    assert_eq!(bucket.len(), 63);
+    assert!(bucket.ends_with("-123456789"));
+}
+
+#[test]
+fn artifacts_are_stored_under_the_sha_with_tags() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = MockS3::start();
+    let provider = artifacts(&server).with_tag("retention".to_string(), "short".to_string());
+    let app = common::application();
+    write_build(&dir.path().join("source"));
+
+    provider
+        .upload(&app, "abc", &dir.path().join("source"))
+        .unwrap();
+    assert_eq!(
+        server.keys(BUCKET),
+        vec![
+            "abc/bin/app".to_string(),
+            "abc/template file.yaml".to_string()
+        ]
+    );
+    let object = server.object(BUCKET, "abc/bin/app").unwrap();
+    assert_eq!(object.body, b"\x7fELF binary");
+    assert_eq!(object.tags["cloud-conveyor:app"], "cloud-conveyor");
+    assert_eq!(object.tags["cloud-conveyor:sha"], "abc");
+    assert_eq!(object.tags["retention"], "short");
+    // The bucket lives in the first region of the default account.
+    assert!(server.requests().iter().all(|r| r.region == "us-east-1"));
+
+    provider
+        .download(&app, "abc", &dir.path().join("download"))
+        .unwrap();
+    assert_eq!(
+        fs::read(dir.path().join("download/bin/app")).unwrap(),
+        b"\x7fELF binary"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("download/template file.yaml")).unwrap(),
+        "Resources: {}"
+    );
+}
+
+#[test]
+fn builds_are_recorded_listed_and_cleaned_up() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = MockS3::start();
+    let provider = artifacts(&server);
+    let app = common::application();
+    assert!(provider.list(&app).unwrap().is_empty());
+    assert!(provider.find_build(&app, "abc").unwrap().is_none());
+
+    write_build(&dir.path().join("source"));
+    for (sha, minute) in &[("ccc", 3), ("aaa", 1), ("bbb", 2)] {
+        provider
+            .upload(&app, sha, &dir.path().join("source"))
+            .unwrap();
+        provider
+            .record_build(&app, &manifest(sha, *minute))
+            .unwrap();
+    }
+    assert_eq!(
+        provider.find_build(&app, "bbb").unwrap(),
+        Some(manifest("bbb", 2))
+    );
+    // Listings are paged, so this also covers following continuation tokens.
+    let shas: Vec<_> = provider
+        .list(&app)
+        .unwrap()
+        .into_iter()
+        .map(|build| build.git_sha)
+        .collect();
+    assert_eq!(shas, vec!["aaa", "bbb", "ccc"]);
+
+    let policy = RetentionPolicy {
+        builds_per_branch: 1,
+    };
+    let mut deleted = provider
+        .cleanup(&app, &policy, &["aaa".to_string()])
+        .unwrap();
+    deleted.sort();
+    assert_eq!(deleted, vec!["bbb"]);
+    assert!(server
+        .keys(BUCKET)
+        .iter()
+        .all(|key| !key.starts_with("bbb")));
+    assert!(provider.find_build(&app, "bbb").unwrap().is_none());
+    assert!(server.object(BUCKET, "aaa/bin/app").is_some());
+}
+
+#[test]
+fn artifacts_are_stored_in_minio() {
+    let endpoint = match env::var("MINIO_ENDPOINT") {
+        Ok(endpoint) => endpoint,
+        Err(_) => {
+            eprintln!("Skipping test since MINIO_ENDPOINT is not set");
+            return;
+        }
+    };
+    let credentials = StaticCredentials::default().with_default(Credentials::new(
+        env::var("MINIO_ACCESS_KEY").unwrap_or_else(|_| "minioadmin".to_string()),
+        env::var("MINIO_SECRET_KEY").unwrap_or_else(|_| "minioadmin".to_string()),
+    ));
+    let dir = tempfile::tempdir().unwrap();
+    let provider = S3Artifacts::new(Box::new(credentials)).with_endpoint(endpoint);
+    let app = common::application();
+    write_build(&dir.path().join("source"));
+
+    provider
+        .upload(&app, "abc", &dir.path().join("source"))
+        .unwrap();
+    provider.record_build(&app, &manifest("abc", 1)).unwrap();
+    assert_eq!(
+        provider.find_build(&app, "abc").unwrap(),
+        Some(manifest("abc", 1))
+    );
+    provider
+        .download(&app, "abc", &dir.path().join("download"))
+        .unwrap();
+    assert!(dir.path().join("download/bin/app").exists());
+
+    provider.delete(&app, "abc").unwrap();
+    assert!(provider.find_build(&app, "abc").unwrap().is_none());
+}
@@ -0,0 +1,615 @@
+//! Shared helpers for the integration tests of the aws runtime.
+#![allow(dead_code)]
+use cloud_conveyor_aws::CloudFormationDeployer;
+use cloud_conveyor_core::approval::MemoryApprovals;
+use cloud_conveyor_core::audit::MemoryAuditLog;
+use cloud_conveyor_core::credentials::{CredentialProvider, Credentials, StaticCredentials};
+use cloud_conveyor_core::history::MemoryHistory;
+use cloud_conveyor_core::runtime::RuntimeContext;
+use cloud_conveyor_core::secrets::NoSecrets;
+use cloud_conveyor_core::yaml::ConfigFile;
+use cloud_conveyor_core::{Application, Stage};
+use cloud_conveyor_local::{FilesystemArtifacts, LocalBuilder};
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+pub const REPO: &str = "https://github.com/zprobst/cloud-conveyor.git";
+
+const CONFIG: &str = r#"
+org: zprobst
+app: cloud-conveyor
+accounts:
+  - name: default
+    id: 123456789
+    regions:
+      - us-east-1
+      - us-west-2
+approvals: {}
+triggers: []
+stages:
+  - name: dev
+"#;
+
+pub fn application() -> Application {
+    let conf: ConfigFile = serde_yaml::from_str(CONFIG).unwrap();
+    conf.into()
+}
+
+pub fn stage(name: &str) -> Stage {
+    let mut stage = application().stages.remove(0);
+    stage.name = name.to_string();
+    stage
+}
+
+pub fn credentials() -> Credentials {
+    Credentials::new("AKIDEXAMPLE".to_string(), "s3cr3t".to_string())
+}
+
+/// Provides the example credentials for every account.
+pub fn static_credentials() -> Box<dyn CredentialProvider> {
+    Box::new(StaticCredentials::default().with_default(credentials()))
+}
+
+pub fn context(dir: &Path, deployer: impl Fn() -> CloudFormationDeployer) -> RuntimeContext {
+    context_with_credentials(dir, deployer, static_credentials())
+}
+
+pub fn context_with_credentials(
+    dir: &Path,
+    deployer: impl Fn() -> CloudFormationDeployer,
+    credentials: Box<dyn CredentialProvider>,
+) -> RuntimeContext {
+    RuntimeContext {
+        artifact_provider: Box::new(FilesystemArtifacts::new(dir.join("artifacts"))),
+        builder: Box::new(LocalBuilder::new(dir.join("builds"), "true".to_string())),
+        infrastructure: Box::new(deployer()),
+        teardown: Box::new(deployer()),
+        secrets: Box::new(NoSecrets),
+        credentials,
+        history: Box::new(MemoryHistory::default()),
+        audit: Box::new(MemoryAuditLog::default()),
+        approvals: Box::new(MemoryApprovals::default()),
+        applications: vec![application()],
+    }
+}
+
+/// Stores a build of the sha whose artifacts hold the template.
+pub fn upload_template(ctx: &RuntimeContext, dir: &Path, sha: &str, template: &str) {
+    let source = dir.join("source").join(sha);
+    fs::create_dir_all(source.join("cloudformation")).unwrap();
+    fs::write(source.join("cloudformation/template.yaml"), template).unwrap();
+    ctx.artifact_provider
+        .upload(&application(), sha, &source)
+        .unwrap();
+}
+
+/// A request that was made to the mock cloud formation api.
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub region: String,
+    pub authorization: String,
+    pub params: BTreeMap<String, String>,
+}
+
+impl Request {
+    pub fn action(&self) -> &str {
+        &self.params["Action"]
+    }
+
+    /// Gets the values of the list parameter, such as the keys and values of the `Parameters` of a stack.
+    pub fn list(&self, prefix: &str, field: &str) -> Vec<String> {
+        (1..)
+            .map(|i| format!("{}.member.{}{}", prefix, i, field))
+            .map(|key| self.params.get(&key).cloned())
+            .take_while(Option::is_some)
+            .map(Option::unwrap)
+            .collect()
+    }
+}
+
+/// A stack that is stored by the mock cloud formation api.
+#[derive(Clone, Debug)]
+pub struct MockStack {
+    pub status: String,
+    pub template: String,
+    pub parameters: Vec<(String, String)>,
+    /// The events of the stack as (logical id, resource type, status, reason), oldest first.
+    pub events: Vec<(String, String, String, String)>,
+}
+
+/// Stands in for cloud formation. Stacks are stored by region and name, and change status only when
+/// the test says so, as cloud formation would once it has done its work.
+#[derive(Debug)]
+pub struct MockCloudFormation {
+    pub url: String,
+    pub stacks: Arc<Mutex<BTreeMap<(String, String), MockStack>>>,
+    pub requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl MockCloudFormation {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let stacks = Arc::new(Mutex::new(BTreeMap::new()));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let (stored, recorded) = (stacks.clone(), requests.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                let request = read_request(&stream);
+                let (status, body) = handle(&mut stored.lock().unwrap(), &request);
+                recorded.lock().unwrap().push(request);
+                write_response(stream, status, body.as_bytes());
+            }
+        });
+        Self {
+            url,
+            stacks,
+            requests,
+        }
+    }
+
+    pub fn stack(&self, region: &str, name: &str) -> Option<MockStack> {
+        let key = (region.to_string(), name.to_string());
+        self.stacks.lock().unwrap().get(&key).cloned()
+    }
+
+    /// Sets the status of the stack and records it as an event.
+    pub fn set_status(&self, region: &str, name: &str, status: &str) {
+        self.push_event(region, name, name, "AWS::CloudFormation::Stack", status, "");
+    }
+
+    /// Records an event of a resource of the stack. Events of the stack itself also set its status.
+    pub fn push_event(
+        &self,
+        region: &str,
+        name: &str,
+        logical_id: &str,
+        resource_type: &str,
+        status: &str,
+        reason: &str,
+    ) {
+        let key = (region.to_string(), name.to_string());
+        let mut stacks = self.stacks.lock().unwrap();
+        let stack = stacks.get_mut(&key).unwrap();
+        if logical_id == name {
+            stack.status = status.to_string();
+        }
+        stack.events.push((
+            logical_id.to_string(),
+            resource_type.to_string(),
+            status.to_string(),
+            reason.to_string(),
+        ));
+    }
+
+    /// Removes the stack, as cloud formation does once it is deleted.
+    pub fn finish_deletion(&self, region: &str, name: &str) {
+        let key = (region.to_string(), name.to_string());
+        self.stacks.lock().unwrap().remove(&key);
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn error(code: &str, message: &str) -> (u16, String) {
+    (
+        400,
+        format!(
+            "<ErrorResponse><Error><Type>Sender</Type><Code>{}</Code><Message>{}</Message></Error></ErrorResponse>",
+            code, message
+        ),
+    )
+}
+
+fn response(action: &str, result: &str) -> (u16, String) {
+    (
+        200,
+        format!(
+            r#"<{0}Response xmlns="http://cloudformation.amazonaws.com/doc/2010-05-15/"><{0}Result>{1}</{0}Result></{0}Response>"#,
+            action, result
+        ),
+    )
+}
+
+/// Answers an sts request to assume a role with credentials whose key id is derived from the arn of the
+/// role, so that tests can tell which role later requests were signed as.
+fn assume_role(request: &Request) -> (u16, String) {
+    let role_arn = &request.params["RoleArn"];
+    let key: String = role_arn
+        .split(':')
+        .skip(4)
+        .collect::<String>()
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect();
+    (
+        200,
+        format!(
+            r#"<AssumeRoleResponse xmlns="https://sts.amazonaws.com/doc/2011-06-15/"><AssumeRoleResult>
+            <Credentials><AccessKeyId>ASIA{}</AccessKeyId><SecretAccessKey>s3cr3t</SecretAccessKey>
+            <SessionToken>token-{}</SessionToken><Expiration>2099-01-01T00:00:00Z</Expiration></Credentials>
+            </AssumeRoleResult></AssumeRoleResponse>"#,
+            key, key
+        ),
+    )
+}
+
+/// Gets the names of the parameters in the `Parameters` section of a yaml template.
+fn declared_parameters(template: &str) -> Vec<String> {
+    template
+        .lines()
+        .skip_while(|line| !line.starts_with("Parameters:"))
+        .skip(1)
+        .take_while(|line| line.is_empty() || line.starts_with(' '))
+        .filter(|line| line.starts_with("  ") && !line.starts_with("   "))
+        .map(|line| line.trim().trim_end_matches(':').to_string())
+        .collect()
+}
+
+fn handle(stacks: &mut BTreeMap<(String, String), MockStack>, request: &Request) -> (u16, String) {
+    if request.authorization.contains("Credential=INVALID/") {
+        return (
+            403,
+            "<ErrorResponse><Error><Code>InvalidClientTokenId</Code><Message>The security token included in the request is invalid.</Message></Error></ErrorResponse>".to_string(),
+        );
+    }
+    let action = request.action();
+    if action == "AssumeRole" {
+        return assume_role(request);
+    }
+    if action == "ValidateTemplate" {
+        let members: String = declared_parameters(&request.params["TemplateBody"])
+            .iter()
+            .map(|name| format!("<member><ParameterKey>{}</ParameterKey></member>", name))
+            .collect();
+        return response(action, &format!("<Parameters>{}</Parameters>", members));
+    }
+
+    let name = request.params["StackName"].clone();
+    let key = (request.region.clone(), name.clone());
+    let parameters: Vec<(String, String)> = request
+        .list("Parameters", ".ParameterKey")
+        .into_iter()
+        .zip(request.list("Parameters", ".ParameterValue"))
+        .collect();
+    let missing = format!("Stack with id {} does not exist", name);
+    match (action, stacks.get_mut(&key)) {
+        ("DescribeStacks", Some(stack)) => response(
+            action,
+            &format!(
+                "<Stacks><member><StackName>{}</StackName><StackStatus>{}</StackStatus></member></Stacks>",
+                name, stack.status
+            ),
+        ),
+        ("DescribeStackEvents", Some(stack)) => {
+            let members: String = stack
+                .events
+                .iter()
+                .rev()
+                .map(|(logical_id, resource_type, status, reason)| {
+                    format!(
+                        "<member><LogicalResourceId>{}</LogicalResourceId><ResourceType>{}</ResourceType>\
+                         <ResourceStatus>{}</ResourceStatus><ResourceStatusReason>{}</ResourceStatusReason></member>",
+                        logical_id, resource_type, status, reason
+                    )
+                })
+                .collect();
+            response(action, &format!("<StackEvents>{}</StackEvents>", members))
+        }
+        ("CreateStack", None) => {
+            stacks.insert(
+                key,
+                MockStack {
+                    status: "CREATE_IN_PROGRESS".to_string(),
+                    template: request.params["TemplateBody"].clone(),
+                    parameters,
+                    events: vec![(
+                        name.clone(),
+                        "AWS::CloudFormation::Stack".to_string(),
+                        "CREATE_IN_PROGRESS".to_string(),
+                        "User Initiated".to_string(),
+                    )],
+                },
+            );
+            response(action, "<StackId>arn:aws:cloudformation:stack</StackId>")
+        }
+        ("CreateStack", Some(_)) => error("AlreadyExistsException", "Stack already exists"),
+        ("UpdateStack", Some(stack)) => {
+            if stack.template == request.params["TemplateBody"] && stack.parameters == parameters {
+                return error("ValidationError", "No updates are to be performed.");
+            }
+            stack.template = request.params["TemplateBody"].clone();
+            stack.parameters = parameters;
+            stack.status = "UPDATE_IN_PROGRESS".to_string();
+            stack.events.push((
+                name,
+                "AWS::CloudFormation::Stack".to_string(),
+                "UPDATE_IN_PROGRESS".to_string(),
+                "User Initiated".to_string(),
+            ));
+            response(action, "<StackId>arn:aws:cloudformation:stack</StackId>")
+        }
+        ("DeleteStack", Some(stack)) => {
+            stack.status = "DELETE_IN_PROGRESS".to_string();
+            response(action, "")
+        }
+        ("DeleteStack", None) => response(action, ""),
+        (_, None) => error("ValidationError", &missing),
+        _ => error("InvalidAction", action),
+    }
+}
+
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap();
+                decoded.push(u8::from_str_radix(hex, 16).unwrap());
+                i += 3;
+            }
+            b'+' => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            b => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).unwrap()
+}
+
+/// Reads the method, target, headers and body of a request.
+fn read_http(stream: &TcpStream) -> (String, String, Vec<(String, String)>, Vec<u8>) {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap().to_string();
+    let target = parts.next().unwrap().to_string();
+
+    let mut headers = Vec::new();
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).unwrap();
+        let header = header.trim().to_string();
+        if header.is_empty() {
+            break;
+        }
+        let (name, value) = header.split_at(header.find(':').unwrap());
+        let (name, value) = (name.to_ascii_lowercase(), value[1..].trim().to_string());
+        if name == "content-length" {
+            length = value.parse().unwrap();
+        }
+        headers.push((name, value));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).unwrap();
+    (method, target, headers, body)
+}
+
+fn header(headers: &[(String, String)], name: &str) -> String {
+    headers
+        .iter()
+        .find(|(header, _)| header == name)
+        .map(|(_, value)| value.clone())
+        .unwrap_or_default()
+}
+
+/// Parses url encoded pairs such as a query string or form body.
+fn parse_pairs(encoded: &str) -> BTreeMap<String, String> {
+    encoded
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            let name = decode(parts.next().unwrap());
+            (name, decode(parts.next().unwrap_or_default()))
+        })
+        .collect()
+}
+
+/// Gets the region out of the credential scope of the signature, which is
+/// `<key>/<date>/<region>/<service>/aws4_request`.
+fn region(authorization: &str) -> String {
+    authorization
+        .split("Credential=")
+        .nth(1)
+        .and_then(|scope| scope.split('/').nth(2))
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn read_request(stream: &TcpStream) -> Request {
+    let (_, _, headers, body) = read_http(stream);
+    let authorization = header(&headers, "authorization");
+    Request {
+        region: region(&authorization),
+        authorization,
+        params: parse_pairs(&String::from_utf8(body).unwrap()),
+    }
+}
+
+fn write_response(mut stream: TcpStream, status: u16, body: &[u8]) {
+    write!(
+        stream,
+        "HTTP/1.1 {} Mock\r\nContent-Type: text/xml\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        body.len(),
+    )
+    .unwrap();
+    stream.write_all(body).unwrap();
+}
+
+/// A request that was made to the mock s3 api.
+#[derive(Clone, Debug)]
+pub struct S3Request {
+    pub method: String,
+    pub path: String,
+    pub query: BTreeMap<String, String>,
+    pub region: String,
+}
+
+/// An object that is stored by the mock s3 api.
+#[derive(Clone, Debug)]
+pub struct MockObject {
+    pub body: Vec<u8>,
+    pub tags: BTreeMap<String, String>,
+}
+
+/// The number of keys the mock s3 api lists at a time, so that listings are paged.
+const PAGE_SIZE: usize = 2;
+
+/// Stands in for s3 or MinIO. Objects are stored by bucket and key.
+#[derive(Debug)]
+pub struct MockS3 {
+    pub url: String,
+    pub buckets: Arc<Mutex<BTreeMap<String, BTreeMap<String, MockObject>>>>,
+    pub requests: Arc<Mutex<Vec<S3Request>>>,
+}
+
+impl MockS3 {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let buckets = Arc::new(Mutex::new(BTreeMap::new()));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let (stored, recorded) = (buckets.clone(), requests.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                let (method, target, headers, body) = read_http(&stream);
+                let mut parts = target.splitn(2, '?');
+                let path = decode(parts.next().unwrap());
+                let request = S3Request {
+                    method,
+                    path,
+                    query: parse_pairs(parts.next().unwrap_or_default()),
+                    region: region(&header(&headers, "authorization")),
+                };
+                let tags = parse_pairs(&header(&headers, "x-amz-tagging"));
+                let (status, body) = handle_s3(&mut stored.lock().unwrap(), &request, body, tags);
+                recorded.lock().unwrap().push(request);
+                write_response(stream, status, &body);
+            }
+        });
+        Self {
+            url,
+            buckets,
+            requests,
+        }
+    }
+
+    pub fn object(&self, bucket: &str, key: &str) -> Option<MockObject> {
+        let buckets = self.buckets.lock().unwrap();
+        buckets.get(bucket).and_then(|b| b.get(key)).cloned()
+    }
+
+    pub fn keys(&self, bucket: &str) -> Vec<String> {
+        let buckets = self.buckets.lock().unwrap();
+        buckets
+            .get(bucket)
+            .map(|b| b.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn requests(&self) -> Vec<S3Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn s3_error(status: u16, code: &str) -> (u16, Vec<u8>) {
+    let body = format!(
+        "<Error><Code>{}</Code><Message>{}</Message></Error>",
+        code, code
+    );
+    (status, body.into_bytes())
+}
+
+fn handle_s3(
+    buckets: &mut BTreeMap<String, BTreeMap<String, MockObject>>,
+    request: &S3Request,
+    body: Vec<u8>,
+    tags: BTreeMap<String, String>,
+) -> (u16, Vec<u8>) {
+    let mut parts = request.path[1..].splitn(2, '/');
+    let bucket = parts.next().unwrap().to_string();
+    let key = parts.next().map(str::to_string);
+    match (request.method.as_str(), key) {
+        ("PUT", None) if buckets.contains_key(&bucket) => s3_error(409, "BucketAlreadyOwnedByYou"),
+        ("PUT", None) => {
+            buckets.insert(bucket, BTreeMap::new());
+            (200, Vec::new())
+        }
+        ("GET", None) => {
+            let objects = match buckets.get(&bucket) {
+                Some(objects) => objects,
+                None => return s3_error(404, "NoSuchBucket"),
+            };
+            let prefix = request.query.get("prefix").cloned().unwrap_or_default();
+            let delimiter = request.query.get("delimiter");
+            let after = request.query.get("continuation-token");
+            let keys: Vec<&String> = objects
+                .keys()
+                .filter(|key| key.starts_with(&prefix))
+                .filter(|key| delimiter.is_none_or(|d| !key[prefix.len()..].contains(d.as_str())))
+                .filter(|key| after.is_none_or(|after| key.as_str() > after.as_str()))
+                .collect();
+            let page = &keys[..keys.len().min(PAGE_SIZE)];
+            let truncated = keys.len() > PAGE_SIZE;
+            let contents: String = page
+                .iter()
+                .map(|key| format!("<Contents><Key>{}</Key></Contents>", key))
+                .collect();
+            let token = if truncated {
+                format!(
+                    "<NextContinuationToken>{}</NextContinuationToken>",
+                    page.last().unwrap()
+                )
+            } else {
+                String::new()
+            };
+            let body = format!(
+                r#"<ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">{}<IsTruncated>{}</IsTruncated>{}</ListBucketResult>"#,
+                contents, truncated, token
+            );
+            (200, body.into_bytes())
+        }
+        (method, Some(key)) => {
+            let objects = match buckets.get_mut(&bucket) {
+                Some(objects) => objects,
+                None => return s3_error(404, "NoSuchBucket"),
+            };
+            match method {
+                "PUT" => {
+                    objects.insert(key, MockObject { body, tags });
+                    (200, Vec::new())
+                }
+                "GET" => match objects.get(&key) {
+                    Some(object) => (200, object.body.clone()),
+                    None => s3_error(404, "NoSuchKey"),
+                },
+                "DELETE" => {
+                    objects.remove(&key);
+                    (204, Vec::new())
+                }
+                _ => s3_error(405, "MethodNotAllowed"),
+            }
+        }
+        _ => s3_error(405, "MethodNotAllowed"),
+    }
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use cloud_conveyor_aws::{AssumeRoleCredentials, ProfileCredentials};
+use cloud_conveyor_core::credentials::{CachedCredentials, CredentialError, CredentialProvider};
+use cloud_conveyor_core::Account;
+use common::MockCloudFormation;
+
+use std::fs;
+
+const CREDENTIALS_FILE: &str = r#"
+[default]
+aws_access_key_id = AKIDDEFAULT
+aws_secret_access_key = s3cr3t
+
+# Temporary keys of the production account.
+[production]
+aws_access_key_id=AKIDPROD
+aws_secret_access_key=s3cr3t
+aws_session_token=t0ken
+"#;
+
+fn account(name: &str, id: usize) -> Account {
+    Account {
+        name: name.to_string(),
+        id,
+        regions: vec!["us-east-1".to_string()],
+    }
+}
+
+#[test]
+fn profiles_are_read_from_the_credentials_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("credentials");
+    fs::write(&path, CREDENTIALS_FILE).unwrap();
+    let provider =
+        ProfileCredentials::new(&path).with_profile("prod".to_string(), "production".to_string());
+
+    let default = provider.credentials(&account("default", 1)).unwrap();
+    assert_eq!(default.access_key_id, "AKIDDEFAULT");
+    assert!(default.session_token.is_none());
+    let prod = provider.credentials(&account("prod", 2)).unwrap();
+    assert_eq!(prod.access_key_id, "AKIDPROD");
+    assert_eq!(prod.session_token.unwrap().expose(), "t0ken");
+
+    match provider.credentials(&account("stage", 3)) {
+        Err(CredentialError::Missing { account }) => assert_eq!(account, "stage"),
+        result => panic!("unexpected result {:?}", result),
+    }
+}
+
+#[test]
+fn roles_are_assumed_through_the_chain() {
+    let server = MockCloudFormation::start();
+    let provider = AssumeRoleCredentials::new(common::static_credentials(), "deployer".to_string())
+        .with_chain("arn:aws:iam::111111111111:role/hub".to_string())
+        .with_endpoint(server.url.clone());
+
+    let credentials = provider.credentials(&account("prod", 42)).unwrap();
+    assert_eq!(credentials.access_key_id, "ASIA000000000042roledeployer");
+    assert!(credentials.expires_at.is_some());
+
+    let requests = server.requests();
+    let roles: Vec<_> = requests
+        .iter()
+        .map(|r| r.params["RoleArn"].clone())
+        .collect();
+    assert_eq!(
+        roles,
+        vec![
+            "arn:aws:iam::111111111111:role/hub".to_string(),
+            "arn:aws:iam::000000000042:role/deployer".to_string(),
+        ]
+    );
+    // Every role is assumed with the credentials of the one before it.
+    assert!(requests[0]
+        .authorization
+        .contains("Credential=AKIDEXAMPLE/"));
+    assert!(requests[1]
+        .authorization
+        .contains("Credential=ASIA111111111111rolehub/"));
+    assert!(requests.iter().all(|r| r.region == "us-east-1"));
+}
+
+#[test]
+fn assumed_roles_are_cached_until_they_expire() {
+    let server = MockCloudFormation::start();
+    let provider = CachedCredentials::new(Box::new(
+        AssumeRoleCredentials::new(common::static_credentials(), "deployer".to_string())
+            .with_endpoint(server.url.clone()),
+    ));
+
+    for _ in 0..3 {
+        provider.credentials(&account("prod", 42)).unwrap();
+    }
+    provider.credentials(&account("stage", 7)).unwrap();
+    assert_eq!(server.requests().len(), 2);
+}
@@ -0,0 +1,401 @@
+mod common;
+
+use cloud_conveyor_aws::{stack_name, CloudFormationDeployer};
+use cloud_conveyor_core::credentials::{
+    CredentialError, Credentials, NoCredentials, StaticCredentials,
+};
+use cloud_conveyor_core::deploy::{DeployPollError, DeployStatus};
+use cloud_conveyor_core::pipelining::{Deploy, Teardown};
+use cloud_conveyor_core::secrets::{SecretError, SecretProvider, Secrets};
+use cloud_conveyor_core::teardown::{TeardownPollError, TeardownStatus};
+use cloud_conveyor_core::{Account, Application};
+use common::{MockCloudFormation, REPO};
+
+use std::env;
+
+const STACK: &str = "cloud-conveyor-pr-42";
+
+const TEMPLATE: &str = r#"
+Parameters:
+  Stage:
+    Type: String
+  GitRef:
+    Type: String
+  DATABASEx5FURL:
+    Type: String
+    NoEcho: true
+Resources:
+  Topic:
+    Type: AWS::SNS::Topic
+    Properties:
+      TopicName: !Sub "${AWS::StackName}-${GitRef}"
+"#;
+
+#[derive(Debug)]
+struct OneSecret;
+
+impl SecretProvider for OneSecret {
+    fn get_secret(&self, _app: &Application, _name: &str) -> Result<String, SecretError> {
+        Ok("postgres://db".to_string())
+    }
+}
+
+fn deployer(server: &MockCloudFormation, dir: &std::path::Path) -> CloudFormationDeployer {
+    CloudFormationDeployer::new(dir.join("workspace")).with_endpoint(server.url.clone())
+}
+
+fn deploy(git_ref: &str) -> Deploy {
+    Deploy::new(
+        common::stage("pr-42"),
+        REPO.to_string(),
+        git_ref.to_string(),
+    )
+}
+
+#[test]
+fn stack_is_named_after_the_stage() {
+    let app = common::application();
+    assert_eq!(stack_name(&app, &common::stage("pr-42")), STACK);
+    assert_eq!(
+        stack_name(&app, &common::stage("cloud-conveyor-pr-42")),
+        STACK
+    );
+    assert_eq!(
+        stack_name(&app, &common::stage("feature/Login_Page")),
+        "cloud-conveyor-feature-Login-Page"
+    );
+    let long = stack_name(&app, &common::stage(&"x".repeat(200)));
+    assert_eq!(long.len(), 128);
+    let longer = stack_name(&app, &common::stage(&"x".repeat(201)));
+    assert_eq!(longer.len(), 128);
+    assert_ne!(long, longer);
+
+    let mut app = common::application();
+    app.app = "3d-viewer".to_string();
+    assert_eq!(
+        stack_name(&app, &common::stage("pr-42")),
+        "stack-3d-viewer-pr-42"
+    );
+}
+
+#[test]
+fn stacks_are_created_in_every_region_with_the_declared_parameters() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = MockCloudFormation::start();
+    let ctx = common::context(dir.path(), || deployer(&server, dir.path()));
+    common::upload_template(&ctx, dir.path(), "abc", TEMPLATE);
+
+    let secrets = Secrets::resolve(
+        &OneSecret,
+        &common::application(),
+        &["DATABASE_URL".to_string()],
+    )
+    .unwrap();
+    ctx.infrastructure
+        .start_deployment(&deploy("abc"), &secrets, &ctx)
+        .unwrap();
+
+    for region in &["us-east-1", "us-west-2"] {
+        let stack = server.stack(region, STACK).unwrap();
+        assert_eq!(stack.status, "CREATE_IN_PROGRESS");
+        assert_eq!(stack.template, TEMPLATE);
+        assert_eq!(
+            stack.parameters,
+            vec![
+                ("Stage".to_string(), "pr-42".to_string()),
+                ("GitRef".to_string(), "abc".to_string()),
+                ("DATABASEx5FURL".to_string(), "postgres://db".to_string()),
+            ]
+        );
+    }
+    let create = server
+        .requests()
+        .into_iter()
+        .find(|r| r.action() == "CreateStack")
+        .unwrap();
+    assert!(create
+        .authorization
+        .starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/"));
+    assert_eq!(
+        create.list("Tags", ".Value"),
+        vec!["cloud-conveyor".to_string(), "pr-42".to_string()]
+    );
+    assert!(create
+        .list("Capabilities", "")
+        .contains(&"CAPABILITY_NAMED_IAM".to_string()));
+}
+
+#[test]
+fn secrets_whose_names_only_differ_in_punctuation_get_their_own_parameters() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = MockCloudFormation::start();
+    let ctx = common::context(dir.path(), || deployer(&server, dir.path()));
+    let template = r#"
+Parameters:
+  APIx5FKEY:
+    Type: String
+    NoEcho: true
+  APIKEY:
+    Type: String
+    NoEcho: true
+  APIx2EKEY:
+    Type: String
+    NoEcho: true
+Resources:
+  Topic:
+    Type: AWS::SNS::Topic
+"#;
+    common::upload_template(&ctx, dir.path(), "abc", template);
+
+    let names: Vec<String> = vec!["API_KEY".into(), "APIKEY".into(), "API.KEY".into()];
+    let secrets = Secrets::resolve(&OneSecret, &common::application(), &names).unwrap();
+    ctx.infrastructure
+        .start_deployment(&deploy("abc"), &secrets, &ctx)
+        .unwrap();
+
+    let stack = server.stack("us-east-1", STACK).unwrap();
+    let mut parameters: Vec<String> = stack.parameters.into_iter().map(|(name, _)| name).collect();
+    parameters.sort();
+    assert_eq!(parameters, vec!["APIKEY", "APIx2EKEY", "APIx5FKEY"]);
+}
+
+#[test]
+fn existing_stacks_are_updated() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = MockCloudFormation::start();
+    let ctx = common::context(dir.path(), || deployer(&server, dir.path()));
+    common::upload_template(&ctx, dir.path(), "abc", TEMPLATE);
+    common::upload_template(&ctx, dir.path(), "def", TEMPLATE);
+
+    ctx.infrastructure
+        .start_deployment(&deploy("abc"), &Secrets::default(), &ctx)
+        .unwrap();
+    for region in &["us-east-1", "us-west-2"] {
+        server.set_status(region, STACK, "CREATE_COMPLETE");
+    }
+
+    // Deploying the same template and parameters again changes nothing and is complete right away.
+    ctx.infrastructure
+        .start_deployment(&deploy("abc"), &Secrets::default(), &ctx)
+        .unwrap();
+    assert_eq!(
+        ctx.infrastructure
+            .check_deployment(&deploy("abc"), &ctx)
+            .unwrap(),
+        DeployStatus::Complete
+    );
+
+    ctx.infrastructure
+        .start_deployment(&deploy("def"), &Secrets::default(), &ctx)
+        .unwrap();
+    let stack = server.stack("us-east-1", STACK).unwrap();
+    assert_eq!(stack.status, "UPDATE_IN_PROGRESS");
+    assert!(stack
+        .parameters
+        .contains(&("GitRef".to_string(), "def".to_string())));
+}
+
+#[test]
+fn deployment_status_follows_the_stacks_and_failures_are_reported() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = MockCloudFormation::start();
+    let ctx = common::context(dir.path(), || deployer(&server, dir.path()));
+    common::upload_template(&ctx, dir.path(), "abc", TEMPLATE);
+    ctx.infrastructure
+        .start_deployment(&deploy("abc"), &Secrets::default(), &ctx)
+        .unwrap();
+    let check = || {
+        ctx.infrastructure
+            .check_deployment(&deploy("abc"), &ctx)
+            .unwrap()
+    };
+    assert_eq!(check(), DeployStatus::Pending);
+
+    server.set_status("us-east-1", STACK, "CREATE_COMPLETE");
+    assert_eq!(check(), DeployStatus::Pending);
+
+    server.push_event(
+        "us-west-2",
+        STACK,
+        "Topic",
+        "AWS::SNS::Topic",
+        "CREATE_FAILED",
+        "Topic already exists",
+    );
+    server.set_status("us-west-2", STACK, "ROLLBACK_IN_PROGRESS");
+    assert_eq!(check(), DeployStatus::Pending);
+    server.set_status("us-west-2", STACK, "ROLLBACK_COMPLETE");
+    assert_eq!(check(), DeployStatus::Failed);
+    assert!(server
+        .requests()
+        .iter()
+        .any(|r| r.action() == "DescribeStackEvents" && r.region == "us-west-2"));
+
+    // A stack that failed to be created cannot be updated and has to be torn down first.
+    match ctx
+        .infrastructure
+        .start_deployment(&deploy("abc"), &Secrets::default(), &ctx)
+    {
+        Err(DeployPollError::Other { info }) => assert!(info.contains("torn down")),
+        result => panic!("unexpected result {:?}", result),
+    }
+}
+
+#[test]
+fn teardown_deletes_the_stacks() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = MockCloudFormation::start();
+    let ctx = common::context(dir.path(), || deployer(&server, dir.path()));
+    common::upload_template(&ctx, dir.path(), "abc", TEMPLATE);
+    ctx.infrastructure
+        .start_deployment(&deploy("abc"), &Secrets::default(), &ctx)
+        .unwrap();
+
+    let teardown = Teardown::new(common::stage("pr-42"), REPO.to_string());
+    let check = || ctx.teardown.check_teardown(&teardown, &ctx).unwrap();
+    ctx.teardown.start_teardown(&teardown, &ctx).unwrap();
+    assert_eq!(
+        server.stack("us-east-1", STACK).unwrap().status,
+        "DELETE_IN_PROGRESS"
+    );
+    assert_eq!(check(), TeardownStatus::Pending);
+
+    server.finish_deletion("us-east-1", STACK);
+    server.set_status("us-west-2", STACK, "DELETE_FAILED");
+    assert_eq!(check(), TeardownStatus::Failed);
+
+    server.finish_deletion("us-west-2", STACK);
+    assert_eq!(check(), TeardownStatus::Complete);
+}
+
+#[test]
+fn rejected_credentials_are_credential_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = MockCloudFormation::start();
+    let credentials = StaticCredentials::default().with_default(Credentials::new(
+        "INVALID".to_string(),
+        "s3cr3t".to_string(),
+    ));
+    let ctx = common::context_with_credentials(
+        dir.path(),
+        || deployer(&server, dir.path()),
+        Box::new(credentials),
+    );
+    match ctx.infrastructure.check_deployment(&deploy("abc"), &ctx) {
+        Err(DeployPollError::Credentials) => {}
+        result => panic!("unexpected result {:?}", result),
+    }
+}
+
+#[test]
+fn stages_are_deployed_with_the_credentials_of_their_account() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = MockCloudFormation::start();
+    let credentials = StaticCredentials::default()
+        .with_account(
+            "prod".to_string(),
+            Credentials::new("AKIDPROD".to_string(), "s3cr3t".to_string()),
+        )
+        .with_default(common::credentials());
+    let ctx = common::context_with_credentials(
+        dir.path(),
+        || deployer(&server, dir.path()),
+        Box::new(credentials),
+    );
+    common::upload_template(&ctx, dir.path(), "abc", TEMPLATE);
+
+    let mut prod = common::stage("prod");
+    prod.account = Account {
+        name: "prod".to_string(),
+        id: 987_654_321,
+        regions: vec!["eu-west-1".to_string()],
+    };
+    let prod = Deploy::new(prod, REPO.to_string(), "abc".to_string());
+    for deploy in &[deploy("abc"), prod] {
+        ctx.infrastructure
+            .start_deployment(deploy, &Secrets::default(), &ctx)
+            .unwrap();
+    }
+
+    let signed_with = |region: &str| {
+        server
+            .requests()
+            .into_iter()
+            .filter(|r| r.region == region && r.action() == "CreateStack")
+            .map(|r| r.authorization.split('/').next().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+    let default = "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE".to_string();
+    assert_eq!(signed_with("us-east-1"), vec![default.clone()]);
+    assert_eq!(signed_with("us-west-2"), vec![default]);
+    assert_eq!(
+        signed_with("eu-west-1"),
+        vec!["AWS4-HMAC-SHA256 Credential=AKIDPROD".to_string()]
+    );
+    assert!(server.stack("eu-west-1", "cloud-conveyor-prod").is_some());
+}
+
+#[test]
+fn missing_credentials_are_credential_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = MockCloudFormation::start();
+    let ctx = common::context_with_credentials(
+        dir.path(),
+        || deployer(&server, dir.path()),
+        Box::new(NoCredentials),
+    );
+    let teardown = Teardown::new(common::stage("pr-42"), REPO.to_string());
+    match ctx.teardown.start_teardown(&teardown, &ctx) {
+        Err(TeardownPollError::CredentialsUnavailable {
+            cause: CredentialError::Missing { .. },
+        }) => {}
+        result => panic!("unexpected result {:?}", result),
+    }
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn stacks_are_deployed_to_localstack() {
+    let endpoint = match env::var("LOCALSTACK_ENDPOINT") {
+        Ok(endpoint) => endpoint,
+        Err(_) => {
+            eprintln!("Skipping test since LOCALSTACK_ENDPOINT is not set");
+            return;
+        }
+    };
+    let dir = tempfile::tempdir().unwrap();
+    let credentials = StaticCredentials::default()
+        .with_default(Credentials::new("test".to_string(), "test".to_string()));
+    let ctx = common::context_with_credentials(
+        dir.path(),
+        || {
+            CloudFormationDeployer::new(dir.path().join("workspace"))
+                .with_endpoint(endpoint.clone())
+        },
+        Box::new(credentials),
+    );
+    common::upload_template(&ctx, dir.path(), "abc", TEMPLATE);
+
+    let poll = |check: &dyn Fn() -> bool| {
+        for _ in 0..60 {
+            if check() {
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_secs(1));
+        }
+        panic!("timed out");
+    };
+    ctx.infrastructure
+        .start_deployment(&deploy("abc"), &Secrets::default(), &ctx)
+        .unwrap();
+    poll(&|| {
+        ctx.infrastructure
+            .check_deployment(&deploy("abc"), &ctx)
+            .unwrap()
+            == DeployStatus::Complete
+    });
+
+    let teardown = Teardown::new(common::stage("pr-42"), REPO.to_string());
+    ctx.teardown.start_teardown(&teardown, &ctx).unwrap();
+    poll(&|| ctx.teardown.check_teardown(&teardown, &ctx).unwrap() == TeardownStatus::Complete);
+}
@@ -1,9 +1,259 @@
-use clap::{App, Arg, SubCommand};
-use cloud_conveyor_core::yaml::{load_app_from_yaml, write_new_config_file};
+use chrono::{Duration, Utc};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use cloud_conveyor_core::audit::{AuditEntry, AuditLog, Origin};
+use cloud_conveyor_core::history::DeploymentHistory;
+use cloud_conveyor_core::promotion::{Promotion, Rollback};
+use cloud_conveyor_core::reaper::Reaper;
+use cloud_conveyor_core::redact::RedactingLogger;
+use cloud_conveyor_core::schedule::next_runs;
+use cloud_conveyor_core::state_machine::StateMachine;
+use cloud_conveyor_core::webhook::handle_manual_request;
+use cloud_conveyor_core::yaml::{
+    load_apps_from_repo, write_new_build_spec_file, write_new_config_file,
+};
+use cloud_conveyor_core::Application;
+use cloud_conveyor_github::api::{GithubApi, GITHUB_API_URL};
+use cloud_conveyor_local::{
+    EncryptedFileSecrets, FilesystemArtifacts, FilesystemAuditLog, FilesystemHistory, GitBranches,
+};
+
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, IsTerminal, Read};
+use std::path::Path;
+
+const SECRETS_FILE: &str = ".conveyor.secrets.yaml";
+const SECRETS_PASSPHRASE_VARIABLE: &str = "CONVEYOR_SECRETS_PASSPHRASE";
+const GITHUB_TOKEN_VARIABLE: &str = "GITHUB_TOKEN";
+const HISTORY_DIR: &str = ".conveyor/history";
+const AUDIT_DIR: &str = ".conveyor/audit";
+const ARTIFACTS_DIR: &str = ".conveyor/artifacts";
+
+/// Logs to stderr at the level that RUST_LOG sets, with the values of secrets scrubbed from every line.
+fn init_logging() {
+    let logger = env_logger::Builder::from_default_env().build();
+    log::set_max_level(logger.filter());
+    // This only fails when a logger was set already, which then keeps logging.
+    let _ = log::set_boxed_logger(Box::new(RedactingLogger::new(logger)));
+}
+
+/// Reads the value of the secret without echoing it, or from stdin when it is not a terminal.
+fn read_secret_value(name: &str) -> io::Result<String> {
+    if io::stdin().is_terminal() {
+        return rpassword::prompt_password(format!("Value of {}: ", name));
+    }
+    let mut value = String::new();
+    io::stdin().read_to_string(&mut value)?;
+    Ok(value.trim_end_matches(&['\r', '\n'][..]).to_string())
+}
+
+/// Loads the app from .conveyor.yaml and checks that it is the app with the name or full name.
+fn load_app(app_name: &str) -> Result<Application, String> {
+    let apps = load_apps_from_repo(Path::new("."))
+        .map_err(|e| format!("Could not load .conveyor.yaml: {}", e))?;
+    let names: Vec<String> = apps.iter().map(Application::full_name).collect();
+    apps.into_iter()
+        .find(|app| app_name == app.app || app_name == app.full_name())
+        .ok_or_else(|| {
+            format!(
+                "The apps in .conveyor.yaml files are {}, not {}.",
+                names.join(", "),
+                app_name
+            )
+        })
+}
+
+/// Creates the pipeline that rolls the stage back and returns it as yaml.
+fn rollback(matches: &ArgMatches<'_>) -> Result<String, String> {
+    let app = load_app(matches.value_of("app").unwrap())?;
+    let history = FilesystemHistory::new(matches.value_of("history").unwrap());
+    let artifacts = FilesystemArtifacts::new(matches.value_of("artifacts").unwrap());
+    let rollback = Rollback::of(
+        &app,
+        matches.value_of("stage").unwrap(),
+        matches.value_of("sha"),
+        &history,
+        &artifacts,
+    )
+    .map_err(|e| e.to_string())?;
+    eprintln!(
+        "Rolling stage {} back to {}",
+        rollback.stage.name, rollback.git_ref
+    );
+
+    let machine = StateMachine::new(rollback.into_pipeline(&app, manual_origin()));
+    serde_yaml::to_string(&machine).map_err(|e| e.to_string())
+}
+
+/// The origin of the pipelines that are created on the command line.
+fn manual_origin() -> Origin {
+    Origin::manual(env::var("USER").unwrap_or_else(|_| "cli".to_string()))
+}
+
+/// Creates the pipeline that promotes what is live in one stage to another and returns it as yaml.
+fn promote(matches: &ArgMatches<'_>) -> Result<String, String> {
+    let app = load_app(matches.value_of("app").unwrap())?;
+    let history = FilesystemHistory::new(matches.value_of("history").unwrap());
+    let artifacts = FilesystemArtifacts::new(matches.value_of("artifacts").unwrap());
+    let promotion = Promotion::of(
+        &app,
+        matches.value_of("from").unwrap(),
+        matches.value_of("to").unwrap(),
+        &history,
+        &artifacts,
+    )
+    .map_err(|e| e.to_string())?;
+    eprintln!(
+        "Promoting {} from {} to {}",
+        promotion.git_ref, promotion.from.name, promotion.to.name
+    );
+
+    let machine = StateMachine::new(promotion.into_pipeline(&app, manual_origin()));
+    serde_yaml::to_string(&machine).map_err(|e| e.to_string())
+}
+
+/// Creates the pipeline that builds the git ref and deploys it to the stages and returns it as yaml. A branch
+/// is resolved to the sha at its head first.
+fn run(matches: &ArgMatches<'_>) -> Result<String, String> {
+    let app = load_app(matches.value_of("app").unwrap())?;
+    let git_ref = matches.value_of("ref").unwrap().to_string();
+    let stages = matches
+        .values_of("stages")
+        .map(|stages| stages.map(String::from).collect())
+        .unwrap_or_default();
+    let pipeline = handle_manual_request(
+        &app,
+        git_ref,
+        stages,
+        manual_origin().triggered_by,
+        Some(&GitBranches),
+    )
+    .map_err(|e| e.to_string())?;
+    serde_yaml::to_string(&StateMachine::new(pipeline)).map_err(|e| e.to_string())
+}
+
+/// Lists the next run of every schedule of the app.
+fn schedule(matches: &ArgMatches<'_>) -> Result<String, String> {
+    let app = load_app(matches.value_of("app").unwrap())?;
+    let runs = next_runs(&app, Utc::now());
+    if runs.is_empty() {
+        return Ok(format!("{} has no schedules.", app.full_name()));
+    }
+    Ok(runs
+        .iter()
+        .map(|run| {
+            format!(
+                "{} builds {} ({}) and deploys it to [{}]",
+                run.at,
+                run.branch,
+                run.cron,
+                run.stages.join(", ")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Describes an entry of the audit log on a single line.
+fn describe(entry: &AuditEntry) -> String {
+    let mut line = format!("{} {} {:?}", entry.at, entry.action, entry.transition);
+    if let Some(result) = &entry.result {
+        line.push_str(&format!(" {:?}", result));
+    }
+    let fields = [
+        ("sha", &entry.git_ref),
+        ("stage", &entry.stage),
+        ("account", &entry.account),
+        ("approver", &entry.approver),
+        ("by", &entry.triggered_by),
+    ];
+    for (name, value) in fields.iter() {
+        if let Some(value) = value {
+            line.push_str(&format!(" {}={}", name, value));
+        }
+    }
+    line
+}
+
+/// Prints the audit log of the app, or what the history of deployments says about a stage and a sha.
+fn audit(matches: &ArgMatches<'_>) -> Result<String, String> {
+    let app = load_app(matches.value_of("app").unwrap())?;
+    let log = FilesystemAuditLog::new(matches.value_of("log").unwrap());
+    let history = FilesystemHistory::new(matches.value_of("history").unwrap());
+    match (matches.value_of("stage"), matches.value_of("sha")) {
+        (Some(stage), Some(sha)) => match history.arrival(&app, sha, stage) {
+            Ok(Some(record)) => Ok(format!(
+                "{} reached {} at {}",
+                sha, stage, record.deployed_at
+            )),
+            Ok(None) => Ok(format!("{} never reached {}", sha, stage)),
+            Err(e) => Err(e.to_string()),
+        },
+        (Some(stage), None) => match history.last_successful(&app, stage) {
+            Ok(Some(record)) => Ok(format!(
+                "{} runs {} since {}",
+                stage, record.git_ref, record.deployed_at
+            )),
+            Ok(None) => Ok(format!("Nothing was deployed to {}", stage)),
+            Err(e) => Err(e.to_string()),
+        },
+        (None, Some(_)) => Err("A sha can only be looked up along with a stage.".to_string()),
+        (None, None) => {
+            let entries = log.entries(&app).map_err(|e| e.to_string())?;
+            Ok(entries.iter().map(describe).collect::<Vec<_>>().join("\n"))
+        }
+    }
+}
+
+/// Reports the stacks of pull requests of every app in the repository that have been idle for longer than
+/// the time to live, or whose pull request github says is closed. Unless this is a dry run, the pipelines that
+/// tear them down are returned as yaml as well. Nothing is torn down until the pipelines are run.
+fn reap(matches: &ArgMatches<'_>) -> Result<String, String> {
+    let hours: i64 = matches
+        .value_of("ttl")
+        .unwrap()
+        .parse()
+        .map_err(|e| format!("The time to live is not a number of hours: {}", e))?;
+    let apps = load_apps_from_repo(Path::new("."))
+        .map_err(|e| format!("Could not load .conveyor.yaml: {}", e))?;
+    let log = FilesystemAuditLog::new(matches.value_of("log").unwrap());
+    let mut api =
+        GithubApi::new().with_base_url(matches.value_of("github-api").unwrap().to_string());
+    if let Ok(token) = env::var(GITHUB_TOKEN_VARIABLE) {
+        api = api.with_token(token);
+    }
+    let report = Reaper::new(Duration::hours(hours))
+        .with_pull_requests(Box::new(api))
+        .with_dry_run(matches.is_present("dry-run"))
+        .reap_applications(&apps, &log, Utc::now())
+        .map_err(|e| e.to_string())?;
+
+    let verb = if report.dry_run {
+        "Would tear down"
+    } else {
+        "Queuing teardown of"
+    };
+    if report.candidates.is_empty() {
+        eprintln!("No stacks of pull requests are idle or closed.");
+    }
+    for candidate in &report.candidates {
+        eprintln!("{} {}", verb, candidate);
+    }
+    if report.dry_run {
+        return Ok(String::new());
+    }
+    let machines: Vec<_> = report
+        .pipelines
+        .into_iter()
+        .map(StateMachine::new)
+        .collect();
+    serde_yaml::to_string(&machines).map_err(|e| e.to_string())
+}
 
 // TODO: We will want to setup the version to come from cargo.toml.
 
 fn main() {
+    init_logging();
     let version = "1.0";
     let author = "The Cloud Conveyor Team";
     let check_command_name = "check";
@@ -15,13 +265,13 @@ fn main() {
         .about("Helps Check and Onbaords Services Wiht Cloud Conveyor.")
         .subcommand(
             SubCommand::with_name(check_command_name)
-                .about("Checks the conveyor.yaml configuration file for anything that's wrong.")
+                .about("Checks the conveyor.yaml configuration files of every app of the repository for anything that's wrong.")
                 .version(version)
                 .author(author),
         )
         .subcommand(
             SubCommand::with_name("init")
-                .about("Creates a new .conveyor.yaml file for the current directory")
+                .about("Creates new .conveyor.yaml and .conveyor-build.yaml files for the current directory")
                 .version(version)
                 .author(author)
                 .arg(
@@ -38,18 +288,256 @@ fn main() {
                 )
                 .alias("i"),
         )
+        .subcommand(
+            SubCommand::with_name("secret")
+                .about(
+                    "Sets a secret in the encrypted secrets file used when running locally. \
+                     The value is prompted for, or read from stdin when it is not a terminal, so \
+                     that it does not end up in the history of the shell. The passphrase is read \
+                     from CONVEYOR_SECRETS_PASSPHRASE.",
+                )
+                .version(version)
+                .author(author)
+                .arg(
+                    Arg::with_name("app")
+                        .help("The name of the app")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("name")
+                        .help("The name of the secret")
+                        .required(true)
+                        .index(2),
+                )
+                .arg(
+                    Arg::with_name("shared")
+                        .help("Sets the secret for every app that does not set its own")
+                        .long("shared"),
+                )
+                .arg(
+                    Arg::with_name("file")
+                        .help("The secrets file to change")
+                        .long("file")
+                        .takes_value(true)
+                        .default_value(SECRETS_FILE),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("rollback")
+                .about(
+                    "Prints the pipeline that rolls a stage back to a sha that was deployed to it \
+                     successfully, or to the last one.",
+                )
+                .version(version)
+                .author(author)
+                .arg(
+                    Arg::with_name("app")
+                        .help("The name of the app")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("stage")
+                        .help("The name of the stage to roll back")
+                        .required(true)
+                        .index(2),
+                )
+                .arg(
+                    Arg::with_name("sha")
+                        .help("The sha to roll back to")
+                        .index(3),
+                )
+                .arg(
+                    Arg::with_name("history")
+                        .help("The directory the history of deployments is stored in")
+                        .long("history")
+                        .takes_value(true)
+                        .default_value(HISTORY_DIR),
+                )
+                .arg(
+                    Arg::with_name("artifacts")
+                        .help("The directory the artifacts are stored in")
+                        .long("artifacts")
+                        .takes_value(true)
+                        .default_value(ARTIFACTS_DIR),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("promote")
+                .about(
+                    "Prints the pipeline that promotes what is live in one stage to another with the \
+                     artifacts it was built with.",
+                )
+                .version(version)
+                .author(author)
+                .arg(
+                    Arg::with_name("app")
+                        .help("The name of the app")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("from")
+                        .help("The name of the stage to promote from")
+                        .required(true)
+                        .index(2),
+                )
+                .arg(
+                    Arg::with_name("to")
+                        .help("The name of the stage to promote to")
+                        .required(true)
+                        .index(3),
+                )
+                .arg(
+                    Arg::with_name("history")
+                        .help("The directory the history of deployments is stored in")
+                        .long("history")
+                        .takes_value(true)
+                        .default_value(HISTORY_DIR),
+                )
+                .arg(
+                    Arg::with_name("artifacts")
+                        .help("The directory the artifacts are stored in")
+                        .long("artifacts")
+                        .takes_value(true)
+                        .default_value(ARTIFACTS_DIR),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("run")
+                .about(
+                    "Prints the pipeline that builds a git ref and deploys it to the stages, which \
+                     have to be allowed by a manual trigger.",
+                )
+                .version(version)
+                .author(author)
+                .arg(
+                    Arg::with_name("app")
+                        .help("The name of the app")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("ref")
+                        .help("The git ref to build")
+                        .required(true)
+                        .index(2),
+                )
+                .arg(
+                    Arg::with_name("stages")
+                        .help("The names of the stages to deploy to")
+                        .multiple(true)
+                        .index(3),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("schedule")
+                .about("Prints when each schedule of an app runs next.")
+                .version(version)
+                .author(author)
+                .arg(
+                    Arg::with_name("app")
+                        .help("The name of the app")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("audit")
+                .about(
+                    "Prints the audit log of an app. Given a stage, prints what the stage runs now \
+                     and given a sha as well, prints when the sha reached the stage, according to \
+                     the history of deployments.",
+                )
+                .version(version)
+                .author(author)
+                .arg(
+                    Arg::with_name("app")
+                        .help("The name of the app")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("stage")
+                        .help("The name of the stage to look up")
+                        .long("stage")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("sha")
+                        .help("The sha to look up")
+                        .long("sha")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("log")
+                        .help("The directory the audit log is stored in")
+                        .long("log")
+                        .takes_value(true)
+                        .default_value(AUDIT_DIR),
+                )
+                .arg(
+                    Arg::with_name("history")
+                        .help("The directory the history of deployments is stored in")
+                        .long("history")
+                        .takes_value(true)
+                        .default_value(HISTORY_DIR),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("reap")
+                .about(
+                    "Prints the teardown pipelines of the stacks of pull requests of every app in the \
+                     repository that have been idle for longer than a time to live or whose pull \
+                     request is closed.",
+                )
+                .version(version)
+                .author(author)
+                .arg(
+                    Arg::with_name("ttl")
+                        .help("The number of hours a stack may be idle for")
+                        .long("ttl")
+                        .takes_value(true)
+                        .default_value("72"),
+                )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .help("Only reports the stacks that would be torn down without printing the pipelines")
+                        .long("dry-run"),
+                )
+                .arg(
+                    Arg::with_name("github-api")
+                        .help(
+                            "The url of the api of github that closed pull requests are looked up \
+                             with, authenticated with the token in GITHUB_TOKEN if it is set",
+                        )
+                        .long("github-api")
+                        .takes_value(true)
+                        .default_value(GITHUB_API_URL),
+                )
+                .arg(
+                    Arg::with_name("log")
+                        .help("The directory the audit log is stored in")
+                        .long("log")
+                        .takes_value(true)
+                        .default_value(AUDIT_DIR),
+                ),
+        )
         .get_matches();
 
     // Run the Check Command - try and load the file. If it succeeds,
     // we are good. If not, we are not good.
     if matches.subcommand_matches(check_command_name).is_some() {
-        match load_app_from_yaml() {
-            // TODO: Write out the error if there is one.
-            Ok(app) => {
-                println!("{:#?}", app);
+        // Every application of a monorepo is validated, and so are they together.
+        match load_apps_from_repo(Path::new(".")) {
+            Ok(apps) => {
+                for app in apps {
+                    println!("{:#?}", app);
+                }
                 println!("Everything is good!")
             }
-            Err(_) => eprintln!("Everything is NOT OK!"),
+            Err(e) => eprintln!("Everything is NOT OK! {}", e),
         }
     }
 
@@ -57,5 +545,95 @@ fn main() {
         let app = subcommand_matches.value_of("app").unwrap().to_owned();
         let org = subcommand_matches.value_of("org").unwrap().to_owned();
         write_new_config_file(app, org).unwrap();
+        write_new_build_spec_file().unwrap();
+    }
+
+    if let Some(subcommand_matches) = matches.subcommand_matches("secret") {
+        let name = subcommand_matches.value_of("name").unwrap();
+        let key = if subcommand_matches.is_present("shared") {
+            name.to_owned()
+        } else {
+            match load_app(subcommand_matches.value_of("app").unwrap()) {
+                Ok(app) => EncryptedFileSecrets::app_key(&app, name),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            }
+        };
+        let file = subcommand_matches.value_of("file").unwrap();
+        let passphrase = match env::var(SECRETS_PASSPHRASE_VARIABLE) {
+            Ok(passphrase) => passphrase,
+            Err(_) => {
+                eprintln!("{} is not set.", SECRETS_PASSPHRASE_VARIABLE);
+                return;
+            }
+        };
+        let value = match read_secret_value(name) {
+            Ok(value) => value,
+            Err(e) => {
+                eprintln!("Could not read the value of {}: {}", name, e);
+                return;
+            }
+        };
+
+        let mut values = if Path::new(file).exists() {
+            match EncryptedFileSecrets::open(file, &passphrase) {
+                Ok(secrets) => secrets.into_values(),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            }
+        } else {
+            HashMap::new()
+        };
+        values.insert(key, value);
+        if let Err(e) = EncryptedFileSecrets::write(file, &passphrase, &values) {
+            eprintln!("{}", e);
+        }
+    }
+
+    if let Some(subcommand_matches) = matches.subcommand_matches("rollback") {
+        match rollback(subcommand_matches) {
+            Ok(pipeline) => println!("{}", pipeline),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+
+    if let Some(subcommand_matches) = matches.subcommand_matches("promote") {
+        match promote(subcommand_matches) {
+            Ok(pipeline) => println!("{}", pipeline),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+
+    if let Some(subcommand_matches) = matches.subcommand_matches("run") {
+        match run(subcommand_matches) {
+            Ok(pipeline) => println!("{}", pipeline),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+
+    if let Some(subcommand_matches) = matches.subcommand_matches("schedule") {
+        match schedule(subcommand_matches) {
+            Ok(runs) => println!("{}", runs),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+
+    if let Some(subcommand_matches) = matches.subcommand_matches("audit") {
+        match audit(subcommand_matches) {
+            Ok(output) => println!("{}", output),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+
+    if let Some(subcommand_matches) = matches.subcommand_matches("reap") {
+        match reap(subcommand_matches) {
+            Ok(pipelines) if pipelines.is_empty() => {}
+            Ok(pipelines) => println!("{}", pipelines),
+            Err(e) => eprintln!("{}", e),
+        }
     }
 }
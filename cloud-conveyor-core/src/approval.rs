@@ -0,0 +1,113 @@
+//! Defines the runtime abstraction for asking people to approve deployments.
+//!
+//! When an [Approval](../pipelining/struct.Approval.html) starts, it asks the people of its approval group
+//! through the [ProvideApprovals](trait.ProvideApprovals.html) of the runtime, and then waits for an answer.
+//! The first answer of someone in the group decides whether the pipeline goes on. Answers of anyone else
+//! are ignored. Every request is answered on its own, so the answers to an earlier request for the same sha
+//! and stage, such as one of a pipeline that was rejected before, never decide a later one.
+use crate::pipelining::Approval;
+use failure::Error;
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+/// Whether a person approved or rejected a deployment.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub enum Decision {
+    /// The deployment may go ahead.
+    Approved,
+    /// The deployment and the rest of its pipeline are canceled.
+    Rejected,
+}
+
+/// The answer of a person to an approval.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Answer {
+    /// The identifier of the person who answered.
+    pub person: String,
+    /// What the person decided.
+    pub decision: Decision,
+}
+
+/// Defines a run time abstraction for asking people to approve the deployment of a sha to a stage,
+/// and for collecting their answers.
+pub trait ProvideApprovals: Debug {
+    /// Asks the people of the approval group of the approval to approve the deployment. Returns the
+    /// identifier of the request, which is unique to it.
+    fn request(&self, approval: &Approval) -> Result<String, Error>;
+
+    /// Lists the answers that were given to the request with the identifier so far, oldest first.
+    fn answers(&self, request_id: &str) -> Result<Vec<Answer>, Error>;
+}
+
+/// The app, stage and sha of an approval.
+type ApprovalKey = (String, String, String);
+
+/// The requests of approvals and their answers.
+#[derive(Debug, Default)]
+struct Requests {
+    /// The app, stage and sha of every request, by the identifier of the request.
+    keys: HashMap<String, ApprovalKey>,
+    /// The answers to every request, by the identifier of the request.
+    answers: HashMap<String, Vec<Answer>>,
+}
+
+/// Keeps the answers to approvals in memory. Clones share their answers, so one clone can be given to the
+/// runtime while another records what people answer.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryApprovals {
+    requests: Arc<Mutex<Requests>>,
+}
+
+impl MemoryApprovals {
+    /// Records the answer of a person to the approval of the deployment of the sha to the stage of the app.
+    /// The answer is given to every request for it that was made so far, and not to the requests made later.
+    pub fn answer(&self, app: &str, stage: &str, git_ref: &str, answer: Answer) {
+        let key = (app.to_string(), stage.to_string(), git_ref.to_string());
+        let mut requests = self.requests.lock().unwrap();
+        let ids: Vec<String> = requests
+            .keys
+            .iter()
+            .filter(|(_, requested)| **requested == key)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in ids {
+            requests.answers.entry(id).or_default().push(answer.clone());
+        }
+    }
+}
+
+impl ProvideApprovals for MemoryApprovals {
+    fn request(&self, approval: &Approval) -> Result<String, Error> {
+        info!(
+            "Waiting for {:?} to approve {} of {} to {}",
+            approval.approval_group.people,
+            approval.git_ref,
+            approval.app_name,
+            approval.stage_name
+        );
+        let mut requests = self.requests.lock().unwrap();
+        let id = requests.keys.len().to_string();
+        let key = (
+            approval.app_name.clone(),
+            approval.stage_name.clone(),
+            approval.git_ref.clone(),
+        );
+        requests.keys.insert(id.clone(), key);
+        Ok(id)
+    }
+
+    fn answers(&self, request_id: &str) -> Result<Vec<Answer>, Error> {
+        Ok(self
+            .requests
+            .lock()
+            .unwrap()
+            .answers
+            .get(request_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+}
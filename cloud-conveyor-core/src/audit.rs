@@ -0,0 +1,208 @@
+//! Defines the runtime abstraction for the audit log of everything the conveyor does.
+//!
+//! The [StateMachine](../state_machine/struct.StateMachine.html) appends an [AuditEntry](struct.AuditEntry.html)
+//! to the [AuditLog](trait.AuditLog.html) of the runtime whenever an action of a pipeline starts and whenever
+//! it completes, is canceled included. Entries are never changed or removed, so the log answers who did what
+//! and why long after the pipelines that did the work are gone. What the stages run is kept by the
+//! [history](../history/index.html) of deployments instead, so that there is one record of it.
+use crate::pipelining::ActionResult;
+use crate::runtime::RuntimeContext;
+use crate::webhook::VcsEvent;
+use crate::Application;
+use chrono::{DateTime, Utc};
+use failure::Error;
+use serde::{Deserialize, Serialize};
+
+use std::fmt::Debug;
+use std::sync::Mutex;
+
+/// Describes who or what created a pipeline.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Origin {
+    /// Who or what triggered the pipeline, such as `webhook` or the name of a person.
+    pub triggered_by: String,
+    /// The event of the version control system that triggered the pipeline, if any.
+    #[serde(default)]
+    pub event: Option<VcsEvent>,
+}
+
+impl Origin {
+    /// Creates the origin of a pipeline that was triggered by an event of the version control system.
+    pub fn webhook(event: VcsEvent) -> Self {
+        Self {
+            triggered_by: "webhook".to_string(),
+            event: Some(event),
+        }
+    }
+
+    /// Creates the origin of a pipeline that was triggered by a schedule coming due.
+    pub fn schedule(event: VcsEvent) -> Self {
+        Self {
+            triggered_by: "schedule".to_string(),
+            event: Some(event),
+        }
+    }
+
+    /// Creates the origin of a pipeline that was triggered by hand.
+    pub fn manual(triggered_by: String) -> Self {
+        Self {
+            triggered_by,
+            event: None,
+        }
+    }
+
+    /// Sets the event that the pipeline was created for.
+    pub fn with_event(mut self, event: VcsEvent) -> Self {
+        self.event = Some(event);
+        self
+    }
+}
+
+/// What an action is about, as far as the audit log is concerned. Every field is optional since not every
+/// kind of action has every detail.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ActionDetails {
+    /// The repo of the application the action is for.
+    pub repo: Option<String>,
+    /// The full name of the application the action is for.
+    pub app: Option<String>,
+    /// The sha the action works with.
+    pub git_ref: Option<String>,
+    /// The name of the stage the action works on.
+    pub stage: Option<String>,
+    /// The name of the account of the stage.
+    pub account: Option<String>,
+    /// The regions of the account that the stage is in.
+    pub regions: Option<Vec<String>>,
+    /// The person who approved the action.
+    pub approver: Option<String>,
+    /// The number of the pull request whose stage the action works on.
+    pub pr: Option<u32>,
+}
+
+impl ActionDetails {
+    /// Finds the application that the action is for.
+    pub fn application<'a>(&self, ctx: &'a RuntimeContext) -> Option<&'a Application> {
+        match (&self.repo, &self.app) {
+            (Some(repo), app) => ctx.load_application(repo, app.as_deref()),
+            (None, Some(name)) => ctx.applications.iter().find(|app| app.full_name() == *name),
+            (None, None) => None,
+        }
+    }
+}
+
+/// The transition of an action that an entry records.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub enum Transition {
+    /// The action was started.
+    Started,
+    /// The action completed, failed or was canceled.
+    Completed,
+}
+
+/// A transition of an action of a pipeline.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct AuditEntry {
+    /// When the transition happened.
+    pub at: DateTime<Utc>,
+    /// The full name of the application, see [full_name](../struct.Application.html#method.full_name).
+    pub app: String,
+    /// The kind of action, such as `Build` or `Deploy`.
+    pub action: String,
+    /// The transition of the action.
+    pub transition: Transition,
+    /// The result of the action once it completed.
+    #[serde(default)]
+    pub result: Option<ActionResult>,
+    /// The sha the action works with.
+    #[serde(default)]
+    pub git_ref: Option<String>,
+    /// The name of the stage the action works on.
+    #[serde(default)]
+    pub stage: Option<String>,
+    /// The name of the account of the stage.
+    #[serde(default)]
+    pub account: Option<String>,
+    /// The regions of the account that the stage is in.
+    #[serde(default)]
+    pub regions: Option<Vec<String>>,
+    /// The person who approved the action.
+    #[serde(default)]
+    pub approver: Option<String>,
+    /// The number of the pull request whose stage the action works on.
+    #[serde(default)]
+    pub pr: Option<u32>,
+    /// Who or what triggered the pipeline of the action.
+    #[serde(default)]
+    pub triggered_by: Option<String>,
+    /// The event of the version control system that triggered the pipeline of the action.
+    #[serde(default)]
+    pub event: Option<VcsEvent>,
+}
+
+impl AuditEntry {
+    /// Creates the entry of a transition of the action of the application that happened just now.
+    pub fn new(
+        app: &Application,
+        action: &str,
+        details: ActionDetails,
+        transition: Transition,
+        origin: Option<&Origin>,
+    ) -> Self {
+        Self {
+            at: Utc::now(),
+            app: app.full_name(),
+            action: action.to_string(),
+            transition,
+            result: None,
+            git_ref: details.git_ref,
+            stage: details.stage,
+            account: details.account,
+            regions: details.regions,
+            approver: details.approver,
+            pr: details.pr,
+            triggered_by: origin.map(|origin| origin.triggered_by.clone()),
+            event: origin.and_then(|origin| origin.event.clone()),
+        }
+    }
+
+    /// Sets the result of the action.
+    pub fn with_result(mut self, result: ActionResult) -> Self {
+        self.result = Some(result);
+        self
+    }
+}
+
+/// Defines a run time abstraction for an append only log of [entries](struct.AuditEntry.html).
+pub trait AuditLog: Debug {
+    /// Appends the entry to the log.
+    fn append(&self, entry: &AuditEntry) -> Result<(), Error>;
+
+    /// Gets every entry of the application, oldest first.
+    fn entries(&self, app: &Application) -> Result<Vec<AuditEntry>, Error>;
+}
+
+/// Keeps the audit log in memory. The log is lost when the conveyor stops, so this is mostly useful for tests.
+#[derive(Debug, Default)]
+pub struct MemoryAuditLog {
+    entries: Mutex<Vec<AuditEntry>>,
+}
+
+impl AuditLog for MemoryAuditLog {
+    fn append(&self, entry: &AuditEntry) -> Result<(), Error> {
+        self.entries.lock().unwrap().push(entry.clone());
+        Ok(())
+    }
+
+    fn entries(&self, app: &Application) -> Result<Vec<AuditEntry>, Error> {
+        let name = app.full_name();
+        Ok(self
+            .entries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|entry| entry.app == name)
+            .cloned()
+            .collect())
+    }
+}
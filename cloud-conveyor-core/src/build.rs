@@ -1,11 +1,18 @@
 //! Defines the runtime abstraction for build source and reporting successes and failures when doing so.
 use crate::pipelining::Build;
+use crate::redact::redact;
 use crate::runtime::RuntimeContext;
+use crate::secrets::Secrets;
 use crate::Application;
 
+use chrono::{DateTime, Utc};
 use failure::Error;
+use log::info;
 use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::HashMap;
 use std::fmt::Debug;
+use std::path::Path;
 
 /// Determines the current status of a stack deployment. This information should signal
 /// the state of the stack itself and not the result of performing the operation to check
@@ -30,6 +37,82 @@ pub enum BuildStatus {
     Pending,
 }
 
+impl BuildStatus {
+    /// Scrubs the values of [registered](../redact/fn.register.html) secrets from the logs and error
+    /// of the status. Builders often put excerpts of the output of a build in them, which may echo secrets.
+    pub fn redacted(self) -> Self {
+        match self {
+            BuildStatus::Succeeded { logs } => BuildStatus::Succeeded {
+                logs: redact(&logs),
+            },
+            BuildStatus::Failed { logs, error } => BuildStatus::Failed {
+                logs: redact(&logs),
+                error: error.as_deref().map(redact),
+            },
+            BuildStatus::Pending => BuildStatus::Pending,
+        }
+    }
+}
+
+/// Describes where the artifacts of a successful build of a sha were stored. Since builds are
+/// stage agnostic, the manifest of one build can be reused by any number of later pipelines that
+/// deploy the same sha.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ArtifactManifest {
+    /// The git sha (or ref) that was built.
+    pub git_sha: String,
+    /// The bucket the artifacts were stored in. See [get_bucket](trait.ProvideArtifactLocation.html#tymethod.get_bucket).
+    pub bucket: String,
+    /// The folder the artifacts were stored in. See [get_folder](trait.ProvideArtifactLocation.html#tymethod.get_folder).
+    pub folder: String,
+    /// Url that can be clicked on to view the logs of the original build.
+    pub logs: String,
+    /// The branch the sha was built for, if the build was for one.
+    #[serde(default)]
+    pub branch: Option<String>,
+    /// When the build completed.
+    pub built_at: DateTime<Utc>,
+}
+
+/// Determines which stored builds are kept when cleaning up the artifacts of an application.
+/// See [cleanup](trait.ProvideArtifactLocation.html#method.cleanup).
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct RetentionPolicy {
+    /// The number of most recent builds to keep for each branch. Builds that were not for a
+    /// branch, such as those of tags, are counted as one group.
+    pub builds_per_branch: usize,
+}
+
+impl RetentionPolicy {
+    /// Gets the builds that are not retained by the policy. Builds of shas that are currently deployed
+    /// to a stage are always retained.
+    pub fn expired<'a>(
+        &self,
+        builds: &'a [ArtifactManifest],
+        deployed: &[String],
+    ) -> Vec<&'a ArtifactManifest> {
+        let mut by_branch: HashMap<Option<&str>, Vec<&ArtifactManifest>> = HashMap::new();
+        for build in builds {
+            by_branch
+                .entry(build.branch.as_deref())
+                .or_default()
+                .push(build);
+        }
+
+        let mut expired = Vec::new();
+        for (_, mut branch_builds) in by_branch {
+            branch_builds.sort_by_key(|build| Reverse(build.built_at));
+            expired.extend(
+                branch_builds
+                    .into_iter()
+                    .skip(self.builds_per_branch)
+                    .filter(|build| !deployed.contains(&build.git_sha)),
+            );
+        }
+        expired
+    }
+}
+
 /// Defines an error that occurrent when attempting to perform an operation on the
 /// [BuildSource](trait.BuildSource.html) trait. This is meant to convey
 /// that the operation was not a success - not that the deployment itself was a failure. That
@@ -69,11 +152,21 @@ pub enum BuildPollError {
 /// provider you are working with to do the work for you and have this implement the polling and reporting functionality only.
 pub trait BuildSource: Debug {
     /// Starts the build of the code given with [Build](../pipelining/struct.Build.html) data passed.
-    /// Builds should be stage agnostic and are potentially re-used for more  than one [stage](../struct.Stage.html)
+    /// The code should be built according to the [spec](../pipelining/struct.Build.html#structfield.spec) of the build,
+    /// or else the [BuildSpec](../yaml/struct.BuildSpec.html) in the directory of the application of the checked
+    /// out ref, if there is one. Builds should be stage agnostic and are potentially re-used for more  than one [stage](../struct.Stage.html)
     /// deployment later in the pipeline. As such, you are not given access to the stage(s) the code is being built for.
     ///
+    /// The values of the [secrets](../pipelining/struct.Build.html#structfield.secrets) of the build are passed
+    /// along side it. They must be made available to the build without being logged or stored.
+    ///
     /// If an error occurs when triggering the build, use the appropriate variant of  [BuildPollError](enum.BuildPollError.html)
-    fn start_build(&self, build: &Build, ctx: &RuntimeContext) -> Result<(), BuildPollError>;
+    fn start_build(
+        &self,
+        build: &Build,
+        secrets: &Secrets,
+        ctx: &RuntimeContext,
+    ) -> Result<(), BuildPollError>;
 
     /// Polls the state of the build given the build data passed.
     /// If that final result of the build is not known use [BuildStatus::Pending](enum.BuildStatus.html#variant.Pending).
@@ -99,6 +192,10 @@ pub trait BuildSource: Debug {
 ///
 /// The `ProvideArtifactLocation` trait works hard to be provider agnostic. To do so, the Result type is fairly generic
 /// and is a [failure::Error](../../failure/struct.Error.html) and has Ok type of String to represent the file paths.
+///
+/// Besides naming locations, implementations move artifacts between the location of a build and the local file system
+/// with [upload](#tymethod.upload) and [download](#tymethod.download) so that builders and infrastructure that run
+/// locally do not need to know how the storage works.
 pub trait ProvideArtifactLocation: Debug {
     /// Gets the name of a storage bucket or location without a path to store the assets. This trait instance
     /// will be passed to the activated implementations of [BuildSource](trait.BuildSource.html) and
@@ -110,4 +207,58 @@ pub trait ProvideArtifactLocation: Debug {
     /// [DeployInfrastructure](../deploy/trait.DeployInfrastructure.html) when they are invoked so they should
     /// understand and be able to interpret this value or need not to.
     fn get_folder(&self, app: &Application, git_sha: &str) -> Result<String, Error>;
+
+    /// Looks up the manifest of an earlier successful build of the sha for the application. When one is found,
+    /// the [Build](../pipelining/struct.Build.html) action reuses it instead of building the sha again.
+    ///
+    /// The default implementation does not remember any builds and thus always returns `Ok(None)`.
+    fn find_build(
+        &self,
+        _app: &Application,
+        _git_sha: &str,
+    ) -> Result<Option<ArtifactManifest>, Error> {
+        Ok(None)
+    }
+
+    /// Records the manifest of a successful build so that it can be returned by [find_build](#method.find_build)
+    /// later on. The default implementation does nothing.
+    fn record_build(&self, _app: &Application, _manifest: &ArtifactManifest) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Stores the contents of the local directory as the artifacts of the sha for the application.
+    fn upload(&self, app: &Application, git_sha: &str, source: &Path) -> Result<(), Error>;
+
+    /// Copies the artifacts of the sha for the application into the local directory.
+    fn download(&self, app: &Application, git_sha: &str, destination: &Path) -> Result<(), Error>;
+
+    /// Lists the manifests of the builds that are stored for the application, oldest first.
+    fn list(&self, app: &Application) -> Result<Vec<ArtifactManifest>, Error>;
+
+    /// Deletes the artifacts and manifest of the sha for the application.
+    fn delete(&self, app: &Application, git_sha: &str) -> Result<(), Error>;
+
+    /// Deletes the builds of the application that are not retained by the policy and returns their shas.
+    /// `deployed` are the shas that are currently deployed to any stage of the application, see
+    /// [deployed_shas](../history/fn.deployed_shas.html). A [Build](../pipelining/struct.Build.html) calls this
+    /// when it succeeds and its application has a [retention](../struct.Application.html#structfield.retention) policy.
+    fn cleanup(
+        &self,
+        app: &Application,
+        policy: &RetentionPolicy,
+        deployed: &[String],
+    ) -> Result<Vec<String>, Error> {
+        let builds = self.list(app)?;
+        let mut deleted = Vec::new();
+        for build in policy.expired(&builds, deployed) {
+            info!(
+                "Deleting artifacts of sha {:?} for app {:?}",
+                build.git_sha,
+                app.full_name()
+            );
+            self.delete(app, &build.git_sha)?;
+            deleted.push(build.git_sha.clone());
+        }
+        Ok(deleted)
+    }
 }
@@ -1,3 +1,64 @@
-trait Approve {}
+//! Defines the abstractions for talking to people through chat services such as slack.
+use crate::audit::Origin;
+use crate::pipelining::Pipeline;
+use crate::promotion::{Promotion, PromotionError};
+use crate::runtime::RuntimeContext;
 
+// TODO: Fill this out once notifications are implemented.
+#[allow(dead_code)]
 trait Notify {}
+
+/// A command that a person sends to the conveyor through a chat service.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChatCommand {
+    /// Promotes whatever is live in one stage of an app to another, see the
+    /// [promotion](../promotion/index.html) module. Sent as `promote <app> from <stage> to <stage>`.
+    Promote {
+        /// The name or full name of the app.
+        app: String,
+        /// The name of the stage to promote from.
+        from: String,
+        /// The name of the stage to promote to.
+        to: String,
+    },
+}
+
+impl ChatCommand {
+    /// Parses the text of a chat message into a command. Returns None when the message is not a command.
+    pub fn parse(message: &str) -> Option<Self> {
+        let words: Vec<&str> = message.split_whitespace().collect();
+        match words.as_slice() {
+            ["promote", app, "from", from, "to", to] => Some(Self::Promote {
+                app: app.to_string(),
+                from: from.to_string(),
+                to: to.to_string(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Creates the pipeline that carries the command out on behalf of the person who sent it.
+    pub fn to_pipeline(
+        &self,
+        person: &str,
+        ctx: &RuntimeContext,
+    ) -> Result<Pipeline, PromotionError> {
+        match self {
+            Self::Promote { app, from, to } => {
+                let application = ctx
+                    .applications
+                    .iter()
+                    .find(|candidate| candidate.app == *app || candidate.full_name() == *app)
+                    .ok_or_else(|| PromotionError::UnknownApp { app: app.clone() })?;
+                let promotion = Promotion::of(
+                    application,
+                    from,
+                    to,
+                    &*ctx.history,
+                    &*ctx.artifact_provider,
+                )?;
+                Ok(promotion.into_pipeline(application, Origin::manual(person.to_string())))
+            }
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! Defines the runtime abstraction for obtaining the credentials of the accounts that applications are deployed to.
+//!
+//! Every [Stage](../struct.Stage.html) is deployed to an [Account](../struct.Account.html) and one conveyor may
+//! deploy the stages of an application to many accounts. Implementations of
+//! [DeployInfrastructure](../deploy/trait.DeployInfrastructure.html) and
+//! [TeardownInfrastructure](../teardown/trait.TeardownInfrastructure.html) ask the
+//! [CredentialProvider](trait.CredentialProvider.html) of the runtime for the credentials of the account of the
+//! stage whenever they need them. Since obtaining credentials may be expensive, for instance when a role has to
+//! be assumed, providers can be wrapped in [CachedCredentials](struct.CachedCredentials.html).
+use crate::deploy::DeployPollError;
+use crate::redact::{register, Secret};
+use crate::teardown::TeardownPollError;
+use crate::Account;
+use chrono::{DateTime, Duration, Utc};
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Mutex;
+
+/// Defines an error that occurred when attempting to obtain the credentials of an account.
+#[derive(Debug, Clone, Fail)]
+pub enum CredentialError {
+    /// When the provider has no credentials for the account, this variant should be used.
+    #[fail(display = "No credentials are known for the account {}.", account)]
+    Missing {
+        /// The name of the account.
+        account: String,
+    },
+
+    /// When the cause does not fit any of the known patterns defined else where in the enum,
+    /// this can be returned. It takes an additional string and passed that information as part
+    /// of the error  context.
+    #[fail(display = "Unknown credential error occurred: {}", info)]
+    Other {
+        /// Additional information to pass back.
+        info: String,
+    },
+}
+
+impl From<CredentialError> for DeployPollError {
+    fn from(cause: CredentialError) -> Self {
+        DeployPollError::CredentialsUnavailable { cause }
+    }
+}
+
+impl From<CredentialError> for TeardownPollError {
+    fn from(cause: CredentialError) -> Self {
+        TeardownPollError::CredentialsUnavailable { cause }
+    }
+}
+
+/// The keys that requests on behalf of an account are signed with. The secret parts are kept out of logs.
+#[derive(Clone, Debug)]
+pub struct Credentials {
+    /// The id of the access key.
+    pub access_key_id: String,
+    /// The secret of the access key.
+    pub secret_access_key: Secret<String>,
+    /// The token of the session the keys belong to, when they are temporary.
+    pub session_token: Option<Secret<String>>,
+    /// When temporary keys stop working.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl Credentials {
+    /// Creates credentials from the id and secret of an access key that does not expire.
+    pub fn new(access_key_id: String, secret_access_key: String) -> Self {
+        register(&secret_access_key);
+        Self {
+            access_key_id,
+            secret_access_key: Secret::new(secret_access_key),
+            session_token: None,
+            expires_at: None,
+        }
+    }
+
+    /// Sets the token of the session of temporary credentials.
+    pub fn with_session_token(mut self, session_token: String) -> Self {
+        register(&session_token);
+        self.session_token = Some(Secret::new(session_token));
+        self
+    }
+
+    /// Sets when the credentials stop working.
+    pub fn with_expiry(mut self, expires_at: DateTime<Utc>) -> Self {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    /// Determines if the credentials stop working within the given time from now.
+    pub fn expires_within(&self, time: Duration) -> bool {
+        self.expires_at
+            .map(|expires_at| expires_at <= Utc::now() + time)
+            .unwrap_or(false)
+    }
+}
+
+/// Defines a run time abstraction for obtaining the [Credentials](struct.Credentials.html) of an account.
+///
+/// Implementations must take care to never log or otherwise expose the credentials they return.
+pub trait CredentialProvider: Debug {
+    /// Gets the credentials of the account.
+    fn credentials(&self, account: &Account) -> Result<Credentials, CredentialError>;
+}
+
+/// A [CredentialProvider](trait.CredentialProvider.html) without any credentials. Use this for runtimes
+/// whose implementations obtain credentials on their own.
+#[derive(Debug)]
+pub struct NoCredentials;
+
+impl CredentialProvider for NoCredentials {
+    fn credentials(&self, account: &Account) -> Result<Credentials, CredentialError> {
+        Err(CredentialError::Missing {
+            account: account.name.clone(),
+        })
+    }
+}
+
+/// Provides fixed credentials for accounts by their name, with optional credentials for every
+/// other account.
+///
+/// ```rust
+/// use cloud_conveyor_core::credentials::{CredentialProvider, Credentials, StaticCredentials};
+/// use cloud_conveyor_core::Account;
+///
+/// let provider = StaticCredentials::default()
+///     .with_account("prod".to_string(), Credentials::new("AKIAPROD".to_string(), "s3cr3t".to_string()))
+///     .with_default(Credentials::new("AKIADEV".to_string(), "s3cr3t".to_string()));
+/// let prod = Account { name: "prod".to_string(), id: 1, regions: vec![] };
+/// let stage = Account { name: "stage".to_string(), id: 2, regions: vec![] };
+/// assert_eq!(provider.credentials(&prod).unwrap().access_key_id, "AKIAPROD");
+/// assert_eq!(provider.credentials(&stage).unwrap().access_key_id, "AKIADEV");
+/// ```
+#[derive(Debug, Default)]
+pub struct StaticCredentials {
+    accounts: HashMap<String, Credentials>,
+    default: Option<Credentials>,
+}
+
+impl StaticCredentials {
+    /// Sets the credentials of the account with the name.
+    pub fn with_account(mut self, account: String, credentials: Credentials) -> Self {
+        self.accounts.insert(account, credentials);
+        self
+    }
+
+    /// Sets the credentials of every account that was not given credentials of its own.
+    pub fn with_default(mut self, credentials: Credentials) -> Self {
+        self.default = Some(credentials);
+        self
+    }
+}
+
+impl CredentialProvider for StaticCredentials {
+    fn credentials(&self, account: &Account) -> Result<Credentials, CredentialError> {
+        self.accounts
+            .get(&account.name)
+            .or(self.default.as_ref())
+            .cloned()
+            .ok_or_else(|| CredentialError::Missing {
+                account: account.name.clone(),
+            })
+    }
+}
+
+/// Caches the credentials of another provider for every account until shortly before they expire.
+/// Credentials that do not expire are cached for as long as the cache lives.
+#[derive(Debug)]
+pub struct CachedCredentials {
+    provider: Box<dyn CredentialProvider>,
+    margin: Duration,
+    cached: Mutex<HashMap<(String, usize), Credentials>>,
+}
+
+impl CachedCredentials {
+    /// Caches the credentials of the provider. Credentials are refreshed five minutes before they expire.
+    pub fn new(provider: Box<dyn CredentialProvider>) -> Self {
+        Self {
+            provider,
+            margin: Duration::minutes(5),
+            cached: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets how long before they expire that credentials are refreshed.
+    pub fn with_margin(mut self, margin: Duration) -> Self {
+        self.margin = margin;
+        self
+    }
+}
+
+impl CredentialProvider for CachedCredentials {
+    fn credentials(&self, account: &Account) -> Result<Credentials, CredentialError> {
+        let key = (account.name.clone(), account.id);
+        if let Some(credentials) = self.cached.lock().unwrap().get(&key) {
+            if !credentials.expires_within(self.margin) {
+                return Ok(credentials.clone());
+            }
+        }
+        // The lock is not held while refreshing, so the provider is free to ask the cache for the
+        // credentials of another account, as providers that assume roles do.
+        let credentials = self.provider.credentials(account)?;
+        self.cached.lock().unwrap().insert(key, credentials.clone());
+        Ok(credentials)
+    }
+}
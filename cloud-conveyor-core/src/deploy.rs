@@ -1,6 +1,8 @@
 //! Defines the runtime abstraction for deploying infrastructure and reporting successes and failures when doing so.
+use crate::credentials::CredentialError;
 use crate::pipelining::Deploy;
 use crate::runtime::RuntimeContext;
+use crate::secrets::Secrets;
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 
@@ -15,6 +17,15 @@ pub enum DeployPollError {
     #[fail(display = "Failed to get credentials or the credentials were invalid.")]
     Credentials,
 
+    /// When the [CredentialProvider](../credentials/trait.CredentialProvider.html) of the runtime could not
+    /// provide the credentials of the account, this variant carries the reason.
+    #[fail(display = "Could not obtain credentials: {}", cause)]
+    CredentialsUnavailable {
+        /// Why the credentials could not be obtained.
+        #[fail(cause)]
+        cause: CredentialError,
+    },
+
     /// When the cause does not fit any of the known patterns defined else where in the enum,
     /// this can be returned. It takes an additional string and passed that information as part
     /// of the error  context.
@@ -59,10 +70,14 @@ pub trait DeployInfrastructure: Debug {
     /// determine whether or not a stack exists ahead of time. To start a deployment, you must check if a stack exists
     /// and if not create the stack if required by the cloud provider. If the stack is there, you should update it.
     ///
+    /// The values of the [secrets](../struct.Stage.html#structfield.secrets) of the stage are passed along
+    /// side the deployment. They must be made available to the deployment without being logged or stored.
+    ///
     /// If an error occurs when triggering the deployment, use the appropriate variant of  [DeployPollError](enum.DeployPollError.html)
     fn start_deployment(
         &self,
         deploy: &Deploy,
+        secrets: &Secrets,
         ctx: &RuntimeContext,
     ) -> Result<(), DeployPollError>;
 
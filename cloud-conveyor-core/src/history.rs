@@ -0,0 +1,154 @@
+//! Defines the runtime abstraction for remembering the deployments of every stage.
+//!
+//! Every [Deploy](../pipelining/struct.Deploy.html) that finishes is recorded in the
+//! [DeploymentHistory](trait.DeploymentHistory.html) of the runtime along with when it finished and
+//! whether it succeeded, and so is every [Teardown](../pipelining/struct.Teardown.html) that succeeds. The
+//! history is what a stage is rolled back to when a deployment to it fails, and the one record of what the
+//! stages run. The [audit log](../audit/index.html) only says who did what.
+use crate::Application;
+use chrono::{DateTime, Utc};
+use failure::Error;
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Mutex;
+
+/// The outcome of a deployment to a stage.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub enum DeploymentOutcome {
+    /// The deployment completed and the stage runs the deployed sha.
+    Succeeded,
+    /// The deployment failed and the stage may be left half updated.
+    Failed,
+    /// The stage was torn down and runs nothing until it is deployed to again.
+    TornDown,
+}
+
+/// A deployment of a sha to a stage.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct DeploymentRecord {
+    /// The sha that was deployed, or that the stage ran when it was torn down.
+    pub git_ref: String,
+    /// When the deployment finished.
+    pub deployed_at: DateTime<Utc>,
+    /// Whether or not the deployment succeeded.
+    pub outcome: DeploymentOutcome,
+    /// Whether or not the deployment rolled the stage back to an earlier sha.
+    #[serde(default)]
+    pub rollback: bool,
+    /// The tag that the sha was deployed for, if it was deployed for one.
+    #[serde(default)]
+    pub tag: Option<String>,
+}
+
+/// Defines a run time abstraction for storing the [records](struct.DeploymentRecord.html) of the
+/// deployments to the stages of applications.
+pub trait DeploymentHistory: Debug {
+    /// Records a deployment to the stage with the name.
+    fn record(
+        &self,
+        app: &Application,
+        stage: &str,
+        record: &DeploymentRecord,
+    ) -> Result<(), Error>;
+
+    /// Lists the deployments to the stage with the name, oldest first.
+    fn list(&self, app: &Application, stage: &str) -> Result<Vec<DeploymentRecord>, Error>;
+
+    /// Lists the names of the stages of the application that were deployed to.
+    fn stages(&self, app: &Application) -> Result<Vec<String>, Error>;
+
+    /// Finds the latest deployment to the stage that succeeded, which is what the stage runs now. There is
+    /// none when the stage was torn down since.
+    fn last_successful(
+        &self,
+        app: &Application,
+        stage: &str,
+    ) -> Result<Option<DeploymentRecord>, Error> {
+        Ok(self
+            .list(app, stage)?
+            .into_iter()
+            .rev()
+            .find(|record| record.outcome != DeploymentOutcome::Failed)
+            .filter(|record| record.outcome == DeploymentOutcome::Succeeded))
+    }
+
+    /// Finds the first deployment of the sha to the stage that succeeded, which is when the sha reached
+    /// the stage.
+    fn arrival(
+        &self,
+        app: &Application,
+        git_ref: &str,
+        stage: &str,
+    ) -> Result<Option<DeploymentRecord>, Error> {
+        Ok(self.list(app, stage)?.into_iter().find(|record| {
+            record.outcome == DeploymentOutcome::Succeeded && record.git_ref == git_ref
+        }))
+    }
+}
+
+/// Gets the shas that the stages of the application run, going by the last successful deployment to each of them.
+pub fn deployed_shas(
+    history: &dyn DeploymentHistory,
+    app: &Application,
+) -> Result<Vec<String>, Error> {
+    let mut shas = Vec::new();
+    for stage in history.stages(app)? {
+        if let Some(record) = history.last_successful(app, &stage)? {
+            if !shas.contains(&record.git_ref) {
+                shas.push(record.git_ref);
+            }
+        }
+    }
+    Ok(shas)
+}
+
+/// Keeps the history of deployments in memory. The history is lost when the conveyor stops, so this is
+/// mostly useful for tests and runtimes that do not roll back.
+#[derive(Debug, Default)]
+pub struct MemoryHistory {
+    records: Mutex<HashMap<(String, String), Vec<DeploymentRecord>>>,
+}
+
+impl DeploymentHistory for MemoryHistory {
+    fn record(
+        &self,
+        app: &Application,
+        stage: &str,
+        record: &DeploymentRecord,
+    ) -> Result<(), Error> {
+        self.records
+            .lock()
+            .unwrap()
+            .entry((app.full_name(), stage.to_string()))
+            .or_default()
+            .push(record.clone());
+        Ok(())
+    }
+
+    fn list(&self, app: &Application, stage: &str) -> Result<Vec<DeploymentRecord>, Error> {
+        let key = (app.full_name(), stage.to_string());
+        Ok(self
+            .records
+            .lock()
+            .unwrap()
+            .get(&key)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    fn stages(&self, app: &Application) -> Result<Vec<String>, Error> {
+        let app = app.full_name();
+        let mut stages: Vec<String> = self
+            .records
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|(other, _)| *other == app)
+            .map(|(_, stage)| stage.clone())
+            .collect();
+        stages.sort();
+        Ok(stages)
+    }
+}
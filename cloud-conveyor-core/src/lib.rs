@@ -4,18 +4,35 @@
     missing_docs,
     rust_2018_idioms,
     missing_debug_implementations,
-    intra_doc_link_resolution_failure
+    rustdoc::broken_intra_doc_links
 )]
+// The derives from failure and typetag expand to impls nested in anonymous constants.
+#![allow(non_local_definitions)]
 
 #[macro_use]
 extern crate failure;
+use glob::{MatchOptions, Pattern};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 
+use crate::build::RetentionPolicy;
+use crate::webhook::PrChange;
+
+pub mod approval;
+pub mod audit;
 pub mod build;
 pub mod chat;
+pub mod credentials;
 pub mod deploy;
+pub mod history;
 pub mod pipelining;
+pub mod promotion;
+pub mod reaper;
+pub mod redact;
 pub mod runtime;
+pub mod schedule;
+pub mod scheduler;
+pub mod secrets;
 pub mod state_machine;
 pub mod teardown;
 pub mod webhook;
@@ -61,6 +78,114 @@ impl Account {
     }
 }
 
+/// Limits a [Trigger](enum.Trigger.html) to the events that change relevant files, so that for instance
+/// changes to documentation do not build and deploy a service. Both lists hold glob patterns such as
+/// `src/**` or `*.md` that are matched against paths relative to the root of the repository.
+///
+/// Events that do not say which files they change are never filtered out. The patterns are checked when the
+/// configuration is [validated](yaml/struct.ConfigFile.html#method.validate).
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct PathFilter {
+    /// When set, at least one of the changed files has to match one of these patterns.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<String>,
+
+    /// Changed files that match one of these patterns are not considered relevant.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub paths_ignore: Vec<String>,
+}
+
+impl PathFilter {
+    /// Determines if the changed files are relevant to the trigger. Returns true when the files are
+    /// not known or there is nothing to filter on.
+    pub fn is_relevant(&self, changed_files: Option<&[String]>) -> bool {
+        let changed_files = match changed_files {
+            Some(files) if !self.paths.is_empty() || !self.paths_ignore.is_empty() => files,
+            _ => return true,
+        };
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::default()
+        };
+        let matches_any = |patterns: &[String], file: &str| {
+            patterns.iter().any(|pattern| {
+                Pattern::new(pattern)
+                    .expect("The path filter is not valid. Validate the config first.")
+                    .matches_with(file, options)
+            })
+        };
+        changed_files.iter().any(|file| {
+            !matches_any(&self.paths_ignore, file)
+                && (self.paths.is_empty() || matches_any(&self.paths, file))
+        })
+    }
+}
+
+/// Limits a [Pr](enum.Trigger.html#variant.Pr) trigger to the pull requests between matching branches, and
+/// holds back the deployment of a pull request until it is labeled and no longer a draft.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct PrFilter {
+    /// The pattern (if any) of branches that the pull request is to be merged into; e.g master.
+    /// Can be any valid regular expression.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+
+    /// The pattern (if any) of branches that the pull request is merged from.
+    /// Can be any valid regular expression.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+
+    /// The label (if any) that the pull request needs before it is deployed; e.g deploy-preview.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+
+    /// Whether or not draft pull requests are skipped until they are marked as ready for review.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skip_drafts: bool,
+}
+
+impl PrFilter {
+    /// Determines if the branches of the pull request match the patterns. A target branch that is not
+    /// known always matches.
+    pub fn matches_branches(&self, target_branch: Option<&str>, source_branch: &str) -> bool {
+        let matches = |pattern: &Option<String>, branch: &str| match pattern {
+            Some(pattern) => Regex::new(pattern)
+                .expect("The branch pattern is not valid. Validate the config first.")
+                .is_match(branch),
+            None => true,
+        };
+        target_branch.is_none_or(|branch| matches(&self.to, branch))
+            && matches(&self.from, source_branch)
+    }
+
+    /// Determines if a pull request with the labels that is or is not a draft should be deployed.
+    pub fn allows_deploy(&self, labels: &[String], draft: bool) -> bool {
+        let labeled = match &self.label {
+            Some(label) => labels.contains(label),
+            None => true,
+        };
+        labeled && !self.skips(draft)
+    }
+
+    /// Determines if the change of a pull request can make a difference to whether it is deployed. Changes
+    /// of other labels than the one that is required, and of the draft state when drafts are not skipped,
+    /// make none.
+    pub fn cares_about(&self, change: &PrChange) -> bool {
+        match change {
+            PrChange::Push => true,
+            PrChange::Labeled(label) | PrChange::Unlabeled(label) => {
+                self.label.as_ref() == Some(label)
+            }
+            PrChange::ReadyForReview | PrChange::ConvertedToDraft => self.skip_drafts,
+        }
+    }
+
+    /// Determines if a pull request that is or is not a draft should be skipped altogether.
+    pub fn skips(&self, draft: bool) -> bool {
+        draft && self.skip_drafts
+    }
+}
+
 /// Defines the kinds of triggers in the application that allow for
 /// things to happen for user actions. For instance, pr deploys, merges to branches, etc
 /// given the information provided by a source control provider such as github.
@@ -74,6 +199,18 @@ pub enum Trigger {
         /// Whether or not a temporary stack should be created, updated, deleted in line
         /// with the lifetime of the PR.
         deploy: bool,
+
+        /// The branches, label and draft state of the PRs that are built and deployed.
+        #[serde(flatten)]
+        pr_filter: PrFilter,
+
+        /// The names, account and regions of the temporary stacks of the PRs.
+        #[serde(flatten)]
+        pr_stages: PrStages,
+
+        /// The files that the changes of the PR have to touch for it to be built.
+        #[serde(flatten)]
+        filter: PathFilter,
     },
 
     /// When a merge is made to a branch. Optional filter on what kind of branch
@@ -89,20 +226,107 @@ pub enum Trigger {
         /// The names of the stages that apply to the merge pattern.
         #[serde(rename = "deploy")]
         stages: Vec<String>,
+
+        /// The files that the merge has to change for it to be built.
+        #[serde(flatten)]
+        filter: PathFilter,
     },
 
     /// When a tag is pushed.
     Tag {
-        /// The regex pattern to apply to tags. Can also be "semver" to allow for
-        /// the really complex semver regex to be used.
+        /// The regex pattern to apply to tags. Can also be "semver" to only apply to tags that are
+        /// semantic versions, optionally prefixed with a `v`; e.g v1.2.3 or 1.2.3-rc.1.
         pattern: String,
 
         /// The names of the stages that apply to the tag pattern.
         #[serde(rename = "deploy")]
         stages: Vec<String>,
+
+        /// The names of the stages that tags of prerelease versions, such as 1.2.3-rc.1, are deployed to
+        /// instead of the stages above. Prereleases are deployed like any other tag when this is not set.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        prerelease: Option<Vec<String>>,
+
+        /// Whether or not a tag is refused by the stages that already run a greater version. Only tags
+        /// that are semantic versions are compared.
+        #[serde(default)]
+        ordered: bool,
+
+        /// The files that the tag has to change, compared to what a stage runs, for it to be deployed to the
+        /// stage. Tags do not say which files they change, so they are only filtered when the changes can be
+        /// looked up, see [CompareCommits](webhook/trait.CompareCommits.html).
+        #[serde(flatten)]
+        filter: PathFilter,
+    },
+
+    /// When a branch is pushed to, outside of any pull request. When they occur, a temporary stack is created
+    /// for the branch on the first push, updated on every push after that and deleted along with the branch.
+    Branch {
+        /// The regex pattern of the branches to apply to; e.g ^feature/.
+        pattern: String,
+
+        /// Whether or not a temporary stack should be created, updated, deleted in line
+        /// with the lifetime of the branch. The branch is only built when this is false.
+        deploy: bool,
+
+        /// The name of the account the stacks of the branches are deployed to. Defaults to the default account.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        account: Option<String>,
+
+        /// The files that the pushes to the branch have to change for it to be built.
+        #[serde(flatten)]
+        filter: PathFilter,
+    },
+
+    /// When a cron schedule comes due, the head of the branch is built again and deployed,
+    /// see the [schedule](schedule/index.html) module. Useful for nightly rebuilds.
+    Schedule {
+        /// The cron expression of the schedule, evaluated in UTC. e.g "0 2 * * *" for every night at 2am.
+        cron: String,
+
+        /// The name of the branch whose head is built.
+        branch: String,
+
+        /// The names of the stages that the head of the branch is deployed to.
+        #[serde(rename = "deploy")]
+        stages: Vec<String>,
+    },
+
+    /// Allows people to build any git ref and deploy it to the stages by hand, see
+    /// [handle_manual_request](webhook/fn.handle_manual_request.html).
+    Manual {
+        /// The names of the stages that may be deployed to by hand.
+        #[serde(rename = "deploy")]
+        stages: Vec<String>,
+    },
+
+    /// Allows whatever is live in one stage to be promoted to another by hand, see the
+    /// [promotion](promotion/index.html) module. Promotions are never started by events of the source
+    /// control provider.
+    Promote {
+        /// The name of the stage to promote from.
+        from: String,
+
+        /// The name of the stage to promote to.
+        to: String,
     },
 }
 
+/// What happens when several pipelines deploy to the same stage, see the [scheduler](scheduler/index.html)
+/// module. Whatever the policy, only one deployment to a stage happens at a time.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Concurrency {
+    /// The pipelines deploy to the stage one after another, in the order they were created.
+    #[default]
+    Queue,
+    /// A new pipeline cancels the older pipelines that have yet to deploy to the stage.
+    Cancel,
+    /// An older pipeline skips the stage, and whatever comes after it, when a newer pipeline
+    /// is waiting to deploy to it as well. Only the newest sha is deployed after the one in progress.
+    Skip,
+}
+
 ///  The stage of the application. This is specific an environment.
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub struct Stage {
@@ -114,26 +338,178 @@ pub struct Stage {
 
     /// The reference to the account that the stage belongs to.
     pub account: Account,
+
+    /// The names of the secrets that deployments to the stage need.
+    #[serde(default)]
+    pub secrets: Vec<String>,
+
+    /// Whether or not a failed deployment to the stage is followed by a deployment of the
+    /// last sha that was deployed to it successfully.
+    #[serde(default)]
+    pub rollback: bool,
+
+    /// What happens when several pipelines deploy to the stage.
+    #[serde(default)]
+    pub concurrency: Concurrency,
+
+    /// The number of the pull request, when this is the temporary stage of one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pr: Option<u32>,
 }
 
 impl Stage {
-    /// Defines a new stage given the a new application, Since the reference to
-    /// app is not mutable, this does not add the stage to the app. However, the stage is
-    /// does contain a reference to the account.
-    pub fn from_pr_number(app: &Application, number: u32) -> Self {
-        let account = app
-            .default_account()
-            .expect("No default account on the app. This is somehow not a valid config.");
-        Self {
-            name: format!("pr-{}", number),
-            approval_group: None,
-            account: account.clone(),
-        }
+    /// Creates the temporary stage of a pull request of the application, named and placed as the
+    /// [PrStages](struct.PrStages.html) say. Since the reference to app is not mutable, this does not
+    /// add the stage to the app. Returns None when the account of the stages does not exist.
+    pub fn for_pr(
+        app: &Application,
+        pr_stages: &PrStages,
+        number: u32,
+        source_branch: &str,
+    ) -> Option<Self> {
+        let name = pr_stages.stage_name(app, number, source_branch);
+        pr_stages.stage(app, name, number)
     }
 
     /// Determines if a branch is for a PR or not.
     pub fn is_for_pr(&self, pr_number: u32) -> bool {
-        self.name == format!("pr-{}", pr_number)
+        self.pr == Some(pr_number)
+    }
+
+    /// Creates the temporary stage of a branch of the application that a [Branch](enum.Trigger.html#variant.Branch)
+    /// trigger deploys to. The stage of `feature-login` of `api` is named `api-branch-feature-login`. Branches whose
+    /// names change when they are made fit for stacks get a short hash of their name appended, so `feature/login`
+    /// does not end up on the stage of `feature-login`. The stage is deployed to the account with the name, or the
+    /// default account when there is none. Returns None when the account does not exist.
+    pub fn for_branch(app: &Application, account: Option<&str>, branch: &str) -> Option<Self> {
+        let mut name = stage_name(&format!("{}{}", branch_stage_prefix(app), branch));
+        if stage_name(branch) != branch {
+            name = format!("{}-{}", name, short_hash(branch));
+        }
+        Some(Self {
+            name,
+            approval_group: None,
+            account: app.account_or_default(account)?.clone(),
+            secrets: Vec::new(),
+            rollback: false,
+            concurrency: Concurrency::default(),
+            pr: None,
+        })
+    }
+}
+
+/// Gets the name of the stage qualified with the name of the application, which is what the deployers name
+/// the stacks, namespaces and the like of the stage after. The name is `<app>-<stage>`, unless the name of the
+/// stage already starts with that of the application, as those of branches and of `{app}-pr-{number}` do. So
+/// the stages `pr-42` and `api-pr-42` of the application `api` are both `api-pr-42`, which
+/// [validate_applications](yaml/fn.validate_applications.html) rejects.
+pub fn qualified_stage_name(app: &Application, stage: &Stage) -> String {
+    qualify(app, &stage_name(&stage.name))
+}
+
+/// Qualifies the sanitized name of a stage of the application, see [qualified_stage_name](fn.qualified_stage_name.html).
+pub(crate) fn qualify(app: &Application, name: &str) -> String {
+    let prefix = app_stage_prefix(app);
+    if name.starts_with(&prefix) {
+        name.to_string()
+    } else {
+        format!("{}{}", prefix, name)
+    }
+}
+
+/// The prefix of the qualified names of the stages of the application.
+pub(crate) fn app_stage_prefix(app: &Application) -> String {
+    format!("{}-", stage_name(&app.app))
+}
+
+/// The prefix of the names of the stages of branches of the application, see [for_branch](struct.Stage.html#method.for_branch).
+pub(crate) fn branch_stage_prefix(app: &Application) -> String {
+    format!("{}branch-", app_stage_prefix(app))
+}
+
+/// Gets a short hash of the text that stays the same across releases, for names that have to tell texts apart
+/// that read the same once they are sanitized or shortened.
+pub fn short_hash(text: &str) -> String {
+    // 32 bit FNV-1a.
+    let hash = text.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    });
+    format!("{:08x}", hash)
+}
+
+/// Replaces every character of the name that is not a letter, a digit or a dash with a dash, so the
+/// name can be used in the names of stacks.
+pub(crate) fn stage_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect::<String>()
+        .trim_matches('-')
+        .to_string()
+}
+
+/// The template that the names of the stages of pull requests follow when none is set.
+pub const DEFAULT_PR_STAGE_NAME: &str = "pr-{number}";
+
+/// Says how the temporary stages of the pull requests of a [Pr](enum.Trigger.html#variant.Pr) trigger are
+/// named and which account and regions they are deployed to.
+///
+/// The name is a template with the placeholders `{app}`, `{number}` and `{branch}` for the name of the application,
+/// the number of the pull request and its source branch. Every character of the result that is not a letter, a digit
+/// or a dash is replaced with a dash, so `{app}-pr-{number}` names the stage of PR 7 of `api` `api-pr-7`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct PrStages {
+    /// The template of the names of the stages. Defaults to `pr-{number}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stage_name: Option<String>,
+
+    /// The name of the account the stages are deployed to. Defaults to the default account.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+
+    /// The regions of the account the stages are deployed to. Defaults to every region of the account.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub regions: Vec<String>,
+}
+
+impl PrStages {
+    /// Gets the template of the names of the stages.
+    pub fn name_template(&self) -> &str {
+        self.stage_name.as_deref().unwrap_or(DEFAULT_PR_STAGE_NAME)
+    }
+
+    /// Gets the name of the stage of the pull request of the application.
+    pub fn stage_name(&self, app: &Application, number: u32, source_branch: &str) -> String {
+        stage_name(
+            &self
+                .name_template()
+                .replace("{app}", &app.app)
+                .replace("{number}", &number.to_string())
+                .replace("{branch}", source_branch),
+        )
+    }
+
+    /// Gets the account that the stages of the application are deployed to, limited to the regions if any are
+    /// set. Returns None when the account does not exist.
+    pub fn account(&self, app: &Application) -> Option<Account> {
+        let mut account = app.account_or_default(self.account.as_deref())?.clone();
+        if !self.regions.is_empty() {
+            account.regions = self.regions.clone();
+        }
+        Some(account)
+    }
+
+    /// Creates the stage with the name of the pull request of the application. Returns None when the account
+    /// of the stages does not exist.
+    pub fn stage(&self, app: &Application, name: String, number: u32) -> Option<Stage> {
+        Some(Stage {
+            name,
+            approval_group: None,
+            account: self.account(app)?,
+            secrets: Vec::new(),
+            rollback: false,
+            concurrency: Concurrency::default(),
+            pr: Some(number),
+        })
     }
 }
 
@@ -161,6 +537,34 @@ pub struct Application {
 
     /// The  different approval groups that are in the application
     pub approval_groups: Vec<ApprovalGroup>,
+
+    /// When set, builds never reuse the artifacts of an earlier build of the same sha.
+    #[serde(default)]
+    pub force_rebuild: bool,
+
+    /// The names of the secrets that builds of the application need.
+    #[serde(default)]
+    pub build_secrets: Vec<String>,
+
+    /// When set, the stored builds of the application that the policy does not retain are deleted after
+    /// every build. See [cleanup](build/trait.ProvideArtifactLocation.html#method.cleanup).
+    #[serde(default)]
+    pub retention: Option<RetentionPolicy>,
+
+    /// The owner and name of the repository of the application, when it is not the org and app.
+    /// This is the case for the applications of a monorepo.
+    #[serde(default)]
+    pub repo: Option<String>,
+
+    /// The git url that the repository of the application is cloned from, when it is not the https url on
+    /// github.com. Private repositories need one that the machine has the credentials of, such as an ssh url.
+    #[serde(default)]
+    pub repo_url: Option<String>,
+
+    /// The directory of the application relative to the root of its repository, when it is not the root.
+    /// Only changes to files in it affect the application.
+    #[serde(default)]
+    pub path: Option<String>,
 }
 
 impl Application {
@@ -173,6 +577,30 @@ impl Application {
         })
     }
 
+    /// Gets how the stages of the pull requests of the application are named and placed, which is what its
+    /// first [Pr](enum.Trigger.html#variant.Pr) trigger that deploys says.
+    pub fn pr_stages(&self) -> PrStages {
+        self.triggers
+            .iter()
+            .find_map(|trigger| match trigger {
+                Trigger::Pr {
+                    deploy: true,
+                    pr_stages,
+                    ..
+                } => Some(pr_stages.clone()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    /// Gets the account with the name, or the default account when no name is given.
+    pub fn account_or_default(&self, name: Option<&str>) -> Option<&Account> {
+        match name {
+            Some(name) => self.accounts.iter().find(|acc| acc.is_named(name)),
+            None => self.default_account(),
+        }
+    }
+
     /// Adds a new stage to the application.
     pub fn add_stage(&mut self, stage: Stage) {
         self.stages.push(stage)
@@ -182,4 +610,50 @@ impl Application {
     pub fn full_name(&self) -> String {
         format!("{}/{}", self.org, self.app)
     }
+
+    /// Determines if a git url (https or ssh) points at the repository of this application.
+    /// This assumes that the org and app match the owner and name of the repository as is
+    /// the case on platforms like github.
+    pub fn is_for_repo(&self, repo: &str) -> bool {
+        let repo = repo.trim_end_matches('/').trim_end_matches(".git");
+        let repo_name = self.repo_name();
+        repo.ends_with(&format!("/{}", repo_name)) || repo.ends_with(&format!(":{}", repo_name))
+    }
+
+    /// The owner and name of the repository of this application, which is the full name unless
+    /// the [repo](#structfield.repo) is set.
+    pub fn repo_name(&self) -> String {
+        self.repo.clone().unwrap_or_else(|| self.full_name())
+    }
+
+    /// The git url of the repository of this application, which is the https url on github.com unless the
+    /// [repo_url](#structfield.repo_url) is set.
+    pub fn repo_url(&self) -> String {
+        self.repo_url
+            .clone()
+            .unwrap_or_else(|| format!("https://github.com/{}.git", self.repo_name()))
+    }
+
+    /// Determines if the changed files, relative to the root of the repository, affect this application.
+    /// They always do when they are not known or the application is at the root of the repository.
+    pub fn is_affected_by(&self, changed_files: Option<&[String]>) -> bool {
+        match (&self.path, changed_files) {
+            (Some(path), Some(files)) => {
+                let dir = format!("{}/", path.trim_end_matches('/'));
+                files.iter().any(|file| file.starts_with(&dir))
+            }
+            _ => true,
+        }
+    }
+
+    /// Determines if a trigger of the application allows promotions from one stage to another.
+    pub fn allows_promotion(&self, from: &str, to: &str) -> bool {
+        self.triggers.iter().any(|trigger| match trigger {
+            Trigger::Promote {
+                from: allowed_from,
+                to: allowed_to,
+            } => allowed_from == from && allowed_to == to,
+            _ => false,
+        })
+    }
 }
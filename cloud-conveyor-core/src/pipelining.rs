@@ -1,12 +1,18 @@
 //! Defines the high order types for saving regarding the state of a pipeline. While this
 //! code does not produce a pipeline (that exists in places like webhook), it does provide
 //! patterns for interacting with and operating on a pipeline.
-use crate::build::BuildStatus;
+use crate::approval::Decision;
+use crate::audit::{ActionDetails, Origin};
+use crate::build::{ArtifactManifest, BuildStatus};
 use crate::deploy::DeployStatus;
+use crate::history::{deployed_shas, DeploymentHistory, DeploymentOutcome, DeploymentRecord};
 use crate::runtime::RuntimeContext;
+use crate::secrets::Secrets;
 use crate::teardown::TeardownStatus;
-use crate::{ApprovalGroup, Stage};
+use crate::yaml::BuildSpec;
+use crate::{Application, ApprovalGroup, Stage};
 
+use chrono::Utc;
 use failure::Error;
 use log::{info, warn};
 use serde::{Deserialize, Serialize};
@@ -23,7 +29,7 @@ pub trait BoxableEq: Any {
 }
 
 /// The result of performing an action via the [Perform](trait.Perform.html) trait.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub enum ActionResult {
     /// The success state shows that job succeeded. This means that everything in the job
     /// went to plan and following steps in the pipeline can occur.
@@ -72,6 +78,11 @@ pub trait Perform: BoxableEq + Debug {
     fn get_new_work(&self, _ctx: &RuntimeContext) -> Option<Vec<Box<dyn Perform>>> {
         None
     }
+
+    /// Describes what the action is about for the [audit log](../audit/index.html).
+    fn details(&self) -> ActionDetails {
+        ActionDetails::default()
+    }
 }
 
 impl<T> BoxableEq for T
@@ -79,7 +90,7 @@ where
     T: Perform + PartialEq,
 {
     fn box_eq(&self, other: &(dyn Any + 'static)) -> bool {
-        other.downcast_ref::<T>().map_or(false, |a| self == a)
+        other.downcast_ref::<T>().is_some_and(|a| self == a)
     }
     fn as_any(&self) -> &(dyn Any + 'static) {
         self
@@ -92,17 +103,35 @@ impl PartialEq for Box<dyn Perform> {
     }
 }
 
+/// Resolves the values of the named secrets for the application of the repo.
+fn resolve_secrets(
+    ctx: &RuntimeContext,
+    app: Option<&Application>,
+    repo: &str,
+    names: &[String],
+) -> Result<Secrets, Error> {
+    if names.is_empty() {
+        return Ok(Secrets::default());
+    }
+    let app = app.ok_or_else(|| format_err!("No application is configured for repo {}", repo))?;
+    Ok(Secrets::resolve(&*ctx.secrets, app, names)?)
+}
+
 // TODO: FIll out the spec for this type.
+#[allow(dead_code)]
 #[derive(Debug, Deserialize, Serialize, PartialEq)]
 struct AppUpdate(String);
 
 // TODO: FIll out the spec for this type.
 // TODO: Add notifications to before and after builds and before and after deploys to an env.
+#[allow(dead_code)]
 #[derive(Debug, Deserialize, Serialize, PartialEq)]
 struct Notify;
 
 /// The Approval action is responsible for managing the need to get approval from a human prior to
 /// continuing through the pipeline. It does so by implementing the [Perform](trait.Perform.html) trait.
+/// Once started, it waits for one of the people of its group to answer its request through the
+/// [ProvideApprovals](../approval/trait.ProvideApprovals.html) of the runtime. A rejection fails the pipeline.
 ///
 /// For example, this is used is many of the [Triggers](../../enum.Trigger.html) use the Approval action. Merges
 /// to a branch and pushes of a tag invoke deployments to [Stages](../struct.Stage.html) that may more many
@@ -116,7 +145,10 @@ struct Notify;
 ///      approval_group: ApprovalGroup { people: vec![] },
 ///      stage_name: "prod".to_string(),
 ///      git_ref: "cda888fd29a23fdb2d905e4ab6cf50230ce4c37b".to_string(),
-///      app_name: "cloud_conveyor".to_string()
+///      app_name: "cloud_conveyor".to_string(),
+///      approved_by: None,
+///      rejected_by: None,
+///      request_id: None,
 ///  };
 ///
 /// let pipeline = Pipeline::empty();
@@ -134,18 +166,62 @@ pub struct Approval {
     pub git_ref: String,
     /// app name that is being deployed.
     pub app_name: String,
+    /// The person who approved the deployment, once it is approved.
+    #[serde(default)]
+    pub approved_by: Option<String>,
+    /// The person who rejected the deployment, once it is rejected.
+    #[serde(default)]
+    pub rejected_by: Option<String>,
+    /// The identifier of the request for approval, once it is made. Only the answers to it count.
+    #[serde(default)]
+    pub request_id: Option<String>,
 }
 
 #[typetag::serde]
 impl Perform for Approval {
-    fn start(&mut self, _: &RuntimeContext) -> std::result::Result<(), Error> {
-        todo!()
+    fn start(&mut self, ctx: &RuntimeContext) -> std::result::Result<(), Error> {
+        self.request_id = Some(ctx.approvals.request(self)?);
+        Ok(())
     }
-    fn is_done(&mut self, _: &RuntimeContext) -> std::result::Result<bool, Error> {
-        todo!()
+    fn is_done(&mut self, ctx: &RuntimeContext) -> std::result::Result<bool, Error> {
+        let request_id = self
+            .request_id
+            .as_ref()
+            .ok_or_else(|| format_err!("The approval of {:?} was never requested", self))?;
+        for answer in ctx.approvals.answers(request_id)? {
+            if !self.approval_group.people.contains(&answer.person) {
+                warn!(
+                    "Ignoring the answer of {:?} to {:?}, who is not an approver",
+                    answer.person, self
+                );
+                continue;
+            }
+            match answer.decision {
+                Decision::Approved => self.approved_by = Some(answer.person),
+                Decision::Rejected => self.rejected_by = Some(answer.person),
+            }
+            return Ok(true);
+        }
+        Ok(false)
     }
     fn get_result(&self, _: &RuntimeContext) -> ActionResult {
-        todo!()
+        match (&self.approved_by, &self.rejected_by) {
+            (Some(_), _) => ActionResult::Success,
+            (None, Some(_)) => ActionResult::Failed,
+            (None, None) => ActionResult::Canceled,
+        }
+    }
+    fn details(&self) -> ActionDetails {
+        ActionDetails {
+            app: Some(self.app_name.clone()),
+            git_ref: Some(self.git_ref.clone()),
+            stage: Some(self.stage_name.clone()),
+            approver: self
+                .approved_by
+                .clone()
+                .or_else(|| self.rejected_by.clone()),
+            ..ActionDetails::default()
+        }
     }
 }
 
@@ -179,7 +255,30 @@ pub struct Build {
     pub git_ref: String,
     /// The repo to check the code out from.
     pub repo: String,
+    /// The branch that is being built, if any.
+    #[serde(default)]
+    pub branch: Option<String>,
+    /// When set, the build is performed even if the artifacts of an earlier build of the
+    /// same ref are available.
+    #[serde(default)]
+    pub force_rebuild: bool,
+    /// The specification of how to build the code. When it is not set, builders read the
+    /// specification of the application from the checkout of the ref, if it has one.
+    #[serde(default)]
+    pub spec: Option<BuildSpec>,
+    /// The names of the secrets the build needs. Their values are resolved when the build is started.
+    #[serde(default)]
+    pub secrets: Vec<String>,
+    /// The full name of the application, which tells the applications that share a repository apart.
+    /// When it is not set, the first application of the repository is used.
+    #[serde(default)]
+    pub app: Option<String>,
+    /// The directory of the application relative to the root of the repository, which the build is run in.
+    #[serde(default)]
+    pub path: Option<String>,
     result: Option<BuildStatus>,
+    #[serde(default)]
+    manifest: Option<ArtifactManifest>,
 }
 
 impl Build {
@@ -188,25 +287,146 @@ impl Build {
         Self {
             git_ref,
             repo,
+            branch: None,
+            force_rebuild: false,
+            spec: None,
+            secrets: Vec::new(),
+            app: None,
+            path: None,
             result: None,
+            manifest: None,
+        }
+    }
+
+    /// Sets the branch that is being built.
+    pub fn with_branch(mut self, branch: Option<String>) -> Self {
+        self.branch = branch;
+        self
+    }
+
+    /// Sets the specification of how to build the code.
+    pub fn with_spec(mut self, spec: Option<BuildSpec>) -> Self {
+        self.spec = spec;
+        self
+    }
+
+    /// Sets the names of the secrets the build needs.
+    pub fn with_secrets(mut self, secrets: Vec<String>) -> Self {
+        self.secrets = secrets;
+        self
+    }
+
+    /// Sets whether or not the build should ignore the artifacts of earlier builds of the same ref.
+    pub fn with_force_rebuild(mut self, force_rebuild: bool) -> Self {
+        self.force_rebuild = force_rebuild;
+        self
+    }
+
+    /// Sets the full name of the application that is being built.
+    pub fn with_app(mut self, app: Option<String>) -> Self {
+        self.app = app;
+        self
+    }
+
+    /// Sets the directory of the application relative to the root of the repository.
+    pub fn with_path(mut self, path: Option<String>) -> Self {
+        self.path = path;
+        self
+    }
+
+    /// Finds the application that is being built.
+    pub fn application<'a>(&self, ctx: &'a RuntimeContext) -> Option<&'a Application> {
+        ctx.load_application(&self.repo, self.app.as_deref())
+    }
+
+    /// Gets the manifest of the artifacts the build produced or reused. This is only
+    /// known once the build has succeeded.
+    pub fn manifest(&self) -> Option<&ArtifactManifest> {
+        self.manifest.as_ref()
+    }
+
+    fn find_previous_build(&self, ctx: &RuntimeContext) -> Result<Option<ArtifactManifest>, Error> {
+        if self.force_rebuild {
+            return Ok(None);
+        }
+        match self.application(ctx) {
+            Some(app) => ctx.artifact_provider.find_build(app, &self.git_ref),
+            None => Ok(None),
         }
     }
+
+    fn record_build(&mut self, ctx: &RuntimeContext, logs: &str) -> Result<(), Error> {
+        let app = match self.application(ctx) {
+            Some(app) => app,
+            None => return Ok(()),
+        };
+        let manifest = ArtifactManifest {
+            git_sha: self.git_ref.clone(),
+            bucket: ctx.artifact_provider.get_bucket(app)?,
+            folder: ctx.artifact_provider.get_folder(app, &self.git_ref)?,
+            logs: logs.to_string(),
+            branch: self.branch.clone(),
+            built_at: Utc::now(),
+        };
+        ctx.artifact_provider.record_build(app, &manifest)?;
+        self.manifest = Some(manifest);
+        Ok(())
+    }
+
+    /// Deletes the stored builds of the application that its retention policy does not keep. The builds of the
+    /// shas that its stages run and of the sha that was just built are always kept.
+    fn clean_up(&self, ctx: &RuntimeContext) -> Result<(), Error> {
+        let app = match self.application(ctx) {
+            Some(app) => app,
+            None => return Ok(()),
+        };
+        let policy = match &app.retention {
+            Some(policy) => policy,
+            None => return Ok(()),
+        };
+        let mut kept = deployed_shas(&*ctx.history, app)?;
+        kept.push(self.git_ref.clone());
+        ctx.artifact_provider.cleanup(app, policy, &kept)?;
+        Ok(())
+    }
 }
 
 #[typetag::serde]
 impl Perform for Build {
     fn start(&mut self, ctx: &RuntimeContext) -> Result<(), Error> {
+        if let Some(manifest) = self.find_previous_build(ctx)? {
+            info!(
+                "Reusing artifacts of previous build: git_ref {:?} for repo {:?} in {:?}",
+                self.git_ref, self.repo, manifest.folder
+            );
+            self.result = Some(BuildStatus::Succeeded {
+                logs: manifest.logs.clone(),
+            });
+            self.manifest = Some(manifest);
+            return Ok(());
+        }
+
         info!(
             "Starting build: git_ref {:?} for repo {:?} ",
             self.git_ref, self.repo
         );
-        ctx.builder.start_build(&*self, ctx).map_err(|e| e.into())
+        let secrets = resolve_secrets(ctx, self.application(ctx), &self.repo, &self.secrets)?;
+        ctx.builder
+            .start_build(&*self, &secrets, ctx)
+            .map_err(|e| e.into())
     }
     fn is_done(&mut self, ctx: &RuntimeContext) -> Result<bool, Error> {
+        // The result is already known when the artifacts of a previous build were reused.
+        if self.result.is_some() {
+            return Ok(true);
+        }
+
         info!(
             "Polling the state of the build: git_ref {:?} for repo {:?} ",
             self.git_ref, self.repo
         );
+        // The build may have been started by another process, whose secrets are not registered here.
+        resolve_secrets(ctx, self.application(ctx), &self.repo, &self.secrets)?;
         match ctx.builder.check_build(&*self, ctx) {
             Ok(status) => match status {
                 BuildStatus::Pending => {
@@ -215,6 +435,24 @@ impl Perform for Build {
                 }
                 _ => {
                     info!("Build completed for git_ref {:?}", self.git_ref);
+                    let status = status.redacted();
+                    if let BuildStatus::Succeeded { logs } = &status {
+                        // Failing to remember the build only costs a rebuild later on.
+                        if let Err(e) = self.record_build(ctx, logs) {
+                            warn!(
+                                "Failed to record build of git_ref {:?}: {}",
+                                self.git_ref, e
+                            );
+                        }
+                        // Failing to clean up only costs storage until the next build.
+                        if let Err(e) = self.clean_up(ctx) {
+                            warn!(
+                                "Failed to clean up the builds before git_ref {:?}: {}",
+                                self.git_ref, e
+                            );
+                        }
+                    }
+                    self.result = Some(status);
                     Ok(true)
                 }
             },
@@ -243,6 +481,14 @@ impl Perform for Build {
             }
         }
     }
+    fn details(&self) -> ActionDetails {
+        ActionDetails {
+            repo: Some(self.repo.clone()),
+            app: self.app.clone(),
+            git_ref: Some(self.git_ref.clone()),
+            ..ActionDetails::default()
+        }
+    }
 }
 
 /// The Deploy action is responsible for managing the updating or creation of an infrastructure stack
@@ -267,7 +513,11 @@ impl Perform for Build {
 ///  # let stage = Stage {
 ///  #      name: "hello_word".to_string(),
 /// #       approval_group: None,
-/// #       account
+/// #       account,
+/// #       secrets: vec![],
+/// #       rollback: false,
+/// #       concurrency: Default::default(),
+/// #       pr: None,
 /// # };
 ///  let deploy = Deploy::new (
 ///     stage,
@@ -289,6 +539,17 @@ pub struct Deploy {
     pub repo: String,
     /// The sha of the code to deploy.
     pub git_ref: String,
+    /// Whether or not the deployment rolls the stage back to an earlier sha. A rollback that fails
+    /// is never rolled back itself.
+    #[serde(default)]
+    pub rollback: bool,
+    /// The full name of the application, which tells the applications that share a repository apart.
+    /// When it is not set, the first application of the repository is used.
+    #[serde(default)]
+    pub app: Option<String>,
+    /// The tag that the sha is deployed for, if it is deployed for one.
+    #[serde(default)]
+    pub tag: Option<String>,
     /// The completed status of the deployment.
     result: Option<DeployStatus>,
 }
@@ -307,7 +568,11 @@ impl Deploy {
     ///  # let stage = Stage {
     ///  #      name: "hello_word".to_string(),
     /// #       approval_group: None,
-    /// #       account
+    /// #       account,
+    /// #       secrets: vec![],
+    /// #       rollback: false,
+    /// #       concurrency: Default::default(),
+    /// #       pr: None,
     /// # };
     ///
     ///  let deploy = Deploy::new (
@@ -322,9 +587,79 @@ impl Deploy {
             git_ref,
             stage,
             repo,
+            rollback: false,
+            app: None,
+            tag: None,
             result: None,
         }
     }
+
+    /// Sets whether or not the deployment rolls the stage back to an earlier sha.
+    pub fn with_rollback(mut self, rollback: bool) -> Self {
+        self.rollback = rollback;
+        self
+    }
+
+    /// Sets the full name of the application that is being deployed.
+    pub fn with_app(mut self, app: Option<String>) -> Self {
+        self.app = app;
+        self
+    }
+
+    /// Sets the tag that the sha is deployed for.
+    pub fn with_tag(mut self, tag: Option<String>) -> Self {
+        self.tag = tag;
+        self
+    }
+
+    /// Finds the application that is being deployed.
+    pub fn application<'a>(&self, ctx: &'a RuntimeContext) -> Option<&'a Application> {
+        ctx.load_application(&self.repo, self.app.as_deref())
+    }
+
+    /// Creates the deployment that rolls the stage of the application back to the sha, or to the last sha
+    /// that was deployed to it successfully according to the history when no sha is given. Returns None
+    /// when the stage has no successful deployment to roll back to.
+    pub fn rollback_of(
+        app: &Application,
+        stage: Stage,
+        repo: String,
+        git_ref: Option<String>,
+        history: &dyn DeploymentHistory,
+    ) -> Result<Option<Self>, Error> {
+        let (git_ref, tag) = match git_ref {
+            Some(git_ref) => (Some(git_ref), None),
+            None => match history.last_successful(app, &stage.name)? {
+                Some(record) => (Some(record.git_ref), record.tag),
+                None => (None, None),
+            },
+        };
+        Ok(git_ref.map(|git_ref| {
+            Self::new(stage, repo, git_ref)
+                .with_rollback(true)
+                .with_app(Some(app.full_name()))
+                .with_tag(tag)
+        }))
+    }
+
+    fn record_deployment(&self, ctx: &RuntimeContext, status: &DeployStatus) -> Result<(), Error> {
+        let app = match self.application(ctx) {
+            Some(app) => app,
+            None => return Ok(()),
+        };
+        let outcome = match status {
+            DeployStatus::Complete => DeploymentOutcome::Succeeded,
+            _ => DeploymentOutcome::Failed,
+        };
+        let record = DeploymentRecord {
+            git_ref: self.git_ref.clone(),
+            deployed_at: Utc::now(),
+            outcome,
+            rollback: self.rollback,
+            tag: self.tag.clone(),
+        };
+        ctx.history.record(app, &self.stage.name, &record)
+    }
 }
 
 #[typetag::serde]
@@ -334,8 +669,9 @@ impl Perform for Deploy {
             "Starting Deploy for sha: sha {:?} for repo {:?} to stage {:?}",
             self.git_ref, self.repo, self.stage
         );
+        let secrets = resolve_secrets(ctx, self.application(ctx), &self.repo, &self.stage.secrets)?;
         ctx.infrastructure
-            .start_deployment(&*self, ctx)
+            .start_deployment(&*self, &secrets, ctx)
             .map_err(|e| e.into())
     }
     fn is_done(&mut self, ctx: &RuntimeContext) -> Result<bool, Error> {
@@ -343,6 +679,8 @@ impl Perform for Deploy {
             "Polling the state of the for ref: ref {:?} for repo {:?} to stage {:?}",
             self.git_ref, self.repo, self.stage
         );
+        // The deployment may have been started by another process, whose secrets are not registered here.
+        resolve_secrets(ctx, self.application(ctx), &self.repo, &self.stage.secrets)?;
         match ctx.infrastructure.check_deployment(&*self, ctx) {
             Ok(status) => match status {
                 DeployStatus::Pending => {
@@ -353,7 +691,15 @@ impl Perform for Deploy {
                     Ok(false)
                 }
                 _ => {
-                    info!("Build completed for ref {:?}", self.git_ref);
+                    info!("Deploy completed for ref {:?}", self.git_ref);
+                    // Failing to remember the deployment only costs the ability to roll back to it.
+                    if let Err(e) = self.record_deployment(ctx, &status) {
+                        warn!(
+                            "Failed to record deployment of git_ref {:?} to stage {:?}: {}",
+                            self.git_ref, self.stage.name, e
+                        );
+                    }
+                    self.result = Some(status);
                     Ok(true)
                 }
             },
@@ -382,6 +728,53 @@ impl Perform for Deploy {
             }
         }
     }
+    fn details(&self) -> ActionDetails {
+        ActionDetails {
+            repo: Some(self.repo.clone()),
+            app: self.app.clone(),
+            git_ref: Some(self.git_ref.clone()),
+            stage: Some(self.stage.name.clone()),
+            account: Some(self.stage.account.name.clone()),
+            regions: Some(self.stage.account.regions.clone()),
+            pr: self.stage.pr,
+            ..ActionDetails::default()
+        }
+    }
+    /// When the deployment failed and the stage is rolled back on failure, the deployment of the last
+    /// sha that was deployed to the stage successfully is done next.
+    fn get_new_work(&self, ctx: &RuntimeContext) -> Option<Vec<Box<dyn Perform>>> {
+        if self.rollback || !self.stage.rollback || self.result != Some(DeployStatus::Failed) {
+            return None;
+        }
+        let app = self.application(ctx)?;
+        let rollback = Self::rollback_of(
+            app,
+            self.stage.clone(),
+            self.repo.clone(),
+            None,
+            &*ctx.history,
+        );
+        match rollback {
+            Ok(Some(rollback)) => {
+                info!(
+                    "Rolling stage {:?} back from git_ref {:?} to git_ref {:?}",
+                    self.stage.name, self.git_ref, rollback.git_ref
+                );
+                Some(vec![Box::new(rollback)])
+            }
+            Ok(None) => {
+                warn!(
+                    "Stage {:?} has no successful deployment to roll back to",
+                    self.stage.name
+                );
+                None
+            }
+            Err(e) => {
+                warn!("Failed to find the deployment to roll back to: {}", e);
+                None
+            }
+        }
+    }
 }
 
 /// The `Teardown` action is responsible for managing the deletion of stacks that are no longer required.
@@ -404,7 +797,11 @@ impl Perform for Deploy {
 ///  # let stage = Stage {
 ///  #      name: "hello_word".to_string(),
 /// #       approval_group: None,
-/// #       account
+/// #       account,
+/// #       secrets: vec![],
+/// #       rollback: false,
+/// #       concurrency: Default::default(),
+/// #       pr: None,
 /// # };
 ///   
 ///  let teardown = Teardown::new(
@@ -425,6 +822,10 @@ pub struct Teardown {
     /// The repo of the application in question. The repo is used to capture what application the stage
     /// belongs to with storing the application or a reference to it.
     pub repo: String,
+    /// The full name of the application, which tells the applications that share a repository apart.
+    /// When it is not set, the first application of the repository is used.
+    #[serde(default)]
+    pub app: Option<String>,
     result: Option<TeardownStatus>,
 }
 
@@ -434,9 +835,41 @@ impl Teardown {
         Self {
             stage,
             repo,
+            app: None,
             result: None,
         }
     }
+
+    /// Sets the full name of the application whose stage is removed.
+    pub fn with_app(mut self, app: Option<String>) -> Self {
+        self.app = app;
+        self
+    }
+
+    /// Finds the application whose stage is removed.
+    pub fn application<'a>(&self, ctx: &'a RuntimeContext) -> Option<&'a Application> {
+        ctx.load_application(&self.repo, self.app.as_deref())
+    }
+
+    fn record_teardown(&self, ctx: &RuntimeContext) -> Result<(), Error> {
+        let app = match self.application(ctx) {
+            Some(app) => app,
+            None => return Ok(()),
+        };
+        // There is nothing to forget when the stage was never deployed to, or was torn down already.
+        let latest = match ctx.history.list(app, &self.stage.name)?.pop() {
+            Some(latest) if latest.outcome != DeploymentOutcome::TornDown => latest,
+            _ => return Ok(()),
+        };
+        let record = DeploymentRecord {
+            git_ref: latest.git_ref,
+            deployed_at: Utc::now(),
+            outcome: DeploymentOutcome::TornDown,
+            rollback: false,
+            tag: latest.tag,
+        };
+        ctx.history.record(app, &self.stage.name, &record)
+    }
 }
 
 #[typetag::serde]
@@ -469,6 +902,15 @@ impl Perform for Teardown {
                         "Teardown completed for  teardown for repo {:?} on stage {:?}",
                         self.repo, self.stage
                     );
+                    if status == TeardownStatus::Complete {
+                        if let Err(e) = self.record_teardown(ctx) {
+                            warn!(
+                                "Failed to record teardown of stage {:?}: {}",
+                                self.stage.name, e
+                            );
+                        }
+                    }
+                    self.result = Some(status);
                     Ok(true)
                 }
             },
@@ -497,15 +939,28 @@ impl Perform for Teardown {
             }
         }
     }
+    fn details(&self) -> ActionDetails {
+        ActionDetails {
+            repo: Some(self.repo.clone()),
+            app: self.app.clone(),
+            stage: Some(self.stage.name.clone()),
+            account: Some(self.stage.account.name.clone()),
+            regions: Some(self.stage.account.regions.clone()),
+            pr: self.stage.pr,
+            ..ActionDetails::default()
+        }
+    }
 }
 
-/// A pipeline is a series of actions that need to be performed in order. It is like a queue, responsible
+/// A pipeline is a series of actions that need to be performed in order. It is a queue, responsible
 /// for popping and pushing actions that implement the [Perform](trait.Perform.html) trait.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Pipeline {
     pending_actions: Vec<Box<dyn Perform>>,
     completed_actions: Vec<Box<dyn Perform>>,
     action_results: Vec<ActionResult>,
+    #[serde(default)]
+    origin: Option<Origin>,
 }
 
 impl Pipeline {
@@ -516,9 +971,21 @@ impl Pipeline {
             pending_actions: Vec::new(),
             completed_actions: Vec::new(),
             action_results: Vec::new(),
+            origin: None,
         }
     }
 
+    /// Sets who or what created the pipeline.
+    pub fn with_origin(mut self, origin: Origin) -> Self {
+        self.origin = Some(origin);
+        self
+    }
+
+    /// Gets who or what created the pipeline, if it is known.
+    pub fn origin(&self) -> Option<&Origin> {
+        self.origin.as_ref()
+    }
+
     /// Adds a new action to the pipeline that can be performed.
     pub fn add_action(mut self, action: Box<dyn Perform>) -> Self {
         if !self.pending_actions.contains(&action) {
@@ -533,9 +1000,14 @@ impl Pipeline {
         self.pending_actions.insert(0, action);
     }
 
-    /// Pops the next action off of the stack of actions to complete.
+    /// Pops the next action off of the queue of actions to complete. Actions are popped in the
+    /// order they were added in.
     pub fn pop_next_action(&mut self) -> Option<Box<dyn Perform>> {
-        self.pending_actions.pop()
+        if self.pending_actions.is_empty() {
+            None
+        } else {
+            Some(self.pending_actions.remove(0))
+        }
     }
 
     /// For an action in a pipeline tha was popped, this will consume that action
@@ -545,6 +1017,19 @@ impl Pipeline {
         self.action_results.push(action_result);
     }
 
+    /// Gets the actions that are yet to be performed, in the order they will be performed.
+    pub fn pending_actions(&self) -> impl Iterator<Item = &dyn Perform> {
+        self.pending_actions.iter().map(|action| &**action)
+    }
+
+    /// Gets the actions that were completed along with their results, in the order they were completed.
+    pub fn completed_actions(&self) -> impl Iterator<Item = (&dyn Perform, &ActionResult)> {
+        self.completed_actions
+            .iter()
+            .map(|action| &**action)
+            .zip(self.action_results.iter())
+    }
+
     /// Marks all remaining steps in the pipeline as cancelled.
     pub fn cancel(&mut self) {
         while let Some(action) = self.pop_next_action() {
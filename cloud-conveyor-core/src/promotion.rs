@@ -0,0 +1,282 @@
+//! Defines how a sha that is proven in one stage is promoted to another.
+//!
+//! A promotion deploys whatever is live in the source stage to the target stage without building it
+//! again, so the target stage runs the exact artifacts that were tested in the source stage. Which
+//! promotions are allowed is declared by the [Promote](../enum.Trigger.html#variant.Promote) triggers
+//! of the application, and a promotion always waits for the approval group of the target stage.
+//!
+//! A [Rollback](struct.Rollback.html) that is asked for by hand is held to the same rules: it deploys a sha
+//! that the stage ran successfully before, from the artifacts that are still around, after the approval
+//! group of the stage approves it.
+use crate::audit::Origin;
+use crate::build::ProvideArtifactLocation;
+use crate::history::{DeploymentHistory, DeploymentOutcome};
+use crate::pipelining::{Approval, Deploy, Pipeline};
+use crate::{Application, Stage};
+
+use log::info;
+
+/// Defines an error that occurred when attempting to promote a stage to another.
+#[derive(Debug, Clone, Fail, PartialEq)]
+pub enum PromotionError {
+    /// When the runtime has no application with the name, this variant should be used.
+    #[fail(display = "No app is named {}.", app)]
+    UnknownApp {
+        /// The name of the application.
+        app: String,
+    },
+
+    /// When the application has no stage with the name, this variant should be used.
+    #[fail(display = "The app has no stage named {}.", stage)]
+    UnknownStage {
+        /// The name of the stage.
+        stage: String,
+    },
+
+    /// When no trigger of the application allows the promotion, this variant should be used.
+    #[fail(display = "The app does not allow promotions from {} to {}.", from, to)]
+    NotAllowed {
+        /// The name of the source stage.
+        from: String,
+        /// The name of the target stage.
+        to: String,
+    },
+
+    /// When nothing was ever deployed to the source stage, or it was torn down since, this variant should be used.
+    #[fail(display = "Nothing was deployed to {}.", stage)]
+    NothingDeployed {
+        /// The name of the source stage.
+        stage: String,
+    },
+
+    /// When the latest deployment to the source stage failed, this variant should be used.
+    #[fail(display = "The deployment of {} to {} failed.", git_ref, stage)]
+    SourceFailed {
+        /// The sha of the failed deployment.
+        git_ref: String,
+        /// The name of the source stage.
+        stage: String,
+    },
+
+    /// When the stage never ran the sha that it is rolled back to successfully, this variant should be used.
+    #[fail(display = "{} was never deployed to {} successfully.", git_ref, stage)]
+    NeverDeployed {
+        /// The sha that the stage is rolled back to.
+        git_ref: String,
+        /// The name of the stage.
+        stage: String,
+    },
+
+    /// When the artifacts of the sha can not be found, this variant should be used.
+    #[fail(display = "No artifacts were built for {}.", git_ref)]
+    MissingArtifacts {
+        /// The sha without artifacts.
+        git_ref: String,
+    },
+
+    /// When the cause does not fit any of the known patterns defined else where in the enum,
+    /// this can be returned. It takes an additional string and passed that information as part
+    /// of the error  context.
+    #[fail(display = "Unknown promotion error occurred: {}", info)]
+    Other {
+        /// Additional information to pass back.
+        info: String,
+    },
+}
+
+/// The promotion of the sha that is live in one stage to another.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Promotion {
+    /// The stage that the sha is promoted from.
+    pub from: Stage,
+    /// The stage that the sha is promoted to.
+    pub to: Stage,
+    /// The sha that is live in the source stage.
+    pub git_ref: String,
+    /// The tag that the sha was deployed to the source stage for, if it was deployed for one.
+    pub tag: Option<String>,
+}
+
+impl Promotion {
+    /// Plans the promotion of what is live in the stage `from` to the stage `to`. The promotion has to be
+    /// allowed by a trigger of the application, the latest deployment to the source stage has to have
+    /// succeeded and the artifacts of its sha have to still be around.
+    pub fn of(
+        app: &Application,
+        from: &str,
+        to: &str,
+        history: &dyn DeploymentHistory,
+        artifacts: &dyn ProvideArtifactLocation,
+    ) -> Result<Self, PromotionError> {
+        let stage = |name: &str| {
+            app.stages
+                .iter()
+                .find(|stage| stage.name == name)
+                .cloned()
+                .ok_or_else(|| PromotionError::UnknownStage {
+                    stage: name.to_string(),
+                })
+        };
+        let (from, to) = (stage(from)?, stage(to)?);
+        if !app.allows_promotion(&from.name, &to.name) {
+            return Err(PromotionError::NotAllowed {
+                from: from.name,
+                to: to.name,
+            });
+        }
+
+        let other = |e: failure::Error| PromotionError::Other {
+            info: e.to_string(),
+        };
+        let latest = history
+            .list(app, &from.name)
+            .map_err(other)?
+            .pop()
+            .filter(|latest| latest.outcome != DeploymentOutcome::TornDown)
+            .ok_or_else(|| PromotionError::NothingDeployed {
+                stage: from.name.clone(),
+            })?;
+        if latest.outcome == DeploymentOutcome::Failed {
+            return Err(PromotionError::SourceFailed {
+                git_ref: latest.git_ref,
+                stage: from.name,
+            });
+        }
+        if artifacts
+            .find_build(app, &latest.git_ref)
+            .map_err(other)?
+            .is_none()
+        {
+            return Err(PromotionError::MissingArtifacts {
+                git_ref: latest.git_ref,
+            });
+        }
+
+        Ok(Self {
+            from,
+            to,
+            git_ref: latest.git_ref,
+            tag: latest.tag,
+        })
+    }
+
+    /// Creates the pipeline that performs the promotion. The pipeline has no build, the deployment
+    /// reuses the artifacts of the sha, and waits for the approval group of the target stage if it has one.
+    pub fn into_pipeline(self, app: &Application, origin: Origin) -> Pipeline {
+        info!(
+            "Promoting {:?} from {:?} to {:?} for app {:?}",
+            self.git_ref,
+            self.from.name,
+            self.to.name,
+            app.full_name()
+        );
+        let deploy = Deploy::new(self.to.clone(), app.repo_url(), self.git_ref.clone())
+            .with_app(Some(app.full_name()))
+            .with_tag(self.tag);
+        approved_deployment(app, &self.to, &self.git_ref, deploy, origin)
+    }
+}
+
+/// The rollback of a stage to a sha that it ran successfully before.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rollback {
+    /// The stage that is rolled back.
+    pub stage: Stage,
+    /// The sha that the stage is rolled back to.
+    pub git_ref: String,
+    /// The tag that the sha was deployed to the stage for, if it was deployed for one.
+    pub tag: Option<String>,
+}
+
+impl Rollback {
+    /// Plans the rollback of the stage to the sha, or to the last sha that was deployed to it successfully
+    /// when no sha is given. The history has to show a successful deployment of the sha to the stage and
+    /// the artifacts of the sha have to still be around.
+    pub fn of(
+        app: &Application,
+        stage: &str,
+        git_ref: Option<&str>,
+        history: &dyn DeploymentHistory,
+        artifacts: &dyn ProvideArtifactLocation,
+    ) -> Result<Self, PromotionError> {
+        let stage = app
+            .stages
+            .iter()
+            .find(|candidate| candidate.name == stage)
+            .cloned()
+            .ok_or_else(|| PromotionError::UnknownStage {
+                stage: stage.to_string(),
+            })?;
+        let other = |e: failure::Error| PromotionError::Other {
+            info: e.to_string(),
+        };
+        let successful = history
+            .list(app, &stage.name)
+            .map_err(other)?
+            .into_iter()
+            .rev()
+            .filter(|record| record.outcome == DeploymentOutcome::Succeeded)
+            .find(|record| git_ref.is_none_or(|git_ref| record.git_ref == git_ref));
+        let (git_ref, tag) = match (successful, git_ref) {
+            (Some(record), _) => (record.git_ref, record.tag),
+            (None, Some(git_ref)) => {
+                return Err(PromotionError::NeverDeployed {
+                    git_ref: git_ref.to_string(),
+                    stage: stage.name,
+                })
+            }
+            (None, None) => return Err(PromotionError::NothingDeployed { stage: stage.name }),
+        };
+        if artifacts
+            .find_build(app, &git_ref)
+            .map_err(other)?
+            .is_none()
+        {
+            return Err(PromotionError::MissingArtifacts { git_ref });
+        }
+        Ok(Self {
+            stage,
+            git_ref,
+            tag,
+        })
+    }
+
+    /// Creates the pipeline that performs the rollback. Like a promotion, the pipeline has no build and
+    /// waits for the approval group of the stage if it has one.
+    pub fn into_pipeline(self, app: &Application, origin: Origin) -> Pipeline {
+        info!(
+            "Rolling stage {:?} of app {:?} back to {:?}",
+            self.stage.name,
+            app.full_name(),
+            self.git_ref
+        );
+        let deploy = Deploy::new(self.stage.clone(), app.repo_url(), self.git_ref.clone())
+            .with_rollback(true)
+            .with_app(Some(app.full_name()))
+            .with_tag(self.tag);
+        approved_deployment(app, &self.stage, &self.git_ref, deploy, origin)
+    }
+}
+
+/// Creates the pipeline of the deployment, which waits for the approval group of the stage first if it has one.
+fn approved_deployment(
+    app: &Application,
+    stage: &Stage,
+    git_ref: &str,
+    deploy: Deploy,
+    origin: Origin,
+) -> Pipeline {
+    let mut pipeline = Pipeline::empty().with_origin(origin);
+    if let Some(approval_group) = &stage.approval_group {
+        pipeline = pipeline.add_action(Box::new(Approval {
+            approval_group: approval_group.clone(),
+            git_ref: git_ref.to_string(),
+            app_name: app.full_name(),
+            stage_name: stage.name.clone(),
+            approved_by: None,
+            rejected_by: None,
+            request_id: None,
+        }));
+    }
+    pipeline.add_action(Box::new(deploy))
+}
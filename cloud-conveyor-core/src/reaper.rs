@@ -0,0 +1,255 @@
+//! Defines a reaper for the temporary stacks of pull requests.
+//!
+//! A [Pr](../enum.Trigger.html#variant.Pr) trigger deploys every pull request to a stage of its own, which
+//! is torn down when the pull request completes. When the event of that never arrives, the stack lives on
+//! forever. The [Reaper](struct.Reaper.html) finds the stages of pull requests that the [audit log](../audit/index.html)
+//! says were deployed to and not torn down since, and tears down the ones whose pull request is closed or
+//! that have been idle for longer than a time to live.
+use crate::audit::{AuditLog, Origin, Transition};
+use crate::pipelining::{ActionResult, Pipeline, Teardown};
+use crate::runtime::RuntimeContext;
+use crate::Application;
+use chrono::{DateTime, Duration, Utc};
+use failure::Error;
+use log::{info, warn};
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Debug};
+
+/// Defines a run time abstraction for finding out whether a pull request is still open.
+pub trait ResolvePullRequests: Debug {
+    /// Determines if the pull request with the number of the repository with the git url is open.
+    fn is_open(&self, repo: &str, number: u32) -> Result<bool, Error>;
+}
+
+/// Why the stage of a pull request is reaped.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReapReason {
+    /// The pull request is closed.
+    Closed,
+    /// Nothing happened to the stage for longer than the time to live.
+    Idle,
+}
+
+/// The stage of a pull request that is reaped.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReapCandidate {
+    /// The full name of the application, see [full_name](../struct.Application.html#method.full_name).
+    pub app: String,
+    /// The name of the stage.
+    pub stage: String,
+    /// The number of the pull request.
+    pub number: u32,
+    /// The name of the account the stage was deployed to, as audited. Entries from before the account was
+    /// audited do not say, and the account of the stages of pull requests of the application is used instead.
+    pub account: Option<String>,
+    /// The regions the stage was deployed to, as audited.
+    pub regions: Option<Vec<String>>,
+    /// When something last happened to the stage.
+    pub last_active: DateTime<Utc>,
+    /// Why the stage is reaped.
+    pub reason: ReapReason,
+}
+
+impl ReapCandidate {
+    /// Creates the pipeline that tears the stage down in the account and regions it was deployed to, which
+    /// may no longer be where the stages of pull requests of the application go. Returns None when the account
+    /// does not exist.
+    pub fn into_pipeline(self, app: &Application) -> Option<Pipeline> {
+        let mut stage = app.pr_stages().stage(app, self.stage, self.number)?;
+        if let Some(name) = &self.account {
+            stage.account = app.accounts.iter().find(|acc| acc.is_named(name))?.clone();
+        }
+        if let Some(regions) = self.regions {
+            stage.account.regions = regions;
+        }
+        let teardown = Teardown::new(stage, app.repo_url()).with_app(Some(app.full_name()));
+        Some(
+            Pipeline::empty()
+                .add_action(Box::new(teardown))
+                .with_origin(Origin::manual("reaper".to_string())),
+        )
+    }
+}
+
+impl fmt::Display for ReapCandidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self.reason {
+            ReapReason::Closed => "the pull request is closed",
+            ReapReason::Idle => "it is idle",
+        };
+        write!(
+            f,
+            "{} {}: {}, last active at {}",
+            self.app, self.stage, reason, self.last_active
+        )
+    }
+}
+
+/// What the reaper found and, unless it was a dry run, the pipelines that tear the stages down.
+#[derive(Debug, Default)]
+pub struct ReapReport {
+    /// Whether or not the reaper only reported what it would tear down.
+    pub dry_run: bool,
+    /// The stages that are torn down, or would be in a dry run.
+    pub candidates: Vec<ReapCandidate>,
+    /// The pipelines that tear the stages down. Empty in a dry run.
+    pub pipelines: Vec<Pipeline>,
+}
+
+/// Finds the stages of pull requests that should no longer exist and creates the pipelines that tear them down.
+#[derive(Debug)]
+pub struct Reaper {
+    ttl: Duration,
+    pull_requests: Option<Box<dyn ResolvePullRequests>>,
+    dry_run: bool,
+}
+
+impl Reaper {
+    /// Creates a reaper of the stages that have been idle for longer than the time to live.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            pull_requests: None,
+            dry_run: false,
+        }
+    }
+
+    /// Sets how to find out whether pull requests are still open, so that the stages of closed ones are
+    /// reaped without waiting for them to become idle.
+    pub fn with_pull_requests(mut self, pull_requests: Box<dyn ResolvePullRequests>) -> Self {
+        self.pull_requests = Some(pull_requests);
+        self
+    }
+
+    /// Sets whether the reaper only reports what it would tear down.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Finds the stages of pull requests of the applications that should be torn down as of now.
+    pub fn candidates(
+        &self,
+        apps: &[Application],
+        audit: &dyn AuditLog,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<ReapCandidate>, Error> {
+        let mut candidates = Vec::new();
+        for app in apps {
+            // The entries are oldest first, so this ends up with the latest entry of every stage.
+            let mut stages: BTreeMap<String, (ReapCandidate, bool)> = BTreeMap::new();
+            for entry in audit.entries(app)? {
+                let stage = match &entry.stage {
+                    Some(stage) => stage.clone(),
+                    None => continue,
+                };
+                // Entries from before the number of the pull request was audited only have the name
+                // of the stage to go by.
+                let number = match entry.pr.or_else(|| pr_number(&stage)) {
+                    Some(number) => number,
+                    None => continue,
+                };
+                // A stage is gone once it is being torn down or was torn down successfully.
+                let torn_down = entry.action == "Teardown"
+                    && (entry.transition == Transition::Started
+                        || entry.result == Some(ActionResult::Success));
+                // Only the entries of deployments and teardowns say where the stage is.
+                let (account, regions) = match stages.remove(&stage) {
+                    Some((previous, _)) => (
+                        entry.account.or(previous.account),
+                        entry.regions.or(previous.regions),
+                    ),
+                    None => (entry.account, entry.regions),
+                };
+                let candidate = ReapCandidate {
+                    app: app.full_name(),
+                    stage: stage.clone(),
+                    number,
+                    account,
+                    regions,
+                    last_active: entry.at,
+                    reason: ReapReason::Idle,
+                };
+                stages.insert(stage, (candidate, torn_down));
+            }
+
+            for (mut candidate, torn_down) in stages.into_values() {
+                if torn_down {
+                    continue;
+                }
+                candidate.reason = if self.is_closed(app, candidate.number) {
+                    ReapReason::Closed
+                } else if now - candidate.last_active > self.ttl {
+                    ReapReason::Idle
+                } else {
+                    continue;
+                };
+                info!("Reaping {}", candidate);
+                candidates.push(candidate);
+            }
+        }
+        Ok(candidates)
+    }
+
+    /// Finds the stages of pull requests of the applications that should be torn down and, unless this is
+    /// a dry run, creates the pipelines that do so.
+    pub fn reap_applications(
+        &self,
+        apps: &[Application],
+        audit: &dyn AuditLog,
+        now: DateTime<Utc>,
+    ) -> Result<ReapReport, Error> {
+        let candidates = self.candidates(apps, audit, now)?;
+        let pipelines = if self.dry_run {
+            Vec::new()
+        } else {
+            candidates
+                .iter()
+                .filter_map(|candidate| {
+                    let app = apps.iter().find(|app| app.full_name() == candidate.app)?;
+                    let pipeline = candidate.clone().into_pipeline(app);
+                    if pipeline.is_none() {
+                        warn!("Could not find the account to tear {} down in", candidate);
+                    }
+                    pipeline
+                })
+                .collect()
+        };
+        Ok(ReapReport {
+            dry_run: self.dry_run,
+            candidates,
+            pipelines,
+        })
+    }
+
+    /// Reaps the stages of pull requests of every application of the runtime, see
+    /// [reap_applications](#method.reap_applications).
+    pub fn reap(&self, runtime: &RuntimeContext, now: DateTime<Utc>) -> Result<ReapReport, Error> {
+        self.reap_applications(&runtime.applications, &*runtime.audit, now)
+    }
+
+    fn is_closed(&self, app: &Application, number: u32) -> bool {
+        let pull_requests = match &self.pull_requests {
+            Some(pull_requests) => pull_requests,
+            None => return false,
+        };
+        match pull_requests.is_open(&app.repo_url(), number) {
+            Ok(open) => !open,
+            Err(e) => {
+                warn!(
+                    "Could not find out if PR {} of app {:?} is open: {}",
+                    number,
+                    app.full_name(),
+                    e
+                );
+                false
+            }
+        }
+    }
+}
+
+/// Gets the number of the pull request of a stage with the default name, if the stage is for one.
+fn pr_number(stage: &str) -> Option<u32> {
+    stage.strip_prefix("pr-")?.parse().ok()
+}
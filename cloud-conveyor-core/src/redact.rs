@@ -0,0 +1,140 @@
+//! Defines the facilities that keep the values of secrets out of logs and out of the serialized state of pipelines.
+//!
+//! There are two layers to this. Values that are known to be secret when they are created are wrapped in a
+//! [Secret](struct.Secret.html) whose `Debug` and `Serialize` implementations only ever print a placeholder.
+//! Since the values of secrets still end up in places that cloud conveyor does not control, such as the output
+//! of a build command, every value is also [registered](fn.register.html) and [scrubbed](fn.redact.html) from
+//! text that passes through cloud conveyor. Wrapping the logger of a runtime in a [RedactingLogger](struct.RedactingLogger.html)
+//! scrubs every log line and the [BuildStatus](../build/enum.BuildStatus.html) of a build is scrubbed before it is
+//! stored on the pipeline.
+use log::{Log, Metadata, Record};
+use serde::{Serialize, Serializer};
+
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Mutex;
+
+/// The text that is printed in place of the value of a secret.
+pub const REDACTED: &str = "[REDACTED]";
+
+static KNOWN_SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Wraps a value that must never be printed or serialized. The value is only available through
+/// [expose](#method.expose) so that every use of it is explicit.
+///
+/// ```rust
+/// use cloud_conveyor_core::redact::Secret;
+/// let secret = Secret::new("hunter2".to_string());
+/// assert_eq!(format!("{:?}", secret), "[REDACTED]");
+/// assert_eq!(secret.expose(), "hunter2");
+/// ```
+#[derive(Clone, PartialEq)]
+pub struct Secret<T>(T);
+
+impl<T> Secret<T> {
+    /// Wraps the value.
+    pub fn new(value: T) -> Self {
+        Secret(value)
+    }
+
+    /// Gets a reference to the wrapped value.
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+
+    /// Unwraps the value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Debug for Secret<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T> Serialize for Secret<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+/// Registers the value of a secret so that it is scrubbed by [redact](fn.redact.html) from then on.
+/// Empty values are ignored since they would match everything.
+pub fn register(value: &str) {
+    if value.is_empty() {
+        return;
+    }
+    let mut known = KNOWN_SECRETS.lock().unwrap();
+    if !known.iter().any(|k| k == value) {
+        known.push(value.to_string());
+        // Longer values go first so that a secret that contains another is scrubbed as a whole.
+        known.sort_by_key(|k| std::cmp::Reverse(k.len()));
+    }
+}
+
+/// Replaces every [registered](fn.register.html) secret value in the text with a placeholder.
+pub fn redact(text: &str) -> String {
+    let known = KNOWN_SECRETS.lock().unwrap();
+    known.iter().fold(text.to_string(), |text, value| {
+        text.replace(value, REDACTED)
+    })
+}
+
+/// A logger that scrubs the values of [registered](fn.register.html) secrets from every log line
+/// before passing it on to the logger it wraps.
+///
+/// ```rust
+/// use cloud_conveyor_core::redact::RedactingLogger;
+/// # #[derive(Debug)]
+/// # struct StdoutLogger;
+/// # impl log::Log for StdoutLogger {
+/// #     fn enabled(&self, _: &log::Metadata<'_>) -> bool { true }
+/// #     fn log(&self, record: &log::Record<'_>) { println!("{}", record.args()) }
+/// #     fn flush(&self) {}
+/// # }
+/// static LOGGER: RedactingLogger<StdoutLogger> = RedactingLogger::new(StdoutLogger);
+/// log::set_logger(&LOGGER).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct RedactingLogger<L> {
+    inner: L,
+}
+
+impl<L: Log> RedactingLogger<L> {
+    /// Wraps the logger.
+    pub const fn new(inner: L) -> Self {
+        Self { inner }
+    }
+
+    /// Gets the logger that is wrapped.
+    pub fn inner(&self) -> &L {
+        &self.inner
+    }
+}
+
+impl<L: Log> Log for RedactingLogger<L> {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = redact(&record.args().to_string());
+        self.inner.log(
+            &Record::builder()
+                .args(format_args!("{}", message))
+                .metadata(record.metadata().clone())
+                .module_path(record.module_path())
+                .file(record.file())
+                .line(record.line())
+                .build(),
+        );
+    }
+
+    fn flush(&self) {
+        self.inner.flush()
+    }
+}
@@ -5,8 +5,13 @@
 //! and the like are defined here and provide the standard interface bindings that all of the runtime implementations
 //! can provide.
 
+use crate::approval::ProvideApprovals;
+use crate::audit::AuditLog;
 use crate::build::{BuildSource, ProvideArtifactLocation};
+use crate::credentials::CredentialProvider;
 use crate::deploy::DeployInfrastructure;
+use crate::history::DeploymentHistory;
+use crate::secrets::SecretProvider;
 use crate::teardown::TeardownInfrastructure;
 use crate::Application;
 use std::fmt::Debug;
@@ -22,11 +27,46 @@ pub struct RuntimeContext {
     pub infrastructure: Box<dyn DeployInfrastructure>,
     /// TODO
     pub teardown: Box<dyn TeardownInfrastructure>,
+    /// The store of the secrets that builds and deployments need.
+    pub secrets: Box<dyn SecretProvider>,
+    /// The provider of the credentials of the accounts that stages are deployed to.
+    pub credentials: Box<dyn CredentialProvider>,
+    /// The record of the deployments to every stage.
+    pub history: Box<dyn DeploymentHistory>,
+    /// The log that every transition of every action is appended to.
+    pub audit: Box<dyn AuditLog>,
+    /// The means of asking people to approve deployments to the stages with approvers.
+    pub approvals: Box<dyn ProvideApprovals>,
+    /// The applications that this runtime is conveying.
+    pub applications: Vec<Application>,
 }
 
 impl RuntimeContext {
-    /// TODO
-    pub fn load_application_from_repo(&self, _: &str) -> Option<&mut Application> {
-        unimplemented!();
+    /// Finds the application that is built from the repository with the given git url.
+    pub fn load_application_from_repo(&self, repo: &str) -> Option<&Application> {
+        self.applications.iter().find(|app| app.is_for_repo(repo))
+    }
+
+    /// Finds every application that is built from the repository with the given git url. There is more
+    /// than one when the repository is a monorepo.
+    pub fn load_applications_from_repo<'a>(
+        &'a self,
+        repo: &'a str,
+    ) -> impl Iterator<Item = &'a Application> + 'a {
+        self.applications
+            .iter()
+            .filter(move |app| app.is_for_repo(repo))
+    }
+
+    /// Finds the application of the repository with the given full name, or the first application of the
+    /// repository when no name is given.
+    pub fn load_application(&self, repo: &str, app: Option<&str>) -> Option<&Application> {
+        match app {
+            Some(name) => self
+                .applications
+                .iter()
+                .find(|candidate| candidate.is_for_repo(repo) && candidate.full_name() == name),
+            None => self.load_application_from_repo(repo),
+        }
     }
 }
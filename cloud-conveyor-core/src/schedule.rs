@@ -0,0 +1,198 @@
+//! Defines a time based source of events for the [Schedule](../enum.Trigger.html#variant.Schedule) triggers
+//! of applications.
+//!
+//! Unlike every other trigger, schedules are not driven by the version control system. Instead, the
+//! server polls a [ScheduleEventSource](struct.ScheduleEventSource.html) every so often, which produces a
+//! [Schedule](../webhook/enum.VcsEvent.html#variant.Schedule) event for every schedule that came due since
+//! it was last polled. Those events are turned into pipelines exactly like the events of webhooks are, so a
+//! scheduled run builds and deploys the head of its branch the same way a merge to that branch would.
+use crate::pipelining::Pipeline;
+use crate::runtime::RuntimeContext;
+use crate::webhook::{event_to_pipeline, VcsEvent, WebhookEvent};
+use crate::{Application, Trigger};
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use failure::Error;
+use log::{info, warn};
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::str::FromStr;
+
+/// Defines an error that occurred when attempting to use the schedule of a trigger.
+#[derive(Debug, Clone, Fail, PartialEq)]
+pub enum ScheduleError {
+    /// When the cron expression of a schedule can not be parsed, this variant should be used.
+    #[fail(display = "The cron expression {:?} is invalid: {}", cron, info)]
+    InvalidCron {
+        /// The cron expression.
+        cron: String,
+        /// Why the expression is invalid.
+        info: String,
+    },
+}
+
+/// Parses a cron expression. Both the standard five fields (minute, hour, day of month, month and day
+/// of week) and the extended form with seconds first and an optional year last are accepted.
+/// Schedules are always evaluated in UTC.
+pub fn parse_cron(cron: &str) -> Result<Schedule, ScheduleError> {
+    let expression = if cron.split_whitespace().count() == 5 {
+        format!("0 {}", cron)
+    } else {
+        cron.to_string()
+    };
+    Schedule::from_str(&expression).map_err(|e| ScheduleError::InvalidCron {
+        cron: cron.to_string(),
+        info: e.to_string(),
+    })
+}
+
+/// The next run of a schedule trigger of an application.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScheduledRun {
+    /// When the run happens.
+    pub at: DateTime<Utc>,
+    /// The cron expression of the trigger.
+    pub cron: String,
+    /// The branch whose head is built.
+    pub branch: String,
+    /// The names of the stages the head is deployed to.
+    pub stages: Vec<String>,
+}
+
+/// Gets the next run of every schedule trigger of the application after the time, soonest first.
+/// Triggers with invalid cron expressions are left out.
+pub fn next_runs(app: &Application, after: DateTime<Utc>) -> Vec<ScheduledRun> {
+    let mut runs: Vec<_> = app
+        .triggers
+        .iter()
+        .filter_map(|trigger| match trigger {
+            Trigger::Schedule {
+                cron,
+                branch,
+                stages,
+            } => {
+                let at = parse_cron(cron).ok()?.after(&after).next()?;
+                Some(ScheduledRun {
+                    at,
+                    cron: cron.clone(),
+                    branch: branch.clone(),
+                    stages: stages.clone(),
+                })
+            }
+            _ => None,
+        })
+        .collect();
+    runs.sort_by_key(|run| run.at);
+    runs
+}
+
+/// Defines a run time abstraction for finding the sha at the head of a branch, since a schedule
+/// builds whatever the head of its branch is when it comes due.
+pub trait ResolveBranches: Debug {
+    /// Gets the sha at the head of the branch of the repository with the git url.
+    fn head(&self, repo: &str, branch: &str) -> Result<String, Error>;
+}
+
+/// Produces the events of the schedule triggers that came due since it was last polled.
+#[derive(Debug)]
+pub struct ScheduleEventSource {
+    branches: Box<dyn ResolveBranches>,
+    last_polled: DateTime<Utc>,
+    /// When the schedules that came due but could not be fired, by app, cron expression and branch, were
+    /// last fired or polled.
+    missed: HashMap<(String, String, String), DateTime<Utc>>,
+}
+
+impl ScheduleEventSource {
+    /// Creates a source that fires the schedules that come due after the time.
+    pub fn new(branches: Box<dyn ResolveBranches>, since: DateTime<Utc>) -> Self {
+        Self {
+            branches,
+            last_polled: since,
+            missed: HashMap::new(),
+        }
+    }
+
+    /// Gets the last time the source was polled at.
+    pub fn last_polled(&self) -> DateTime<Utc> {
+        self.last_polled
+    }
+
+    /// Gets the events of the schedules of every application that came due between the last poll and
+    /// now. A schedule that came due more than once in that window fires only once. When the head of the
+    /// branch of a schedule that came due can not be found, it is tried again on the next poll.
+    pub fn poll(&mut self, runtime: &RuntimeContext, now: DateTime<Utc>) -> Vec<WebhookEvent> {
+        let mut events = Vec::new();
+        for app in &runtime.applications {
+            let mut fired: Vec<(&str, &str)> = Vec::new();
+            for trigger in &app.triggers {
+                let (cron, branch) = match trigger {
+                    Trigger::Schedule { cron, branch, .. } => (cron, branch),
+                    _ => continue,
+                };
+                // Triggers with the same schedule and branch all handle the same event.
+                if fired.contains(&(cron.as_str(), branch.as_str())) {
+                    continue;
+                }
+                let schedule = match parse_cron(cron) {
+                    Ok(schedule) => schedule,
+                    Err(e) => {
+                        warn!("Skipping schedule of app {:?}: {}", app.full_name(), e);
+                        continue;
+                    }
+                };
+                let key = (app.full_name(), cron.clone(), branch.clone());
+                let since = self.missed.get(&key).unwrap_or(&self.last_polled);
+                match schedule.after(since).next() {
+                    Some(at) if at <= now => {}
+                    _ => continue,
+                }
+
+                let repo = app.repo_url();
+                let sha = match self.branches.head(&repo, branch) {
+                    Ok(sha) => sha,
+                    Err(e) => {
+                        warn!(
+                            "Could not find the head of {:?} in {:?}: {}",
+                            branch, repo, e
+                        );
+                        self.missed.entry(key).or_insert(self.last_polled);
+                        continue;
+                    }
+                };
+                info!(
+                    "Schedule {:?} of app {:?} came due for {:?} at {:?}",
+                    cron,
+                    app.full_name(),
+                    branch,
+                    sha
+                );
+                self.missed.remove(&key);
+                fired.push((cron, branch));
+                let event = VcsEvent::Schedule {
+                    cron: cron.clone(),
+                    branch: branch.clone(),
+                    sha,
+                };
+                events.push(WebhookEvent::new(event, app.clone(), repo));
+            }
+        }
+        self.last_polled = now;
+        events
+    }
+}
+
+/// Polls the source and converts the events of every schedule that came due into pipelines the same way
+/// [handle_web_hook_event](../webhook/fn.handle_web_hook_event.html) does for the events of webhooks.
+pub fn handle_schedule(
+    source: &mut ScheduleEventSource,
+    runtime: &RuntimeContext,
+    now: DateTime<Utc>,
+) -> Vec<Pipeline> {
+    source
+        .poll(runtime, now)
+        .iter_mut()
+        .filter_map(|event| event_to_pipeline(event, Some(&*runtime.history), None))
+        .collect()
+}
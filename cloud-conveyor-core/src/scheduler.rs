@@ -0,0 +1,201 @@
+//! Defines a scheduler that drives many pipelines at once without letting them race each other.
+//!
+//! Two merges in quick succession produce two pipelines that deploy to the same stage. Left alone, their
+//! deployments could overlap and the older sha could be deployed last. The [Scheduler](struct.Scheduler.html)
+//! only lets one [Deploy](../pipelining/struct.Deploy.html) or [Teardown](../pipelining/struct.Teardown.html)
+//! of a stage run at a time, and starts them in the order the pipelines were added, so the newest sha wins.
+//! What happens to the older pipelines depends on the [Concurrency](../enum.Concurrency.html) of the stage.
+use crate::pipelining::{Perform, Pipeline};
+use crate::runtime::RuntimeContext;
+use crate::state_machine::StateMachine;
+use crate::Concurrency;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+/// Whether a state machine may start its next action.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Admission {
+    /// The action starts now.
+    Start,
+    /// The action waits for another pipeline to be done with its stage.
+    Wait,
+    /// The action and the rest of the pipeline are canceled.
+    Cancel,
+}
+
+/// The application and stage that an action locks, as the full name of the application and the name of
+/// the stage.
+type StageKey = (String, String);
+
+/// The stages that the pipeline of a state machine deploys to right now and will deploy to later.
+struct Claims {
+    running: Option<StageKey>,
+    pending: Vec<StageKey>,
+}
+
+impl Claims {
+    fn of(machine: &StateMachine) -> Self {
+        Self {
+            running: machine.current_action().and_then(stage_key),
+            pending: machine
+                .pipeline()
+                .pending_actions()
+                .filter_map(stage_key)
+                .collect(),
+        }
+    }
+
+    fn claims(&self, key: &StageKey) -> bool {
+        self.running.as_ref() == Some(key) || self.pending.contains(key)
+    }
+}
+
+/// Drives the state machines of many pipelines, only deploying to a stage from one of them at a time.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Scheduler {
+    machines: Vec<StateMachine>,
+}
+
+impl Scheduler {
+    /// Creates a scheduler without any pipelines.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the pipeline to the scheduler. The older pipelines that deploy to any stage with the
+    /// [Cancel](../enum.Concurrency.html#variant.Cancel) policy that this pipeline deploys to as well
+    /// are canceled.
+    pub fn add(&mut self, pipeline: Pipeline, context: &RuntimeContext) {
+        let machine = StateMachine::new(pipeline);
+        let keys = Claims::of(&machine).pending;
+        for older in &mut self.machines {
+            let claims = Claims::of(older);
+            let superseded = claims
+                .pending
+                .iter()
+                .find(|key| keys.contains(key) && concurrency(key, context) == Concurrency::Cancel);
+            if let Some((app, stage)) = superseded {
+                info!(
+                    "Canceling pipeline {:?}, a newer one deploys to stage {:?} of app {:?}",
+                    older.pipeline().origin(),
+                    stage,
+                    app
+                );
+                older.cancel(context);
+            }
+        }
+        self.machines.push(machine);
+    }
+
+    /// Performs one cycle of every state machine, oldest first, and drops the ones that are done.
+    /// A pipeline whose action returns an error is [aborted](../state_machine/struct.StateMachine.html#method.abort),
+    /// so that it does not hold up the others. Returns whether or not any pipeline still has work to do.
+    pub fn tick(&mut self, context: &RuntimeContext) -> bool {
+        let mut claims: Vec<Claims> = self.machines.iter().map(Claims::of).collect();
+        let mut done = Vec::new();
+        for index in 0..self.machines.len() {
+            let admit = |action: &dyn Perform| admit(action, index, &claims, context);
+            let machine = &mut self.machines[index];
+            match machine.tick_admitted(context, &admit) {
+                Ok(true) => {}
+                Ok(false) => done.push(index),
+                Err(e) => {
+                    warn!(
+                        "Aborting pipeline {:?} after an error: {}",
+                        machine.pipeline().origin(),
+                        e
+                    );
+                    machine.abort(context);
+                    done.push(index);
+                }
+            }
+            claims[index] = Claims::of(&self.machines[index]);
+        }
+        for index in done.into_iter().rev() {
+            self.machines.remove(index);
+        }
+        !self.machines.is_empty()
+    }
+
+    /// Gets the state machines of the pipelines that still have work to do, oldest first.
+    pub fn machines(&self) -> &[StateMachine] {
+        &self.machines
+    }
+
+    /// Gets the number of seconds to wait before the next tick, which is the shortest wait of any machine.
+    pub fn recommended_wait(&self) -> Option<u64> {
+        self.machines
+            .iter()
+            .map(StateMachine::recommended_wait)
+            .min()
+    }
+}
+
+/// Decides whether the machine at the index may start the action, given what every machine claims.
+fn admit(
+    action: &dyn Perform,
+    index: usize,
+    claims: &[Claims],
+    context: &RuntimeContext,
+) -> Admission {
+    let key = match stage_key(action) {
+        Some(key) => key,
+        None => return Admission::Start,
+    };
+    if concurrency(&key, context) == Concurrency::Skip
+        && claims[index + 1..].iter().any(|newer| newer.claims(&key))
+    {
+        info!(
+            "Skipping stage {:?} of app {:?}, a newer pipeline deploys to it",
+            key.1, key.0
+        );
+        return Admission::Cancel;
+    }
+    let running = claims
+        .iter()
+        .enumerate()
+        .any(|(other, claims)| other != index && claims.running.as_ref() == Some(&key));
+    let queued_behind = claims[..index]
+        .iter()
+        .any(|older| older.pending.contains(&key));
+    if running || queued_behind {
+        info!(
+            "Waiting for stage {:?} of app {:?}, another pipeline deploys to it",
+            key.1, key.0
+        );
+        return Admission::Wait;
+    }
+    Admission::Start
+}
+
+/// Gets the stage that the action locks, which are the stages of deployments and teardowns. The applications of a
+/// monorepo share their repository, so only actions that name their application lock anything.
+fn stage_key(action: &dyn Perform) -> Option<StageKey> {
+    match action.typetag_name() {
+        "Deploy" | "Teardown" => {}
+        _ => return None,
+    }
+    let details = action.details();
+    match (details.app, details.stage) {
+        (Some(app), Some(stage)) => Some((app, stage)),
+        _ => {
+            warn!(
+                "Not locking the stage of {:?}, it does not name its application",
+                action
+            );
+            None
+        }
+    }
+}
+
+/// Gets the concurrency policy of the stage. The temporary stages of pull requests and branches are not
+/// stages of the application and are queued.
+fn concurrency(key: &StageKey, context: &RuntimeContext) -> Concurrency {
+    context
+        .applications
+        .iter()
+        .find(|app| app.full_name() == key.0)
+        .and_then(|app| app.stages.iter().find(|stage| stage.name == key.1))
+        .map(|stage| stage.concurrency)
+        .unwrap_or_default()
+}
@@ -0,0 +1,103 @@
+//! Defines the runtime abstraction for resolving the values of the secrets that builds and deployments need.
+//!
+//! Applications declare the names of the secrets they need in their configuration. The values are only
+//! resolved with the [SecretProvider](trait.SecretProvider.html) of the runtime when a build or deployment
+//! is started and are handed to the [BuildSource](../build/trait.BuildSource.html) or
+//! [DeployInfrastructure](../deploy/trait.DeployInfrastructure.html) directly. They are never stored
+//! on the actions of a pipeline and thus never serialized with it. Every value that is resolved is also
+//! [registered](../redact/fn.register.html) so that it is scrubbed from logs. Since registrations only last
+//! as long as the process, the secrets are resolved again whenever a build or deployment is polled, which
+//! may happen in a process other than the one that started it.
+use crate::redact::{self, Secret};
+use crate::Application;
+
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+
+/// Defines an error that occurred when attempting to resolve the value of a secret.
+#[derive(Debug, Clone, Fail)]
+pub enum SecretError {
+    /// When the provider does not have a secret by the name requested, this variant should be used.
+    #[fail(display = "The secret {} could not be found.", name)]
+    Missing {
+        /// The name of the secret.
+        name: String,
+    },
+
+    /// When the cause does not fit any of the known patterns defined else where in the enum,
+    /// this can be returned. It takes an additional string and passed that information as part
+    /// of the error  context.
+    #[fail(display = "Unknown secret error occurred: {}", info)]
+    Other {
+        /// Additional information to pass back.
+        info: String,
+    },
+}
+
+/// Defines a run time abstraction for a store of secret values such as api tokens and passwords.
+///
+/// Implementations must take care to never log or otherwise expose the values they return.
+pub trait SecretProvider: Debug {
+    /// Gets the value of the named secret for the application.
+    fn get_secret(&self, app: &Application, name: &str) -> Result<String, SecretError>;
+}
+
+/// A [SecretProvider](trait.SecretProvider.html) without any secrets. Use this for runtimes
+/// whose applications do not declare any secrets.
+#[derive(Debug)]
+pub struct NoSecrets;
+
+impl SecretProvider for NoSecrets {
+    fn get_secret(&self, _app: &Application, name: &str) -> Result<String, SecretError> {
+        Err(SecretError::Missing {
+            name: name.to_string(),
+        })
+    }
+}
+
+/// The resolved values of a set of secrets. This type intentionally does not implement
+/// `Serialize` and its `Debug` implementation only shows the names of the secrets.
+#[derive(Clone, Default)]
+pub struct Secrets {
+    values: HashMap<String, Secret<String>>,
+}
+
+impl Secrets {
+    /// Resolves the values of all of the named secrets for the application.
+    pub fn resolve(
+        provider: &dyn SecretProvider,
+        app: &Application,
+        names: &[String],
+    ) -> Result<Self, SecretError> {
+        let mut values = HashMap::new();
+        for name in names {
+            let value = provider.get_secret(app, name)?;
+            redact::register(&value);
+            values.insert(name.clone(), Secret::new(value));
+        }
+        Ok(Self { values })
+    }
+
+    /// Gets the value of a secret by its name.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(|v| v.expose().as_str())
+    }
+
+    /// Iterates over the names and values of the secrets.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.values
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.expose().as_str()))
+    }
+
+    /// Determines if there are no secrets.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl Debug for Secrets {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.values.keys()).finish()
+    }
+}
@@ -1,14 +1,17 @@
-//! Defines a high level mechanism for
+//! Defines a high level mechanism for driving a pipeline to completion.
+use crate::audit::{AuditEntry, Transition};
 use crate::pipelining::{ActionResult, Perform, Pipeline};
 use crate::runtime::RuntimeContext;
+use crate::scheduler::Admission;
 
 use failure::Error;
-use log::info;
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
 
 const START_WAIT_TIME: u64 = 10;
 
-/// TODO
+/// Works through the actions of a [Pipeline](../pipelining/struct.Pipeline.html) one at a time by
+/// starting each action and polling it until it is done.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StateMachine {
     pipeline: Pipeline,
@@ -17,24 +20,33 @@ pub struct StateMachine {
 }
 
 impl StateMachine {
-    /// Creates a new state machine from a pipeline object.
+    /// Creates a new state machine from a pipeline object. The first action of the pipeline is
+    /// started by the first [tick](#method.tick_machine_state).
     pub fn new(pipeline: Pipeline) -> Self {
-        let mut result = Self {
+        Self {
             pipeline,
             current_action: None,
             recommended_wait: START_WAIT_TIME,
-        };
-        result.current_action = result.pipeline.pop_next_action();
-        result
+        }
     }
 
     /// Performs one cycle of the state machine by polling the current action's state. If the current
     /// action is completed, the result is evaluated and any new works is added to the pipeline to
-    /// work on.
+    /// work on. Returns whether or not the pipeline still has work to do.
     pub fn tick_machine_state(&mut self, context: &RuntimeContext) -> Result<bool, Error> {
-        // Get the current action and see if it is done.
+        self.tick_admitted(context, &|_| Admission::Start)
+    }
+
+    /// Performs one cycle of the state machine like [tick_machine_state](#method.tick_machine_state), but
+    /// only starts the next action when admit says so.
+    pub(crate) fn tick_admitted(
+        &mut self,
+        context: &RuntimeContext,
+        admit: &dyn Fn(&dyn Perform) -> Admission,
+    ) -> Result<bool, Error> {
+        // When no action is in progress, the next one is started.
         if self.current_action.is_none() {
-            return Ok(true);
+            return self.start_next_action(context, admit);
         }
 
         // Get the current state of the action.
@@ -48,12 +60,19 @@ impl StateMachine {
         if !is_done {
             info!("Action Pending. No state transition. {:?}", action);
             self.recommended_wait = self.recommended_wait * 3 / 2;
-            return Ok(false);
+            return Ok(true);
         }
 
         // If the current action is done, we need to pop the next action and start it.
         // If the pipeline is done, we can return that we are done.
         let result = action.get_result(context);
+        audit(
+            &self.pipeline,
+            &**action,
+            Transition::Completed,
+            Some(&result),
+            context,
+        );
         let should_cancel_pending_actions = match result {
             ActionResult::Success => false,
             ActionResult::FailedAllow => false,
@@ -62,26 +81,153 @@ impl StateMachine {
         };
         if should_cancel_pending_actions {
             info!("Action cancelled pipeline. {:?}", action);
-            self.pipeline.cancel()
+            cancel_pending_actions(&mut self.pipeline, context);
         }
 
-        // If there is new work, we will push these items onto the pipeline.
+        // If there is new work, we will push these items onto the pipeline in the order they were given.
         if let Some(actions) = action.get_new_work(context) {
-            for action in actions {
+            for action in actions.into_iter().rev() {
                 info!("Pushing new immediate action {:?}", action);
                 self.pipeline.add_immediate_action(action);
             }
         }
+        let action = self.current_action.take().unwrap();
+        self.pipeline.complete_action(action, result);
 
         // We will dequeue the next action and start it (if there is any).
-        self.current_action = self.pipeline.pop_next_action();
-        let has_remaining_actions = self.current_action.is_some();
-        if has_remaining_actions {
-            let action = self.current_action.as_mut().unwrap();
-            self.recommended_wait = START_WAIT_TIME;
-            info!("Starting new action {:?}", action);
-            action.start(context)?;
+        self.start_next_action(context, admit)
+    }
+
+    /// Pops the next action off of the pipeline and starts it, if admit says so. Returns whether or not
+    /// the pipeline still has work to do. When the action fails to start, it is completed as failed and
+    /// the pending actions are canceled, all of which is audited, before the error is returned.
+    fn start_next_action(
+        &mut self,
+        context: &RuntimeContext,
+        admit: &dyn Fn(&dyn Perform) -> Admission,
+    ) -> Result<bool, Error> {
+        let admission = self.pipeline.pending_actions().next().map(admit);
+        match admission {
+            Some(Admission::Wait) => {
+                self.recommended_wait = START_WAIT_TIME;
+                return Ok(true);
+            }
+            Some(Admission::Cancel) => {
+                self.cancel(context);
+                return Ok(false);
+            }
+            _ => {}
+        }
+        let mut action = match self.pipeline.pop_next_action() {
+            Some(action) => action,
+            None => return Ok(false),
+        };
+        self.recommended_wait = START_WAIT_TIME;
+        info!("Starting new action {:?}", action);
+        if let Err(e) = action.start(context) {
+            // The action never got going, so it is completed as failed here and the error leaves no
+            // action in progress for abort to complete again.
+            warn!("Action {:?} failed to start: {}", action, e);
+            audit(
+                &self.pipeline,
+                &*action,
+                Transition::Completed,
+                Some(&ActionResult::Failed),
+                context,
+            );
+            cancel_pending_actions(&mut self.pipeline, context);
+            self.pipeline.complete_action(action, ActionResult::Failed);
+            return Err(e);
+        }
+        audit(&self.pipeline, &*action, Transition::Started, None, context);
+        self.current_action = Some(action);
+        Ok(true)
+    }
+
+    /// Gives up on the pipeline after its current action returned an error. The action is completed as failed
+    /// and every action that has yet to start is canceled, all of which is audited.
+    pub fn abort(&mut self, context: &RuntimeContext) {
+        cancel_pending_actions(&mut self.pipeline, context);
+        if let Some(action) = self.current_action.take() {
+            audit(
+                &self.pipeline,
+                &*action,
+                Transition::Completed,
+                Some(&ActionResult::Failed),
+                context,
+            );
+            self.pipeline.complete_action(action, ActionResult::Failed);
+        }
+    }
+
+    /// Cancels every action of the pipeline that has yet to start. The action in progress, if any, is
+    /// left to complete.
+    pub fn cancel(&mut self, context: &RuntimeContext) {
+        cancel_pending_actions(&mut self.pipeline, context);
+    }
+
+    /// Gets the action in progress, if any.
+    pub fn current_action(&self) -> Option<&dyn Perform> {
+        self.current_action.as_deref()
+    }
+
+    /// Gets the number of seconds to wait before the next tick.
+    pub fn recommended_wait(&self) -> u64 {
+        self.recommended_wait
+    }
+
+    /// Gets the pipeline the machine is working through.
+    pub fn pipeline(&self) -> &Pipeline {
+        &self.pipeline
+    }
+}
+
+/// Cancels the pending actions of the pipeline and audits that they were canceled.
+fn cancel_pending_actions(pipeline: &mut Pipeline, context: &RuntimeContext) {
+    let already_completed = pipeline.completed_actions().count();
+    pipeline.cancel();
+    for (canceled, result) in pipeline.completed_actions().skip(already_completed) {
+        audit(
+            pipeline,
+            canceled,
+            Transition::Completed,
+            Some(result),
+            context,
+        );
+    }
+}
+
+/// Appends the transition of the action to the audit log of the runtime. The pipeline carries on
+/// when the log cannot be appended to, so failures are only logged.
+fn audit(
+    pipeline: &Pipeline,
+    action: &dyn Perform,
+    transition: Transition,
+    result: Option<&ActionResult>,
+    context: &RuntimeContext,
+) {
+    let details = action.details();
+    let app = match details.application(context) {
+        Some(app) => app,
+        None => {
+            warn!(
+                "Could not find the application of action {:?} to audit",
+                action
+            );
+            return;
         }
-        Ok(has_remaining_actions)
+    };
+    let mut entry = AuditEntry::new(
+        app,
+        action.typetag_name(),
+        details,
+        transition,
+        pipeline.origin(),
+    );
+    if let Some(result) = result {
+        entry = entry.with_result(result.clone());
+    }
+    if let Err(e) = context.audit.append(&entry) {
+        warn!("Could not append {:?} to the audit log: {}", entry, e);
     }
 }
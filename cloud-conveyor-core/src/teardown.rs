@@ -1,4 +1,5 @@
 //! Defines the runtime abstraction for tearing down infrastructure and reporting successes and failures when doing so.
+use crate::credentials::CredentialError;
 use crate::pipelining::Teardown;
 use crate::runtime::RuntimeContext;
 use serde::{Deserialize, Serialize};
@@ -15,6 +16,15 @@ pub enum TeardownPollError {
     #[fail(display = "Failed to get credentials or the credentials were invalid.")]
     Credentials,
 
+    /// When the [CredentialProvider](../credentials/trait.CredentialProvider.html) of the runtime could not
+    /// provide the credentials of the account, this variant carries the reason.
+    #[fail(display = "Could not obtain credentials: {}", cause)]
+    CredentialsUnavailable {
+        /// Why the credentials could not be obtained.
+        #[fail(cause)]
+        cause: CredentialError,
+    },
+
     /// When a stack cannot be deleted entirely then this should be used.
     #[fail(display = "When a stack cannot be deleted entirely and should be.")]
     CannotDelete,
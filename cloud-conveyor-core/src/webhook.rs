@@ -11,24 +11,49 @@
 //!
 //!  2.) When a tag is pushed and the application has a tag trigger who's pattern matches the tag
 //!  that was pushed, we will want to build and deploy the code to the environment list
-//! that exists in the aforementioned trigger.
+//! that exists in the aforementioned trigger. Tags of prereleases can be routed to stages of their own,
+//! and tags older than the version that a stage runs can be refused. A stage is skipped when the tag changes
+//! no relevant files compared to what the stage runs.
 //!
 //!  3.) When a branch is merged, and the application has a merge trigger who's branch name pattern
 //! matches the name of the branch merged into (and optionally the same for the source branch) then
 //! we will want to build and deploy the code to the environment list  that exists in the aforementioned trigger.
+//!
+//!  4.) When a schedule trigger comes due, the [schedule](../schedule/index.html) module produces an event for
+//! it that is processed here as well, which rebuilds the head of the branch of the trigger and deploys it.
 
 // DEV NOTE: There are many locations where we "just clone" stuff. This _seems_ like it has to be a
 // necessary evil. We have to have a lot of owned information in structures because many of the types in the
 // core library implement serialize and deserialize for downstream crates.
 
+use crate::audit::Origin;
+use crate::history::DeploymentHistory;
 use crate::pipelining::{Approval, Build, Deploy, Pipeline, Teardown};
 use crate::runtime::RuntimeContext;
-use crate::{Application, Stage, Trigger};
-use log::info;
+use crate::schedule::ResolveBranches;
+use crate::{Application, PathFilter, PrFilter, PrStages, Stage, Trigger};
+use failure::Error;
+use log::{info, warn};
 use regex::Regex;
+use semver::Version;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt::Debug;
+
+/// The special pattern of [Tag](../enum.Trigger.html#variant.Tag) triggers that matches semantic versions.
+pub(crate) const SEMVER_PATTERN: &str = "semver";
+
+/// Parses a tag that is a semantic version, optionally prefixed with a `v`; e.g v1.2.3 or 1.2.3-rc.1.
+/// The whole tag has to be the version, so `foo1.2bar` or `1.2` are not versions.
+pub fn parse_version_tag(tag: &str) -> Option<Version> {
+    Version::parse(tag.strip_prefix('v').unwrap_or(tag)).ok()
+}
 
-const SEMVER_REGEX: &str = "(0|(?:[1-9]\\d*))(?:\\.(0|(?:[1-9]\\d*))(?:\\.(0|(?:[1-9]\\d*)))?(?:\\-([\\w][\\w\\.\\-_]*))?)?";
+/// Determines if the git ref is a full sha of a commit, rather than a branch, a tag or a short sha,
+/// which can all point at other commits later on.
+pub fn is_full_sha(git_ref: &str) -> bool {
+    git_ref.len() == 40 && git_ref.chars().all(|c| c.is_ascii_hexdigit())
+}
 
 /// Defines a simple object that roughly scaffolds some of the information in an
 /// HTTP Post request. This module assumes that the underlying hook system for
@@ -43,7 +68,7 @@ pub struct WebhookRequest {
 
 /// Defines a standard form of event from the version controls system that occurs against the remote repository.
 /// This enum is certainly not a
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub enum VcsEvent {
     /// Indicates when one branch is merged into another.
     Merge {
@@ -53,37 +78,140 @@ pub enum VcsEvent {
         from_branch: String,
         /// The new sha at the current branch.
         sha: String,
+        /// The files that the event changed, relative to the root of the repository, when they are known.
+        #[serde(default)]
+        changed_files: Option<Vec<String>>,
     },
     /// Indicates when a new tag was pushed to the repository.
     TagPush {
         /// The tag name to push.
         tag: String,
+        /// The sha of the commit that the tag points at.
+        sha: String,
+        /// The files that the event changed, relative to the root of the repository, when they are known.
+        #[serde(default)]
+        changed_files: Option<Vec<String>>,
+    },
+    /// Indicates that a branch was pushed to.
+    BranchPush {
+        /// The name of the branch.
+        branch: String,
+        /// The new sha at the head of the branch.
+        sha: String,
+        /// The files that the event changed, relative to the root of the repository, when they are known.
+        #[serde(default)]
+        changed_files: Option<Vec<String>>,
+    },
+    /// Indicates that a branch was deleted.
+    BranchDelete {
+        /// The name of the branch.
+        branch: String,
     },
     /// Indicates that a pull request was created.
     PullRequestCreate {
         /// The name of the branch that has the code to be merged.
         source_branch: String,
+        /// The name of the branch that the pull request is to be merged into, when it is known.
+        #[serde(default)]
+        target_branch: Option<String>,
+        /// The labels of the pull request.
+        #[serde(default)]
+        labels: Vec<String>,
+        /// Whether or not the pull request is a draft.
+        #[serde(default)]
+        draft: bool,
         /// The number of the pr being created.
         number: u32,
         /// The sha to deploy.
         sha: String,
+        /// The files that the event changed, relative to the root of the repository, when they are known.
+        #[serde(default)]
+        changed_files: Option<Vec<String>>,
     },
-    /// Indicates that new commits have been pushed to an existing PR.
+    /// Indicates that new commits have been pushed to an existing PR, or that its labels or draft state changed.
     PullRequestUpdate {
         /// The name of the branch that has the code to be merged.
         source_branch: String,
+        /// The name of the branch that the pull request is to be merged into, when it is known.
+        #[serde(default)]
+        target_branch: Option<String>,
+        /// The labels of the pull request.
+        #[serde(default)]
+        labels: Vec<String>,
+        /// Whether or not the pull request is a draft.
+        #[serde(default)]
+        draft: bool,
         /// The number of the pr being created.
         number: u32,
         /// The sha to deploy.
         sha: String,
+        /// The files that the event changed, relative to the root of the repository, when they are known.
+        #[serde(default)]
+        changed_files: Option<Vec<String>>,
+        /// What changed about the pull request.
+        #[serde(default)]
+        change: PrChange,
     },
     /// Indicates that a pull request was completed.
     PullRequestComplete {
         /// The number of the pr being completed.
         number: u32,
+        /// The name of the branch that has the code to be merged.
+        #[serde(default)]
+        source_branch: String,
         /// Wether or not the pr was merged to the branch it was intended for.
         merged: bool,
     },
+    /// Indicates that a person asked for a pipeline by hand, see
+    /// [handle_manual_request](fn.handle_manual_request.html).
+    Manual {
+        /// The git ref to build and deploy.
+        git_ref: String,
+        /// The names of the stages to deploy to.
+        stages: Vec<String>,
+        /// The person who asked for the pipeline.
+        requested_by: String,
+    },
+    /// Indicates that a schedule trigger came due. Unlike the other events, this one does not come from
+    /// the version control system but from the [schedule](../schedule/index.html) module.
+    Schedule {
+        /// The cron expression of the trigger that came due.
+        cron: String,
+        /// The branch that is built.
+        branch: String,
+        /// The sha at the head of the branch.
+        sha: String,
+    },
+}
+
+/// What changed about a pull request when it was [updated](enum.VcsEvent.html#variant.PullRequestUpdate).
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub enum PrChange {
+    /// New commits were pushed to the source branch.
+    #[default]
+    Push,
+    /// The label with the name was added.
+    Labeled(String),
+    /// The label with the name was removed.
+    Unlabeled(String),
+    /// The draft was marked as ready for review.
+    ReadyForReview,
+    /// The pull request was converted to a draft.
+    ConvertedToDraft,
+}
+
+impl VcsEvent {
+    /// Gets the files that the event changed, if the event carries them.
+    pub fn changed_files(&self) -> Option<&[String]> {
+        match self {
+            VcsEvent::Merge { changed_files, .. }
+            | VcsEvent::TagPush { changed_files, .. }
+            | VcsEvent::BranchPush { changed_files, .. }
+            | VcsEvent::PullRequestCreate { changed_files, .. }
+            | VcsEvent::PullRequestUpdate { changed_files, .. } => changed_files.as_deref(),
+            _ => None,
+        }
+    }
 }
 
 /// Defines a parsed event that came from a web request hook
@@ -94,6 +222,22 @@ pub struct WebhookEvent {
     repo: String,
 }
 
+impl WebhookEvent {
+    /// Creates the event of the application that is built from the repository with the git url.
+    pub(crate) fn new(event: VcsEvent, app: Application, repo: String) -> Self {
+        Self { event, app, repo }
+    }
+}
+
+/// Defines a run time abstraction for finding the files that changed between two commits of a repository.
+/// Tags do not say which files they change, so this is what the path filters of
+/// [Tag](../enum.Trigger.html#variant.Tag) triggers compare them to what the stages run with.
+pub trait CompareCommits: Debug {
+    /// Gets every file that changed between the base and head commits of the repository with the git url,
+    /// relative to the root of the repository.
+    fn changed_files(&self, repo: &str, base: &str, head: &str) -> Result<Vec<String>, Error>;
+}
+
 /// Defines a trait for something that takes [WebhookRequest](struct.WebhookRequest.html) objects and
 /// parses them for any version control events as specified in [VcsEvent](enum.VcsEvent.html). Since the
 /// payloads for various different vcs providers (github, bitbucket, etc.) are different,
@@ -128,6 +272,12 @@ pub trait InterpretWebhooks {
     /// with which defines the git url for the repo.
     fn get_repo<'a>(&self, intermediary: &'a Self::Intermediary) -> &'a str;
 
+    /// Gets the means of finding the files that changed between two commits, if there is one. Tags are not
+    /// filtered on paths without it.
+    fn commits(&self) -> Option<&dyn CompareCommits> {
+        None
+    }
+
     /// The high order function that converts payloads from a webhook to a serialized and standard
     /// form for processing in the rest of the cloud conveyor pipelining code.
     fn interpret_webhook_payload(
@@ -139,16 +289,25 @@ pub trait InterpretWebhooks {
 
         for inter in self.parse_to_intermediary(req) {
             let repo = self.get_repo(&inter);
-            let maybe_app = runtime.load_application_from_repo(repo);
             let vcs_events = self.get_vcs_event(&inter);
 
-            if let Some(app) = maybe_app {
-                for event in vcs_events {
-                    result.push(WebhookEvent {
-                        repo: repo.to_string(),
-                        app: app.clone(),
-                        event,
-                    });
+            // A monorepo has many applications, and every event is processed for each of the
+            // applications that the files it changed affect.
+            for app in runtime.load_applications_from_repo(repo) {
+                for event in &vcs_events {
+                    if !app.is_affected_by(event.changed_files()) {
+                        info!(
+                            "Event {:?} does not affect app {:?}",
+                            event,
+                            app.full_name()
+                        );
+                        continue;
+                    }
+                    result.push(WebhookEvent::new(
+                        event.clone(),
+                        app.clone(),
+                        repo.to_string(),
+                    ));
                 }
             }
         }
@@ -160,10 +319,30 @@ pub trait InterpretWebhooks {
 fn add_build_and_deploy_stages(
     pipeline: Option<Pipeline>,
     git_ref: &str,
+    branch: Option<&str>,
     deploy_stages: Vec<Stage>,
     event: &mut WebhookEvent,
 ) -> Pipeline {
-    let build_action = Build::new(event.repo.clone(), git_ref.to_string());
+    let tag = match &event.event {
+        VcsEvent::TagPush { tag, .. } => Some(tag.clone()),
+        _ => None,
+    };
+    let build_action = Build::new(git_ref.to_string(), event.repo.clone())
+        .with_branch(branch.map(String::from))
+        .with_app(Some(event.app.full_name()))
+        .with_path(event.app.path.clone())
+        .with_secrets(event.app.build_secrets.clone())
+        // Scheduled runs exist to rebuild the head of a branch, even when it has not changed. The
+        // artifacts of a git ref that can point at other commits, which manual requests can ask for,
+        // might be of another commit than the one the ref points at now.
+        .with_force_rebuild(
+            event.app.force_rebuild
+                || match &event.event {
+                    VcsEvent::Schedule { .. } => true,
+                    VcsEvent::Manual { git_ref, .. } => !is_full_sha(git_ref),
+                    _ => false,
+                },
+        );
     info!(
         "Pushing build action for  for sha {:?} with action {:?} ",
         git_ref, build_action
@@ -180,6 +359,9 @@ fn add_build_and_deploy_stages(
                 git_ref: git_ref.to_string(),
                 app_name: event.app.full_name(),
                 stage_name: stage.name.clone(),
+                approved_by: None,
+                rejected_by: None,
+                request_id: None,
             };
             info!(
                 "Pushing approval required  for stage {:?} with action {:?} ",
@@ -188,7 +370,9 @@ fn add_build_and_deploy_stages(
             new_pipeline = new_pipeline.add_action(Box::new(approval_action));
         }
 
-        let deploy_action = Deploy::new(stage.clone(), event.repo.clone(), git_ref.to_string());
+        let deploy_action = Deploy::new(stage.clone(), event.repo.clone(), git_ref.to_string())
+            .with_app(Some(event.app.full_name()))
+            .with_tag(tag.clone());
         info!(
             "Pushing deploy  action for stage {:?} with action {:?}",
             stage, deploy_action
@@ -199,26 +383,164 @@ fn add_build_and_deploy_stages(
     new_pipeline
 }
 
+/// What the path filter of a tag trigger needs to find the files that a tag changes for each stage.
+struct TagFilter<'a> {
+    filter: PathFilter,
+    history: Option<&'a dyn DeploymentHistory>,
+    commits: Option<&'a dyn CompareCommits>,
+}
+
+impl TagFilter<'_> {
+    /// Determines if the sha of the tag changes files that are relevant to the stage, compared to the sha that
+    /// the stage runs. The tag is relevant when those files are not known.
+    fn is_relevant(&self, event: &WebhookEvent, stage: &str, sha: &str) -> bool {
+        if self.filter == PathFilter::default() || event.event.changed_files().is_some() {
+            return self.filter.is_relevant(event.event.changed_files());
+        }
+        let (history, commits) = match (self.history, self.commits) {
+            (Some(history), Some(commits)) => (history, commits),
+            _ => return true,
+        };
+        let deployed = match history.last_successful(&event.app, stage) {
+            Ok(Some(record)) => record.git_ref,
+            Ok(None) => return true,
+            Err(e) => {
+                warn!(
+                    "Could not find out what stage {:?} of app {:?} runs: {}",
+                    stage,
+                    event.app.full_name(),
+                    e
+                );
+                return true;
+            }
+        };
+        match commits.changed_files(&event.repo, &deployed, sha) {
+            Ok(files) => self.filter.is_relevant(Some(&files)),
+            Err(e) => {
+                warn!(
+                    "Could not get the files that changed between {:?} and {:?}: {}",
+                    deployed, sha, e
+                );
+                true
+            }
+        }
+    }
+}
+
 fn handle_tag_trigger(
     pipeline: Option<Pipeline>,
     event: &mut WebhookEvent,
     pattern: String,
     stages: Vec<String>,
+    prerelease: Option<Vec<String>>,
+    ordered_by: Option<&dyn DeploymentHistory>,
+    tag_filter: TagFilter<'_>,
 ) -> Option<Pipeline> {
     match event.event.clone() {
-        VcsEvent::TagPush { tag } => {
-            let pattern = if pattern == "semver" {
-                SEMVER_REGEX
+        VcsEvent::TagPush { tag, sha, .. } => {
+            let version = parse_version_tag(&tag);
+            let matches = if pattern == SEMVER_PATTERN {
+                version.is_some()
             } else {
-                &pattern
+                Regex::new(&pattern)
+                    .expect("The tag pattern is not valid. Validate the config first.")
+                    .is_match(&tag)
             };
-
-            let re = Regex::new(pattern).unwrap();
-            if !re.is_match(&tag) {
+            if !matches {
                 info!("Tag {:?} does not follow the pattern  {:?}", tag, pattern);
                 return pipeline;
             }
 
+            let stages = match (&version, prerelease) {
+                (Some(version), Some(prerelease)) if !version.pre.is_empty() => prerelease,
+                _ => stages,
+            };
+            let (deploy_stages, refused): (Vec<Stage>, Vec<Stage>) = event
+                .app
+                .stages
+                .iter()
+                .filter(|s| stages.contains(&s.name))
+                .cloned()
+                // Tags of ordered triggers that are older than what a stage runs are not deployed to it.
+                .partition(|s| match (&version, ordered_by) {
+                    (Some(version), Some(history)) => {
+                        !is_older_than(&tag, version, &event.app, &s.name, history)
+                    }
+                    _ => true,
+                });
+            let (deploy_stages, irrelevant): (Vec<Stage>, Vec<Stage>) = deploy_stages
+                .into_iter()
+                .partition(|s| tag_filter.is_relevant(event, &s.name, &sha));
+            for stage in &irrelevant {
+                info!(
+                    "Tag {:?} changes no relevant files for stage {:?}",
+                    tag, stage.name
+                );
+            }
+            if deploy_stages.is_empty() && !(refused.is_empty() && irrelevant.is_empty()) {
+                return pipeline;
+            }
+
+            // The sha is built rather than the tag, so the build of a merge of the same commit is reused.
+            add_build_and_deploy_stages(pipeline, &sha, None, deploy_stages, event).into()
+        }
+        _ => pipeline,
+    }
+}
+
+/// Determines if the version of a tag is older than the version that the stage runs, going by the last
+/// successful deployment to it in the history. This is only known when what the stage runs was deployed from
+/// a tag that is a semantic version as well. Deployments that were recorded without their tag were deployed
+/// by the tag itself.
+fn is_older_than(
+    tag: &str,
+    version: &Version,
+    app: &Application,
+    stage: &str,
+    history: &dyn DeploymentHistory,
+) -> bool {
+    let deployed = match history.last_successful(app, stage) {
+        Ok(Some(record)) => record.tag.unwrap_or(record.git_ref),
+        Ok(None) => return false,
+        Err(e) => {
+            warn!(
+                "Could not find out what stage {:?} of app {:?} runs: {}",
+                stage,
+                app.full_name(),
+                e
+            );
+            return false;
+        }
+    };
+    match parse_version_tag(&deployed) {
+        Some(deployed) if deployed > *version => {
+            warn!(
+                "Not deploying tag {:?} to stage {:?}, which runs the greater version {}",
+                tag, stage, deployed
+            );
+            true
+        }
+        _ => false,
+    }
+}
+
+fn handle_schedule_trigger(
+    pipeline: Option<Pipeline>,
+    event: &mut WebhookEvent,
+    cron: String,
+    branch: String,
+    stages: Vec<String>,
+) -> Option<Pipeline> {
+    match event.event.clone() {
+        VcsEvent::Schedule {
+            cron: due_cron,
+            branch: due_branch,
+            sha,
+        } => {
+            if due_cron != cron || due_branch != branch {
+                return pipeline;
+            }
+
             let deploy_stages: Vec<Stage> = event
                 .app
                 .stages
@@ -227,7 +549,36 @@ fn handle_tag_trigger(
                 .cloned()
                 .collect();
 
-            add_build_and_deploy_stages(pipeline, &tag, deploy_stages, event).into()
+            add_build_and_deploy_stages(pipeline, &sha, Some(&branch), deploy_stages, event).into()
+        }
+        _ => pipeline,
+    }
+}
+
+fn handle_manual_trigger(
+    pipeline: Option<Pipeline>,
+    event: &mut WebhookEvent,
+    allowed_stages: Vec<String>,
+) -> Option<Pipeline> {
+    match event.event.clone() {
+        VcsEvent::Manual {
+            git_ref, stages, ..
+        } => {
+            // Only the stages that the triggers allow are deployed, once each, in the order they were asked for.
+            let mut asked: Vec<&String> = Vec::new();
+            let deploy_stages: Vec<Stage> = stages
+                .iter()
+                .filter(|name| allowed_stages.contains(name))
+                .filter(|name| {
+                    let first = !asked.contains(name);
+                    asked.push(name);
+                    first
+                })
+                .filter_map(|name| event.app.stages.iter().find(|s| s.name == *name))
+                .cloned()
+                .collect();
+
+            add_build_and_deploy_stages(pipeline, &git_ref, None, deploy_stages, event).into()
         }
         _ => pipeline,
     }
@@ -239,17 +590,19 @@ fn handle_merge_trigger(
     to_regex: String,
     from_regex: Option<String>,
     stages: Vec<String>,
+    filter: PathFilter,
 ) -> Option<Pipeline> {
     match event.event.clone() {
         VcsEvent::Merge {
             to_branch,
             from_branch,
             sha,
+            ..
         } => {
             // If the merge is to a branch that matches the to_regex, we are good.
-            // If not, we can abandon the version. We are going to consider it an
-            // invariant that all regular expressions will compile. So this unwrap should be okay.
-            let regex = Regex::new(&to_regex).unwrap();
+            // If not, we can abandon the version.
+            let regex = Regex::new(&to_regex)
+                .expect("The branch pattern is not valid. Validate the config first.");
             if !regex.is_match(&to_branch) {
                 info!(
                     "Branch {:?} does not match pattern {:?}",
@@ -260,7 +613,8 @@ fn handle_merge_trigger(
 
             // If the trigger has a match regex use that or match to anything.
             // If the match is not a success, keep the current pipeline.
-            let regex = Regex::new(&from_regex.unwrap_or_else(|| String::from(".*"))).unwrap();
+            let regex = Regex::new(&from_regex.unwrap_or_else(|| String::from(".*")))
+                .expect("The branch pattern is not valid. Validate the config first.");
             if !regex.is_match(&from_branch) {
                 info!(
                     "Branch {:?} does not match pattern {:?}",
@@ -269,6 +623,12 @@ fn handle_merge_trigger(
                 return pipeline;
             }
 
+            // If the merge does not change any of the files the trigger cares about, skip it.
+            if !filter.is_relevant(event.event.changed_files()) {
+                info!("Merge of {:?} changes no relevant files", sha);
+                return pipeline;
+            }
+
             // Now we need to find the stages in the app by the names.
             let deploy_stages: Vec<Stage> = event
                 .app
@@ -282,112 +642,317 @@ fn handle_merge_trigger(
             // a deploy job. The deploy jobs need be in the same order
             // as the vec for the stage names. That is the pattern for pipelining
             // envs.
-            add_build_and_deploy_stages(pipeline, &sha, deploy_stages, event).into()
+            add_build_and_deploy_stages(pipeline, &sha, Some(&to_branch), deploy_stages, event)
+                .into()
         }
         _ => pipeline,
     }
 }
 
+fn handle_branch_trigger(
+    pipeline: Option<Pipeline>,
+    event: &mut WebhookEvent,
+    pattern: String,
+    should_deploy: bool,
+    account: Option<String>,
+    filter: PathFilter,
+) -> Option<Pipeline> {
+    let (branch, sha) = match event.event.clone() {
+        VcsEvent::BranchPush { branch, sha, .. } => (branch, Some(sha)),
+        VcsEvent::BranchDelete { branch } => (branch, None),
+        _ => return pipeline,
+    };
+
+    let regex =
+        Regex::new(&pattern).expect("The branch pattern is not valid. Validate the config first.");
+    if !regex.is_match(&branch) {
+        info!("Branch {:?} does not match pattern {:?}", branch, pattern);
+        return pipeline;
+    }
+
+    // The stage of a branch always has the same name, so the first push creates it and the
+    // pushes after that update it.
+    let stage = if should_deploy {
+        let stage = Stage::for_branch(&event.app, account.as_deref(), &branch);
+        if stage.is_none() {
+            warn!(
+                "Not deploying branch {:?} since the account of its stage does not exist",
+                branch
+            );
+        }
+        stage
+    } else {
+        None
+    };
+
+    match sha {
+        Some(sha) => {
+            // If the push does not change any of the files the trigger cares about, skip it.
+            if !filter.is_relevant(event.event.changed_files()) {
+                info!("Push of {:?} changes no relevant files", sha);
+                return pipeline;
+            }
+            info!(
+                "Building branch {:?} with deploy {:?}",
+                branch, should_deploy
+            );
+            let stages = stage.into_iter().collect();
+            add_build_and_deploy_stages(pipeline, &sha, Some(&branch), stages, event).into()
+        }
+        // When the branch is deleted, its stage is "undeployed" from the appropriate account.
+        None => match stage {
+            Some(stage) => {
+                info!("Tearing down the stage of deleted branch {:?}", branch);
+                let teardown =
+                    Teardown::new(stage, event.repo.clone()).with_app(Some(event.app.full_name()));
+                pipeline
+                    .unwrap_or_default()
+                    .add_action(Box::new(teardown))
+                    .into()
+            }
+            None => pipeline,
+        },
+    }
+}
+
 fn handle_pr_trigger(
     pipeline: Option<Pipeline>,
     should_deploy: bool,
+    pr_filter: PrFilter,
+    pr_stages: PrStages,
+    filter: PathFilter,
     event: &mut WebhookEvent,
 ) -> Option<Pipeline> {
+    // Changes to irrelevant files are neither built nor deployed, but a PR is always torn down.
+    if !filter.is_relevant(event.event.changed_files()) {
+        info!("PR event {:?} changes no relevant files", event.event);
+        return pipeline;
+    }
     match event.event.clone() {
-        // When a pull request is created we need to create a build job. So we will create or
-        // populate the pipeline with a build step.
-        VcsEvent::PullRequestCreate { number, sha, .. } => {
-            info!("Creating PR {:?} with deploy {:?}", number, should_deploy);
-
-            // If wes should deploy, we need a new stage to be created.
-            let stages = if should_deploy {
-                let new_stage = Stage::from_pr_number(&event.app, number);
-                event.app.add_stage(new_stage.clone());
-                vec![new_stage]
+        // When a pull request is created or updated we need to create a build job. So we will create or
+        // populate the pipeline with a build step, along with a deploy to the stack of the PR if it should
+        // have one.
+        VcsEvent::PullRequestCreate {
+            number,
+            sha,
+            source_branch,
+            target_branch,
+            labels,
+            draft,
+            ..
+        }
+        | VcsEvent::PullRequestUpdate {
+            number,
+            sha,
+            source_branch,
+            target_branch,
+            labels,
+            draft,
+            ..
+        } => {
+            if !pr_filter.matches_branches(target_branch.as_deref(), &source_branch) {
+                info!(
+                    "PR {:?} from {:?} to {:?} does not match the trigger",
+                    number, source_branch, target_branch
+                );
+                return pipeline;
+            }
+            let change = match &event.event {
+                VcsEvent::PullRequestUpdate { change, .. } => change.clone(),
+                _ => PrChange::Push,
+            };
+            if !pr_filter.cares_about(&change) {
+                info!("Ignoring change {:?} of PR {:?}", change, number);
+                return pipeline;
+            }
+
+            // A PR that stops qualifying for a deployment, since it lost its label or became a draft,
+            // has its stack torn down. Pushes to a PR that does not qualify are only built.
+            let deploy = should_deploy && pr_filter.allows_deploy(&labels, draft);
+            if change != PrChange::Push && !deploy {
+                if !should_deploy {
+                    return pipeline;
+                }
+                return teardown_pr_stage(pipeline, &pr_stages, number, &source_branch, event);
+            }
+            if pr_filter.skips(draft) {
+                info!("Skipping PR {:?} since it is a draft", number);
+                return pipeline;
+            }
+            info!("Building PR {:?} with deploy {:?}", number, deploy);
+
+            // The stage of a PR always has the same name, so the first deployment creates it and the
+            // deployments after that update it.
+            let stages = if deploy {
+                let stage = Stage::for_pr(&event.app, &pr_stages, number, &source_branch);
+                if stage.is_none() {
+                    warn!(
+                        "Not deploying PR {:?} since the account of its stage does not exist",
+                        number
+                    );
+                }
+                stage.into_iter().collect()
             } else {
                 Vec::new()
             };
-            let result = add_build_and_deploy_stages(pipeline, &sha, stages, event);
-            result.into()
+            add_build_and_deploy_stages(pipeline, &sha, Some(&source_branch), stages, event).into()
         }
-        // If the pull request is complete and there is a defined stage, then
+        // If the pull request is complete and its stage was deployed, then
         // we should add an undeploy job to the pipeline.
-        VcsEvent::PullRequestComplete { number, .. } => {
+        VcsEvent::PullRequestComplete {
+            number,
+            source_branch,
+            ..
+        } if should_deploy => {
             info!("Completing PR {:?}", number);
+            teardown_pr_stage(pipeline, &pr_stages, number, &source_branch, event)
+        }
+        _ => pipeline,
+    }
+}
 
-            // Scan for the stage in the application for the PR.
-            let stage = event.app.stages.iter().find(|s| s.is_for_pr(number));
-
-            // If there is a stage, we need to "undeploy" it from the appropriate
-            // account.  We do not need to do any kind of final builds.
-            if let Some(stage) = stage {
-                let teardown = Teardown::new(stage.clone(), event.repo.clone());
-                return pipeline
-                    .unwrap_or_default()
-                    .add_action(Box::new(teardown))
-                    .into();
-            }
+/// Adds a teardown of the stage of the PR to the pipeline. The stage is named after the configuration of the
+/// trigger, like it was when it was deployed.
+fn teardown_pr_stage(
+    pipeline: Option<Pipeline>,
+    pr_stages: &PrStages,
+    number: u32,
+    source_branch: &str,
+    event: &WebhookEvent,
+) -> Option<Pipeline> {
+    // If there is a stage, we need to "undeploy" it from the appropriate
+    // account.  We do not need to do any kind of final builds.
+    match Stage::for_pr(&event.app, pr_stages, number, source_branch) {
+        Some(stage) => {
+            let teardown =
+                Teardown::new(stage, event.repo.clone()).with_app(Some(event.app.full_name()));
             pipeline
+                .unwrap_or_default()
+                .add_action(Box::new(teardown))
+                .into()
         }
-        // If the pull request is updated and there is a defined stage, then
-        // we should add an new build / deploy  job to the pipeline.
-        VcsEvent::PullRequestUpdate { number, sha, .. } => {
-            // Scan for the stage in the application for the PR.
-            info!("Updating PR {:?}", number);
-            let stage = event.app.stages.iter().find(|s| s.is_for_pr(number));
-
-            // Add the build and deploy stages to the pipeline.
-            // Of there is a stage for the pr, then we can copy that and use that
-            // to deploy.
-            add_build_and_deploy_stages(
-                pipeline,
-                &sha,
-                match stage {
-                    Some(s) => vec![s.clone()],
-                    None => Vec::new(),
-                },
-                event,
-            )
-            .into()
+        None => {
+            warn!(
+                "Not tearing PR {:?} down since the account of its stage does not exist",
+                number
+            );
+            pipeline
         }
-        _ => pipeline,
     }
 }
 
-fn event_to_pipeline(event: &mut WebhookEvent) -> Option<Pipeline> {
+/// Converts the event into the pipeline that the triggers of its application ask for. Ordered tag triggers look
+/// up what the stages run in the history, and deploy tags whatever the stages run without one. The path filters
+/// of tag triggers compare tags to what the stages run with the commits, and do not filter without them.
+pub(crate) fn event_to_pipeline(
+    event: &mut WebhookEvent,
+    history: Option<&dyn DeploymentHistory>,
+    commits: Option<&dyn CompareCommits>,
+) -> Option<Pipeline> {
     let mut result = None;
+    let mut manual_stages: Option<Vec<String>> = None;
 
     for trigger in event.app.triggers.clone() {
         match trigger {
-            Trigger::Pr { deploy } => {
+            Trigger::Pr {
+                deploy,
+                pr_filter,
+                pr_stages,
+                filter,
+            } => {
                 info!(
                     "Processing PR Trigger with deploy set to {:?} for app {:?}",
                     deploy,
                     event.app.full_name()
                 );
-                result = handle_pr_trigger(result, deploy, event);
+                result = handle_pr_trigger(result, deploy, pr_filter, pr_stages, filter, event);
             }
-            Trigger::Merge { to, from, stages } => {
+            Trigger::Merge {
+                to,
+                from,
+                stages,
+                filter,
+            } => {
                 info!(
                     "Processing merge trigger from {:?} to {:?} for app {:?}",
                     from,
                     to,
                     event.app.full_name()
                 );
-                result = handle_merge_trigger(result, event, to, from, stages);
+                result = handle_merge_trigger(result, event, to, from, stages, filter);
             }
-            Trigger::Tag { pattern, stages } => {
+            Trigger::Branch {
+                pattern,
+                deploy,
+                account,
+                filter,
+            } => {
+                info!(
+                    "Processing branch trigger with pattern {:?} for app {:?}",
+                    pattern,
+                    event.app.full_name()
+                );
+                result = handle_branch_trigger(result, event, pattern, deploy, account, filter);
+            }
+            Trigger::Tag {
+                pattern,
+                stages,
+                prerelease,
+                ordered,
+                filter,
+            } => {
                 info!(
                     "Processing tag trigger with pattern {:?} for app {:?}",
                     pattern,
                     event.app.full_name()
                 );
-                result = handle_tag_trigger(result, event, pattern, stages);
+                let ordered_by = history.filter(|_| ordered);
+                let tag_filter = TagFilter {
+                    filter,
+                    history,
+                    commits,
+                };
+                result = handle_tag_trigger(
+                    result, event, pattern, stages, prerelease, ordered_by, tag_filter,
+                );
+            }
+            Trigger::Schedule {
+                cron,
+                branch,
+                stages,
+            } => {
+                info!(
+                    "Processing schedule trigger {:?} of branch {:?} for app {:?}",
+                    cron,
+                    branch,
+                    event.app.full_name()
+                );
+                result = handle_schedule_trigger(result, event, cron, branch, stages);
             }
+            // Every manual trigger allows some stages, and a request builds once and deploys once to each
+            // allowed stage it asks for, so the triggers are handled together below.
+            Trigger::Manual { stages } => manual_stages.get_or_insert_with(Vec::new).extend(stages),
+            // Promotions are only ever started by hand.
+            Trigger::Promote { .. } => {}
         }
     }
+    if let Some(stages) = manual_stages {
+        info!(
+            "Processing manual triggers of stages {:?} for app {:?}",
+            stages,
+            event.app.full_name()
+        );
+        result = handle_manual_trigger(result, event, stages);
+    }
 
-    result
+    let origin = match &event.event {
+        VcsEvent::Schedule { .. } => Origin::schedule(event.event.clone()),
+        VcsEvent::Manual { requested_by, .. } => {
+            Origin::manual(requested_by.clone()).with_event(event.event.clone())
+        }
+        _ => Origin::webhook(event.event.clone()),
+    };
+    result.map(|pipeline| pipeline.with_origin(origin))
 }
 
 /// Given a request to a webhook endpoint, that request is passed to the specific
@@ -402,7 +967,101 @@ pub fn handle_web_hook_event<T: InterpretWebhooks>(
     interpreter
         .interpret_webhook_payload(request, runtime)
         .iter_mut()
-        .map(event_to_pipeline)
-        .filter_map(|o| o)
+        .filter_map(|event| {
+            event_to_pipeline(event, Some(&*runtime.history), interpreter.commits())
+        })
         .collect()
 }
+
+/// Defines an error that occurred when a pipeline was asked for by hand.
+#[derive(Debug, Clone, Fail, PartialEq)]
+pub enum ManualRequestError {
+    /// When the application has no stage with one of the names, this variant should be used.
+    #[fail(display = "The app has no stage named {}.", stage)]
+    UnknownStage {
+        /// The name of the stage.
+        stage: String,
+    },
+
+    /// When no manual trigger of the application allows some of the stages to be deployed by hand,
+    /// or the application has no manual trigger at all, this variant should be used.
+    #[fail(display = "The stages {:?} may not be deployed by hand.", stages)]
+    NotAllowed {
+        /// The names of the stages that are not allowed.
+        stages: Vec<String>,
+    },
+}
+
+/// Creates the pipeline that a person asked for by hand, which builds the git ref and deploys it to the stages.
+/// The request is turned into a [Manual](enum.VcsEvent.html#variant.Manual) event and processed against the
+/// triggers of the application like any other event, so the stages get their approvals as usual. Every stage
+/// has to be allowed by a [Manual](../enum.Trigger.html#variant.Manual) trigger of the application.
+///
+/// A git ref that names a branch is resolved to the sha at its head with the branches, if any are given, so the
+/// pipeline builds and records the commit that was asked for even when the branch moves on. Any other ref that is
+/// not a full sha is built again every time, since its earlier builds may be of another commit.
+pub fn handle_manual_request(
+    app: &Application,
+    git_ref: String,
+    stages: Vec<String>,
+    requested_by: String,
+    branches: Option<&dyn ResolveBranches>,
+) -> Result<Pipeline, ManualRequestError> {
+    if let Some(stage) = stages
+        .iter()
+        .find(|name| !app.stages.iter().any(|s| s.name == **name))
+    {
+        return Err(ManualRequestError::UnknownStage {
+            stage: stage.clone(),
+        });
+    }
+    let allowed: Vec<&String> = app
+        .triggers
+        .iter()
+        .filter_map(|trigger| match trigger {
+            Trigger::Manual { stages } => Some(stages),
+            _ => None,
+        })
+        .flatten()
+        .collect();
+    let has_manual_trigger = app
+        .triggers
+        .iter()
+        .any(|trigger| matches!(trigger, Trigger::Manual { .. }));
+    let not_allowed: Vec<String> = stages
+        .iter()
+        .filter(|name| !allowed.contains(name))
+        .cloned()
+        .collect();
+    if !has_manual_trigger || !not_allowed.is_empty() {
+        return Err(ManualRequestError::NotAllowed {
+            stages: not_allowed,
+        });
+    }
+
+    let git_ref = match branches {
+        Some(branches) if !is_full_sha(&git_ref) => {
+            match branches.head(&app.repo_url(), &git_ref) {
+                Ok(sha) => {
+                    info!("Resolved git ref {:?} to sha {:?}", git_ref, sha);
+                    sha
+                }
+                Err(e) => {
+                    warn!(
+                        "Could not resolve git ref {:?}, so it is built again: {}",
+                        git_ref, e
+                    );
+                    git_ref
+                }
+            }
+        }
+        _ => git_ref,
+    };
+    let event = VcsEvent::Manual {
+        git_ref,
+        stages,
+        requested_by,
+    };
+    let mut event = WebhookEvent::new(event, app.clone(), app.repo_url());
+    Ok(event_to_pipeline(&mut event, None, None).unwrap_or_default())
+}
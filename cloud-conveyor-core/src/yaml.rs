@@ -6,8 +6,24 @@ use serde_yaml::{from_reader, to_string};
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::prelude::*;
+use std::io::{Error, ErrorKind};
+use std::path::{Component, Path};
 
-use crate::{Account, Application, ApprovalGroup, Stage, Trigger};
+use crate::build::RetentionPolicy;
+use crate::schedule::{parse_cron, ScheduleError};
+use crate::webhook::SEMVER_PATTERN;
+use crate::{
+    app_stage_prefix, branch_stage_prefix, qualified_stage_name, qualify, stage_name, Account,
+    Application, ApprovalGroup, Concurrency, PathFilter, PrFilter, PrStages, Stage, Trigger,
+};
+use glob::Pattern;
+use regex::Regex;
+
+/// The name of the file that holds the configuration of an application.
+pub const CONFIG_FILE: &str = ".conveyor.yaml";
+
+/// The name of the file that holds the build specification of an application.
+pub const BUILD_SPEC_FILE: &str = ".conveyor-build.yaml";
 
 // TODO: Make this docs way better.
 
@@ -22,11 +38,9 @@ pub struct ApprovalDefinition {
     people: Vec<String>,
 }
 
-impl Into<ApprovalGroup> for ApprovalDefinition {
-    fn into(self) -> ApprovalGroup {
-        ApprovalGroup {
-            people: self.people,
-        }
+impl From<ApprovalDefinition> for ApprovalGroup {
+    fn from(def: ApprovalDefinition) -> Self {
+        Self { people: def.people }
     }
 }
 
@@ -41,6 +55,18 @@ pub struct StageDefinition {
 
     /// The name of the account to deploy with.
     pub account: Option<String>,
+
+    /// The names of the secrets that deployments to the stage need.
+    #[serde(default)]
+    pub secrets: Vec<String>,
+
+    /// Whether or not to roll the stage back to its last successful deployment when a deployment fails.
+    #[serde(default)]
+    pub rollback: bool,
+
+    /// What happens when several pipelines deploy to the stage; queue, cancel or skip.
+    #[serde(default)]
+    pub concurrency: Concurrency,
 }
 
 impl StageDefinition {
@@ -48,29 +74,448 @@ impl StageDefinition {
         &self,
         approvers: &HashMap<String, ApprovalDefinition>,
         accounts: &[Account],
-    ) -> Stage {
+    ) -> Result<Stage, ConfigError> {
         let name = self.name.clone();
         let target = self
             .account
             .clone()
             .unwrap_or_else(|| String::from("default"));
-        let account = accounts.iter().find(|&acc| acc.is_named(target.as_str()));
-        let approval_group = self
-            .approvers
-            .as_ref()
-            .map(|app| &approvers[app])
-            .map(|def| def.clone().into());
-
-        Stage {
+        let account = accounts
+            .iter()
+            .find(|&acc| acc.is_named(target.as_str()))
+            .ok_or_else(|| ConfigError::UnknownAccount {
+                stage: name.clone(),
+                account: target.clone(),
+            })?;
+        let approval_group = match &self.approvers {
+            Some(approvers_name) => Some(
+                approvers
+                    .get(approvers_name)
+                    .ok_or_else(|| ConfigError::UnknownApprovers {
+                        stage: name.clone(),
+                        approvers: approvers_name.clone(),
+                    })?
+                    .clone()
+                    .into(),
+            ),
+            None => None,
+        };
+
+        Ok(Stage {
             name,
             approval_group,
-            account: account
-                .expect("The account was not found for the sage.")
-                .clone(),
+            account: account.clone(),
+            secrets: self.secrets.clone(),
+            rollback: self.rollback,
+            concurrency: self.concurrency,
+            pr: None,
+        })
+    }
+}
+
+/// Defines an error that was found when validating a configuration.
+#[derive(Debug, Clone, Fail, PartialEq)]
+pub enum ConfigError {
+    /// When a stage refers to an account that does not exist, this variant should be used.
+    #[fail(display = "The account {} of stage {} does not exist.", account, stage)]
+    UnknownAccount {
+        /// The name of the stage, the name template of the stages of pull requests or the pattern of the
+        /// branches of the stages of branches.
+        stage: String,
+        /// The name of the account.
+        account: String,
+    },
+
+    /// When a stage refers to a group of approvers that does not exist, this variant should be used.
+    #[fail(
+        display = "The approvers {} of stage {} do not exist.",
+        approvers, stage
+    )]
+    UnknownApprovers {
+        /// The name of the stage.
+        stage: String,
+        /// The name of the group of approvers.
+        approvers: String,
+    },
+
+    /// When pull requests are deployed without an account while there is no default account,
+    /// this variant should be used.
+    #[fail(
+        display = "Pull requests are deployed but no account is set and there is no default account."
+    )]
+    NoPrAccount,
+
+    /// When branches are deployed without an account while there is no default account,
+    /// this variant should be used.
+    #[fail(
+        display = "Branches are deployed but no account is set and there is no default account."
+    )]
+    NoBranchAccount,
+
+    /// When the stages of pull requests are deployed to a region that their account does not have,
+    /// this variant should be used.
+    #[fail(display = "The account {} has no region {}.", account, region)]
+    UnknownRegion {
+        /// The name of the account.
+        account: String,
+        /// The name of the region.
+        region: String,
+    },
+
+    /// When the name template of the stages of pull requests is not valid, this variant should be used.
+    #[fail(
+        display = "The stage name {:?} of pull requests is invalid: {}",
+        template, info
+    )]
+    InvalidPrStageName {
+        /// The name template.
+        template: String,
+        /// Why the template is invalid.
+        info: String,
+    },
+
+    /// When a stage of a pull request of an application could have the same qualified name as another stage
+    /// in its account, this variant should be used.
+    #[fail(
+        display = "The stages of pull requests of {:?} could have the same names as other stages in account {}. Change the template of their names.",
+        apps, account
+    )]
+    ConflictingPrStages {
+        /// The full names of the applications.
+        apps: Vec<String>,
+        /// The name of the account.
+        account: String,
+    },
+
+    /// When a stage of a branch of an application could have the same qualified name as another stage in its
+    /// account, this variant should be used.
+    #[fail(
+        display = "The stages of branches of {:?} could have the same names in account {}. Rename one of the apps.",
+        apps, account
+    )]
+    ConflictingBranchStages {
+        /// The full names of the applications.
+        apps: Vec<String>,
+        /// The name of the account.
+        account: String,
+    },
+
+    /// When two stages of the applications that share an account have the same qualified name, this variant
+    /// should be used.
+    #[fail(
+        display = "Stages of {:?} have the same names in account {}. Rename one of the stages.",
+        apps, account
+    )]
+    ConflictingStages {
+        /// The full names of the applications.
+        apps: Vec<String>,
+        /// The name of the account.
+        account: String,
+    },
+
+    /// When a path filter of a trigger holds a pattern that is not a valid glob, this variant should be used.
+    #[fail(display = "The path pattern {:?} is invalid: {}", pattern, info)]
+    InvalidPathPattern {
+        /// The pattern.
+        pattern: String,
+        /// Why the pattern is invalid.
+        info: String,
+    },
+
+    /// When a trigger holds a pattern of branches or tags that is not a valid regular expression, this variant
+    /// should be used.
+    #[fail(
+        display = "The pattern {:?} is not a valid regular expression: {}",
+        pattern, info
+    )]
+    InvalidPattern {
+        /// The pattern.
+        pattern: String,
+        /// Why the pattern is invalid.
+        info: String,
+    },
+
+    /// When a schedule trigger holds a cron expression that can not be parsed, this variant should be used.
+    #[fail(display = "The cron expression {:?} is invalid: {}", expression, info)]
+    InvalidCron {
+        /// The cron expression.
+        expression: String,
+        /// Why the expression is invalid.
+        info: String,
+    },
+}
+
+/// Validates that the patterns of a path filter are valid globs.
+fn validate_path_filter(filter: &PathFilter) -> Result<(), ConfigError> {
+    for pattern in filter.paths.iter().chain(filter.paths_ignore.iter()) {
+        if let Err(e) = Pattern::new(pattern) {
+            return Err(ConfigError::InvalidPathPattern {
+                pattern: pattern.clone(),
+                info: e.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Validates that a pattern of branches or tags is a valid regular expression.
+fn validate_pattern(pattern: &str) -> Result<(), ConfigError> {
+    match Regex::new(pattern) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(ConfigError::InvalidPattern {
+            pattern: pattern.to_string(),
+            info: e.to_string(),
+        }),
+    }
+}
+
+/// Validates how the stages of pull requests are named and placed.
+fn validate_pr_stages(pr_stages: &PrStages, accounts: &[Account]) -> Result<(), ConfigError> {
+    let template = pr_stages.name_template();
+    let invalid = |info: &str| ConfigError::InvalidPrStageName {
+        template: template.to_string(),
+        info: info.to_string(),
+    };
+    let placeholders = Regex::new(r"\{[^}]*\}").unwrap();
+    for placeholder in placeholders.find_iter(template) {
+        if !["{app}", "{number}", "{branch}"].contains(&placeholder.as_str()) {
+            return Err(invalid(&format!(
+                "{} is not one of {{app}}, {{number}} or {{branch}}",
+                placeholder.as_str()
+            )));
+        }
+    }
+    // Every pull request needs a stage of its own.
+    if !template.contains("{number}") && !template.contains("{branch}") {
+        return Err(invalid("it has to hold {number} or {branch}"));
+    }
+
+    let account = match &pr_stages.account {
+        Some(name) => accounts
+            .iter()
+            .find(|acc| acc.is_named(name))
+            .ok_or_else(|| ConfigError::UnknownAccount {
+                stage: template.to_string(),
+                account: name.clone(),
+            })?,
+        None => accounts
+            .iter()
+            .find(|acc| acc.is_candidate_for_default())
+            .ok_or(ConfigError::NoPrAccount)?,
+    };
+    match pr_stages
+        .regions
+        .iter()
+        .find(|region| !account.regions.contains(region))
+    {
+        Some(region) => Err(ConfigError::UnknownRegion {
+            account: account.name.clone(),
+            region: region.clone(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Validates that the account the stages of branches are deployed to exists.
+fn validate_branch_account(
+    pattern: &str,
+    account: Option<&str>,
+    accounts: &[Account],
+) -> Result<(), ConfigError> {
+    match account {
+        Some(name) if !accounts.iter().any(|acc| acc.is_named(name)) => {
+            Err(ConfigError::UnknownAccount {
+                stage: pattern.to_string(),
+                account: name.to_string(),
+            })
+        }
+        None if !accounts.iter().any(Account::is_candidate_for_default) => {
+            Err(ConfigError::NoBranchAccount)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Validates that no two stages of the applications that share an account can ever have the same qualified name,
+/// see [qualified_stage_name](../fn.qualified_stage_name.html), since the stacks of those stages would then be mixed
+/// up. This covers the static stages of the applications, the names that the templates of the stages of pull
+/// requests can render and the stages of branches.
+pub fn validate_applications(apps: &[Application]) -> Result<(), ConfigError> {
+    let mut seen: Vec<StageNames> = Vec::new();
+    for app in apps {
+        for names in StageNames::of(app) {
+            if names.is_ambiguous {
+                return Err(names.conflict(&names));
+            }
+            let conflict = seen.iter().find(|other| {
+                other.account.id == names.account.id
+                    && !(other.app == names.app
+                        && other.kind == names.kind
+                        && names.kind != StageKind::Static)
+                    && other.overlaps(&names)
+            });
+            if let Some(other) = conflict {
+                return Err(other.conflict(&names));
+            }
+            seen.push(names);
+        }
+    }
+    Ok(())
+}
+
+/// Which stages of an application a [StageNames](struct.StageNames.html) stands for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum StageKind {
+    Static,
+    PullRequest,
+    Branch,
+}
+
+/// The qualified names that stages of an application can have in an account. A name is the prefix, followed by
+/// the name of a pull request or branch when `variable` is set, followed by the suffix. When only the number of
+/// a pull request varies, `digits` is set as well.
+#[derive(Debug)]
+struct StageNames {
+    app: String,
+    kind: StageKind,
+    account: Account,
+    prefix: String,
+    suffix: String,
+    variable: bool,
+    digits: bool,
+    /// Whether two different names can be qualified to the same name, e.g. `{branch}` of `api` for the branches
+    /// `x` and `api-x`.
+    is_ambiguous: bool,
+}
+
+impl StageNames {
+    /// Gets the names of every static stage, of the stages of pull requests and of the stages of branches of the
+    /// application.
+    fn of(app: &Application) -> Vec<Self> {
+        let named = |kind, account: Account, prefix: String| Self {
+            app: app.full_name(),
+            kind,
+            account,
+            prefix,
+            suffix: String::new(),
+            variable: false,
+            digits: false,
+            is_ambiguous: false,
+        };
+        let mut names: Vec<Self> = app
+            .stages
+            .iter()
+            .map(|stage| {
+                named(
+                    StageKind::Static,
+                    stage.account.clone(),
+                    qualified_stage_name(app, stage),
+                )
+            })
+            .collect();
+
+        let deploys_prs = app
+            .triggers
+            .iter()
+            .any(|trigger| matches!(trigger, Trigger::Pr { deploy: true, .. }));
+        if let Some(account) = app.pr_stages().account(app).filter(|_| deploys_prs) {
+            let template = app.pr_stages().name_template().replace("{app}", &app.app);
+            let placeholders: Vec<(usize, &str)> = template
+                .match_indices("{number}")
+                .chain(template.match_indices("{branch}"))
+                .collect();
+            let mut pr_names = named(StageKind::PullRequest, account, String::new());
+            match (
+                placeholders.iter().map(|(at, _)| *at).min(),
+                placeholders.iter().map(|(at, text)| at + text.len()).max(),
+            ) {
+                (Some(first), Some(last)) => {
+                    let prefix = sanitize(&template[..first]);
+                    let app_prefix = app_stage_prefix(app);
+                    pr_names.variable = true;
+                    pr_names.digits = placeholders.iter().all(|(_, text)| *text == "{number}");
+                    pr_names.suffix = sanitize(&template[last..])
+                        .trim_end_matches('-')
+                        .to_string();
+                    pr_names.prefix = qualify(app, prefix.trim_start_matches('-'));
+                    // The name of a pull request or branch could complete the prefix to that of the app.
+                    pr_names.is_ambiguous = !prefix.starts_with(&app_prefix)
+                        && app_prefix.starts_with(&prefix)
+                        && (!pr_names.digits
+                            || app_prefix[prefix.len()..]
+                                .starts_with(|c: char| c.is_ascii_digit()));
+                }
+                _ => pr_names.prefix = qualify(app, &stage_name(&template)),
+            }
+            names.push(pr_names);
+        }
+
+        for trigger in &app.triggers {
+            if let Trigger::Branch {
+                deploy: true,
+                account,
+                ..
+            } = trigger
+            {
+                if let Some(account) = app.account_or_default(account.as_deref()) {
+                    let mut branch_names =
+                        named(StageKind::Branch, account.clone(), branch_stage_prefix(app));
+                    branch_names.variable = true;
+                    names.push(branch_names);
+                }
+            }
+        }
+        names
+    }
+
+    /// Whether the name is one of the names.
+    fn contains(&self, name: &str) -> bool {
+        if !self.variable {
+            return name == self.prefix;
+        }
+        name.len() > self.prefix.len() + self.suffix.len()
+            && name.starts_with(&self.prefix)
+            && name.ends_with(&self.suffix)
+            && (!self.digits
+                || name[self.prefix.len()..name.len() - self.suffix.len()]
+                    .chars()
+                    .all(|c| c.is_ascii_digit()))
+    }
+
+    /// Whether a name could be one of these names and of the other names alike.
+    fn overlaps(&self, other: &Self) -> bool {
+        match (self.variable, other.variable) {
+            (false, _) => other.contains(&self.prefix),
+            (_, false) => self.contains(&other.prefix),
+            _ => {
+                (self.prefix.starts_with(&other.prefix) || other.prefix.starts_with(&self.prefix))
+                    && (self.suffix.ends_with(&other.suffix)
+                        || other.suffix.ends_with(&self.suffix))
+            }
+        }
+    }
+
+    /// Gets the error for names of these stages that other stages could have as well.
+    fn conflict(&self, other: &Self) -> ConfigError {
+        let apps = vec![self.app.clone(), other.app.clone()];
+        let account = other.account.name.clone();
+        if self.kind == StageKind::PullRequest || other.kind == StageKind::PullRequest {
+            ConfigError::ConflictingPrStages { apps, account }
+        } else if self.kind == StageKind::Branch || other.kind == StageKind::Branch {
+            ConfigError::ConflictingBranchStages { apps, account }
+        } else {
+            ConfigError::ConflictingStages { apps, account }
         }
     }
 }
 
+/// Replaces every character of the part of a name that is not a letter, a digit or a dash with a dash.
+fn sanitize(text: &str) -> String {
+    text.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
 /// The root configuration file object. This is a representation of
 /// what the user has stored at a given version of their ".conveyor.yaml"
 /// file.
@@ -88,53 +533,294 @@ pub struct ConfigFile {
     pub triggers: Vec<Trigger>,
     /// The approvals of the config.
     pub approvals: HashMap<String, ApprovalDefinition>,
+    /// Whether or not to always rebuild a sha, even when it was already built.
+    #[serde(default)]
+    pub force_rebuild: bool,
+    /// The names of the secrets that builds need.
+    #[serde(default)]
+    pub build_secrets: Vec<String>,
+    /// Which of the stored builds are kept. Every build is kept when this is not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retention: Option<RetentionPolicy>,
+    /// The owner and name of the repository, when it is not the org and app as in a monorepo.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    /// The git url that the repository is cloned from, when it is not the https url on github.com, such as
+    /// the ssh url of a private repository.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo_url: Option<String>,
+}
+
+impl ConfigFile {
+    /// Checks that everything the configuration refers to exists, so that the application it is
+    /// converted into does not fail at runtime.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for stage in &self.stages {
+            stage.as_stage(&self.approvals, &self.accounts)?;
+        }
+        for trigger in &self.triggers {
+            match trigger {
+                Trigger::Pr { filter, .. }
+                | Trigger::Merge { filter, .. }
+                | Trigger::Tag { filter, .. }
+                | Trigger::Branch { filter, .. } => validate_path_filter(filter)?,
+                _ => {}
+            }
+            match trigger {
+                Trigger::Pr { pr_filter, .. } => {
+                    for pattern in pr_filter.to.iter().chain(pr_filter.from.iter()) {
+                        validate_pattern(pattern)?;
+                    }
+                }
+                Trigger::Merge { to, from, .. } => {
+                    for pattern in std::iter::once(to).chain(from.iter()) {
+                        validate_pattern(pattern)?;
+                    }
+                }
+                Trigger::Tag { pattern, .. } if pattern != SEMVER_PATTERN => {
+                    validate_pattern(pattern)?
+                }
+                Trigger::Branch { pattern, .. } => validate_pattern(pattern)?,
+                Trigger::Schedule { cron, .. } => {
+                    if let Err(ScheduleError::InvalidCron { info, .. }) = parse_cron(cron) {
+                        return Err(ConfigError::InvalidCron {
+                            expression: cron.clone(),
+                            info,
+                        });
+                    }
+                }
+                _ => {}
+            }
+            if let Trigger::Pr {
+                deploy: true,
+                pr_stages,
+                ..
+            } = trigger
+            {
+                validate_pr_stages(pr_stages, &self.accounts)?;
+            }
+            if let Trigger::Branch {
+                pattern,
+                deploy: true,
+                account,
+                ..
+            } = trigger
+            {
+                validate_branch_account(pattern, account.as_deref(), &self.accounts)?;
+            }
+        }
+        Ok(())
+    }
 }
 
-impl Into<Application> for ConfigFile {
-    /// Taken the Configuration File, computes an application instance.
-    fn into(self) -> Application {
-        let default_account_index = self
+impl From<ConfigFile> for Application {
+    /// Taken the Configuration File, computes an application instance. The configuration is expected
+    /// to be [valid](struct.ConfigFile.html#method.validate).
+    fn from(conf: ConfigFile) -> Self {
+        let default_account_index = conf
             .accounts
             .iter()
             .position(|acc| acc.is_candidate_for_default());
-        let stages = self
+        let stages = conf
             .stages
             .iter()
-            .map(|s| s.as_stage(&self.approvals, &self.accounts))
+            .map(|s| {
+                s.as_stage(&conf.approvals, &conf.accounts)
+                    .expect("The stage is not valid. Validate the config first.")
+            })
             .collect();
-        let approval_groups = self
+        let approval_groups = conf
             .approvals
-            .iter()
-            .map(|(_, val)| val.clone().into())
+            .values()
+            .map(|val| val.clone().into())
             .collect();
 
-        Application {
-            org: self.org,
-            app: self.app,
-            triggers: self.triggers,
-            accounts: self.accounts,
+        Self {
+            org: conf.org,
+            app: conf.app,
+            triggers: conf.triggers,
+            accounts: conf.accounts,
             approval_groups,
             default_account_index,
             stages,
+            force_rebuild: conf.force_rebuild,
+            build_secrets: conf.build_secrets,
+            retention: conf.retention,
+            repo: conf.repo,
+            repo_url: conf.repo_url,
+            path: None,
         }
     }
 }
 
+/// The commands of a build, grouped into phases that are run in order. A phase fails as soon as
+/// one of its commands fails and the phases after it are not run.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct BuildPhases {
+    /// Commands that install the tools and dependencies the build needs.
+    #[serde(default)]
+    pub install: Vec<String>,
+    /// Commands that test the code.
+    #[serde(default)]
+    pub test: Vec<String>,
+    /// Commands that build the code.
+    #[serde(default)]
+    pub build: Vec<String>,
+    /// Commands that package the build outputs into artifacts.
+    #[serde(default)]
+    pub package: Vec<String>,
+}
+
+impl BuildPhases {
+    /// Gets the commands of every phase in the order they should be run.
+    pub fn commands(&self) -> impl Iterator<Item = &String> {
+        self.install
+            .iter()
+            .chain(self.test.iter())
+            .chain(self.build.iter())
+            .chain(self.package.iter())
+    }
+}
+
+/// The standardized build specification of an application. This is a representation of what
+/// the user has stored at a given version of their ".conveyor-build.yaml" file. Every
+/// implementation of [BuildSource](../build/trait.BuildSource.html) interprets the same
+/// specification which is handed to it on the [Build](../pipelining/struct.Build.html) action.
+///
+/// ```yaml
+/// image: rust:1.44
+/// env:
+///   CARGO_TERM_COLOR: always
+/// phases:
+///   test:
+///     - cargo test
+///   build:
+///     - cargo build --release
+/// artifacts:
+///   - target/release/my-awesome-service
+/// cache:
+///   - target
+/// ```
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct BuildSpec {
+    /// The name of the base image to build in for builders that build in containers.
+    #[serde(default)]
+    pub image: Option<String>,
+    /// The environment variables to set for every command.
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// The commands to run.
+    #[serde(default)]
+    pub phases: BuildPhases,
+    /// Glob patterns, relative to the directory of the application, of the files that are artifacts of the build.
+    #[serde(default)]
+    pub artifacts: Vec<String>,
+    /// Paths, relative to the directory of the application, that builders may keep between builds to speed them up.
+    #[serde(default)]
+    pub cache: Vec<String>,
+}
+
 /// Loads a configuration as a config file struct.
 pub fn load_conf_from_yaml() -> Result<ConfigFile, std::io::Error> {
-    let file = File::open(".conveyor.yaml")?;
+    let file = File::open(CONFIG_FILE)?;
     let yaml: ConfigFile = from_reader(file).expect("file is not valid yaml format");
     Ok(yaml)
 }
 
-/// Loads a configuration as an application struct.
+/// Loads the build specification in the directory if there is one. Builders load it from the directory of the
+/// application in their checkout, so every build uses the specification of the sha that it builds.
+pub fn load_build_spec_from_dir(dir: &Path) -> Result<Option<BuildSpec>, std::io::Error> {
+    match File::open(dir.join(BUILD_SPEC_FILE)) {
+        Ok(file) => from_reader(file)
+            .map(Some)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn invalid_config(e: ConfigError) -> Error {
+    Error::new(ErrorKind::InvalidData, e.to_string())
+}
+
+/// Loads a configuration as an application struct. Configurations that are not
+/// [valid](struct.ConfigFile.html#method.validate) fail to load.
 pub fn load_app_from_yaml() -> Result<Application, std::io::Error> {
-    load_conf_from_yaml().map(|conf| conf.into())
+    let conf = load_conf_from_yaml()?;
+    conf.validate().map_err(invalid_config)?;
+    Ok(conf.into())
+}
+
+/// Loads the configuration in the directory as an application struct.
+pub fn load_app_from_dir(dir: &Path) -> Result<Application, std::io::Error> {
+    let file = File::open(dir.join(CONFIG_FILE))?;
+    let conf: ConfigFile = from_reader(file).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    conf.validate().map_err(invalid_config)?;
+    Ok(conf.into())
+}
+
+/// The part of the configuration at the root of a repository that says which applications it has.
+#[derive(Debug, Deserialize)]
+struct RepoConfig {
+    /// The name of the application at the root. A monorepo that only has applications in its directories does
+    /// not have one.
+    #[serde(default)]
+    app: Option<String>,
+    /// The directories of the applications of a monorepo, relative to its root.
+    #[serde(default)]
+    apps: Vec<String>,
+    /// The owner and name of the repository, for the applications of the monorepo that do not set their own.
+    #[serde(default)]
+    repo: Option<String>,
+    /// The git url of the repository, for the applications of the monorepo that do not set their own.
+    #[serde(default)]
+    repo_url: Option<String>,
+}
+
+/// Loads every application of a checkout of a repository. The configuration at the root is either that of
+/// an application, or lists the directories of the applications of a monorepo under `apps`, or both. Each of
+/// those directories has a configuration of its own, and is set as the [path](../struct.Application.html#structfield.path)
+/// of its application. Nothing else of the checkout is searched. The applications in directories that do not
+/// set a `repo` or `repo_url` take those of the root. The applications have to be
+/// [valid](fn.validate_applications.html) together.
+pub fn load_apps_from_repo(root: &Path) -> Result<Vec<Application>, std::io::Error> {
+    let file = File::open(root.join(CONFIG_FILE))?;
+    let repo: RepoConfig = from_reader(file).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    let mut apps = Vec::new();
+    if repo.app.is_some() {
+        apps.push(load_app_from_dir(root)?);
+    }
+    for dir in &repo.apps {
+        let path = dir.trim_matches('/');
+        let outside = Path::new(path)
+            .components()
+            .any(|part| !matches!(part, Component::Normal(_)));
+        if path.is_empty() || outside {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "The app directory {:?} is not a directory in the repository",
+                    dir
+                ),
+            ));
+        }
+        let mut app = load_app_from_dir(&root.join(path))?;
+        app.path = Some(path.to_string());
+        if app.repo.is_none() {
+            app.repo = repo.repo.clone();
+        }
+        if app.repo_url.is_none() {
+            app.repo_url = repo.repo_url.clone();
+        }
+        apps.push(app);
+    }
+    validate_applications(&apps).map_err(invalid_config)?;
+    Ok(apps)
 }
 
 /// Creates a new file and saves it in the current directory for the config.
 pub fn write_new_config_file(app: String, org: String) -> Result<(), std::io::Error> {
-    let mut file = File::create(".conveyor.yaml")?;
+    let mut file = File::create(CONFIG_FILE)?;
 
     let config = ConfigFile {
         app,
@@ -145,16 +831,30 @@ pub fn write_new_config_file(app: String, org: String) -> Result<(), std::io::Er
             regions: vec!["us-east-1".to_owned()],
         }],
         approvals: HashMap::new(),
+        force_rebuild: false,
+        build_secrets: Vec::new(),
+        retention: None,
+        repo: None,
+        repo_url: None,
         triggers: vec![
-            Trigger::Pr { deploy: true },
+            Trigger::Pr {
+                deploy: true,
+                pr_filter: PrFilter::default(),
+                pr_stages: PrStages::default(),
+                filter: PathFilter::default(),
+            },
             Trigger::Merge {
                 to: "master".to_owned(),
                 from: None,
                 stages: vec!["stage".to_owned()],
+                filter: PathFilter::default(),
             },
             Trigger::Tag {
                 pattern: "semver".to_owned(),
                 stages: vec!["prod".to_owned()],
+                prerelease: None,
+                ordered: false,
+                filter: PathFilter::default(),
             },
         ],
         stages: vec![
@@ -162,11 +862,17 @@ pub fn write_new_config_file(app: String, org: String) -> Result<(), std::io::Er
                 name: "stage".to_owned(),
                 approvers: None,
                 account: None,
+                secrets: Vec::new(),
+                rollback: false,
+                concurrency: Concurrency::default(),
             },
             StageDefinition {
                 name: "prod".to_owned(),
                 approvers: None,
                 account: None,
+                secrets: Vec::new(),
+                rollback: false,
+                concurrency: Concurrency::default(),
             },
         ],
     };
@@ -175,3 +881,21 @@ pub fn write_new_config_file(app: String, org: String) -> Result<(), std::io::Er
     file.write_all(config_string.as_ref()).unwrap();
     Ok(())
 }
+
+/// Creates a new build specification file in the current directory.
+pub fn write_new_build_spec_file() -> Result<(), std::io::Error> {
+    let mut file = File::create(BUILD_SPEC_FILE)?;
+
+    let spec = BuildSpec {
+        phases: BuildPhases {
+            build: vec!["make".to_owned()],
+            ..BuildPhases::default()
+        },
+        artifacts: vec!["out/**".to_owned()],
+        ..BuildSpec::default()
+    };
+
+    let spec_string = to_string(&spec).unwrap();
+    file.write_all(spec_string.as_ref()).unwrap();
+    Ok(())
+}
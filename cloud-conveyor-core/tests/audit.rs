@@ -0,0 +1,205 @@
+mod common;
+
+use cloud_conveyor_core::audit::{Origin, Transition};
+use cloud_conveyor_core::deploy::{DeployInfrastructure, DeployPollError, DeployStatus};
+use cloud_conveyor_core::pipelining::{ActionResult, Build, Deploy, Pipeline, Teardown};
+use cloud_conveyor_core::runtime::RuntimeContext;
+use cloud_conveyor_core::secrets::Secrets;
+use cloud_conveyor_core::state_machine::StateMachine;
+use cloud_conveyor_core::webhook::VcsEvent;
+use common::{FailingInfrastructure, REPO};
+
+/// Refuses to start any deployment.
+#[derive(Debug)]
+struct UnstartableInfrastructure;
+
+impl DeployInfrastructure for UnstartableInfrastructure {
+    fn start_deployment(
+        &self,
+        _deploy: &Deploy,
+        _secrets: &Secrets,
+        _ctx: &RuntimeContext,
+    ) -> Result<(), DeployPollError> {
+        Err(DeployPollError::Other {
+            info: "the cluster is gone".to_string(),
+        })
+    }
+
+    fn check_deployment(
+        &self,
+        _deploy: &Deploy,
+        _ctx: &RuntimeContext,
+    ) -> Result<DeployStatus, DeployPollError> {
+        Ok(DeployStatus::Pending)
+    }
+}
+
+fn merge(sha: &str) -> VcsEvent {
+    VcsEvent::Merge {
+        to_branch: "master".to_string(),
+        from_branch: "feature".to_string(),
+        sha: sha.to_string(),
+        changed_files: None,
+    }
+}
+
+/// Builds and deploys the git ref to the first stage, then tears the stage down if asked to.
+fn run(ctx: &RuntimeContext, git_ref: &str, tear_down: bool) {
+    let stage = ctx.applications[0].stages[0].clone();
+    let mut pipeline = Pipeline::empty()
+        .add_action(Box::new(Build::new(git_ref.to_string(), REPO.to_string())))
+        .add_action(Box::new(Deploy::new(
+            stage.clone(),
+            REPO.to_string(),
+            git_ref.to_string(),
+        )));
+    if tear_down {
+        pipeline = pipeline.add_action(Box::new(Teardown::new(stage, REPO.to_string())));
+    }
+    let mut machine = StateMachine::new(pipeline.with_origin(Origin::webhook(merge(git_ref))));
+    while machine.tick_machine_state(ctx).unwrap() {}
+}
+
+#[test]
+fn every_transition_of_every_action_is_audited() {
+    let ctx = common::context();
+    run(&ctx, "good", true);
+
+    let entries = ctx.audit.entries(&common::application()).unwrap();
+    let transitions: Vec<_> = entries
+        .iter()
+        .map(|e| (e.action.as_str(), e.transition.clone(), e.result.clone()))
+        .collect();
+    assert_eq!(
+        transitions,
+        vec![
+            ("Build", Transition::Started, None),
+            ("Build", Transition::Completed, Some(ActionResult::Success)),
+            ("Deploy", Transition::Started, None),
+            ("Deploy", Transition::Completed, Some(ActionResult::Success)),
+            ("Teardown", Transition::Started, None),
+            (
+                "Teardown",
+                Transition::Completed,
+                Some(ActionResult::Success)
+            ),
+        ]
+    );
+    let deploy = &entries[3];
+    assert_eq!(deploy.app, "zprobst/cloud-conveyor");
+    assert_eq!(deploy.git_ref.as_deref(), Some("good"));
+    assert_eq!(deploy.stage.as_deref(), Some("stage"));
+    assert_eq!(deploy.account.as_deref(), Some("default"));
+    assert_eq!(deploy.triggered_by.as_deref(), Some("webhook"));
+    assert_eq!(deploy.event, Some(merge("good")));
+    assert!(entries.windows(2).all(|pair| pair[0].at <= pair[1].at));
+}
+
+#[test]
+fn history_knows_what_is_deployed_and_when_it_arrived() {
+    let mut ctx = common::context();
+    ctx.infrastructure = Box::new(FailingInfrastructure);
+    let app = common::application();
+    run(&ctx, "first", false);
+    run(&ctx, "second", false);
+    run(&ctx, "bad", true);
+
+    let deployed = ctx.history.last_successful(&app, "stage").unwrap().unwrap();
+    assert_eq!(deployed.git_ref, "second");
+    let arrival = ctx
+        .history
+        .arrival(&app, "first", "stage")
+        .unwrap()
+        .unwrap();
+    assert!(arrival.deployed_at <= deployed.deployed_at);
+    assert!(ctx.history.arrival(&app, "bad", "stage").unwrap().is_none());
+    assert!(ctx.history.last_successful(&app, "prod").unwrap().is_none());
+
+    // The teardown after the failed deployment is canceled without ever being started.
+    let entries = ctx.audit.entries(&app).unwrap();
+    let last = entries.last().unwrap();
+    assert_eq!(last.action, "Teardown");
+    assert_eq!(last.transition, Transition::Completed);
+    assert_eq!(last.result, Some(ActionResult::Canceled));
+    assert_eq!(
+        entries[entries.len() - 2].result,
+        Some(ActionResult::Failed)
+    );
+}
+
+#[test]
+fn nothing_is_deployed_to_a_stage_once_it_is_torn_down() {
+    let ctx = common::context();
+    let app = common::application();
+    run(&ctx, "first", false);
+    assert!(ctx
+        .history
+        .last_successful(&app, "stage")
+        .unwrap()
+        .is_some());
+
+    run(&ctx, "second", true);
+    assert!(ctx
+        .history
+        .last_successful(&app, "stage")
+        .unwrap()
+        .is_none());
+    assert!(ctx
+        .history
+        .arrival(&app, "second", "stage")
+        .unwrap()
+        .is_some());
+    // The teardown is audited too, but the history is what says the stage runs nothing.
+    let entries = ctx.audit.entries(&app).unwrap();
+    assert_eq!(entries.last().unwrap().action, "Teardown");
+
+    run(&ctx, "third", false);
+    let deployed = ctx.history.last_successful(&app, "stage").unwrap().unwrap();
+    assert_eq!(deployed.git_ref, "third");
+}
+
+#[test]
+fn actions_that_fail_to_start_are_audited_once() {
+    let mut ctx = common::context();
+    ctx.infrastructure = Box::new(UnstartableInfrastructure);
+    let stage = ctx.applications[0].stages[0].clone();
+    let pipeline = Pipeline::empty()
+        .add_action(Box::new(Build::new("abc".to_string(), REPO.to_string())))
+        .add_action(Box::new(Deploy::new(
+            stage.clone(),
+            REPO.to_string(),
+            "abc".to_string(),
+        )))
+        .add_action(Box::new(Teardown::new(stage, REPO.to_string())));
+    let mut machine = StateMachine::new(pipeline);
+    let error = loop {
+        match machine.tick_machine_state(&ctx) {
+            Ok(true) => {}
+            Ok(false) => panic!("the pipeline finished without an error"),
+            Err(e) => break e,
+        }
+    };
+    assert!(error.to_string().contains("the cluster is gone"));
+    assert!(machine.current_action().is_none());
+    machine.abort(&ctx);
+
+    let entries = ctx.audit.entries(&common::application()).unwrap();
+    let transitions: Vec<_> = entries
+        .iter()
+        .map(|e| (e.action.as_str(), e.transition.clone(), e.result.clone()))
+        .collect();
+    assert_eq!(
+        transitions,
+        vec![
+            ("Build", Transition::Started, None),
+            ("Build", Transition::Completed, Some(ActionResult::Success)),
+            ("Deploy", Transition::Completed, Some(ActionResult::Failed)),
+            (
+                "Teardown",
+                Transition::Completed,
+                Some(ActionResult::Canceled)
+            ),
+        ]
+    );
+    assert!(machine.pipeline().pending_actions().next().is_none());
+}
@@ -0,0 +1,165 @@
+mod common;
+
+use cloud_conveyor_core::pipelining::{Build, Perform, Teardown};
+use cloud_conveyor_core::webhook::{handle_web_hook_event, VcsEvent, WebhookRequest};
+use cloud_conveyor_core::yaml::{validate_applications, ConfigError, ConfigFile};
+use cloud_conveyor_core::{Application, Stage};
+use common::{FixedEvent, REPO};
+
+use std::collections::HashMap;
+
+const CONFIG: &str = r#"
+org: zprobst
+app: cloud-conveyor
+accounts:
+  - name: default
+    id: 123456789
+    regions:
+      - us-east-1
+  - name: sandbox
+    id: 987654321
+    regions:
+      - us-east-1
+approvals: {}
+triggers:
+  - branch:
+      pattern: ^feature/
+      deploy: true
+      account: sandbox
+stages:
+  - name: stage
+"#;
+
+fn application() -> Application {
+    let conf: ConfigFile = serde_yaml::from_str(CONFIG).unwrap();
+    assert_eq!(conf.validate(), Ok(()));
+    conf.into()
+}
+
+fn handle(event: VcsEvent) -> Vec<Box<dyn Perform>> {
+    let mut ctx = common::context();
+    ctx.applications = vec![application()];
+    let request = WebhookRequest {
+        headers: HashMap::new(),
+        body: String::new(),
+    };
+    let mut actions = Vec::new();
+    for mut pipeline in handle_web_hook_event(&FixedEvent::new(event), &mut ctx, request) {
+        while let Some(action) = pipeline.pop_next_action() {
+            actions.push(action);
+        }
+    }
+    actions
+}
+
+fn push(branch: &str) -> VcsEvent {
+    VcsEvent::BranchPush {
+        branch: branch.to_string(),
+        sha: "abc".to_string(),
+        changed_files: None,
+    }
+}
+
+#[test]
+fn pushes_to_matching_branches_are_deployed_to_a_stage_of_their_own() {
+    let app = application();
+    let stage = Stage::for_branch(&app, Some("sandbox"), "feature/login").unwrap();
+    assert!(stage
+        .name
+        .starts_with("cloud-conveyor-branch-feature-login-"));
+    assert_eq!(stage.account.name, "sandbox");
+
+    // Every push deploys to the same stage, which is created by the first one.
+    for _ in 0..2 {
+        let actions = handle(push("feature/login"));
+        assert_eq!(actions.len(), 2);
+        let build: Box<dyn Perform> = Box::new(
+            Build::new("abc".to_string(), REPO.to_string())
+                .with_branch(Some("feature/login".to_string()))
+                .with_app(Some(app.full_name())),
+        );
+        assert!(actions[0] == build);
+        assert_eq!(actions[1].details().stage, Some(stage.name.clone()));
+        assert_eq!(actions[1].details().account, Some("sandbox".to_string()));
+    }
+
+    assert!(handle(push("master")).is_empty());
+}
+
+#[test]
+fn deleted_branches_are_torn_down() {
+    let app = application();
+    let actions = handle(VcsEvent::BranchDelete {
+        branch: "feature/login".to_string(),
+    });
+    let stage = Stage::for_branch(&app, Some("sandbox"), "feature/login").unwrap();
+    let teardown: Box<dyn Perform> =
+        Box::new(Teardown::new(stage, REPO.to_string()).with_app(Some(app.full_name())));
+    assert_eq!(actions.len(), 1);
+    assert!(actions[0] == teardown);
+
+    assert!(handle(VcsEvent::BranchDelete {
+        branch: "master".to_string()
+    })
+    .is_empty());
+}
+
+#[test]
+fn branch_stages_need_an_account() {
+    let mut conf: ConfigFile =
+        serde_yaml::from_str(&CONFIG.replace("account: sandbox", "account: nope")).unwrap();
+    assert_eq!(
+        conf.validate(),
+        Err(ConfigError::UnknownAccount {
+            stage: "^feature/".to_string(),
+            account: "nope".to_string(),
+        })
+    );
+
+    conf = serde_yaml::from_str(&CONFIG.replace("      account: sandbox\n", "")).unwrap();
+    conf.accounts.remove(0);
+    conf.stages.clear();
+    assert_eq!(conf.validate(), Err(ConfigError::NoBranchAccount));
+}
+
+#[test]
+fn branches_that_read_the_same_once_sanitized_get_stages_of_their_own() {
+    let app = application();
+    let name = |branch: &str| Stage::for_branch(&app, None, branch).unwrap().name;
+    assert_eq!(name("feature-x"), "cloud-conveyor-branch-feature-x");
+    assert_ne!(name("feature/x"), name("feature-x"));
+    assert_ne!(name("feature/x"), name("feature_x"));
+    assert_eq!(name("feature/x"), name("feature/x"));
+}
+
+#[test]
+fn branch_stages_of_apps_sharing_an_account_are_unique() {
+    let app = |name: &str| {
+        let conf: ConfigFile =
+            serde_yaml::from_str(&CONFIG.replace("app: cloud-conveyor", &format!("app: {}", name)))
+                .unwrap();
+        Application::from(conf)
+    };
+    assert_eq!(validate_applications(&[app("api"), app("web")]), Ok(()));
+    assert_eq!(
+        validate_applications(&[app("api"), app("api-branch-v2")]),
+        Err(ConfigError::ConflictingBranchStages {
+            apps: vec![
+                "zprobst/api".to_string(),
+                "zprobst/api-branch-v2".to_string()
+            ],
+            account: "sandbox".to_string(),
+        })
+    );
+
+    // Apps in different accounts never conflict.
+    let mut other = app("api");
+    other.org = "other".to_string();
+    for account in &mut other.accounts {
+        account.id += 1;
+    }
+    for stage in &mut other.stages {
+        stage.account.id += 1;
+    }
+    assert_eq!(validate_applications(&[app("api"), other]), Ok(()));
+}
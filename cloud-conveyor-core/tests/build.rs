@@ -0,0 +1,285 @@
+mod common;
+
+use chrono::{Duration, TimeZone, Utc};
+use cloud_conveyor_core::build::{ArtifactManifest, ProvideArtifactLocation, RetentionPolicy};
+use cloud_conveyor_core::history::{DeploymentOutcome, DeploymentRecord};
+use cloud_conveyor_core::pipelining::{ActionResult, Build, Perform};
+use cloud_conveyor_core::runtime::RuntimeContext;
+use cloud_conveyor_core::state_machine::StateMachine;
+use cloud_conveyor_core::webhook::{handle_web_hook_event, VcsEvent, WebhookRequest};
+use common::{FakeBuilder, FixedEvent, MemoryArtifacts, REPO};
+
+use std::collections::HashMap;
+
+const SHA: &str = "cda888fd29a23fdb2d905e4ab6cf50230ce4c37b";
+
+fn previous_build() -> ArtifactManifest {
+    ArtifactManifest {
+        git_sha: SHA.to_string(),
+        bucket: "cloud-conveyor".to_string(),
+        folder: format!("cloud-conveyor/{}", SHA),
+        logs: "logs/previous".to_string(),
+        branch: Some("master".to_string()),
+        built_at: Utc.with_ymd_and_hms(2020, 6, 1, 12, 0, 0).unwrap(),
+    }
+}
+
+fn build_of(git_sha: &str, branch: Option<&str>, age_in_days: i64) -> ArtifactManifest {
+    ArtifactManifest {
+        git_sha: git_sha.to_string(),
+        bucket: "cloud-conveyor".to_string(),
+        folder: format!("cloud-conveyor/{}", git_sha),
+        logs: format!("logs/{}", git_sha),
+        branch: branch.map(String::from),
+        built_at: Utc.with_ymd_and_hms(2020, 6, 30, 12, 0, 0).unwrap()
+            - Duration::days(age_in_days),
+    }
+}
+
+#[test]
+fn build_reuses_artifacts_of_previous_build() {
+    let builder = FakeBuilder::default();
+    let starts = builder.starts.clone();
+    let artifacts = MemoryArtifacts::default();
+    artifacts
+        .builds
+        .borrow_mut()
+        .insert(SHA.to_string(), previous_build());
+    let mut ctx = common::context();
+    ctx.builder = Box::new(builder);
+    ctx.artifact_provider = Box::new(artifacts);
+
+    let mut build = Build::new(SHA.to_string(), REPO.to_string());
+    build.start(&ctx).unwrap();
+    assert!(build.is_done(&ctx).unwrap());
+    assert!(matches!(build.get_result(&ctx), ActionResult::Success));
+    assert_eq!(build.manifest(), Some(&previous_build()));
+    assert_eq!(starts.get(), 0);
+}
+
+#[test]
+fn forced_build_ignores_previous_build() {
+    let builder = FakeBuilder::default();
+    let starts = builder.starts.clone();
+    let artifacts = MemoryArtifacts::default();
+    artifacts
+        .builds
+        .borrow_mut()
+        .insert(SHA.to_string(), previous_build());
+    let mut ctx = common::context();
+    ctx.builder = Box::new(builder);
+    ctx.artifact_provider = Box::new(artifacts);
+
+    let mut build = Build::new(SHA.to_string(), REPO.to_string()).with_force_rebuild(true);
+    build.start(&ctx).unwrap();
+    assert!(build.is_done(&ctx).unwrap());
+    assert_eq!(starts.get(), 1);
+    assert_eq!(build.manifest().unwrap().logs, format!("logs/{}", SHA));
+}
+
+#[test]
+fn successful_build_is_reused_by_later_builds() {
+    let builder = FakeBuilder::default();
+    let starts = builder.starts.clone();
+    let artifacts = MemoryArtifacts::default();
+    let builds = artifacts.builds.clone();
+    let mut ctx = common::context();
+    ctx.builder = Box::new(builder);
+    ctx.artifact_provider = Box::new(artifacts);
+
+    let mut first = Build::new(SHA.to_string(), REPO.to_string());
+    first.start(&ctx).unwrap();
+    assert!(first.is_done(&ctx).unwrap());
+    assert_eq!(
+        builds.borrow()[SHA].folder,
+        format!("cloud-conveyor/{}", SHA)
+    );
+
+    let mut second = Build::new(SHA.to_string(), REPO.to_string());
+    second.start(&ctx).unwrap();
+    assert!(second.is_done(&ctx).unwrap());
+    assert_eq!(first.manifest(), second.manifest());
+    assert_eq!(starts.get(), 1);
+}
+
+#[test]
+fn build_of_unknown_repo_is_not_cached() {
+    let builder = FakeBuilder::default();
+    let starts = builder.starts.clone();
+    let mut ctx = common::context();
+    ctx.builder = Box::new(builder);
+
+    let repo = "https://github.com/zprobst/another-service.git".to_string();
+    for _ in 0..2 {
+        let mut build = Build::new(SHA.to_string(), repo.clone());
+        build.start(&ctx).unwrap();
+        assert!(build.is_done(&ctx).unwrap());
+        assert!(build.manifest().is_none());
+    }
+    assert_eq!(starts.get(), 2);
+}
+
+#[test]
+fn successful_build_records_its_branch() {
+    let artifacts = MemoryArtifacts::default();
+    let builds = artifacts.builds.clone();
+    let mut ctx = common::context();
+    ctx.artifact_provider = Box::new(artifacts);
+
+    let mut build =
+        Build::new(SHA.to_string(), REPO.to_string()).with_branch(Some("master".to_string()));
+    build.start(&ctx).unwrap();
+    assert!(build.is_done(&ctx).unwrap());
+    assert_eq!(builds.borrow()[SHA].branch, Some("master".to_string()));
+}
+
+#[test]
+fn retention_keeps_recent_builds_per_branch() {
+    let builds = vec![
+        build_of("a", Some("master"), 3),
+        build_of("b", Some("master"), 2),
+        build_of("c", Some("master"), 1),
+        build_of("d", Some("feature"), 5),
+        build_of("e", None, 4),
+    ];
+    let policy = RetentionPolicy {
+        builds_per_branch: 2,
+    };
+    let expired: Vec<_> = policy
+        .expired(&builds, &[])
+        .into_iter()
+        .map(|b| b.git_sha.as_str())
+        .collect();
+    assert_eq!(expired, vec!["a"]);
+}
+
+#[test]
+fn retention_keeps_deployed_builds() {
+    let builds = vec![
+        build_of("a", Some("master"), 3),
+        build_of("b", Some("master"), 2),
+        build_of("c", Some("master"), 1),
+    ];
+    let policy = RetentionPolicy {
+        builds_per_branch: 1,
+    };
+    let expired: Vec<_> = policy
+        .expired(&builds, &["a".to_string()])
+        .into_iter()
+        .map(|b| b.git_sha.as_str())
+        .collect();
+    assert_eq!(expired, vec!["b"]);
+}
+
+#[test]
+fn cleanup_deletes_expired_builds() {
+    let artifacts = MemoryArtifacts::default();
+    for build in [build_of("a", None, 2), build_of("b", None, 1)]
+        .iter()
+        .cloned()
+    {
+        artifacts
+            .builds
+            .borrow_mut()
+            .insert(build.git_sha.clone(), build);
+    }
+    let policy = RetentionPolicy {
+        builds_per_branch: 1,
+    };
+    let app = common::application();
+    let deleted = artifacts.cleanup(&app, &policy, &[]).unwrap();
+    assert_eq!(deleted, vec!["a".to_string()]);
+    let remaining: Vec<_> = artifacts
+        .list(&app)
+        .unwrap()
+        .into_iter()
+        .map(|b| b.git_sha)
+        .collect();
+    assert_eq!(remaining, vec!["b".to_string()]);
+}
+
+#[test]
+fn successful_build_cleans_up_the_builds_its_app_does_not_retain() {
+    let artifacts = MemoryArtifacts::default();
+    let builds = artifacts.builds.clone();
+    for build in &[
+        build_of("a", Some("master"), 3),
+        build_of("b", Some("master"), 2),
+        build_of("c", Some("master"), 1),
+    ] {
+        builds
+            .borrow_mut()
+            .insert(build.git_sha.clone(), build.clone());
+    }
+    let mut app = common::application();
+    app.retention = Some(RetentionPolicy {
+        builds_per_branch: 1,
+    });
+    let mut ctx = common::context();
+    ctx.artifact_provider = Box::new(artifacts);
+    ctx.applications = vec![app.clone()];
+    // The stage still runs the oldest build, so it is kept.
+    let record = DeploymentRecord {
+        git_ref: "a".to_string(),
+        deployed_at: Utc::now(),
+        outcome: DeploymentOutcome::Succeeded,
+        rollback: false,
+        tag: None,
+    };
+    ctx.history.record(&app, "prod", &record).unwrap();
+
+    let mut build =
+        Build::new(SHA.to_string(), REPO.to_string()).with_branch(Some("master".to_string()));
+    build.start(&ctx).unwrap();
+    assert!(build.is_done(&ctx).unwrap());
+    let mut remaining: Vec<_> = builds.borrow().keys().cloned().collect();
+    remaining.sort();
+    assert_eq!(remaining, vec!["a".to_string(), SHA.to_string()]);
+}
+
+/// Runs every pipeline of the event to completion.
+fn run_event(ctx: &mut RuntimeContext, event: VcsEvent) {
+    let request = WebhookRequest {
+        headers: HashMap::new(),
+        body: String::new(),
+    };
+    for pipeline in handle_web_hook_event(&FixedEvent::new(event), ctx, request) {
+        let mut machine = StateMachine::new(pipeline);
+        while machine.tick_machine_state(ctx).unwrap() {}
+    }
+}
+
+#[test]
+fn tag_of_a_merged_commit_reuses_the_build_of_the_merge() {
+    let builder = FakeBuilder::default();
+    let starts = builder.starts.clone();
+    let mut app = common::application();
+    app.triggers
+        .push(serde_yaml::from_str("tag:\n  pattern: semver\n  deploy:\n    - stage").unwrap());
+    let mut ctx = common::context();
+    ctx.builder = Box::new(builder);
+    ctx.applications = vec![app.clone()];
+
+    run_event(
+        &mut ctx,
+        VcsEvent::Merge {
+            to_branch: "master".to_string(),
+            from_branch: "feature".to_string(),
+            sha: SHA.to_string(),
+            changed_files: None,
+        },
+    );
+    run_event(
+        &mut ctx,
+        VcsEvent::TagPush {
+            tag: "v1.0.0".to_string(),
+            sha: SHA.to_string(),
+            changed_files: None,
+        },
+    );
+
+    assert_eq!(starts.get(), 1);
+    let deployed = ctx.history.last_successful(&app, "stage").unwrap().unwrap();
+    assert_eq!(deployed.git_ref, SHA);
+    assert_eq!(deployed.tag, Some("v1.0.0".to_string()));
+}
@@ -0,0 +1,284 @@
+//! Fakes of the runtime traits that are shared between the integration tests.
+#![allow(dead_code)]
+use cloud_conveyor_core::approval::MemoryApprovals;
+use cloud_conveyor_core::audit::MemoryAuditLog;
+use cloud_conveyor_core::build::{
+    ArtifactManifest, BuildPollError, BuildSource, BuildStatus, ProvideArtifactLocation,
+};
+use cloud_conveyor_core::credentials::NoCredentials;
+use cloud_conveyor_core::deploy::{DeployInfrastructure, DeployPollError, DeployStatus};
+use cloud_conveyor_core::history::MemoryHistory;
+use cloud_conveyor_core::pipelining::{Build, Deploy, Teardown};
+use cloud_conveyor_core::runtime::RuntimeContext;
+use cloud_conveyor_core::secrets::{SecretError, SecretProvider, Secrets};
+use cloud_conveyor_core::teardown::{TeardownInfrastructure, TeardownPollError, TeardownStatus};
+use cloud_conveyor_core::webhook::{CompareCommits, InterpretWebhooks, VcsEvent, WebhookRequest};
+use cloud_conveyor_core::yaml::ConfigFile;
+use cloud_conveyor_core::Application;
+use failure::Error;
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+
+pub const REPO: &str = "https://github.com/zprobst/cloud-conveyor.git";
+
+/// Reports the event in the body of every request as is, as an event of [REPO](constant.REPO.html) unless
+/// another repository is set.
+pub struct FixedEvent {
+    pub event: VcsEvent,
+    pub repo: &'static str,
+    pub commits: Option<Box<dyn CompareCommits>>,
+}
+
+impl FixedEvent {
+    pub fn new(event: VcsEvent) -> Self {
+        Self {
+            event,
+            repo: REPO,
+            commits: None,
+        }
+    }
+
+    pub fn with_repo(mut self, repo: &'static str) -> Self {
+        self.repo = repo;
+        self
+    }
+
+    pub fn with_commits(mut self, commits: impl CompareCommits + 'static) -> Self {
+        self.commits = Some(Box::new(commits));
+        self
+    }
+}
+
+impl InterpretWebhooks for FixedEvent {
+    type Intermediary = VcsEvent;
+
+    fn parse_to_intermediary(&self, _req: WebhookRequest) -> Vec<VcsEvent> {
+        vec![self.event.clone()]
+    }
+
+    fn get_vcs_event(&self, intermediary: &VcsEvent) -> Vec<VcsEvent> {
+        vec![intermediary.clone()]
+    }
+
+    fn get_repo<'a>(&self, _intermediary: &'a VcsEvent) -> &'a str {
+        self.repo
+    }
+
+    fn commits(&self) -> Option<&dyn CompareCommits> {
+        self.commits.as_deref()
+    }
+}
+
+const CONFIG: &str = r#"
+org: zprobst
+app: cloud-conveyor
+accounts:
+  - name: default
+    id: 123456789
+    regions:
+      - us-east-1
+approvals: {}
+triggers:
+  - pr:
+      deploy: true
+  - merge:
+      to: master
+      deploy:
+        - stage
+stages:
+  - name: stage
+"#;
+
+pub fn application() -> Application {
+    let conf: ConfigFile = serde_yaml::from_str(CONFIG).unwrap();
+    conf.into()
+}
+
+/// Creates a runtime context for the test application that does nothing when asked to
+/// perform any operation.
+pub fn context() -> RuntimeContext {
+    RuntimeContext {
+        artifact_provider: Box::new(MemoryArtifacts::default()),
+        builder: Box::new(FakeBuilder::default()),
+        infrastructure: Box::new(FakeInfrastructure),
+        teardown: Box::new(FakeInfrastructure),
+        secrets: Box::new(MemorySecrets::default()),
+        credentials: Box::new(NoCredentials),
+        history: Box::new(MemoryHistory::default()),
+        audit: Box::new(MemoryAuditLog::default()),
+        approvals: Box::new(MemoryApprovals::default()),
+        applications: vec![application()],
+    }
+}
+
+/// A builder that counts the builds it was asked to start and reports them as succeeded.
+#[derive(Debug, Default)]
+pub struct FakeBuilder {
+    pub starts: Rc<Cell<u32>>,
+    pub secrets: Rc<RefCell<Option<Secrets>>>,
+}
+
+impl BuildSource for FakeBuilder {
+    fn start_build(
+        &self,
+        _build: &Build,
+        secrets: &Secrets,
+        _ctx: &RuntimeContext,
+    ) -> Result<(), BuildPollError> {
+        self.starts.set(self.starts.get() + 1);
+        self.secrets.replace(Some(secrets.clone()));
+        Ok(())
+    }
+
+    fn check_build(
+        &self,
+        build: &Build,
+        _ctx: &RuntimeContext,
+    ) -> Result<BuildStatus, BuildPollError> {
+        Ok(BuildStatus::Succeeded {
+            logs: format!("logs/{}", build.git_ref),
+        })
+    }
+}
+
+/// Remembers build manifests in memory.
+#[derive(Debug, Default)]
+pub struct MemoryArtifacts {
+    pub builds: Rc<RefCell<HashMap<String, ArtifactManifest>>>,
+}
+
+impl ProvideArtifactLocation for MemoryArtifacts {
+    fn get_bucket(&self, app: &Application) -> Result<String, Error> {
+        Ok(app.app.clone())
+    }
+
+    fn get_folder(&self, app: &Application, git_sha: &str) -> Result<String, Error> {
+        Ok(format!("{}/{}", app.app, git_sha))
+    }
+
+    fn find_build(
+        &self,
+        _app: &Application,
+        git_sha: &str,
+    ) -> Result<Option<ArtifactManifest>, Error> {
+        Ok(self.builds.borrow().get(git_sha).cloned())
+    }
+
+    fn record_build(&self, _app: &Application, manifest: &ArtifactManifest) -> Result<(), Error> {
+        self.builds
+            .borrow_mut()
+            .insert(manifest.git_sha.clone(), manifest.clone());
+        Ok(())
+    }
+
+    fn upload(&self, _app: &Application, _git_sha: &str, _source: &Path) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn download(
+        &self,
+        _app: &Application,
+        _git_sha: &str,
+        _destination: &Path,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn list(&self, _app: &Application) -> Result<Vec<ArtifactManifest>, Error> {
+        let mut builds: Vec<_> = self.builds.borrow().values().cloned().collect();
+        builds.sort_by_key(|build| build.built_at);
+        Ok(builds)
+    }
+
+    fn delete(&self, _app: &Application, git_sha: &str) -> Result<(), Error> {
+        self.builds.borrow_mut().remove(git_sha);
+        Ok(())
+    }
+}
+
+/// Secrets that are kept in memory.
+#[derive(Debug, Default)]
+pub struct MemorySecrets {
+    pub values: HashMap<String, String>,
+}
+
+impl SecretProvider for MemorySecrets {
+    fn get_secret(&self, _app: &Application, name: &str) -> Result<String, SecretError> {
+        self.values
+            .get(name)
+            .cloned()
+            .ok_or_else(|| SecretError::Missing {
+                name: name.to_string(),
+            })
+    }
+}
+
+/// Infrastructure that completes every deployment and teardown immediately.
+#[derive(Debug)]
+pub struct FakeInfrastructure;
+
+impl DeployInfrastructure for FakeInfrastructure {
+    fn start_deployment(
+        &self,
+        _deploy: &Deploy,
+        _secrets: &Secrets,
+        _ctx: &RuntimeContext,
+    ) -> Result<(), DeployPollError> {
+        Ok(())
+    }
+
+    fn check_deployment(
+        &self,
+        _deploy: &Deploy,
+        _ctx: &RuntimeContext,
+    ) -> Result<DeployStatus, DeployPollError> {
+        Ok(DeployStatus::Complete)
+    }
+}
+
+impl TeardownInfrastructure for FakeInfrastructure {
+    fn start_teardown(
+        &self,
+        _teardown: &Teardown,
+        _ctx: &RuntimeContext,
+    ) -> Result<(), TeardownPollError> {
+        Ok(())
+    }
+
+    fn check_teardown(
+        &self,
+        _teardown: &Teardown,
+        _ctx: &RuntimeContext,
+    ) -> Result<TeardownStatus, TeardownPollError> {
+        Ok(TeardownStatus::Complete)
+    }
+}
+
+/// Infrastructure that fails every deployment of the sha `bad` and completes every other.
+#[derive(Debug)]
+pub struct FailingInfrastructure;
+
+impl DeployInfrastructure for FailingInfrastructure {
+    fn start_deployment(
+        &self,
+        _deploy: &Deploy,
+        _secrets: &Secrets,
+        _ctx: &RuntimeContext,
+    ) -> Result<(), DeployPollError> {
+        Ok(())
+    }
+
+    fn check_deployment(
+        &self,
+        deploy: &Deploy,
+        _ctx: &RuntimeContext,
+    ) -> Result<DeployStatus, DeployPollError> {
+        match deploy.git_ref.as_str() {
+            "bad" => Ok(DeployStatus::Failed),
+            _ => Ok(DeployStatus::Complete),
+        }
+    }
+}
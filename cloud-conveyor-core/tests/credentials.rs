@@ -0,0 +1,143 @@
+use chrono::{Duration, Utc};
+use cloud_conveyor_core::credentials::{
+    CachedCredentials, CredentialError, CredentialProvider, Credentials, NoCredentials,
+};
+use cloud_conveyor_core::deploy::DeployPollError;
+use cloud_conveyor_core::Account;
+
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Provides credentials that expire after the lifetime and counts how often it is asked for them.
+#[derive(Debug)]
+struct CountingCredentials {
+    calls: Arc<AtomicUsize>,
+    lifetime: Option<Duration>,
+}
+
+impl CredentialProvider for CountingCredentials {
+    fn credentials(&self, account: &Account) -> Result<Credentials, CredentialError> {
+        let call = self.calls.fetch_add(1, Ordering::SeqCst);
+        let credentials =
+            Credentials::new(format!("{}-{}", account.name, call), "s3cr3t".to_string());
+        Ok(match self.lifetime {
+            Some(lifetime) => credentials.with_expiry(Utc::now() + lifetime),
+            None => credentials,
+        })
+    }
+}
+
+/// Provides the credentials of the account `role` by asking the cache it is wrapped in for the credentials
+/// of the account `base`, like assuming a role does.
+#[derive(Debug, Default)]
+struct RoleCredentials {
+    cache: Rc<RefCell<Weak<CachedCredentials>>>,
+}
+
+impl CredentialProvider for RoleCredentials {
+    fn credentials(&self, account: &Account) -> Result<Credentials, CredentialError> {
+        let name = if account.name == "role" {
+            let cache = self.cache.borrow().upgrade().unwrap();
+            let base = cache.credentials(&self::account("base", 1))?;
+            format!("role-from-{}", base.access_key_id)
+        } else {
+            account.name.clone()
+        };
+        Ok(Credentials::new(name, "s3cr3t".to_string()))
+    }
+}
+
+fn account(name: &str, id: usize) -> Account {
+    Account {
+        name: name.to_string(),
+        id,
+        regions: vec!["us-east-1".to_string()],
+    }
+}
+
+fn cached(lifetime: Option<Duration>) -> (CachedCredentials, Arc<AtomicUsize>) {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let provider = CountingCredentials {
+        calls: calls.clone(),
+        lifetime,
+    };
+    (CachedCredentials::new(Box::new(provider)), calls)
+}
+
+#[test]
+fn credentials_are_cached_per_account() {
+    let (provider, calls) = cached(None);
+    for _ in 0..3 {
+        assert_eq!(
+            provider
+                .credentials(&account("stage", 1))
+                .unwrap()
+                .access_key_id,
+            "stage-0"
+        );
+    }
+    assert_eq!(
+        provider
+            .credentials(&account("prod", 2))
+            .unwrap()
+            .access_key_id,
+        "prod-1"
+    );
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn credentials_are_refreshed_before_they_expire() {
+    let (provider, calls) = cached(Some(Duration::minutes(3)));
+    provider.credentials(&account("stage", 1)).unwrap();
+    // The default margin is five minutes, so credentials that live for three are never reused.
+    provider.credentials(&account("stage", 1)).unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+    let (provider, calls) = cached(Some(Duration::minutes(3)));
+    let provider = provider.with_margin(Duration::minutes(1));
+    provider.credentials(&account("stage", 1)).unwrap();
+    provider.credentials(&account("stage", 1)).unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn missing_credentials_name_the_account() {
+    match NoCredentials.credentials(&account("prod", 2)) {
+        Err(CredentialError::Missing { account }) => assert_eq!(account, "prod"),
+        result => panic!("unexpected result {:?}", result),
+    }
+}
+
+#[test]
+fn providers_can_use_the_cache_while_refreshing() {
+    let provider = RoleCredentials::default();
+    let cache = provider.cache.clone();
+    let cached = Rc::new(CachedCredentials::new(Box::new(provider)));
+    *cache.borrow_mut() = Rc::downgrade(&cached);
+
+    assert_eq!(
+        cached
+            .credentials(&account("role", 2))
+            .unwrap()
+            .access_key_id,
+        "role-from-base"
+    );
+}
+
+#[test]
+fn credential_errors_are_carried_by_poll_errors() {
+    let error: DeployPollError = NoCredentials
+        .credentials(&account("prod", 2))
+        .unwrap_err()
+        .into();
+    match &error {
+        DeployPollError::CredentialsUnavailable {
+            cause: CredentialError::Missing { account },
+        } => assert_eq!(account, "prod"),
+        error => panic!("unexpected error {:?}", error),
+    }
+    assert!(error.to_string().contains("prod"));
+}
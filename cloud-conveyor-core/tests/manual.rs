@@ -0,0 +1,202 @@
+mod common;
+
+use cloud_conveyor_core::pipelining::{Approval, Build, Deploy, Perform};
+use cloud_conveyor_core::schedule::ResolveBranches;
+use cloud_conveyor_core::webhook::{handle_manual_request, ManualRequestError, VcsEvent};
+use cloud_conveyor_core::yaml::ConfigFile;
+use cloud_conveyor_core::Application;
+use common::REPO;
+use failure::Error;
+
+const SHA: &str = "cda888fd29a23fdb2d905e4ab6cf50230ce4c37b";
+
+const CONFIG: &str = r#"
+org: zprobst
+app: cloud-conveyor
+accounts:
+  - name: default
+    id: 123456789
+    regions:
+      - us-east-1
+approvals:
+  admins:
+    type: slack
+    people:
+      - zprobst
+triggers:
+  - manual:
+      deploy:
+        - stage
+        - prod
+stages:
+  - name: stage
+  - name: prod
+    approvers: admins
+  - name: secret
+"#;
+
+/// Only knows the branch `main`, whose head is [SHA](constant.SHA.html).
+#[derive(Debug)]
+struct MainBranch;
+
+impl ResolveBranches for MainBranch {
+    fn head(&self, _repo: &str, branch: &str) -> Result<String, Error> {
+        match branch {
+            "main" => Ok(SHA.to_string()),
+            _ => failure::bail!("The repository has no branch {}", branch),
+        }
+    }
+}
+
+fn application() -> Application {
+    let conf: ConfigFile = serde_yaml::from_str(CONFIG).unwrap();
+    conf.into()
+}
+
+fn request(
+    app: &Application,
+    stages: &[&str],
+) -> Result<Vec<Box<dyn Perform>>, ManualRequestError> {
+    let stages = stages.iter().map(|s| s.to_string()).collect();
+    let mut pipeline =
+        handle_manual_request(app, SHA.to_string(), stages, "zprobst".to_string(), None)?;
+    let mut actions = Vec::new();
+    while let Some(action) = pipeline.pop_next_action() {
+        actions.push(action);
+    }
+    Ok(actions)
+}
+
+#[test]
+fn manual_request_builds_and_deploys_with_approvals() {
+    let app = application();
+    let mut pipeline = handle_manual_request(
+        &app,
+        "v1.0.0".to_string(),
+        vec!["prod".to_string(), "stage".to_string()],
+        "zprobst".to_string(),
+        None,
+    )
+    .unwrap();
+    let origin = pipeline.origin().unwrap();
+    assert_eq!(origin.triggered_by, "zprobst");
+    assert!(matches!(origin.event, Some(VcsEvent::Manual { .. })));
+
+    let prod = app.stages[1].clone();
+    let expected: Vec<Box<dyn Perform>> = vec![
+        // A tag may point at another commit than its earlier builds did.
+        Box::new(
+            Build::new("v1.0.0".to_string(), REPO.to_string())
+                .with_app(Some(app.full_name()))
+                .with_force_rebuild(true),
+        ),
+        Box::new(Approval {
+            approval_group: prod.approval_group.clone().unwrap(),
+            stage_name: "prod".to_string(),
+            git_ref: "v1.0.0".to_string(),
+            app_name: app.full_name(),
+            approved_by: None,
+            rejected_by: None,
+            request_id: None,
+        }),
+        Box::new(
+            Deploy::new(prod, REPO.to_string(), "v1.0.0".to_string())
+                .with_app(Some(app.full_name())),
+        ),
+        Box::new(
+            Deploy::new(
+                app.stages[0].clone(),
+                REPO.to_string(),
+                "v1.0.0".to_string(),
+            )
+            .with_app(Some(app.full_name())),
+        ),
+    ];
+    for action in expected {
+        assert!(pipeline.pop_next_action().unwrap() == action);
+    }
+    assert!(pipeline.pop_next_action().is_none());
+}
+
+#[test]
+fn manual_request_only_deploys_allowed_stages() {
+    let app = application();
+    assert_eq!(request(&app, &[]).unwrap().len(), 1);
+    assert_eq!(
+        request(&app, &["stage", "secret"]).err(),
+        Some(ManualRequestError::NotAllowed {
+            stages: vec!["secret".to_string()]
+        })
+    );
+    assert_eq!(
+        request(&app, &["dev"]).err(),
+        Some(ManualRequestError::UnknownStage {
+            stage: "dev".to_string()
+        })
+    );
+
+    let mut app = app;
+    app.triggers.clear();
+    assert_eq!(
+        request(&app, &[]).err(),
+        Some(ManualRequestError::NotAllowed { stages: vec![] })
+    );
+}
+
+#[test]
+fn manual_triggers_are_merged_into_one_build() {
+    let conf: ConfigFile = serde_yaml::from_str(&CONFIG.replace(
+        "triggers:\n",
+        "triggers:\n  - manual:\n      deploy:\n        - secret\n        - stage\n",
+    ))
+    .unwrap();
+    let app: Application = conf.into();
+    let actions = request(&app, &["stage", "secret", "stage"]).unwrap();
+    let expected: Vec<Box<dyn Perform>> = vec![
+        Box::new(Build::new(SHA.to_string(), REPO.to_string()).with_app(Some(app.full_name()))),
+        Box::new(
+            Deploy::new(app.stages[0].clone(), REPO.to_string(), SHA.to_string())
+                .with_app(Some(app.full_name())),
+        ),
+        Box::new(
+            Deploy::new(app.stages[2].clone(), REPO.to_string(), SHA.to_string())
+                .with_app(Some(app.full_name())),
+        ),
+    ];
+    assert_eq!(actions.len(), expected.len());
+    for (action, expected) in actions.into_iter().zip(expected) {
+        assert!(action == expected);
+    }
+}
+
+#[test]
+fn manual_requests_of_branches_build_the_head_of_the_branch() {
+    let app = application();
+    let request = |git_ref: &str| {
+        let mut pipeline = handle_manual_request(
+            &app,
+            git_ref.to_string(),
+            vec!["stage".to_string()],
+            "zprobst".to_string(),
+            Some(&MainBranch),
+        )
+        .unwrap();
+        let build = pipeline.pop_next_action().unwrap();
+        let deploy = pipeline.pop_next_action().unwrap();
+        assert_eq!(build.details().git_ref, deploy.details().git_ref);
+        build
+    };
+
+    let head = || -> Box<dyn Perform> {
+        Box::new(Build::new(SHA.to_string(), REPO.to_string()).with_app(Some(app.full_name())))
+    };
+    assert!(request("main") == head());
+    assert!(request(SHA) == head());
+
+    let expected: Box<dyn Perform> = Box::new(
+        Build::new("cda888f".to_string(), REPO.to_string())
+            .with_app(Some(app.full_name()))
+            .with_force_rebuild(true),
+    );
+    assert!(request("cda888f") == expected);
+}
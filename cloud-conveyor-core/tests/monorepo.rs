@@ -0,0 +1,203 @@
+mod common;
+
+use cloud_conveyor_core::pipelining::{Build, Deploy, Perform};
+use cloud_conveyor_core::webhook::{handle_web_hook_event, VcsEvent, WebhookRequest};
+use cloud_conveyor_core::yaml::{load_apps_from_repo, ConfigFile};
+use cloud_conveyor_core::Application;
+
+use common::FixedEvent;
+
+use std::collections::HashMap;
+use std::fs;
+
+const MONOREPO: &str = "https://github.com/zprobst/monorepo.git";
+
+const CONFIG: &str = r#"
+org: zprobst
+app: APP
+repo: zprobst/monorepo
+accounts:
+  - name: default
+    id: 123456789
+    regions:
+      - us-east-1
+approvals: {}
+triggers:
+  - merge:
+      to: master
+      deploy:
+        - stage
+stages:
+  - name: stage
+"#;
+
+fn application(name: &str, path: &str) -> Application {
+    let conf: ConfigFile = serde_yaml::from_str(&CONFIG.replace("APP", name)).unwrap();
+    let mut app: Application = conf.into();
+    app.path = Some(path.to_string());
+    app
+}
+
+#[test]
+fn merges_fan_out_to_the_affected_applications() {
+    let mut ctx = common::context();
+    ctx.applications = vec![
+        application("api", "services/api"),
+        application("web", "services/web"),
+    ];
+    let event = VcsEvent::Merge {
+        to_branch: "master".to_string(),
+        from_branch: "feature".to_string(),
+        sha: "abc".to_string(),
+        changed_files: Some(vec!["services/web/index.html".to_string()]),
+    };
+    let request = WebhookRequest {
+        headers: HashMap::new(),
+        body: String::new(),
+    };
+    let mut pipelines = handle_web_hook_event(
+        &FixedEvent::new(event.clone()).with_repo(MONOREPO),
+        &mut ctx,
+        request,
+    );
+    assert_eq!(pipelines.len(), 1);
+
+    let web = &ctx.applications[1];
+    let build: Box<dyn Perform> = Box::new(
+        Build::new("abc".to_string(), MONOREPO.to_string())
+            .with_branch(Some("master".to_string()))
+            .with_app(Some("zprobst/web".to_string()))
+            .with_path(Some("services/web".to_string())),
+    );
+    assert!(pipelines[0].pop_next_action().unwrap() == build);
+    let deploy: Box<dyn Perform> = Box::new(
+        Deploy::new(
+            web.stages[0].clone(),
+            MONOREPO.to_string(),
+            "abc".to_string(),
+        )
+        .with_app(Some("zprobst/web".to_string())),
+    );
+    assert!(pipelines[0].pop_next_action().unwrap() == deploy);
+
+    // Every application is affected when the changed files are not known.
+    let event = match event {
+        VcsEvent::Merge {
+            to_branch,
+            from_branch,
+            sha,
+            ..
+        } => VcsEvent::Merge {
+            to_branch,
+            from_branch,
+            sha,
+            changed_files: None,
+        },
+        _ => unreachable!(),
+    };
+    let request = WebhookRequest {
+        headers: HashMap::new(),
+        body: String::new(),
+    };
+    assert_eq!(
+        handle_web_hook_event(
+            &FixedEvent::new(event).with_repo(MONOREPO),
+            &mut ctx,
+            request
+        )
+        .len(),
+        2
+    );
+}
+
+#[test]
+fn applications_are_loaded_from_their_directories() {
+    let dir = tempfile::tempdir().unwrap();
+    for (name, path) in &[("api", "services/api"), ("web", "services/web")] {
+        fs::create_dir_all(dir.path().join(path)).unwrap();
+        fs::write(
+            dir.path().join(path).join(".conveyor.yaml"),
+            CONFIG.replace("APP", name),
+        )
+        .unwrap();
+    }
+    // Only the directories that the root lists are searched.
+    fs::create_dir_all(dir.path().join("node_modules/dep")).unwrap();
+    fs::write(
+        dir.path().join("node_modules/dep/.conveyor.yaml"),
+        CONFIG.replace("APP", "dep"),
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join(".conveyor.yaml"),
+        "apps:\n  - services/api\n  - services/web/\n",
+    )
+    .unwrap();
+
+    let apps = load_apps_from_repo(dir.path()).unwrap();
+    let names: Vec<_> = apps
+        .iter()
+        .map(|app| (app.full_name(), app.path.clone().unwrap()))
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            ("zprobst/api".to_string(), "services/api".to_string()),
+            ("zprobst/web".to_string(), "services/web".to_string()),
+        ]
+    );
+    assert!(apps.iter().all(|app| app.is_for_repo(MONOREPO)));
+    assert_eq!(apps[0].repo_url(), MONOREPO);
+}
+
+#[test]
+fn app_directories_have_to_be_in_the_repository() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = dir.path().join("repo");
+    fs::create_dir_all(&repo).unwrap();
+    fs::write(
+        dir.path().join(".conveyor.yaml"),
+        CONFIG.replace("APP", "outside"),
+    )
+    .unwrap();
+    fs::write(repo.join(".conveyor.yaml"), "apps:\n  - ../\n").unwrap();
+    assert!(load_apps_from_repo(&repo).is_err());
+
+    // The root can be an application and list others alike.
+    fs::create_dir_all(repo.join("web")).unwrap();
+    fs::write(
+        repo.join("web/.conveyor.yaml"),
+        CONFIG.replace("APP", "web"),
+    )
+    .unwrap();
+    fs::write(
+        repo.join(".conveyor.yaml"),
+        format!("{}apps:\n  - web\n", CONFIG.replace("APP", "api")),
+    )
+    .unwrap();
+    let apps = load_apps_from_repo(&repo).unwrap();
+    let paths: Vec<_> = apps.iter().map(|app| app.path.clone()).collect();
+    assert_eq!(paths, vec![None, Some("web".to_string())]);
+}
+
+#[test]
+fn applications_inherit_the_repository_of_the_root() {
+    let dir = tempfile::tempdir().unwrap();
+    let own = CONFIG.replace("APP", "api");
+    let inherited = CONFIG
+        .replace("APP", "web")
+        .replace("repo: zprobst/monorepo\n", "");
+    for (path, config) in &[("api", own), ("web", inherited)] {
+        fs::create_dir_all(dir.path().join(path)).unwrap();
+        fs::write(dir.path().join(path).join(".conveyor.yaml"), config).unwrap();
+    }
+    fs::write(
+        dir.path().join(".conveyor.yaml"),
+        "repo: zprobst/other\napps:\n  - api\n  - web\n",
+    )
+    .unwrap();
+
+    let apps = load_apps_from_repo(dir.path()).unwrap();
+    assert_eq!(apps[0].repo_name(), "zprobst/monorepo");
+    assert_eq!(apps[1].repo_name(), "zprobst/other");
+}
@@ -0,0 +1,158 @@
+mod common;
+
+use chrono::Utc;
+use cloud_conveyor_core::history::{DeploymentOutcome, DeploymentRecord};
+use cloud_conveyor_core::webhook::{
+    handle_web_hook_event, CompareCommits, VcsEvent, WebhookRequest,
+};
+use cloud_conveyor_core::yaml::{ConfigError, ConfigFile};
+use cloud_conveyor_core::PathFilter;
+use common::FixedEvent;
+use failure::Error;
+
+use std::collections::HashMap;
+
+const CONFIG: &str = r#"
+org: zprobst
+app: cloud-conveyor
+accounts:
+  - name: default
+    id: 123456789
+    regions:
+      - us-east-1
+approvals: {}
+triggers:
+  - merge:
+      to: master
+      deploy:
+        - stage
+      paths:
+        - "services/api/**"
+      paths_ignore:
+        - "**/*.md"
+stages:
+  - name: stage
+"#;
+
+fn merge(changed_files: Option<&[&str]>) -> usize {
+    let conf: ConfigFile = serde_yaml::from_str(CONFIG).unwrap();
+    let mut ctx = common::context();
+    ctx.applications = vec![conf.into()];
+    let event = VcsEvent::Merge {
+        to_branch: "master".to_string(),
+        from_branch: "feature".to_string(),
+        sha: "abc".to_string(),
+        changed_files: changed_files.map(|files| files.iter().map(|f| f.to_string()).collect()),
+    };
+    let request = WebhookRequest {
+        headers: HashMap::new(),
+        body: String::new(),
+    };
+    handle_web_hook_event(&FixedEvent::new(event), &mut ctx, request).len()
+}
+
+#[test]
+fn merges_that_change_no_relevant_files_are_skipped() {
+    assert_eq!(merge(Some(&["services/api/src/main.rs"])), 1);
+    assert_eq!(merge(Some(&["docs/index.md", "services/api/lib.rs"])), 1);
+    assert_eq!(merge(Some(&["services/web/src/main.rs"])), 0);
+    assert_eq!(merge(Some(&["services/api/README.md"])), 0);
+    assert_eq!(merge(Some(&[])), 0);
+    // When the changed files are not known, the merge is never skipped.
+    assert_eq!(merge(None), 1);
+}
+
+#[test]
+fn path_patterns_do_not_cross_directories_unless_asked_to() {
+    let filter = PathFilter {
+        paths: vec!["src/*".to_string()],
+        paths_ignore: Vec::new(),
+    };
+    let files = |files: &[&str]| files.iter().map(|f| f.to_string()).collect::<Vec<_>>();
+    assert!(filter.is_relevant(Some(&files(&["src/main.rs"]))));
+    assert!(!filter.is_relevant(Some(&files(&["src/bin/cli.rs"]))));
+    assert!(PathFilter::default().is_relevant(Some(&files(&[]))));
+}
+
+#[test]
+fn invalid_path_patterns_fail_validation() {
+    let conf: ConfigFile = serde_yaml::from_str(&CONFIG.replace("**/*.md", "docs/[")).unwrap();
+    assert!(matches!(
+        conf.validate(),
+        Err(ConfigError::InvalidPathPattern { pattern, .. }) if pattern == "docs/["
+    ));
+}
+
+/// Knows the files that changed since the commit `v1`, which is what the stage runs.
+#[derive(Debug)]
+struct SinceV1(&'static [&'static str]);
+
+impl CompareCommits for SinceV1 {
+    fn changed_files(&self, _repo: &str, base: &str, _head: &str) -> Result<Vec<String>, Error> {
+        match base {
+            "v1" => Ok(self.0.iter().map(|f| f.to_string()).collect()),
+            _ => failure::bail!("Unknown commit {}", base),
+        }
+    }
+}
+
+/// Gets the names of the stages that the tag of the sha is deployed to, after `v1` was deployed to `stage`.
+fn tag(commits: Option<SinceV1>) -> Vec<String> {
+    let tag = r#"
+  - tag:
+      pattern: semver
+      deploy:
+        - stage
+        - prod
+      paths:
+        - "services/api/**"
+stages:"#;
+    let config = CONFIG.replacen("\nstages:", tag, 1) + "  - name: prod\n";
+    let conf: ConfigFile = serde_yaml::from_str(&config).unwrap();
+    assert_eq!(conf.validate(), Ok(()));
+    let mut ctx = common::context();
+    ctx.applications = vec![conf.into()];
+    let record = DeploymentRecord {
+        git_ref: "v1".to_string(),
+        deployed_at: Utc::now(),
+        outcome: DeploymentOutcome::Succeeded,
+        rollback: false,
+        tag: Some("v1.0.0".to_string()),
+    };
+    ctx.history
+        .record(&ctx.applications[0], "stage", &record)
+        .unwrap();
+
+    let event = VcsEvent::TagPush {
+        tag: "v1.1.0".to_string(),
+        sha: "v2".to_string(),
+        changed_files: None,
+    };
+    let mut interpreter = FixedEvent::new(event);
+    if let Some(commits) = commits {
+        interpreter = interpreter.with_commits(commits);
+    }
+    let request = WebhookRequest {
+        headers: HashMap::new(),
+        body: String::new(),
+    };
+    let mut stages = Vec::new();
+    for mut pipeline in handle_web_hook_event(&interpreter, &mut ctx, request) {
+        while let Some(action) = pipeline.pop_next_action() {
+            stages.extend(action.details().stage);
+        }
+    }
+    stages
+}
+
+#[test]
+fn tags_are_filtered_on_the_files_they_change_since_what_a_stage_runs() {
+    // Nothing was deployed to prod, so every file of the tag is new to it.
+    assert_eq!(tag(Some(SinceV1(&["docs/index.md"]))), vec!["prod"]);
+    assert_eq!(
+        tag(Some(SinceV1(&["services/api/main.rs"]))),
+        vec!["stage", "prod"]
+    );
+    // When the changes can not be looked up, the tag is never skipped.
+    assert_eq!(tag(None), vec!["stage", "prod"]);
+}
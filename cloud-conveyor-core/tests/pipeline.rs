@@ -1,5 +1,12 @@
-#[cfg(test)]
-use cloud_conveyor_core::pipelining::{Build, Pipeline};
+mod common;
+
+use cloud_conveyor_core::history::{DeploymentOutcome, DeploymentRecord};
+use cloud_conveyor_core::pipelining::{ActionResult, Build, Deploy, Perform, Pipeline};
+use cloud_conveyor_core::runtime::RuntimeContext;
+use cloud_conveyor_core::state_machine::StateMachine;
+use common::{FailingInfrastructure, REPO};
+
+use chrono::Utc;
 
 #[test]
 fn pipeline_allows_one_build() {
@@ -14,3 +21,125 @@ fn pipeline_allows_one_build() {
     assert!(pipeline.pop_next_action().is_some());
     assert!(pipeline.pop_next_action().is_none());
 }
+
+#[test]
+fn actions_are_popped_in_the_order_they_were_added() {
+    let mut pipeline = Pipeline::default()
+        .add_action(Box::new(Build::new("one".to_string(), REPO.to_string())))
+        .add_action(Box::new(Build::new("two".to_string(), REPO.to_string())));
+    pipeline.add_immediate_action(Box::new(Build::new("zero".to_string(), REPO.to_string())));
+    for sha in &["zero", "one", "two"] {
+        let next = pipeline.pop_next_action().unwrap();
+        let expected: Box<dyn Perform> = Box::new(Build::new(sha.to_string(), REPO.to_string()));
+        assert!(next == expected);
+    }
+}
+
+fn run(machine: &mut StateMachine, ctx: &RuntimeContext) {
+    while machine.tick_machine_state(ctx).unwrap() {}
+}
+
+fn deploy(ctx: &RuntimeContext, git_ref: &str, rollback: bool) -> Pipeline {
+    let mut stage = ctx.applications[0].stages[0].clone();
+    stage.rollback = rollback;
+    Pipeline::empty()
+        .add_action(Box::new(Build::new(git_ref.to_string(), REPO.to_string())))
+        .add_action(Box::new(Deploy::new(
+            stage,
+            REPO.to_string(),
+            git_ref.to_string(),
+        )))
+}
+
+fn outcomes(ctx: &RuntimeContext) -> Vec<(String, DeploymentOutcome, bool)> {
+    ctx.history
+        .list(&common::application(), "stage")
+        .unwrap()
+        .into_iter()
+        .map(|record| (record.git_ref, record.outcome, record.rollback))
+        .collect()
+}
+
+#[test]
+fn state_machine_starts_every_action_in_order() {
+    let ctx = common::context();
+    let mut machine = StateMachine::new(deploy(&ctx, "good", false));
+    run(&mut machine, &ctx);
+
+    let results: Vec<_> = machine
+        .pipeline()
+        .completed_actions()
+        .map(|(_, result)| result)
+        .collect();
+    assert_eq!(
+        results,
+        vec![&ActionResult::Success, &ActionResult::Success]
+    );
+    assert_eq!(
+        outcomes(&ctx),
+        vec![("good".to_string(), DeploymentOutcome::Succeeded, false)]
+    );
+}
+
+#[test]
+fn failed_deployment_is_rolled_back_to_the_last_successful_one() {
+    let mut ctx = common::context();
+    ctx.infrastructure = Box::new(FailingInfrastructure);
+    for (git_ref, outcome) in &[
+        ("older", DeploymentOutcome::Succeeded),
+        ("good", DeploymentOutcome::Succeeded),
+        ("broken", DeploymentOutcome::Failed),
+    ] {
+        let record = DeploymentRecord {
+            git_ref: git_ref.to_string(),
+            deployed_at: Utc::now(),
+            outcome: outcome.clone(),
+            rollback: false,
+            tag: None,
+        };
+        ctx.history
+            .record(&common::application(), "stage", &record)
+            .unwrap();
+    }
+
+    let mut machine = StateMachine::new(deploy(&ctx, "bad", true));
+    run(&mut machine, &ctx);
+    assert_eq!(
+        outcomes(&ctx)[3..].to_vec(),
+        vec![
+            ("bad".to_string(), DeploymentOutcome::Failed, false),
+            ("good".to_string(), DeploymentOutcome::Succeeded, true),
+        ]
+    );
+    let results: Vec<_> = machine
+        .pipeline()
+        .completed_actions()
+        .map(|(_, result)| result)
+        .collect();
+    assert_eq!(
+        results,
+        vec![
+            &ActionResult::Success,
+            &ActionResult::Failed,
+            &ActionResult::Success
+        ]
+    );
+}
+
+#[test]
+fn failed_deployment_is_not_rolled_back_unless_the_stage_says_so() {
+    let mut ctx = common::context();
+    ctx.infrastructure = Box::new(FailingInfrastructure);
+    let mut machine = StateMachine::new(deploy(&ctx, "good", true));
+    run(&mut machine, &ctx);
+
+    let mut machine = StateMachine::new(deploy(&ctx, "bad", false));
+    run(&mut machine, &ctx);
+    assert_eq!(
+        outcomes(&ctx),
+        vec![
+            ("good".to_string(), DeploymentOutcome::Succeeded, false),
+            ("bad".to_string(), DeploymentOutcome::Failed, false),
+        ]
+    );
+}
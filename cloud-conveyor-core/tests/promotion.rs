@@ -0,0 +1,443 @@
+mod common;
+
+use cloud_conveyor_core::approval::{Answer, Decision, MemoryApprovals};
+use cloud_conveyor_core::audit::Origin;
+use cloud_conveyor_core::chat::ChatCommand;
+use cloud_conveyor_core::pipelining::{ActionResult, Build, Deploy, Pipeline, Teardown};
+use cloud_conveyor_core::promotion::{Promotion, PromotionError, Rollback};
+use cloud_conveyor_core::runtime::RuntimeContext;
+use cloud_conveyor_core::state_machine::StateMachine;
+use cloud_conveyor_core::webhook::{handle_web_hook_event, VcsEvent, WebhookRequest};
+use cloud_conveyor_core::yaml::ConfigFile;
+use cloud_conveyor_core::Application;
+use common::{FailingInfrastructure, FixedEvent, REPO};
+
+use std::collections::HashMap;
+
+const CONFIG: &str = r#"
+org: zprobst
+app: cloud-conveyor
+accounts:
+  - name: default
+    id: 123456789
+    regions:
+      - us-east-1
+approvals:
+  admins:
+    type: slack
+    people:
+      - zprobst
+triggers:
+  - promote:
+      from: stage
+      to: prod
+  - tag:
+      pattern: semver
+      deploy:
+        - prod
+      ordered: true
+stages:
+  - name: stage
+  - name: prod
+    approvers: admins
+"#;
+
+fn application() -> Application {
+    let conf: ConfigFile = serde_yaml::from_str(CONFIG).unwrap();
+    conf.into()
+}
+
+fn context() -> RuntimeContext {
+    let mut ctx = common::context();
+    ctx.infrastructure = Box::new(FailingInfrastructure);
+    ctx.applications = vec![application()];
+    ctx
+}
+
+/// Builds the sha and deploys it to the stage named `stage`.
+fn deploy_to_stage(ctx: &RuntimeContext, git_ref: &str) {
+    deploy_tag_to_stage(ctx, git_ref, None);
+}
+
+/// Builds the sha and deploys it to the stage named `stage` for the tag.
+fn deploy_tag_to_stage(ctx: &RuntimeContext, git_ref: &str, tag: Option<&str>) {
+    let stage = ctx.applications[0].stages[0].clone();
+    let pipeline = Pipeline::empty()
+        .add_action(Box::new(Build::new(git_ref.to_string(), REPO.to_string())))
+        .add_action(Box::new(
+            Deploy::new(stage, REPO.to_string(), git_ref.to_string())
+                .with_tag(tag.map(str::to_string)),
+        ));
+    let mut machine = StateMachine::new(pipeline);
+    while machine.tick_machine_state(ctx).unwrap() {}
+}
+
+/// Gets the names of the stages that a push of the tag deploys to.
+fn tag_stages(ctx: &mut RuntimeContext, tag: &str) -> Vec<String> {
+    let event = VcsEvent::TagPush {
+        tag: tag.to_string(),
+        sha: "newer".to_string(),
+        changed_files: None,
+    };
+    let request = WebhookRequest {
+        headers: HashMap::new(),
+        body: String::new(),
+    };
+    let mut stages = Vec::new();
+    for mut pipeline in handle_web_hook_event(&FixedEvent::new(event), ctx, request) {
+        while let Some(action) = pipeline.pop_next_action() {
+            if action.typetag_name() == "Deploy" {
+                stages.extend(action.details().stage);
+            }
+        }
+    }
+    stages
+}
+
+/// Answers the approval of the deployment of the sha to prod on behalf of the person.
+fn answer(approvals: &MemoryApprovals, git_ref: &str, person: &str, decision: Decision) {
+    let answer = Answer {
+        person: person.to_string(),
+        decision,
+    };
+    approvals.answer("zprobst/cloud-conveyor", "prod", git_ref, answer);
+}
+
+fn promote(ctx: &RuntimeContext, from: &str, to: &str) -> Result<Promotion, PromotionError> {
+    Promotion::of(
+        &ctx.applications[0],
+        from,
+        to,
+        &*ctx.history,
+        &*ctx.artifact_provider,
+    )
+}
+
+#[test]
+fn live_sha_is_promoted_after_approval_without_a_build() {
+    let ctx = context();
+    deploy_to_stage(&ctx, "good");
+
+    let promotion = promote(&ctx, "stage", "prod").unwrap();
+    assert_eq!(promotion.git_ref, "good");
+    let mut pipeline = ChatCommand::parse("promote cloud-conveyor from stage to prod")
+        .unwrap()
+        .to_pipeline("zprobst", &ctx)
+        .unwrap();
+    assert_eq!(
+        pipeline.origin().map(|origin| origin.triggered_by.as_str()),
+        Some("zprobst")
+    );
+
+    let approval = pipeline.pop_next_action().unwrap();
+    assert_eq!(approval.typetag_name(), "Approval");
+    assert_eq!(approval.details().stage.as_deref(), Some("prod"));
+    let deploy = pipeline.pop_next_action().unwrap();
+    assert_eq!(deploy.typetag_name(), "Deploy");
+    assert_eq!(deploy.details().git_ref.as_deref(), Some("good"));
+    assert_eq!(deploy.details().stage.as_deref(), Some("prod"));
+    assert!(pipeline.pop_next_action().is_none());
+}
+
+#[test]
+fn promotion_is_refused_when_the_source_deployment_failed() {
+    let ctx = context();
+    assert_eq!(
+        promote(&ctx, "stage", "prod"),
+        Err(PromotionError::NothingDeployed {
+            stage: "stage".to_string()
+        })
+    );
+
+    deploy_to_stage(&ctx, "good");
+    deploy_to_stage(&ctx, "bad");
+    assert_eq!(
+        promote(&ctx, "stage", "prod"),
+        Err(PromotionError::SourceFailed {
+            git_ref: "bad".to_string(),
+            stage: "stage".to_string()
+        })
+    );
+}
+
+#[test]
+fn promotion_is_refused_when_the_source_was_torn_down() {
+    let ctx = context();
+    deploy_to_stage(&ctx, "good");
+    let stage = ctx.applications[0].stages[0].clone();
+    let pipeline = Pipeline::empty().add_action(Box::new(Teardown::new(stage, REPO.to_string())));
+    let mut machine = StateMachine::new(pipeline);
+    while machine.tick_machine_state(&ctx).unwrap() {}
+
+    assert_eq!(
+        promote(&ctx, "stage", "prod"),
+        Err(PromotionError::NothingDeployed {
+            stage: "stage".to_string()
+        })
+    );
+}
+
+#[test]
+fn promotion_must_be_allowed_by_a_trigger() {
+    let ctx = context();
+    deploy_to_stage(&ctx, "good");
+    assert_eq!(
+        promote(&ctx, "prod", "stage"),
+        Err(PromotionError::NotAllowed {
+            from: "prod".to_string(),
+            to: "stage".to_string()
+        })
+    );
+    assert_eq!(
+        promote(&ctx, "stage", "dev"),
+        Err(PromotionError::UnknownStage {
+            stage: "dev".to_string()
+        })
+    );
+    assert!(ChatCommand::parse("promote cloud-conveyor to prod").is_none());
+}
+
+#[test]
+fn promotion_waits_for_an_approver_before_deploying() {
+    let approvals = MemoryApprovals::default();
+    let mut ctx = context();
+    ctx.approvals = Box::new(approvals.clone());
+    deploy_to_stage(&ctx, "good");
+
+    let promotion = promote(&ctx, "stage", "prod").unwrap();
+    let pipeline = promotion.into_pipeline(&ctx.applications[0], Origin::manual("zprobst".into()));
+    let mut machine = StateMachine::new(pipeline);
+    assert!(machine.tick_machine_state(&ctx).unwrap());
+    assert!(machine.tick_machine_state(&ctx).unwrap());
+    assert_eq!(machine.current_action().unwrap().typetag_name(), "Approval");
+
+    answer(&approvals, "good", "someone-else", Decision::Approved);
+    assert!(machine.tick_machine_state(&ctx).unwrap());
+    assert_eq!(machine.current_action().unwrap().typetag_name(), "Approval");
+
+    answer(&approvals, "good", "zprobst", Decision::Approved);
+    while machine.tick_machine_state(&ctx).unwrap() {}
+    let completed: Vec<_> = machine
+        .pipeline()
+        .completed_actions()
+        .map(|(action, result)| {
+            (
+                action.typetag_name(),
+                action.details().approver,
+                result.clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        completed,
+        vec![
+            (
+                "Approval",
+                Some("zprobst".to_string()),
+                ActionResult::Success
+            ),
+            ("Deploy", None, ActionResult::Success),
+        ]
+    );
+    let live = ctx.history.last_successful(&ctx.applications[0], "prod");
+    assert_eq!(live.unwrap().unwrap().git_ref, "good");
+}
+
+#[test]
+fn rejected_promotion_is_not_deployed() {
+    let approvals = MemoryApprovals::default();
+    let mut ctx = context();
+    ctx.approvals = Box::new(approvals.clone());
+    deploy_to_stage(&ctx, "good");
+
+    let promotion = promote(&ctx, "stage", "prod").unwrap();
+    let pipeline = promotion.into_pipeline(&ctx.applications[0], Origin::manual("zprobst".into()));
+    let mut machine = StateMachine::new(pipeline);
+    assert!(machine.tick_machine_state(&ctx).unwrap());
+    answer(&approvals, "good", "zprobst", Decision::Rejected);
+    while machine.tick_machine_state(&ctx).unwrap() {}
+
+    let results: Vec<_> = machine
+        .pipeline()
+        .completed_actions()
+        .map(|(action, result)| (action.typetag_name(), result.clone()))
+        .collect();
+    assert_eq!(
+        results,
+        vec![
+            ("Deploy", ActionResult::Canceled),
+            ("Approval", ActionResult::Failed),
+        ]
+    );
+    assert!(ctx
+        .history
+        .list(&ctx.applications[0], "prod")
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn answers_only_decide_the_request_they_were_given_to() {
+    let approvals = MemoryApprovals::default();
+    let mut ctx = context();
+    ctx.approvals = Box::new(approvals.clone());
+    deploy_to_stage(&ctx, "good");
+    let start_promotion = |ctx: &RuntimeContext| {
+        let promotion = promote(ctx, "stage", "prod").unwrap();
+        let pipeline =
+            promotion.into_pipeline(&ctx.applications[0], Origin::manual("zprobst".into()));
+        let mut machine = StateMachine::new(pipeline);
+        assert!(machine.tick_machine_state(ctx).unwrap());
+        machine
+    };
+
+    // An answer given before the request does not count.
+    answer(&approvals, "good", "zprobst", Decision::Approved);
+    let mut rejected = start_promotion(&ctx);
+    assert!(rejected.tick_machine_state(&ctx).unwrap());
+    assert_eq!(
+        rejected.current_action().unwrap().typetag_name(),
+        "Approval"
+    );
+    answer(&approvals, "good", "zprobst", Decision::Rejected);
+    while rejected.tick_machine_state(&ctx).unwrap() {}
+
+    // Neither does the rejection of the request before.
+    let mut approved = start_promotion(&ctx);
+    assert!(approved.tick_machine_state(&ctx).unwrap());
+    assert_eq!(
+        approved.current_action().unwrap().typetag_name(),
+        "Approval"
+    );
+    answer(&approvals, "good", "zprobst", Decision::Approved);
+    while approved.tick_machine_state(&ctx).unwrap() {}
+
+    let results = |machine: &StateMachine| -> Vec<_> {
+        machine
+            .pipeline()
+            .completed_actions()
+            .map(|(action, result)| (action.typetag_name(), result.clone()))
+            .collect()
+    };
+    assert_eq!(
+        results(&rejected),
+        vec![
+            ("Deploy", ActionResult::Canceled),
+            ("Approval", ActionResult::Failed),
+        ]
+    );
+    assert_eq!(
+        results(&approved),
+        vec![
+            ("Approval", ActionResult::Success),
+            ("Deploy", ActionResult::Success),
+        ]
+    );
+    let live = ctx.history.last_successful(&ctx.applications[0], "prod");
+    assert_eq!(live.unwrap().unwrap().git_ref, "good");
+}
+
+#[test]
+fn rollback_is_held_to_the_rules_of_a_promotion() {
+    let ctx = context();
+    let app = &ctx.applications[0];
+    let rollback = |git_ref: Option<&str>| {
+        Rollback::of(
+            app,
+            "stage",
+            git_ref,
+            &*ctx.history,
+            &*ctx.artifact_provider,
+        )
+    };
+    assert_eq!(
+        rollback(None),
+        Err(PromotionError::NothingDeployed {
+            stage: "stage".to_string()
+        })
+    );
+
+    deploy_to_stage(&ctx, "good");
+    deploy_to_stage(&ctx, "bad");
+    assert_eq!(rollback(None).unwrap().git_ref, "good");
+    assert_eq!(rollback(Some("good")).unwrap().git_ref, "good");
+    assert_eq!(
+        rollback(Some("bad")),
+        Err(PromotionError::NeverDeployed {
+            git_ref: "bad".to_string(),
+            stage: "stage".to_string()
+        })
+    );
+
+    ctx.artifact_provider.delete(app, "good").unwrap();
+    assert_eq!(
+        rollback(Some("good")),
+        Err(PromotionError::MissingArtifacts {
+            git_ref: "good".to_string()
+        })
+    );
+}
+
+#[test]
+fn rollback_waits_for_the_approvers_of_the_stage() {
+    let mut ctx = context();
+    let mut prod = ctx.applications[0].stages[1].clone();
+    prod.name = "stage".to_string();
+    ctx.applications[0].stages[0] = prod;
+    deploy_to_stage(&ctx, "good");
+
+    let app = &ctx.applications[0];
+    let rollback = Rollback::of(
+        app,
+        "stage",
+        Some("good"),
+        &*ctx.history,
+        &*ctx.artifact_provider,
+    );
+    let mut pipeline = rollback
+        .unwrap()
+        .into_pipeline(app, Origin::manual("zprobst".into()));
+    let approval = pipeline.pop_next_action().unwrap();
+    assert_eq!(approval.typetag_name(), "Approval");
+    let deploy = pipeline.pop_next_action().unwrap();
+    assert_eq!(deploy.details().git_ref.as_deref(), Some("good"));
+    assert!(pipeline.pop_next_action().is_none());
+}
+
+#[test]
+fn promotion_keeps_the_tag_that_ordered_triggers_go_by() {
+    let approvals = MemoryApprovals::default();
+    let mut ctx = context();
+    ctx.approvals = Box::new(approvals.clone());
+    deploy_tag_to_stage(&ctx, "good", Some("v2.0.0"));
+
+    let promotion = promote(&ctx, "stage", "prod").unwrap();
+    assert_eq!(promotion.tag.as_deref(), Some("v2.0.0"));
+    let pipeline = promotion.into_pipeline(&ctx.applications[0], Origin::manual("zprobst".into()));
+    let mut machine = StateMachine::new(pipeline);
+    assert!(machine.tick_machine_state(&ctx).unwrap());
+    answer(&approvals, "good", "zprobst", Decision::Approved);
+    while machine.tick_machine_state(&ctx).unwrap() {}
+    let live = ctx.history.last_successful(&ctx.applications[0], "prod");
+    assert_eq!(live.unwrap().unwrap().tag.as_deref(), Some("v2.0.0"));
+
+    assert!(tag_stages(&mut ctx, "v1.9.0").is_empty());
+    assert_eq!(tag_stages(&mut ctx, "v2.1.0"), vec!["prod"]);
+}
+
+#[test]
+fn rollback_keeps_the_tag_that_ordered_triggers_go_by() {
+    let ctx = context();
+    deploy_tag_to_stage(&ctx, "good", Some("v2.0.0"));
+    deploy_tag_to_stage(&ctx, "bad", Some("v2.1.0"));
+
+    let app = &ctx.applications[0];
+    let rollback = Rollback::of(app, "stage", None, &*ctx.history, &*ctx.artifact_provider);
+    let mut pipeline = rollback
+        .unwrap()
+        .into_pipeline(app, Origin::manual("zprobst".into()));
+    let deploy = pipeline.pop_next_action().unwrap();
+    assert_eq!(deploy.details().git_ref.as_deref(), Some("good"));
+    let deploy: &Deploy = deploy.as_any().downcast_ref().unwrap();
+    assert_eq!(deploy.tag.as_deref(), Some("v2.0.0"));
+}
@@ -0,0 +1,256 @@
+mod common;
+
+use cloud_conveyor_core::audit::Transition;
+use cloud_conveyor_core::pipelining::Pipeline;
+use cloud_conveyor_core::runtime::RuntimeContext;
+use cloud_conveyor_core::state_machine::StateMachine;
+use cloud_conveyor_core::webhook::{handle_web_hook_event, PrChange, VcsEvent, WebhookRequest};
+use cloud_conveyor_core::yaml::{ConfigError, ConfigFile};
+use cloud_conveyor_core::{Application, PrFilter};
+use common::FixedEvent;
+
+use std::collections::HashMap;
+
+const CONFIG: &str = r#"
+org: zprobst
+app: cloud-conveyor
+accounts:
+  - name: default
+    id: 123456789
+    regions:
+      - us-east-1
+approvals: {}
+triggers:
+  - pr:
+      deploy: true
+      to: ^master$
+      from: ^feature/
+      label: deploy-preview
+      skip_drafts: true
+stages:
+  - name: stage
+"#;
+
+fn application(config: &str) -> Application {
+    let conf: ConfigFile = serde_yaml::from_str(config).unwrap();
+    conf.into()
+}
+
+/// Handles the event and returns the resulting pipelines.
+fn handle(ctx: &mut RuntimeContext, event: VcsEvent) -> Vec<Pipeline> {
+    let request = WebhookRequest {
+        headers: HashMap::new(),
+        body: String::new(),
+    };
+    handle_web_hook_event(&FixedEvent::new(event), ctx, request)
+}
+
+/// Describes the actions of the pipelines by their kinds and stages.
+fn describe(pipelines: Vec<Pipeline>) -> Vec<String> {
+    let mut actions = Vec::new();
+    for mut pipeline in pipelines {
+        while let Some(action) = pipeline.pop_next_action() {
+            let stage = action.details().stage.unwrap_or_default();
+            actions.push(
+                format!("{} {}", action.typetag_name(), stage)
+                    .trim()
+                    .to_string(),
+            );
+        }
+    }
+    actions
+}
+
+/// The update of PR 7 from the source branch into the target branch.
+fn pr_update(
+    source: &str,
+    target: &str,
+    labels: &[&str],
+    draft: bool,
+    change: PrChange,
+) -> VcsEvent {
+    VcsEvent::PullRequestUpdate {
+        source_branch: source.to_string(),
+        target_branch: Some(target.to_string()),
+        labels: labels.iter().map(|l| l.to_string()).collect(),
+        draft,
+        number: 7,
+        sha: "abc".to_string(),
+        changed_files: None,
+        change,
+    }
+}
+
+/// Handles the update of PR 7 and returns the kinds of the actions of the resulting pipelines.
+fn update(
+    source: &str,
+    target: &str,
+    labels: &[&str],
+    draft: bool,
+    change: PrChange,
+) -> Vec<String> {
+    let mut ctx = common::context();
+    ctx.applications = vec![application(CONFIG)];
+    describe(handle(
+        &mut ctx,
+        pr_update(source, target, labels, draft, change),
+    ))
+}
+
+fn labeled(label: &str) -> PrChange {
+    PrChange::Labeled(label.to_string())
+}
+
+fn unlabeled(label: &str) -> PrChange {
+    PrChange::Unlabeled(label.to_string())
+}
+
+#[test]
+fn prs_are_deployed_once_labeled() {
+    let label = &["deploy-preview"];
+    assert_eq!(
+        update("feature/a", "master", &[], false, PrChange::Push),
+        vec!["Build"]
+    );
+    assert_eq!(
+        update("feature/a", "master", label, false, PrChange::Push),
+        vec!["Build", "Deploy pr-7"]
+    );
+    assert_eq!(
+        update(
+            "feature/a",
+            "master",
+            label,
+            false,
+            labeled("deploy-preview")
+        ),
+        vec!["Build", "Deploy pr-7"]
+    );
+    // Removing the label tears the stack of the PR down.
+    assert_eq!(
+        update(
+            "feature/a",
+            "master",
+            &["bug"],
+            false,
+            unlabeled("deploy-preview")
+        ),
+        vec!["Teardown pr-7"]
+    );
+}
+
+#[test]
+fn changes_of_other_labels_are_ignored() {
+    let label = &["deploy-preview"];
+    assert!(update(
+        "feature/a",
+        "master",
+        &["deploy-preview", "bug"],
+        false,
+        labeled("bug")
+    )
+    .is_empty());
+    assert!(update("feature/a", "master", label, false, unlabeled("bug")).is_empty());
+    assert!(update("feature/a", "master", &["bug"], false, labeled("bug")).is_empty());
+}
+
+#[test]
+fn draft_prs_are_skipped_until_ready() {
+    let label = &["deploy-preview"];
+    assert!(update("feature/a", "master", label, true, PrChange::Push).is_empty());
+    assert_eq!(
+        update(
+            "feature/a",
+            "master",
+            label,
+            true,
+            PrChange::ConvertedToDraft
+        ),
+        vec!["Teardown pr-7"]
+    );
+    assert_eq!(
+        update(
+            "feature/a",
+            "master",
+            label,
+            false,
+            PrChange::ReadyForReview
+        ),
+        vec!["Build", "Deploy pr-7"]
+    );
+}
+
+#[test]
+fn prs_between_other_branches_are_ignored() {
+    let label = &["deploy-preview"];
+    assert!(update("bugfix/a", "master", label, false, PrChange::Push).is_empty());
+    assert!(update("feature/a", "release", label, false, PrChange::Push).is_empty());
+
+    let filter = PrFilter::default();
+    assert!(filter.matches_branches(None, "anything"));
+    assert!(filter.allows_deploy(&[], true));
+}
+
+#[test]
+fn invalid_branch_patterns_fail_validation() {
+    let conf: ConfigFile = serde_yaml::from_str(&CONFIG.replace("^feature/", "feature/(")).unwrap();
+    assert!(matches!(
+        conf.validate(),
+        Err(ConfigError::InvalidPattern { pattern, .. }) if pattern == "feature/("
+    ));
+}
+
+#[test]
+fn stack_that_was_deployed_is_torn_down_when_unlabeled_or_closed() {
+    let config = CONFIG.replace(
+        "      skip_drafts: true\n",
+        "      skip_drafts: true\n      stage_name: \"{app}-{branch}\"\n",
+    );
+    let mut ctx = common::context();
+    ctx.applications = vec![application(&config)];
+    let create = VcsEvent::PullRequestCreate {
+        source_branch: "feature/a".to_string(),
+        target_branch: Some("master".to_string()),
+        labels: vec!["deploy-preview".to_string()],
+        draft: false,
+        number: 7,
+        sha: "abc".to_string(),
+        changed_files: None,
+    };
+    let unlabel = pr_update(
+        "feature/a",
+        "master",
+        &[],
+        false,
+        unlabeled("deploy-preview"),
+    );
+    let close = VcsEvent::PullRequestComplete {
+        number: 7,
+        merged: false,
+        source_branch: "feature/a".to_string(),
+    };
+    for event in [create, unlabel, close] {
+        for pipeline in handle(&mut ctx, event) {
+            let mut machine = StateMachine::new(pipeline);
+            while machine.tick_machine_state(&ctx).unwrap() {}
+        }
+    }
+
+    let stages: Vec<_> = ctx
+        .audit
+        .entries(&ctx.applications[0])
+        .unwrap()
+        .into_iter()
+        .filter(|entry| entry.transition == Transition::Completed && entry.stage.is_some())
+        .map(|entry| (entry.action, entry.stage.unwrap(), entry.pr))
+        .collect();
+    let stage = "cloud-conveyor-feature-a".to_string();
+    assert_eq!(
+        stages,
+        vec![
+            ("Deploy".to_string(), stage.clone(), Some(7)),
+            ("Teardown".to_string(), stage.clone(), Some(7)),
+            ("Teardown".to_string(), stage, Some(7)),
+        ]
+    );
+}
@@ -0,0 +1,141 @@
+mod common;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use cloud_conveyor_core::audit::{ActionDetails, AuditEntry, AuditLog, MemoryAuditLog, Transition};
+use cloud_conveyor_core::pipelining::{ActionResult, Perform, Teardown};
+use cloud_conveyor_core::reaper::{ReapReason, Reaper, ResolvePullRequests};
+use cloud_conveyor_core::{Account, PrStages, Stage};
+use failure::Error;
+
+/// Knows that only PR 1 is open.
+#[derive(Debug)]
+struct OnlyFirstOpen;
+
+impl ResolvePullRequests for OnlyFirstOpen {
+    fn is_open(&self, _repo: &str, number: u32) -> Result<bool, Error> {
+        Ok(number == 1)
+    }
+}
+
+fn day(day: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2020, 6, day, 12, 0, 0).unwrap()
+}
+
+/// Records that the action completed on the stage on the day.
+fn record(log: &MemoryAuditLog, action: &str, stage: &str, on: u32) {
+    let details = ActionDetails {
+        stage: Some(stage.to_string()),
+        ..ActionDetails::default()
+    };
+    let mut entry = AuditEntry::new(
+        &common::application(),
+        action,
+        details,
+        Transition::Completed,
+        None,
+    )
+    .with_result(ActionResult::Success);
+    entry.at = day(on);
+    log.append(&entry).unwrap();
+}
+
+fn audit_log() -> MemoryAuditLog {
+    let log = MemoryAuditLog::default();
+    record(&log, "Deploy", "pr-1", 1);
+    record(&log, "Deploy", "pr-1", 9);
+    record(&log, "Deploy", "pr-2", 8);
+    record(&log, "Deploy", "pr-3", 1);
+    record(&log, "Deploy", "pr-4", 1);
+    record(&log, "Teardown", "pr-4", 2);
+    record(&log, "Deploy", "stage", 1);
+    log
+}
+
+#[test]
+fn idle_and_closed_pr_stages_are_torn_down() {
+    let apps = vec![common::application()];
+    let log = audit_log();
+    let reaper = Reaper::new(Duration::days(3)).with_pull_requests(Box::new(OnlyFirstOpen));
+
+    let mut report = reaper.reap_applications(&apps, &log, day(10)).unwrap();
+    assert!(!report.dry_run);
+    let reaped: Vec<_> = report
+        .candidates
+        .iter()
+        .map(|c| (c.stage.as_str(), c.reason.clone(), c.last_active))
+        .collect();
+    assert_eq!(
+        reaped,
+        vec![
+            ("pr-2", ReapReason::Closed, day(8)),
+            ("pr-3", ReapReason::Closed, day(1)),
+        ]
+    );
+
+    assert_eq!(report.pipelines.len(), 2);
+    let origin = report.pipelines[0].origin().unwrap();
+    assert_eq!(origin.triggered_by, "reaper");
+    let expected: Box<dyn Perform> = Box::new(
+        Teardown::new(
+            Stage::for_pr(&apps[0], &PrStages::default(), 2, "feature").unwrap(),
+            apps[0].repo_url(),
+        )
+        .with_app(Some(apps[0].full_name())),
+    );
+    assert!(report.pipelines[0].pop_next_action().unwrap() == expected);
+}
+
+#[test]
+fn dry_runs_only_report_idle_stages() {
+    let apps = vec![common::application()];
+    let log = audit_log();
+    let reaper = Reaper::new(Duration::days(3)).with_dry_run(true);
+
+    let report = reaper.reap_applications(&apps, &log, day(10)).unwrap();
+    assert!(report.dry_run);
+    assert!(report.pipelines.is_empty());
+    let reaped: Vec<_> = report.candidates.iter().map(|c| c.to_string()).collect();
+    assert_eq!(
+        reaped,
+        vec!["zprobst/cloud-conveyor pr-3: it is idle, last active at 2020-06-01 12:00:00 UTC"]
+    );
+}
+
+#[test]
+fn pr_stages_are_torn_down_where_they_were_deployed() {
+    let mut app = common::application();
+    app.accounts.push(Account {
+        name: "previews".to_string(),
+        id: 2,
+        regions: vec!["us-east-1".to_string(), "eu-west-1".to_string()],
+    });
+    let log = MemoryAuditLog::default();
+    let details = ActionDetails {
+        stage: Some("pr-5".to_string()),
+        account: Some("previews".to_string()),
+        regions: Some(vec!["eu-west-1".to_string()]),
+        pr: Some(5),
+        ..ActionDetails::default()
+    };
+    let mut entry = AuditEntry::new(&app, "Deploy", details, Transition::Completed, None)
+        .with_result(ActionResult::Success);
+    entry.at = day(1);
+    log.append(&entry).unwrap();
+    // Entries that do not say where the stage is do not change it.
+    record(&log, "Approval", "pr-5", 2);
+
+    let apps = vec![app];
+    let mut report = Reaper::new(Duration::days(3))
+        .reap_applications(&apps, &log, day(10))
+        .unwrap();
+    assert_eq!(report.candidates[0].last_active, day(2));
+    assert_eq!(report.candidates[0].account, Some("previews".to_string()));
+
+    // The stages of pull requests of the app now go to the default account.
+    let mut stage = Stage::for_pr(&apps[0], &PrStages::default(), 5, "feature").unwrap();
+    stage.account = apps[0].accounts[1].clone();
+    stage.account.regions = vec!["eu-west-1".to_string()];
+    let expected: Box<dyn Perform> =
+        Box::new(Teardown::new(stage, apps[0].repo_url()).with_app(Some(apps[0].full_name())));
+    assert!(report.pipelines[0].pop_next_action().unwrap() == expected);
+}
@@ -0,0 +1,160 @@
+mod common;
+
+use cloud_conveyor_core::build::{BuildPollError, BuildSource, BuildStatus};
+use cloud_conveyor_core::pipelining::{Build, Perform, Pipeline};
+use cloud_conveyor_core::redact::{self, RedactingLogger, Secret, REDACTED};
+use cloud_conveyor_core::runtime::RuntimeContext;
+use cloud_conveyor_core::secrets::Secrets;
+use cloud_conveyor_core::state_machine::StateMachine;
+use common::{MemorySecrets, REPO};
+use log::{Level, Log, Metadata, Record};
+use serde::Serialize;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// A builder whose builds fail with an error that echoes the secrets they were given,
+/// like a build script that prints its environment would.
+#[derive(Debug, Default)]
+struct EchoingBuilder {
+    echoed: RefCell<String>,
+}
+
+impl BuildSource for EchoingBuilder {
+    fn start_build(
+        &self,
+        _build: &Build,
+        secrets: &Secrets,
+        _ctx: &RuntimeContext,
+    ) -> Result<(), BuildPollError> {
+        let echoed: Vec<String> = secrets
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect();
+        self.echoed.replace(echoed.join(" "));
+        Ok(())
+    }
+
+    fn check_build(
+        &self,
+        _build: &Build,
+        _ctx: &RuntimeContext,
+    ) -> Result<BuildStatus, BuildPollError> {
+        Ok(BuildStatus::Failed {
+            logs: format!("logs?env={}", self.echoed.borrow()),
+            error: Some(format!("npm ERR! {}", self.echoed.borrow())),
+        })
+    }
+}
+
+/// Remembers the log lines it receives.
+#[derive(Debug, Default)]
+struct MemoryLogger {
+    lines: Mutex<Vec<String>>,
+}
+
+impl Log for MemoryLogger {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        self.lines.lock().unwrap().push(record.args().to_string());
+    }
+
+    fn flush(&self) {}
+}
+
+#[derive(Debug, Serialize)]
+struct Credentials {
+    user: String,
+    password: Secret<String>,
+}
+
+#[test]
+fn secret_is_not_debug_printed_or_serialized() {
+    let credentials = Credentials {
+        user: "admin".to_string(),
+        password: Secret::new("correct horse".to_string()),
+    };
+
+    let printed = format!("{:?}", credentials);
+    let serialized = serde_yaml::to_string(&credentials).unwrap();
+    for text in &[printed, serialized] {
+        assert!(text.contains("admin"));
+        assert!(text.contains(REDACTED));
+        assert!(!text.contains("correct horse"));
+    }
+    assert_eq!(credentials.password.expose(), "correct horse");
+}
+
+#[test]
+fn registered_values_are_redacted() {
+    redact::register("s3cr3t-value");
+    redact::register("s3cr3t");
+    redact::register("");
+
+    assert_eq!(
+        redact::redact("token s3cr3t-value and s3cr3t"),
+        format!("token {} and {}", REDACTED, REDACTED)
+    );
+    assert_eq!(redact::redact("nothing to see"), "nothing to see");
+}
+
+#[test]
+fn logger_redacts_registered_values() {
+    redact::register("log-line-secret");
+    let logger = RedactingLogger::new(MemoryLogger::default());
+
+    logger.log(
+        &Record::builder()
+            .args(format_args!("Starting Deploy with {}", "log-line-secret"))
+            .level(Level::Info)
+            .build(),
+    );
+
+    let lines = logger.inner().lines.lock().unwrap();
+    assert_eq!(*lines, vec![format!("Starting Deploy with {}", REDACTED)]);
+}
+
+#[test]
+fn build_secrets_never_reach_serialized_pipeline() {
+    let mut values = HashMap::new();
+    values.insert("NPM_TOKEN".to_string(), "npm-0123456789".to_string());
+    let mut ctx = common::context();
+    ctx.builder = Box::new(EchoingBuilder::default());
+    ctx.secrets = Box::new(MemorySecrets { values });
+
+    let mut build =
+        Build::new("sha".to_string(), REPO.to_string()).with_secrets(vec!["NPM_TOKEN".to_string()]);
+    build.start(&ctx).unwrap();
+    assert!(build.is_done(&ctx).unwrap());
+
+    let machine = StateMachine::new(Pipeline::empty().add_action(Box::new(build)));
+    let serialized = serde_yaml::to_string(&machine).unwrap();
+    assert!(serialized.contains("NPM_TOKEN"));
+    assert!(serialized.contains(REDACTED));
+    assert!(!serialized.contains("npm-0123456789"));
+}
+
+#[test]
+fn secrets_of_builds_started_elsewhere_are_redacted() {
+    let mut values = HashMap::new();
+    values.insert("NPM_TOKEN".to_string(), "npm-started-elsewhere".to_string());
+    let mut ctx = common::context();
+    let builder = EchoingBuilder::default();
+    builder
+        .echoed
+        .replace("NPM_TOKEN=npm-started-elsewhere".to_string());
+    ctx.builder = Box::new(builder);
+    ctx.secrets = Box::new(MemorySecrets { values });
+
+    // The build was started by another process, so this one only polls it.
+    let mut build =
+        Build::new("sha".to_string(), REPO.to_string()).with_secrets(vec!["NPM_TOKEN".to_string()]);
+    assert!(build.is_done(&ctx).unwrap());
+    let serialized = serde_yaml::to_string(&build).unwrap();
+    assert!(serialized.contains(REDACTED));
+    assert!(!serialized.contains("npm-started-elsewhere"));
+}
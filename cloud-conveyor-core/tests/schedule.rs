@@ -0,0 +1,182 @@
+mod common;
+
+use chrono::{DateTime, TimeZone, Utc};
+use cloud_conveyor_core::pipelining::{Build, Deploy, Perform};
+use cloud_conveyor_core::runtime::RuntimeContext;
+use cloud_conveyor_core::schedule::{
+    handle_schedule, next_runs, parse_cron, ResolveBranches, ScheduleEventSource,
+};
+use cloud_conveyor_core::webhook::VcsEvent;
+use cloud_conveyor_core::yaml::{ConfigError, ConfigFile};
+use cloud_conveyor_core::Application;
+use common::REPO;
+use failure::Error;
+
+use std::cell::Cell;
+
+const CONFIG: &str = r#"
+org: zprobst
+app: cloud-conveyor
+accounts:
+  - name: default
+    id: 123456789
+    regions:
+      - us-east-1
+approvals: {}
+triggers:
+  - schedule:
+      cron: "0 2 * * *"
+      branch: master
+      deploy:
+        - stage
+  - schedule:
+      cron: "0 0 12 * * Sun"
+      branch: release
+      deploy: []
+stages:
+  - name: stage
+"#;
+
+/// Every branch has the same head.
+#[derive(Debug)]
+struct FixedHead;
+
+impl ResolveBranches for FixedHead {
+    fn head(&self, _repo: &str, branch: &str) -> Result<String, Error> {
+        Ok(format!("{}-head", branch))
+    }
+}
+
+/// Only knows the heads of the repository at the url.
+#[derive(Debug)]
+struct OnlyRepo(&'static str);
+
+impl ResolveBranches for OnlyRepo {
+    fn head(&self, repo: &str, branch: &str) -> Result<String, Error> {
+        if repo != self.0 {
+            failure::bail!("Permission denied for {}", repo);
+        }
+        Ok(format!("{}-head", branch))
+    }
+}
+
+/// Fails to find the head of a branch the first time it is asked.
+#[derive(Debug, Default)]
+struct FailsOnce(Cell<bool>);
+
+impl ResolveBranches for FailsOnce {
+    fn head(&self, _repo: &str, branch: &str) -> Result<String, Error> {
+        if !self.0.replace(true) {
+            failure::bail!("Connection reset");
+        }
+        Ok(format!("{}-head", branch))
+    }
+}
+
+fn application() -> Application {
+    let conf: ConfigFile = serde_yaml::from_str(CONFIG).unwrap();
+    conf.into()
+}
+
+fn context() -> RuntimeContext {
+    let mut ctx = common::context();
+    ctx.applications = vec![application()];
+    ctx
+}
+
+/// A time on the first of june 2020, which is a monday.
+fn june(day: u32, hour: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2020, 6, day, hour, 0, 0).unwrap()
+}
+
+#[test]
+fn due_schedules_rebuild_and_deploy_the_head_of_the_branch() {
+    let ctx = context();
+    let mut source = ScheduleEventSource::new(Box::new(FixedHead), june(1, 0));
+    assert!(handle_schedule(&mut source, &ctx, june(1, 1)).is_empty());
+
+    let mut pipelines = handle_schedule(&mut source, &ctx, june(1, 3));
+    assert_eq!(pipelines.len(), 1);
+    let pipeline = &mut pipelines[0];
+    let origin = pipeline.origin().unwrap();
+    assert_eq!(origin.triggered_by, "schedule");
+    assert_eq!(
+        origin.event,
+        Some(VcsEvent::Schedule {
+            cron: "0 2 * * *".to_string(),
+            branch: "master".to_string(),
+            sha: "master-head".to_string(),
+        })
+    );
+
+    let build: Box<dyn Perform> = Box::new(
+        Build::new("master-head".to_string(), REPO.to_string())
+            .with_branch(Some("master".to_string()))
+            .with_force_rebuild(true)
+            .with_app(Some(ctx.applications[0].full_name())),
+    );
+    assert!(pipeline.pop_next_action().unwrap() == build);
+    let deploy: Box<dyn Perform> = Box::new(
+        Deploy::new(
+            ctx.applications[0].stages[0].clone(),
+            REPO.to_string(),
+            "master-head".to_string(),
+        )
+        .with_app(Some(ctx.applications[0].full_name())),
+    );
+    assert!(pipeline.pop_next_action().unwrap() == deploy);
+    assert!(pipeline.pop_next_action().is_none());
+
+    // A schedule only fires once for every time it comes due, even when it was missed several times.
+    assert!(handle_schedule(&mut source, &ctx, june(1, 4)).is_empty());
+    assert_eq!(source.last_polled(), june(1, 4));
+    assert_eq!(handle_schedule(&mut source, &ctx, june(5, 4)).len(), 1);
+}
+
+#[test]
+fn schedules_look_up_heads_at_the_configured_url_of_the_repository() {
+    const PRIVATE: &str = "git@github.com:zprobst/cloud-conveyor.git";
+    let mut ctx = context();
+    let mut source = ScheduleEventSource::new(Box::new(OnlyRepo(PRIVATE)), june(1, 0));
+    assert!(handle_schedule(&mut source, &ctx, june(1, 3)).is_empty());
+
+    let conf: ConfigFile =
+        serde_yaml::from_str(&format!("{}repo_url: \"{}\"\n", CONFIG, PRIVATE)).unwrap();
+    ctx.applications = vec![conf.into()];
+    assert_eq!(handle_schedule(&mut source, &ctx, june(2, 3)).len(), 1);
+}
+
+#[test]
+fn schedules_whose_head_could_not_be_found_fire_on_the_next_poll() {
+    let ctx = context();
+    let mut source = ScheduleEventSource::new(Box::new(FailsOnce::default()), june(1, 0));
+    assert!(handle_schedule(&mut source, &ctx, june(1, 3)).is_empty());
+    assert_eq!(handle_schedule(&mut source, &ctx, june(1, 4)).len(), 1);
+    assert!(handle_schedule(&mut source, &ctx, june(1, 5)).is_empty());
+}
+
+#[test]
+fn next_runs_are_listed_soonest_first() {
+    let runs = next_runs(&application(), june(6, 3));
+    let runs: Vec<_> = runs
+        .iter()
+        .map(|run| (run.at, run.branch.as_str()))
+        .collect();
+    assert_eq!(runs, vec![(june(7, 2), "master"), (june(7, 12), "release")]);
+}
+
+#[test]
+fn cron_expressions_are_validated() {
+    assert!(parse_cron("*/15 * * * *").is_ok());
+    assert!(parse_cron("0 */15 * * * *").is_ok());
+    assert!(parse_cron("every night").is_err());
+
+    let conf: ConfigFile = serde_yaml::from_str(CONFIG).unwrap();
+    assert_eq!(conf.validate(), Ok(()));
+    let conf: ConfigFile =
+        serde_yaml::from_str(&CONFIG.replace("0 2 * * *", "every night")).unwrap();
+    assert!(matches!(
+        conf.validate(),
+        Err(ConfigError::InvalidCron { expression, .. }) if expression == "every night"
+    ));
+}
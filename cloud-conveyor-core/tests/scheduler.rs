@@ -0,0 +1,186 @@
+mod common;
+
+use cloud_conveyor_core::audit::Transition;
+use cloud_conveyor_core::deploy::{DeployInfrastructure, DeployPollError, DeployStatus};
+use cloud_conveyor_core::pipelining::{ActionResult, Build, Deploy, Pipeline};
+use cloud_conveyor_core::runtime::RuntimeContext;
+use cloud_conveyor_core::scheduler::Scheduler;
+use cloud_conveyor_core::secrets::Secrets;
+use cloud_conveyor_core::yaml::ConfigFile;
+use cloud_conveyor_core::{Application, Concurrency};
+use common::REPO;
+
+/// Creates the context whose application deploys to its stage with the policy.
+fn context(concurrency: Concurrency) -> RuntimeContext {
+    let mut ctx = common::context();
+    ctx.applications[0].stages[0].concurrency = concurrency;
+    ctx
+}
+
+/// Creates the pipeline that builds the sha and deploys it to the stage.
+fn merge(ctx: &RuntimeContext, sha: &str) -> Pipeline {
+    let app = &ctx.applications[0];
+    let build = Build::new(sha.to_string(), REPO.to_string()).with_app(Some(app.full_name()));
+    let deploy = Deploy::new(app.stages[0].clone(), REPO.to_string(), sha.to_string())
+        .with_app(Some(app.full_name()));
+    Pipeline::empty()
+        .add_action(Box::new(build))
+        .add_action(Box::new(deploy))
+}
+
+/// Gets the transitions of the deployments in the audit log as the sha, whether it started and the result.
+fn deployments(ctx: &RuntimeContext) -> Vec<(String, bool, Option<ActionResult>)> {
+    ctx.audit
+        .entries(&ctx.applications[0])
+        .unwrap()
+        .into_iter()
+        .filter(|entry| entry.action == "Deploy")
+        .map(|entry| {
+            (
+                entry.git_ref.unwrap(),
+                entry.transition == Transition::Started,
+                entry.result,
+            )
+        })
+        .collect()
+}
+
+fn run(scheduler: &mut Scheduler, ctx: &RuntimeContext) {
+    while scheduler.tick(ctx) {}
+    assert!(scheduler.machines().is_empty());
+}
+
+#[test]
+fn deployments_to_a_stage_are_queued() {
+    let ctx = context(Concurrency::Queue);
+    let mut scheduler = Scheduler::new();
+    scheduler.add(merge(&ctx, "old"), &ctx);
+    scheduler.add(merge(&ctx, "new"), &ctx);
+    run(&mut scheduler, &ctx);
+
+    // The deployments never overlap and the newest sha is deployed last.
+    assert_eq!(
+        deployments(&ctx),
+        vec![
+            ("old".to_string(), true, None),
+            ("old".to_string(), false, Some(ActionResult::Success)),
+            ("new".to_string(), true, None),
+            ("new".to_string(), false, Some(ActionResult::Success)),
+        ]
+    );
+}
+
+#[test]
+fn newer_pipelines_cancel_older_ones() {
+    let ctx = context(Concurrency::Cancel);
+    let mut scheduler = Scheduler::new();
+    scheduler.add(merge(&ctx, "old"), &ctx);
+    scheduler.add(merge(&ctx, "new"), &ctx);
+    run(&mut scheduler, &ctx);
+
+    assert_eq!(
+        deployments(&ctx),
+        vec![
+            ("old".to_string(), false, Some(ActionResult::Canceled)),
+            ("new".to_string(), true, None),
+            ("new".to_string(), false, Some(ActionResult::Success)),
+        ]
+    );
+}
+
+#[test]
+fn intermediate_shas_are_skipped() {
+    let ctx = context(Concurrency::Skip);
+    let mut scheduler = Scheduler::new();
+    scheduler.add(merge(&ctx, "first"), &ctx);
+    // The first pipeline starts deploying before the others exist, so it completes.
+    scheduler.tick(&ctx);
+    scheduler.tick(&ctx);
+    scheduler.add(merge(&ctx, "second"), &ctx);
+    scheduler.add(merge(&ctx, "third"), &ctx);
+    run(&mut scheduler, &ctx);
+
+    assert_eq!(
+        deployments(&ctx),
+        vec![
+            ("first".to_string(), true, None),
+            ("first".to_string(), false, Some(ActionResult::Success)),
+            ("second".to_string(), false, Some(ActionResult::Canceled)),
+            ("third".to_string(), true, None),
+            ("third".to_string(), false, Some(ActionResult::Success)),
+        ]
+    );
+}
+
+/// Infrastructure that cannot find out how the deployment of the sha `broken` is going.
+#[derive(Debug)]
+struct BrokenInfrastructure;
+
+impl DeployInfrastructure for BrokenInfrastructure {
+    fn start_deployment(
+        &self,
+        _deploy: &Deploy,
+        _secrets: &Secrets,
+        _ctx: &RuntimeContext,
+    ) -> Result<(), DeployPollError> {
+        Ok(())
+    }
+
+    fn check_deployment(
+        &self,
+        deploy: &Deploy,
+        _ctx: &RuntimeContext,
+    ) -> Result<DeployStatus, DeployPollError> {
+        match deploy.git_ref.as_str() {
+            "broken" => Err(DeployPollError::Other {
+                info: "the api is down".to_string(),
+            }),
+            _ => Ok(DeployStatus::Complete),
+        }
+    }
+}
+
+#[test]
+fn pipelines_that_error_are_aborted_without_holding_up_the_others() {
+    let mut ctx = context(Concurrency::Queue);
+    ctx.infrastructure = Box::new(BrokenInfrastructure);
+    let mut scheduler = Scheduler::new();
+    scheduler.add(merge(&ctx, "broken"), &ctx);
+    scheduler.add(merge(&ctx, "new"), &ctx);
+    run(&mut scheduler, &ctx);
+
+    assert_eq!(
+        deployments(&ctx),
+        vec![
+            ("broken".to_string(), true, None),
+            ("broken".to_string(), false, Some(ActionResult::Failed)),
+            ("new".to_string(), true, None),
+            ("new".to_string(), false, Some(ActionResult::Success)),
+        ]
+    );
+}
+
+#[test]
+fn the_policy_is_read_from_the_config() {
+    let conf: ConfigFile = serde_yaml::from_str(
+        r#"
+org: zprobst
+app: cloud-conveyor
+accounts:
+  - name: default
+    id: 123456789
+    regions:
+      - us-east-1
+approvals: {}
+triggers: []
+stages:
+  - name: stage
+    concurrency: skip
+  - name: prod
+"#,
+    )
+    .unwrap();
+    let app: Application = conf.into();
+    assert_eq!(app.stages[0].concurrency, Concurrency::Skip);
+    assert_eq!(app.stages[1].concurrency, Concurrency::Queue);
+}
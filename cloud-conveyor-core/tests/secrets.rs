@@ -0,0 +1,57 @@
+mod common;
+
+use cloud_conveyor_core::pipelining::{Build, Perform};
+use common::{FakeBuilder, MemorySecrets, REPO};
+
+use std::collections::HashMap;
+
+fn secrets() -> MemorySecrets {
+    let mut values = HashMap::new();
+    values.insert("NPM_TOKEN".to_string(), "hunter2".to_string());
+    MemorySecrets { values }
+}
+
+#[test]
+fn build_secrets_are_resolved_when_started() {
+    let builder = FakeBuilder::default();
+    let received = builder.secrets.clone();
+    let mut ctx = common::context();
+    ctx.builder = Box::new(builder);
+    ctx.secrets = Box::new(secrets());
+
+    let mut build =
+        Build::new("sha".to_string(), REPO.to_string()).with_secrets(vec!["NPM_TOKEN".to_string()]);
+    build.start(&ctx).unwrap();
+    let received = received.borrow();
+    assert_eq!(received.as_ref().unwrap().get("NPM_TOKEN"), Some("hunter2"));
+}
+
+#[test]
+fn build_fails_to_start_when_secret_is_missing() {
+    let builder = FakeBuilder::default();
+    let starts = builder.starts.clone();
+    let mut ctx = common::context();
+    ctx.builder = Box::new(builder);
+    ctx.secrets = Box::new(secrets());
+
+    let mut build = Build::new("sha".to_string(), REPO.to_string())
+        .with_secrets(vec!["DATABASE_URL".to_string()]);
+    assert!(build.start(&ctx).is_err());
+    assert_eq!(starts.get(), 0);
+}
+
+#[test]
+fn secret_values_are_not_debug_printed() {
+    let builder = FakeBuilder::default();
+    let received = builder.secrets.clone();
+    let mut ctx = common::context();
+    ctx.builder = Box::new(builder);
+    ctx.secrets = Box::new(secrets());
+
+    let mut build =
+        Build::new("sha".to_string(), REPO.to_string()).with_secrets(vec!["NPM_TOKEN".to_string()]);
+    build.start(&ctx).unwrap();
+    let printed = format!("{:?} {:?}", build, received.borrow());
+    assert!(printed.contains("NPM_TOKEN"));
+    assert!(!printed.contains("hunter2"));
+}
@@ -0,0 +1,151 @@
+mod common;
+
+use chrono::Utc;
+use cloud_conveyor_core::history::{DeploymentHistory, DeploymentOutcome, DeploymentRecord};
+use cloud_conveyor_core::runtime::RuntimeContext;
+use cloud_conveyor_core::webhook::{
+    handle_web_hook_event, parse_version_tag, VcsEvent, WebhookRequest,
+};
+use cloud_conveyor_core::yaml::ConfigFile;
+use cloud_conveyor_core::Application;
+use common::FixedEvent;
+use failure::Error;
+
+use std::collections::HashMap;
+
+const SHA: &str = "cda888fd29a23fdb2d905e4ab6cf50230ce4c37b";
+
+const CONFIG: &str = r#"
+org: zprobst
+app: cloud-conveyor
+accounts:
+  - name: default
+    id: 123456789
+    regions:
+      - us-east-1
+approvals: {}
+triggers:
+  - tag:
+      pattern: semver
+      deploy:
+        - prod
+      prerelease:
+        - stage
+      ordered: true
+stages:
+  - name: stage
+  - name: prod
+"#;
+
+/// Fails the test when it is read, for events that should never look up what the stages run.
+#[derive(Debug)]
+struct UnreadHistory;
+
+impl DeploymentHistory for UnreadHistory {
+    fn record(&self, _: &Application, _: &str, _: &DeploymentRecord) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn list(&self, app: &Application, stage: &str) -> Result<Vec<DeploymentRecord>, Error> {
+        panic!("Read the history of {} of {}", stage, app.full_name());
+    }
+
+    fn stages(&self, app: &Application) -> Result<Vec<String>, Error> {
+        panic!("Read the history of {}", app.full_name());
+    }
+}
+
+fn application() -> Application {
+    let conf: ConfigFile = serde_yaml::from_str(CONFIG).unwrap();
+    conf.into()
+}
+
+fn context() -> RuntimeContext {
+    let mut ctx = common::context();
+    ctx.applications = vec![application()];
+    ctx
+}
+
+fn deployed(ctx: &RuntimeContext, stage: &str, tag: &str) {
+    let record = DeploymentRecord {
+        git_ref: SHA.to_string(),
+        deployed_at: Utc::now(),
+        outcome: DeploymentOutcome::Succeeded,
+        rollback: false,
+        tag: Some(tag.to_string()),
+    };
+    ctx.history.record(&application(), stage, &record).unwrap();
+}
+
+/// Gets the names of the stages that the tag is deployed to.
+fn deploy_stages(ctx: &mut RuntimeContext, tag: &str) -> Vec<String> {
+    let event = VcsEvent::TagPush {
+        tag: tag.to_string(),
+        sha: SHA.to_string(),
+        changed_files: None,
+    };
+    let request = WebhookRequest {
+        headers: HashMap::new(),
+        body: String::new(),
+    };
+    let mut stages = Vec::new();
+    for mut pipeline in handle_web_hook_event(&FixedEvent::new(event), ctx, request) {
+        while let Some(action) = pipeline.pop_next_action() {
+            if let Some(stage) = action.details().stage {
+                stages.push(stage);
+            }
+        }
+    }
+    stages
+}
+
+#[test]
+fn only_whole_versions_are_semver_tags() {
+    assert_eq!(parse_version_tag("1.2.3").unwrap().to_string(), "1.2.3");
+    assert_eq!(parse_version_tag("v1.2.3").unwrap().to_string(), "1.2.3");
+    assert_eq!(
+        parse_version_tag("v1.2.3-rc.1").unwrap().to_string(),
+        "1.2.3-rc.1"
+    );
+    assert_eq!(parse_version_tag("foo1.2bar"), None);
+    assert_eq!(parse_version_tag("1.2"), None);
+    assert_eq!(parse_version_tag("v1.2.3 "), None);
+    assert_eq!(parse_version_tag("V1.2.3"), None);
+
+    let mut ctx = context();
+    assert!(deploy_stages(&mut ctx, "foo1.2.3bar").is_empty());
+}
+
+#[test]
+fn prereleases_are_routed_to_their_own_stages() {
+    let mut ctx = context();
+    assert_eq!(deploy_stages(&mut ctx, "v1.2.3-rc.1"), vec!["stage"]);
+    assert_eq!(deploy_stages(&mut ctx, "v1.2.3"), vec!["prod"]);
+}
+
+#[test]
+fn older_tags_are_refused() {
+    let mut ctx = context();
+    deployed(&ctx, "prod", "v1.2.3");
+    assert!(deploy_stages(&mut ctx, "v1.2.2").is_empty());
+    // Prereleases go to another stage, which runs nothing yet.
+    assert_eq!(deploy_stages(&mut ctx, "v1.2.3-rc.1"), vec!["stage"]);
+    assert_eq!(deploy_stages(&mut ctx, "v1.2.4"), vec!["prod"]);
+
+    // Prereleases sort before their release.
+    deployed(&ctx, "stage", "v1.3.0");
+    assert!(deploy_stages(&mut ctx, "v1.3.0-rc.1").is_empty());
+    assert_eq!(deploy_stages(&mut ctx, "v1.3.1-rc.1"), vec!["stage"]);
+}
+
+#[test]
+fn the_history_is_only_read_for_tags_of_ordered_triggers() {
+    let mut ctx = context();
+    ctx.history = Box::new(UnreadHistory);
+    assert!(deploy_stages(&mut ctx, "foo1.2.3bar").is_empty());
+
+    ctx.applications[0].triggers[0] =
+        serde_yaml::from_str("tag:\n  pattern: semver\n  deploy:\n    - prod\n  ordered: false")
+            .unwrap();
+    assert_eq!(deploy_stages(&mut ctx, "v1.2.2"), vec!["prod"]);
+}
@@ -1,4 +1,6 @@
-use cloud_conveyor_core::pipelining::Teardown;
+mod common;
+
+use cloud_conveyor_core::pipelining::{Perform, Teardown};
 use cloud_conveyor_core::runtime::RuntimeContext;
 use cloud_conveyor_core::teardown::{TeardownInfrastructure, TeardownPollError, TeardownStatus};
 #[cfg(test)]
@@ -27,4 +29,13 @@ impl TeardownInfrastructure for TestImpl {
 }
 
 #[test]
-fn teardown_errors_when_start_fails() {}
+fn teardown_errors_when_start_fails() {
+    let mut ctx = common::context();
+    ctx.teardown = Box::new(TestImpl(
+        Err(TeardownPollError::Credentials),
+        Ok(TeardownStatus::Complete),
+    ));
+    let stage = common::application().stages[0].clone();
+    let mut teardown = Teardown::new(stage, common::REPO.to_string());
+    assert!(teardown.start(&ctx).is_err());
+}
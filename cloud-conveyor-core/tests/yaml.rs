@@ -0,0 +1,224 @@
+use cloud_conveyor_core::yaml::{validate_applications, BuildSpec, ConfigError, ConfigFile};
+use cloud_conveyor_core::{Application, Stage};
+
+const CONFIG: &str = r#"
+org: zprobst
+app: APP
+accounts:
+  - name: default
+    id: 1
+    regions:
+      - us-east-1
+  - name: previews
+    id: 2
+    regions:
+      - us-east-1
+      - eu-west-1
+approvals: {}
+triggers:
+  - pr:
+      deploy: true
+      PR_STAGES
+stages:
+  - name: stage
+"#;
+
+fn config(app: &str, pr_stages: &str) -> ConfigFile {
+    let yaml = CONFIG
+        .replace("APP", app)
+        .replace("PR_STAGES", &pr_stages.replace('\n', "\n      "));
+    serde_yaml::from_str(&yaml).unwrap()
+}
+
+#[test]
+fn build_spec_commands_run_in_phase_order() {
+    let spec: BuildSpec = serde_yaml::from_str(
+        r#"
+phases:
+  package:
+    - zip -r app.zip out
+  test:
+    - make test
+  install:
+    - make deps
+  build:
+    - make
+"#,
+    )
+    .unwrap();
+    let commands: Vec<&str> = spec.phases.commands().map(String::as_str).collect();
+    assert_eq!(
+        commands,
+        vec!["make deps", "make test", "make", "zip -r app.zip out"]
+    );
+}
+
+#[test]
+fn build_spec_sections_are_optional() {
+    let spec: BuildSpec = serde_yaml::from_str("image: rust:1.44").unwrap();
+    assert_eq!(spec.image, Some("rust:1.44".to_string()));
+    assert!(spec.env.is_empty());
+    assert!(spec.artifacts.is_empty());
+    assert!(spec.cache.is_empty());
+    assert_eq!(spec.phases.commands().count(), 0);
+}
+
+#[test]
+fn pr_stages_follow_the_template_of_the_trigger() {
+    let conf = config(
+        "api",
+        "stage_name: \"{app}-{branch}-{number}\"\naccount: previews\nregions:\n  - eu-west-1",
+    );
+    assert_eq!(conf.validate(), Ok(()));
+    let app: Application = conf.into();
+
+    let stage = Stage::for_pr(&app, &app.pr_stages(), 7, "feature/login").unwrap();
+    assert_eq!(stage.name, "api-feature-login-7");
+    assert_eq!(stage.account.name, "previews");
+    assert_eq!(stage.account.regions, vec!["eu-west-1".to_string()]);
+    assert!(stage.is_for_pr(7));
+
+    // The default name is kept when there is no template.
+    let app: Application = config("api", "label: preview").into();
+    let stage = Stage::for_pr(&app, &app.pr_stages(), 7, "feature/login").unwrap();
+    assert_eq!(stage.name, "pr-7");
+    assert_eq!(stage.account.name, "default");
+}
+
+#[test]
+fn invalid_pr_stages_fail_validation() {
+    let error = |pr_stages: &str| config("api", pr_stages).validate().unwrap_err();
+    assert_eq!(
+        error("account: missing"),
+        ConfigError::UnknownAccount {
+            stage: "pr-{number}".to_string(),
+            account: "missing".to_string(),
+        }
+    );
+    assert_eq!(
+        error("account: previews\nregions:\n  - ap-south-1"),
+        ConfigError::UnknownRegion {
+            account: "previews".to_string(),
+            region: "ap-south-1".to_string(),
+        }
+    );
+    assert!(matches!(
+        error("stage_name: \"{app}-preview\""),
+        ConfigError::InvalidPrStageName { .. }
+    ));
+    assert!(matches!(
+        error("stage_name: \"{app}-{sha}\""),
+        ConfigError::InvalidPrStageName { .. }
+    ));
+
+    // Without a default account, the stages of pull requests have to name one.
+    let mut conf = config("api", "label: preview");
+    conf.accounts.remove(0);
+    conf.stages[0].account = Some("previews".to_string());
+    assert_eq!(conf.validate(), Err(ConfigError::NoPrAccount));
+}
+
+#[test]
+fn pr_stages_of_apps_sharing_an_account_are_unique() {
+    // The stages are qualified with the name of their app, and the template may already hold it.
+    let apps: Vec<Application> = vec![
+        config("api", "label: preview").into(),
+        config("web", "stage_name: \"{app}-pr-{number}\"").into(),
+        config("docs", "account: previews").into(),
+    ];
+    assert_eq!(validate_applications(&apps), Ok(()));
+
+    // api-pr-7 could be the stage of pull request 7 of api and the static stage of api-pr.
+    let mut other = config("api-pr", "account: previews");
+    other.stages[0].name = "7".to_string();
+    let apps: Vec<Application> = vec![config("api", "label: preview").into(), other.into()];
+    assert_eq!(
+        validate_applications(&apps),
+        Err(ConfigError::ConflictingPrStages {
+            apps: vec!["zprobst/api".to_string(), "zprobst/api-pr".to_string()],
+            account: "default".to_string(),
+        })
+    );
+
+    // The source branch could be anything, including the stage of a branch of another app.
+    let apps: Vec<Application> = vec![
+        config("api", "stage_name: \"{app}-{branch}\"").into(),
+        config("api-branch", "account: previews").into(),
+    ];
+    assert!(matches!(
+        validate_applications(&apps),
+        Err(ConfigError::ConflictingPrStages { .. })
+    ));
+
+    // Without a prefix of its own, a branch x and a branch api-x would both get the stage api-x.
+    let apps: Vec<Application> = vec![config("api", "stage_name: \"{branch}\"").into()];
+    assert_eq!(
+        validate_applications(&apps),
+        Err(ConfigError::ConflictingPrStages {
+            apps: vec!["zprobst/api".to_string(), "zprobst/api".to_string()],
+            account: "default".to_string(),
+        })
+    );
+
+    // Characters that are replaced once sanitized are taken into account as well.
+    let apps: Vec<Application> = vec![config("api", "stage_name: \"stage_{number}\"").into()];
+    assert_eq!(validate_applications(&apps), Ok(()));
+    let mut conf = config("api", "stage_name: \"stage_{number}\"");
+    conf.stages[0].name = "stage/1".to_string();
+    assert_eq!(
+        validate_applications(&[conf.into()]),
+        Err(ConfigError::ConflictingPrStages {
+            apps: vec!["zprobst/api".to_string(), "zprobst/api".to_string()],
+            account: "default".to_string(),
+        })
+    );
+}
+
+#[test]
+fn static_stages_of_apps_sharing_an_account_are_unique() {
+    let mut other = config("api-stage", "");
+    other.stages[0].name = "api-stage".to_string();
+    let apps: Vec<Application> = vec![config("api", "").into(), other.into()];
+    assert_eq!(validate_applications(&apps), Ok(()));
+
+    let mut other = config("api", "");
+    other.org = "other".to_string();
+    other.stages[0].name = "api-stage".to_string();
+    let apps: Vec<Application> = vec![config("api", "").into(), other.into()];
+    assert_eq!(
+        validate_applications(&apps),
+        Err(ConfigError::ConflictingStages {
+            apps: vec!["zprobst/api".to_string(), "other/api".to_string()],
+            account: "default".to_string(),
+        })
+    );
+}
+
+#[test]
+fn invalid_branch_and_tag_patterns_fail_validation() {
+    let validate = |trigger: &str| {
+        let mut conf = config("api", "label: preview");
+        conf.triggers = vec![serde_yaml::from_str(trigger).unwrap()];
+        conf.validate()
+    };
+    let invalid = |result: Result<(), ConfigError>, expected: &str| match result {
+        Err(ConfigError::InvalidPattern { pattern, .. }) => pattern == expected,
+        _ => false,
+    };
+    assert!(invalid(
+        validate("branch:\n  pattern: \"feature/(\"\n  deploy: false"),
+        "feature/("
+    ));
+    assert!(invalid(
+        validate("tag:\n  pattern: \"v[\"\n  deploy: [stage]"),
+        "v["
+    ));
+    assert!(invalid(
+        validate("merge:\n  to: master\n  from: \"*\"\n  deploy: [stage]"),
+        "*"
+    ));
+    assert_eq!(
+        validate("tag:\n  pattern: semver\n  deploy: [stage]"),
+        Ok(())
+    );
+}
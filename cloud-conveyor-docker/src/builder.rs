@@ -0,0 +1,433 @@
+use crate::client::{demultiplex, Demultiplexer, DockerClient, Response};
+use cloud_conveyor_core::build::{BuildPollError, BuildSource, BuildStatus};
+use cloud_conveyor_core::pipelining::Build;
+use cloud_conveyor_core::runtime::RuntimeContext;
+use cloud_conveyor_core::secrets::Secrets;
+use cloud_conveyor_core::yaml::BuildSpec;
+use cloud_conveyor_local::{
+    app_dir, build_command, build_key, build_spec, checkout, log_excerpt, upload_outputs,
+};
+use log::{info, warn};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde_json::{json, Value};
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+
+/// The directory of the container that the checkout is mounted at.
+const CONTAINER_WORKSPACE: &str = "/workspace";
+
+/// The file of the container that the secrets of the build are mounted at.
+const CONTAINER_SECRETS: &str = "/run/conveyor/secrets";
+
+/// Exports the secrets of the build and runs the command of the build, which is passed as the first argument.
+const WITH_SECRETS: &str = ". /run/conveyor/secrets && exec sh -c \"$1\"";
+
+/// The characters that are left as they are in the values of query parameters.
+const QUERY_VALUE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+fn other<E: ToString>(e: E) -> BuildPollError {
+    BuildPollError::Other {
+        info: e.to_string(),
+    }
+}
+
+fn daemon_error(response: &Response) -> BuildPollError {
+    BuildPollError::Other {
+        info: response.error_message(),
+    }
+}
+
+/// Builds code in a container of the local docker daemon.
+///
+/// The code is checked out into a directory underneath the workspace directory which is mounted into the
+/// container at `/workspace`. The command is run in that directory with `sh -c` in a container of the image with
+/// `CONVEYOR_REPO` and `CONVEYOR_GIT_REF` set. The secrets of the build are not set on the container, where anyone
+/// who can inspect it would see them, but written to a file next to the checkout that only the owner can read. The
+/// file is mounted into the container and exported before the command runs.
+///
+/// The output of the container is written to a file next to the checkout as it runs, and the path of that file is
+/// reported as the logs of the build. Checking on a build only inspects the container. Once the container exits, it
+/// is removed along with the file of the secrets. When the exit code is zero, the outputs of the build are uploaded
+/// the same way as they are by [LocalBuilder](../cloud_conveyor_local/struct.LocalBuilder.html).
+///
+/// When the build has a [BuildSpec](../cloud_conveyor_core/yaml/struct.BuildSpec.html), either set on it or in the
+/// directory of the application in the checkout, the image of the spec is used in place of the image of the
+/// builder, the commands of its phases are run in place of the command and the environment variables of the spec
+/// are set. Images that the daemon does not have are pulled.
+#[derive(Debug)]
+pub struct DockerBuilder {
+    client: DockerClient,
+    workspace: PathBuf,
+    image: String,
+    command: String,
+    output_dir: PathBuf,
+    /// The threads that write the output of the containers that this builder started to their log files.
+    followers: Mutex<HashMap<String, JoinHandle<io::Result<()>>>>,
+}
+
+impl DockerBuilder {
+    /// Creates a builder that checks out code underneath the workspace directory and builds it
+    /// by running the command in a container of the image. The daemon is reached at `/var/run/docker.sock`.
+    pub fn new<P: Into<PathBuf>>(workspace: P, image: String, command: String) -> Self {
+        Self {
+            client: DockerClient::new(PathBuf::from("/var/run/docker.sock")),
+            workspace: workspace.into(),
+            image,
+            command,
+            output_dir: PathBuf::from("out"),
+            followers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets the path of the unix socket of the docker daemon.
+    pub fn with_socket<P: Into<PathBuf>>(mut self, socket: P) -> Self {
+        self.client = DockerClient::new(socket.into());
+        self
+    }
+
+    /// Sets the directory, relative to the root of the checkout, that the build command writes
+    /// its artifacts to. Defaults to `out`.
+    pub fn with_output_dir<P: Into<PathBuf>>(mut self, output_dir: P) -> Self {
+        self.output_dir = output_dir.into();
+        self
+    }
+
+    fn container_name(build: &Build) -> String {
+        format!("conveyor-{}", build_key(build))
+    }
+
+    fn checkout_dir(&self, build: &Build) -> PathBuf {
+        self.workspace.join(build_key(build))
+    }
+
+    fn log_file(&self, build: &Build) -> PathBuf {
+        self.workspace.join(format!("{}.log", build_key(build)))
+    }
+
+    fn secrets_file(&self, build: &Build) -> PathBuf {
+        self.workspace.join(format!("{}.secrets", build_key(build)))
+    }
+
+    fn image<'a>(&'a self, spec: Option<&'a BuildSpec>) -> &'a str {
+        spec.and_then(|spec| spec.image.as_deref())
+            .unwrap_or(&self.image)
+    }
+
+    fn request(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<Response, BuildPollError> {
+        self.client.request(method, path, body).map_err(other)
+    }
+
+    fn remove_container(&self, name: &str) -> Result<(), BuildPollError> {
+        let response = self.request("DELETE", &format!("/containers/{}?force=true", name), None)?;
+        if response.is_success() || response.status == 404 {
+            Ok(())
+        } else {
+            Err(daemon_error(&response))
+        }
+    }
+
+    fn pull_image(&self, image: &str) -> Result<(), BuildPollError> {
+        info!("Pulling image {:?} for a docker build", image);
+        let has_tag = image
+            .rsplit('/')
+            .next()
+            .is_some_and(|name| name.contains(':') || name.contains('@'));
+        let mut path = format!(
+            "/images/create?fromImage={}",
+            utf8_percent_encode(image, QUERY_VALUE)
+        );
+        if !has_tag {
+            path.push_str("&tag=latest");
+        }
+        let response = self.request("POST", &path, None)?;
+        if !response.is_success() {
+            return Err(daemon_error(&response));
+        }
+        // The daemon answers with a stream of progress messages, and a pull that fails after it started is
+        // only told apart by a message with an error.
+        let messages = serde_json::Deserializer::from_slice(&response.body).into_iter::<Value>();
+        for message in messages {
+            let message = message.map_err(other)?;
+            let error = message["error"]
+                .as_str()
+                .or_else(|| message["errorDetail"]["message"].as_str());
+            if let Some(error) = error {
+                return Err(BuildPollError::Other {
+                    info: format!("Could not pull image {}: {}", image, error),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn create_container(
+        &self,
+        build: &Build,
+        spec: Option<&BuildSpec>,
+        secrets: Option<&Path>,
+        checkout: &str,
+    ) -> Result<(), BuildPollError> {
+        let mut env = Vec::new();
+        if let Some(spec) = spec {
+            env.extend(spec.env.iter().map(|(k, v)| format!("{}={}", k, v)));
+        }
+        env.push(format!("CONVEYOR_REPO={}", build.repo));
+        env.push(format!("CONVEYOR_GIT_REF={}", build.git_ref));
+
+        let image = self.image(spec);
+        let working_dir = app_dir(build, CONTAINER_WORKSPACE.as_ref());
+        let command = build_command(spec, &self.command);
+        let mut binds = vec![format!("{}:{}", checkout, CONTAINER_WORKSPACE)];
+        let cmd = match secrets {
+            Some(secrets) => {
+                binds.push(format!("{}:{}:ro", secrets.display(), CONTAINER_SECRETS));
+                json!(["sh", "-c", WITH_SECRETS, "sh", command])
+            }
+            None => json!(["sh", "-c", command]),
+        };
+        let config = json!({
+            "Image": image,
+            "Cmd": cmd,
+            "Env": env,
+            "WorkingDir": working_dir,
+            "Labels": {
+                "cloud-conveyor.repo": build.repo,
+                "cloud-conveyor.git-ref": build.git_ref,
+            },
+            "HostConfig": {
+                "Binds": binds,
+            },
+        });
+        let path = format!("/containers/create?name={}", Self::container_name(build));
+
+        let mut response = self.request("POST", &path, Some(&config))?;
+        if response.status == 404 {
+            self.pull_image(image)?;
+            response = self.request("POST", &path, Some(&config))?;
+        }
+        if response.is_success() {
+            Ok(())
+        } else {
+            Err(daemon_error(&response))
+        }
+    }
+
+    /// Writes the secrets to the file of the secrets of the build as shell commands that export them, so
+    /// that only the owner can read them. Returns the absolute path of the file, or None without secrets.
+    fn write_secrets(
+        &self,
+        build: &Build,
+        secrets: &Secrets,
+    ) -> Result<Option<PathBuf>, BuildPollError> {
+        let path = self.secrets_file(build);
+        if path.exists() {
+            fs::remove_file(&path).map_err(other)?;
+        }
+        if secrets.iter().next().is_none() {
+            return Ok(None);
+        }
+        for (name, _) in secrets.iter() {
+            let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid {
+                return Err(BuildPollError::Other {
+                    info: format!("The secret {:?} is not a valid name of a variable", name),
+                });
+            }
+        }
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+            .map_err(other)?;
+        let written = secrets
+            .iter()
+            .try_for_each(|(name, value)| {
+                writeln!(file, "export {}='{}'", name, value.replace('\'', "'\\''"))
+            })
+            .and_then(|_| fs::canonicalize(&path));
+        if written.is_err() {
+            let _ = fs::remove_file(&path);
+        }
+        written.map(Some).map_err(other)
+    }
+
+    /// Writes the output of the container to the log file of the build as it runs, until the container exits.
+    fn follow_logs(&self, build: &Build, name: &str) -> Result<(), BuildPollError> {
+        let mut log = File::create(self.log_file(build)).map_err(other)?;
+        let client = self.client.clone();
+        let path = format!(
+            "/containers/{}/logs?follow=true&stdout=true&stderr=true",
+            name
+        );
+        let follower = thread::spawn(move || {
+            let mut demultiplexer = Demultiplexer::default();
+            let response = client.stream("GET", &path, &mut |bytes| {
+                log.write_all(&demultiplexer.feed(bytes))?;
+                log.flush()
+            })?;
+            if !response.is_success() {
+                return Err(io::Error::other(response.error_message()));
+            }
+            log.write_all(&demultiplexer.finish())
+        });
+        self.followers
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), follower);
+        Ok(())
+    }
+
+    /// Waits until the output of the container that exited is written to the log file of the build. When the
+    /// container was started by another process, or following it failed, its output is fetched as a whole.
+    fn finish_logs(&self, build: &Build, name: &str) -> Result<(), BuildPollError> {
+        let follower = self.followers.lock().unwrap().remove(name);
+        match follower.map(JoinHandle::join) {
+            Some(Ok(Ok(()))) => return Ok(()),
+            Some(Ok(Err(e))) => warn!("Could not follow the logs of container {:?}: {}", name, e),
+            Some(Err(_)) => warn!("Following the logs of container {:?} panicked", name),
+            None => {}
+        }
+        self.save_logs(build, name)
+    }
+
+    /// Creates and starts the container of the build, and follows its output.
+    fn start_container(
+        &self,
+        build: &Build,
+        name: &str,
+        spec: Option<&BuildSpec>,
+        secrets: Option<&Path>,
+        checkout: &Path,
+    ) -> Result<(), BuildPollError> {
+        self.create_container(build, spec, secrets, &checkout.to_string_lossy())?;
+        let response = self.request("POST", &format!("/containers/{}/start", name), None)?;
+        if !response.is_success() && response.status != 304 {
+            return Err(daemon_error(&response));
+        }
+        self.follow_logs(build, name)
+    }
+
+    /// Writes the output of the container to the log file of the build.
+    fn save_logs(&self, build: &Build, name: &str) -> Result<(), BuildPollError> {
+        let response = self.request(
+            "GET",
+            &format!("/containers/{}/logs?stdout=true&stderr=true", name),
+            None,
+        )?;
+        if !response.is_success() {
+            return Err(daemon_error(&response));
+        }
+        fs::write(self.log_file(build), demultiplex(&response.body)).map_err(other)
+    }
+}
+
+impl BuildSource for DockerBuilder {
+    fn start_build(
+        &self,
+        build: &Build,
+        secrets: &Secrets,
+        _ctx: &RuntimeContext,
+    ) -> Result<(), BuildPollError> {
+        let dir = self.checkout_dir(build);
+        if dir.exists() {
+            fs::remove_dir_all(&dir).map_err(other)?;
+        }
+        fs::create_dir_all(&self.workspace).map_err(other)?;
+        checkout(&build.repo, &build.git_ref, &dir)?;
+        // The daemon resolves bind mounts on its own, so the path has to be absolute.
+        let dir = fs::canonicalize(&dir).map_err(other)?;
+        let spec = build_spec(build, &app_dir(build, &dir))?;
+
+        let name = Self::container_name(build);
+        self.remove_container(&name)?;
+        let secrets = self.write_secrets(build, secrets)?;
+        let started = self.start_container(build, &name, spec.as_ref(), secrets.as_deref(), &dir);
+        if started.is_err() {
+            // Nothing is left to remove the secrets once the build is done, since it never started.
+            if let Some(secrets) = &secrets {
+                let _ = fs::remove_file(secrets);
+            }
+        }
+        started?;
+
+        info!(
+            "Started docker build of git_ref {:?} in container {:?}",
+            build.git_ref, name
+        );
+        Ok(())
+    }
+
+    fn check_build(
+        &self,
+        build: &Build,
+        ctx: &RuntimeContext,
+    ) -> Result<BuildStatus, BuildPollError> {
+        let name = Self::container_name(build);
+        let response = self.request("GET", &format!("/containers/{}/json", name), None)?;
+        if response.status == 404 {
+            return Err(BuildPollError::Other {
+                info: format!("No docker build was started for git_ref {}", build.git_ref),
+            });
+        }
+        if !response.is_success() {
+            return Err(daemon_error(&response));
+        }
+
+        let state = response.json().map_err(other)?["State"].clone();
+        match state["Status"].as_str() {
+            Some("exited") | Some("dead") => {}
+            _ => return Ok(BuildStatus::Pending),
+        }
+        let exit_code = state["ExitCode"].as_i64().ok_or_else(|| {
+            other(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Docker did not report the exit code of the container",
+            ))
+        })?;
+
+        self.finish_logs(build, &name)?;
+        self.remove_container(&name)?;
+        let secrets = self.secrets_file(build);
+        if secrets.exists() {
+            fs::remove_file(secrets).map_err(other)?;
+        }
+
+        let logs = self.log_file(build).to_string_lossy().into_owned();
+        if exit_code == 0 {
+            let dir = app_dir(build, &self.checkout_dir(build));
+            let spec = build_spec(build, &dir)?;
+            upload_outputs(build, spec.as_ref(), &dir, &self.output_dir, ctx)?;
+            Ok(BuildStatus::Succeeded { logs })
+        } else {
+            warn!(
+                "Docker build of git_ref {:?} failed with exit code {}",
+                build.git_ref, exit_code
+            );
+            Ok(BuildStatus::Failed {
+                logs,
+                error: Some(format!(
+                    "Build container exited with code {}\n{}",
+                    exit_code,
+                    log_excerpt(&self.log_file(build))
+                )),
+            })
+        }
+    }
+}
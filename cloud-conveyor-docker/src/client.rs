@@ -0,0 +1,258 @@
+use serde_json::Value;
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+
+/// The version of the engine api that requests are made against.
+const API_VERSION: &str = "v1.41";
+
+/// A response of the docker daemon.
+#[derive(Debug)]
+pub(crate) struct Response {
+    pub(crate) status: u16,
+    pub(crate) body: Vec<u8>,
+}
+
+impl Response {
+    pub(crate) fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    pub(crate) fn json(&self) -> Result<Value, io::Error> {
+        serde_json::from_slice(&self.body)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Gets the error message the daemon sent, falling back to the status code.
+    pub(crate) fn error_message(&self) -> String {
+        self.json()
+            .ok()
+            .and_then(|body| body["message"].as_str().map(str::to_string))
+            .unwrap_or_else(|| format!("Docker responded with status {}", self.status))
+    }
+}
+
+/// A minimal http client for the docker engine api over a unix socket. Every request is made
+/// on a new connection, which is plenty for the handful of requests that a build makes.
+#[derive(Clone, Debug)]
+pub(crate) struct DockerClient {
+    socket: PathBuf,
+}
+
+impl DockerClient {
+    pub(crate) fn new(socket: PathBuf) -> Self {
+        Self { socket }
+    }
+
+    fn send(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<UnixStream, io::Error> {
+        let body = body.map(Value::to_string).unwrap_or_default();
+        let mut stream = UnixStream::connect(&self.socket)?;
+        write!(
+            stream,
+            "{} /{}{} HTTP/1.1\r\nHost: docker\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            API_VERSION,
+            path,
+            body.len(),
+            body
+        )?;
+        Ok(stream)
+    }
+
+    pub(crate) fn request(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<Response, io::Error> {
+        let mut stream = self.send(method, path, body)?;
+        let mut raw = Vec::new();
+        stream.read_to_end(&mut raw)?;
+        parse_response(&raw)
+    }
+
+    /// Makes a request whose response is streamed, such as following the logs of a container, and hands
+    /// the body to the sink piece by piece as it arrives. The body of a response that is not successful is
+    /// read whole and returned instead.
+    pub(crate) fn stream(
+        &self,
+        method: &str,
+        path: &str,
+        sink: &mut dyn FnMut(&[u8]) -> io::Result<()>,
+    ) -> Result<Response, io::Error> {
+        let mut reader = BufReader::new(self.send(method, path, None)?);
+        let mut head = Vec::new();
+        loop {
+            let mut line = Vec::new();
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                return Err(invalid("Docker sent an incomplete response"));
+            }
+            if line == b"\r\n" {
+                break;
+            }
+            head.extend_from_slice(&line);
+        }
+        head.extend_from_slice(b"\r\n");
+        let status = parse_status(&String::from_utf8_lossy(&head))?;
+        let mut raw = Vec::new();
+        if !(200..300).contains(&status) {
+            reader.read_to_end(&mut raw)?;
+            head.extend_from_slice(&raw);
+            return parse_response(&head);
+        }
+
+        if is_chunked(&String::from_utf8_lossy(&head)) {
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line)?;
+                let size = chunk_size(&line)?;
+                if size == 0 {
+                    break;
+                }
+                // The chunk is followed by a line break.
+                raw.resize(size + 2, 0);
+                reader.read_exact(&mut raw)?;
+                sink(&raw[..size])?;
+            }
+        } else {
+            let mut buffer = [0; 8192];
+            loop {
+                let size = reader.read(&mut buffer)?;
+                if size == 0 {
+                    break;
+                }
+                sink(&buffer[..size])?;
+            }
+        }
+        Ok(Response {
+            status,
+            body: Vec::new(),
+        })
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn parse_response(raw: &[u8]) -> Result<Response, io::Error> {
+    let header_end = raw
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| invalid("Docker sent an incomplete response"))?;
+    let head = String::from_utf8_lossy(&raw[..header_end]);
+    let body = &raw[header_end + 4..];
+
+    let status = parse_status(&head)?;
+    let chunked = is_chunked(&head);
+
+    let body = if chunked {
+        dechunk(body)?
+    } else {
+        body.to_vec()
+    };
+    Ok(Response { status, body })
+}
+
+fn is_chunked(headers: &str) -> bool {
+    headers.split("\r\n").any(|line| {
+        let line = line.to_ascii_lowercase();
+        line.starts_with("transfer-encoding:") && line.contains("chunked")
+    })
+}
+
+/// Parses the status code from the status line that the head of a response starts with.
+fn parse_status(head: &str) -> Result<u16, io::Error> {
+    head.split("\r\n")
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| invalid("Docker sent an invalid status line"))
+}
+
+fn chunk_size(line: &str) -> Result<usize, io::Error> {
+    usize::from_str_radix(line.split(';').next().unwrap_or("").trim(), 16)
+        .map_err(|_| invalid("Docker sent an invalid chunk size"))
+}
+
+fn dechunk(mut raw: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = raw
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or_else(|| invalid("Docker sent an incomplete chunk"))?;
+        let size = chunk_size(&String::from_utf8_lossy(&raw[..line_end]))?;
+        if size == 0 {
+            return Ok(body);
+        }
+        let start = line_end + 2;
+        let chunk = raw
+            .get(start..start + size)
+            .ok_or_else(|| invalid("Docker sent an incomplete chunk"))?;
+        body.extend_from_slice(chunk);
+        raw = raw.get(start + size + 2..).unwrap_or_default();
+    }
+}
+
+/// Splits the multiplexed stdout and stderr of a container without a tty into the plain output.
+/// Each frame of the stream has an eight byte header with the stream type and the big endian
+/// size of the frame. Output that is not multiplexed is returned as is.
+pub(crate) fn demultiplex(raw: &[u8]) -> Vec<u8> {
+    let mut demultiplexer = Demultiplexer::default();
+    let mut output = demultiplexer.feed(raw);
+    output.extend(demultiplexer.finish());
+    output
+}
+
+/// Splits multiplexed output like [demultiplex](fn.demultiplex.html) does as it arrives, when frames may
+/// be split across the pieces that are fed to it.
+#[derive(Debug, Default)]
+pub(crate) struct Demultiplexer {
+    pending: Vec<u8>,
+    /// Set once the output turned out not to be multiplexed.
+    plain: bool,
+}
+
+impl Demultiplexer {
+    /// Gets the output of every frame that is complete with the bytes.
+    pub(crate) fn feed(&mut self, bytes: &[u8]) -> Vec<u8> {
+        if self.plain {
+            return bytes.to_vec();
+        }
+        self.pending.extend_from_slice(bytes);
+        let mut output = Vec::new();
+        let mut start = 0;
+        while self.pending.len() - start >= 8 {
+            let header = &self.pending[start..start + 8];
+            if header[0] > 2 || header[1..4] != [0, 0, 0] {
+                self.plain = true;
+                output.extend_from_slice(&self.pending[start..]);
+                self.pending.clear();
+                return output;
+            }
+            let size = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+            if self.pending.len() - start - 8 < size {
+                break;
+            }
+            output.extend_from_slice(&self.pending[start + 8..start + 8 + size]);
+            start += 8 + size;
+        }
+        self.pending.drain(..start);
+        output
+    }
+
+    /// Gets what is left of a frame that the output ended in the middle of.
+    pub(crate) fn finish(self) -> Vec<u8> {
+        match self.pending.get(8..) {
+            Some(rest) if !self.plain => rest.to_vec(),
+            _ => self.pending,
+        }
+    }
+}
@@ -0,0 +1,16 @@
+//! This crate defines a runtime implementation that builds code in containers of a local docker daemon.
+//! It talks to the [Docker Engine API](https://docs.docker.com/engine/api/) over the unix socket of the
+//! daemon so that builds are isolated from the machine cloud conveyor is running on and from each other.
+//!
+//! This crate also uses the standard logging pattern that the core crate uses.
+#![warn(
+    missing_docs,
+    rust_2018_idioms,
+    missing_debug_implementations,
+    rustdoc::broken_intra_doc_links
+)]
+
+mod builder;
+mod client;
+
+pub use builder::DockerBuilder;
@@ -0,0 +1,410 @@
+mod common;
+
+use cloud_conveyor_core::build::BuildStatus;
+use cloud_conveyor_core::pipelining::Build;
+use cloud_conveyor_core::secrets::{SecretError, SecretProvider, Secrets};
+use cloud_conveyor_core::yaml::BuildSpec;
+use cloud_conveyor_core::Application;
+use cloud_conveyor_docker::DockerBuilder;
+use cloud_conveyor_local::{build_key, FilesystemArtifacts};
+use common::{multiplexed, FakeDocker, Request};
+
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[derive(Debug)]
+struct OneSecret;
+
+impl SecretProvider for OneSecret {
+    fn get_secret(&self, _app: &Application, _name: &str) -> Result<String, SecretError> {
+        Ok("hunter2".to_string())
+    }
+}
+
+/// Answers like a daemon whose build container runs for one inspection and then exits
+/// with the exit code.
+fn daemon(dir: &Path, exit_code: i64, output: &'static str) -> FakeDocker {
+    let inspections = AtomicUsize::new(0);
+    FakeDocker::start(dir, move |request: &Request| {
+        match (request.method.as_str(), request.path.as_str()) {
+            ("POST", path) if path.starts_with("/containers/create") => {
+                (201, br#"{"Id": "abc123", "Warnings": []}"#.to_vec())
+            }
+            ("GET", path) if path.ends_with("/json") => {
+                let state = if inspections.fetch_add(1, Ordering::SeqCst) == 0 {
+                    r#"{"Status": "running", "Running": true, "ExitCode": 0}"#.to_string()
+                } else {
+                    format!(
+                        r#"{{"Status": "exited", "Running": false, "ExitCode": {}}}"#,
+                        exit_code
+                    )
+                };
+                (
+                    200,
+                    format!(r#"{{"Id": "abc123", "State": {}}}"#, state).into_bytes(),
+                )
+            }
+            ("GET", path) if path.contains("/logs") => (200, multiplexed(&[(1, output)])),
+            _ => (204, Vec::new()),
+        }
+    })
+}
+
+fn build_in(dir: &Path) -> (Build, String) {
+    let repo_dir = dir.join("zprobst/cloud-conveyor");
+    let sha = common::create_repo(&repo_dir, &[("README.md", "hello")]);
+    let build = Build::new(sha.clone(), format!("file://{}", repo_dir.display()));
+    (build, sha)
+}
+
+#[test]
+fn successful_build_saves_logs_and_uploads_outputs() {
+    let dir = tempfile::tempdir().unwrap();
+    let docker = daemon(dir.path(), 0, "building\n");
+    let (build, sha) = build_in(dir.path());
+    let builder = DockerBuilder::new(
+        dir.path().join("workspace"),
+        "alpine:3".to_string(),
+        "make".to_string(),
+    )
+    .with_socket(&docker.socket);
+    let ctx = common::context(
+        builder,
+        FilesystemArtifacts::new(dir.path().join("artifacts")),
+    );
+
+    ctx.builder
+        .start_build(&build, &Secrets::default(), &ctx)
+        .unwrap();
+    let create = docker.find("POST", "/containers/create").unwrap();
+    let config = create.json();
+    assert_eq!(config["Image"], "alpine:3");
+    assert_eq!(config["Cmd"][2], "make");
+    assert_eq!(config["WorkingDir"], "/workspace");
+    let checkout = fs::canonicalize(dir.path().join("workspace").join(build_key(&build))).unwrap();
+    assert_eq!(
+        config["HostConfig"]["Binds"][0],
+        format!("{}:/workspace", checkout.display())
+    );
+    let name = format!("conveyor-{}", build_key(&build));
+    assert!(create.path.ends_with(&name));
+    assert!(docker
+        .find("POST", &format!("/containers/{}/start", name))
+        .is_some());
+
+    // The container would write this into the mounted checkout.
+    fs::create_dir_all(checkout.join("out")).unwrap();
+    fs::write(checkout.join("out/app.zip"), "zip").unwrap();
+
+    assert_eq!(
+        ctx.builder.check_build(&build, &ctx).unwrap(),
+        BuildStatus::Pending
+    );
+    let logs = match ctx.builder.check_build(&build, &ctx).unwrap() {
+        BuildStatus::Succeeded { logs } => logs,
+        status => panic!("unexpected build status {:?}", status),
+    };
+    assert_eq!(fs::read_to_string(logs).unwrap(), "building\n");
+    let logs: Vec<Request> = docker
+        .requests()
+        .into_iter()
+        .filter(|r| r.path.contains("/logs"))
+        .collect();
+    assert_eq!(logs.len(), 1);
+    assert!(logs[0].path.contains("follow=true"));
+    let artifact = dir
+        .path()
+        .join("artifacts/zprobst/cloud-conveyor")
+        .join(&sha)
+        .join("app.zip");
+    assert_eq!(fs::read_to_string(artifact).unwrap(), "zip");
+    assert!(docker
+        .requests()
+        .iter()
+        .any(|r| r.method == "DELETE" && r.path.starts_with(&format!("/containers/{}", name))));
+}
+
+#[test]
+fn failing_container_fails_build() {
+    let dir = tempfile::tempdir().unwrap();
+    let docker = daemon(dir.path(), 2, "npm ERR! missing script\n");
+    let (build, _) = build_in(dir.path());
+    let builder = DockerBuilder::new(
+        dir.path().join("workspace"),
+        "node:14".to_string(),
+        "npm run build".to_string(),
+    )
+    .with_socket(&docker.socket);
+    let ctx = common::context(
+        builder,
+        FilesystemArtifacts::new(dir.path().join("artifacts")),
+    );
+
+    ctx.builder
+        .start_build(&build, &Secrets::default(), &ctx)
+        .unwrap();
+    ctx.builder.check_build(&build, &ctx).unwrap();
+    match ctx.builder.check_build(&build, &ctx).unwrap() {
+        BuildStatus::Failed { error, .. } => {
+            let error = error.unwrap();
+            assert!(error.contains("exited with code 2"));
+            assert!(error.contains("npm ERR! missing script"));
+        }
+        status => panic!("unexpected build status {:?}", status),
+    }
+}
+
+#[test]
+fn spec_image_env_and_secrets_are_used() {
+    let dir = tempfile::tempdir().unwrap();
+    let docker = daemon(dir.path(), 0, "");
+    let (build, _) = build_in(dir.path());
+    let spec: BuildSpec = serde_yaml::from_str(
+        r#"
+image: rust:1.44
+env:
+  RUST_LOG: debug
+phases:
+  test:
+    - cargo test
+  build:
+    - cargo build --release
+"#,
+    )
+    .unwrap();
+    let build = build.with_spec(Some(spec));
+    let builder = DockerBuilder::new(
+        dir.path().join("workspace"),
+        "alpine:3".to_string(),
+        "make".to_string(),
+    )
+    .with_socket(&docker.socket);
+    let ctx = common::context(
+        builder,
+        FilesystemArtifacts::new(dir.path().join("artifacts")),
+    );
+    let secrets = Secrets::resolve(
+        &OneSecret,
+        &common::application(),
+        &["NPM_TOKEN".to_string()],
+    )
+    .unwrap();
+
+    ctx.builder.start_build(&build, &secrets, &ctx).unwrap();
+    let config = docker.find("POST", "/containers/create").unwrap().json();
+    assert_eq!(config["Image"], "rust:1.44");
+    assert_eq!(config["Cmd"][4], "cargo test && cargo build --release");
+    let secrets_file = fs::canonicalize(
+        dir.path()
+            .join("workspace")
+            .join(format!("{}.secrets", build_key(&build))),
+    )
+    .unwrap();
+    assert_eq!(
+        config["HostConfig"]["Binds"][1],
+        format!("{}:/run/conveyor/secrets:ro", secrets_file.display())
+    );
+    assert_eq!(
+        fs::read_to_string(&secrets_file).unwrap(),
+        "export NPM_TOKEN='hunter2'\n"
+    );
+    let env: Vec<&str> = config["Env"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| v.as_str().unwrap())
+        .collect();
+    assert!(env.contains(&"RUST_LOG=debug"));
+    assert!(!env.iter().any(|v| v.contains("hunter2")));
+    assert!(env.contains(&format!("CONVEYOR_GIT_REF={}", build.git_ref).as_str()));
+}
+
+#[test]
+fn missing_image_is_pulled() {
+    let dir = tempfile::tempdir().unwrap();
+    let pulled = Arc::new(AtomicBool::new(false));
+    let pulled_by_daemon = pulled.clone();
+    let docker = FakeDocker::start(dir.path(), move |request: &Request| {
+        match (request.method.as_str(), request.path.as_str()) {
+            ("POST", path) if path.starts_with("/images/create") => {
+                pulled_by_daemon.store(true, Ordering::SeqCst);
+                (200, br#"{"status": "Downloaded newer image"}"#.to_vec())
+            }
+            ("POST", path) if path.starts_with("/containers/create") => {
+                if pulled_by_daemon.load(Ordering::SeqCst) {
+                    (201, br#"{"Id": "abc123"}"#.to_vec())
+                } else {
+                    (
+                        404,
+                        br#"{"message": "No such image: alpine:latest"}"#.to_vec(),
+                    )
+                }
+            }
+            _ => (204, Vec::new()),
+        }
+    });
+    let (build, _) = build_in(dir.path());
+    let builder = DockerBuilder::new(
+        dir.path().join("workspace"),
+        "alpine".to_string(),
+        "make".to_string(),
+    )
+    .with_socket(&docker.socket);
+    let ctx = common::context(
+        builder,
+        FilesystemArtifacts::new(dir.path().join("artifacts")),
+    );
+
+    ctx.builder
+        .start_build(&build, &Secrets::default(), &ctx)
+        .unwrap();
+    assert!(pulled.load(Ordering::SeqCst));
+    assert_eq!(
+        docker.find("POST", "/images/create").unwrap().path,
+        "/images/create?fromImage=alpine&tag=latest"
+    );
+}
+
+#[test]
+fn image_is_encoded_in_the_pull() {
+    let dir = tempfile::tempdir().unwrap();
+    let docker = FakeDocker::start(dir.path(), |request: &Request| {
+        match (request.method.as_str(), request.path.as_str()) {
+            ("POST", path) if path.starts_with("/containers/create") => {
+                (404, br#"{"message": "No such image"}"#.to_vec())
+            }
+            _ => (200, Vec::new()),
+        }
+    });
+    let (build, _) = build_in(dir.path());
+    let builder = DockerBuilder::new(
+        dir.path().join("workspace"),
+        "registry.example.com:5000/team/app@sha256:abc".to_string(),
+        "make".to_string(),
+    )
+    .with_socket(&docker.socket);
+    let ctx = common::context(
+        builder,
+        FilesystemArtifacts::new(dir.path().join("artifacts")),
+    );
+
+    let _ = ctx.builder.start_build(&build, &Secrets::default(), &ctx);
+    assert_eq!(
+        docker.find("POST", "/images/create").unwrap().path,
+        "/images/create?fromImage=registry.example.com%3A5000%2Fteam%2Fapp%40sha256%3Aabc"
+    );
+}
+
+#[test]
+fn errors_while_pulling_are_reported() {
+    let dir = tempfile::tempdir().unwrap();
+    let docker = FakeDocker::start(dir.path(), |request: &Request| {
+        match (request.method.as_str(), request.path.as_str()) {
+            ("POST", path) if path.starts_with("/images/create") => (
+                200,
+                br#"{"status": "Pulling from library/alpine"}
+{"errorDetail": {"message": "unauthorized"}, "error": "unauthorized"}"#
+                    .to_vec(),
+            ),
+            ("POST", path) if path.starts_with("/containers/create") => {
+                (404, br#"{"message": "No such image"}"#.to_vec())
+            }
+            _ => (204, Vec::new()),
+        }
+    });
+    let (build, _) = build_in(dir.path());
+    let builder = DockerBuilder::new(
+        dir.path().join("workspace"),
+        "alpine:3".to_string(),
+        "make".to_string(),
+    )
+    .with_socket(&docker.socket);
+    let ctx = common::context(
+        builder,
+        FilesystemArtifacts::new(dir.path().join("artifacts")),
+    );
+
+    let error = ctx
+        .builder
+        .start_build(&build, &Secrets::default(), &ctx)
+        .unwrap_err();
+    assert!(error.to_string().contains("unauthorized"));
+    let creates = docker
+        .requests()
+        .into_iter()
+        .filter(|r| r.path.starts_with("/containers/create"))
+        .count();
+    assert_eq!(creates, 1);
+}
+
+#[test]
+fn daemon_errors_are_reported() {
+    let dir = tempfile::tempdir().unwrap();
+    let docker = FakeDocker::start(dir.path(), |request: &Request| {
+        if request.path.starts_with("/containers/create") {
+            (500, br#"{"message": "no space left on device"}"#.to_vec())
+        } else {
+            (204, Vec::new())
+        }
+    });
+    let (build, _) = build_in(dir.path());
+    let builder = DockerBuilder::new(
+        dir.path().join("workspace"),
+        "alpine:3".to_string(),
+        "make".to_string(),
+    )
+    .with_socket(&docker.socket);
+    let ctx = common::context(
+        builder,
+        FilesystemArtifacts::new(dir.path().join("artifacts")),
+    );
+
+    let error = ctx
+        .builder
+        .start_build(&build, &Secrets::default(), &ctx)
+        .unwrap_err();
+    assert!(error.to_string().contains("no space left on device"));
+}
+
+#[test]
+fn secrets_are_not_left_behind_when_the_build_fails_to_start() {
+    let dir = tempfile::tempdir().unwrap();
+    let docker = FakeDocker::start(dir.path(), |request: &Request| {
+        match (request.method.as_str(), request.path.as_str()) {
+            ("POST", path) if path.starts_with("/containers/create") => {
+                (500, br#"{"message": "No space left on device"}"#.to_vec())
+            }
+            _ => (204, Vec::new()),
+        }
+    });
+    let (build, _) = build_in(dir.path());
+    let builder = DockerBuilder::new(
+        dir.path().join("workspace"),
+        "alpine:3".to_string(),
+        "make".to_string(),
+    )
+    .with_socket(&docker.socket);
+    let ctx = common::context(
+        builder,
+        FilesystemArtifacts::new(dir.path().join("artifacts")),
+    );
+    let secrets_file = dir
+        .path()
+        .join("workspace")
+        .join(format!("{}.secrets", build_key(&build)));
+    let start = |names: &[&str]| {
+        let names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        let secrets = Secrets::resolve(&OneSecret, &common::application(), &names).unwrap();
+        ctx.builder.start_build(&build, &secrets, &ctx).unwrap_err()
+    };
+
+    let error = start(&["NPM_TOKEN"]);
+    assert!(error.to_string().contains("No space left on device"));
+    assert!(!secrets_file.exists());
+
+    let error = start(&["NPM_TOKEN", "NOT-A-VARIABLE"]);
+    assert!(error.to_string().contains("not a valid name"));
+    assert!(!secrets_file.exists());
+}
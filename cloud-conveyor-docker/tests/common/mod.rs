@@ -0,0 +1,247 @@
+//! Shared helpers for the integration tests of the docker runtime.
+#![allow(dead_code)]
+use cloud_conveyor_core::approval::MemoryApprovals;
+use cloud_conveyor_core::audit::MemoryAuditLog;
+use cloud_conveyor_core::credentials::NoCredentials;
+use cloud_conveyor_core::deploy::{DeployInfrastructure, DeployPollError, DeployStatus};
+use cloud_conveyor_core::history::MemoryHistory;
+use cloud_conveyor_core::pipelining::{Deploy, Teardown};
+use cloud_conveyor_core::runtime::RuntimeContext;
+use cloud_conveyor_core::secrets::{NoSecrets, Secrets};
+use cloud_conveyor_core::teardown::{TeardownInfrastructure, TeardownPollError, TeardownStatus};
+use cloud_conveyor_core::yaml::ConfigFile;
+use cloud_conveyor_core::Application;
+use cloud_conveyor_docker::DockerBuilder;
+use cloud_conveyor_local::FilesystemArtifacts;
+
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const CONFIG: &str = r#"
+org: zprobst
+app: cloud-conveyor
+accounts:
+  - name: default
+    id: 123456789
+    regions:
+      - us-east-1
+approvals: {}
+triggers: []
+stages:
+  - name: stage
+"#;
+
+pub fn application() -> Application {
+    let conf: ConfigFile = serde_yaml::from_str(CONFIG).unwrap();
+    conf.into()
+}
+
+pub fn context(builder: DockerBuilder, artifacts: FilesystemArtifacts) -> RuntimeContext {
+    RuntimeContext {
+        artifact_provider: Box::new(artifacts),
+        builder: Box::new(builder),
+        infrastructure: Box::new(NoInfrastructure),
+        teardown: Box::new(NoInfrastructure),
+        secrets: Box::new(NoSecrets),
+        credentials: Box::new(NoCredentials),
+        history: Box::new(MemoryHistory::default()),
+        audit: Box::new(MemoryAuditLog::default()),
+        approvals: Box::new(MemoryApprovals::default()),
+        applications: vec![application()],
+    }
+}
+
+fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "git {:?} failed", args);
+    String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
+
+/// Creates a git repository for the test application with a single commit and
+/// returns the sha of that commit.
+pub fn create_repo(dir: &Path, files: &[(&str, &str)]) -> String {
+    fs::create_dir_all(dir).unwrap();
+    git(dir, &["init", "--quiet"]);
+    for (name, content) in files {
+        fs::write(dir.join(name), content).unwrap();
+    }
+    git(dir, &["add", "."]);
+    git(
+        dir,
+        &[
+            "-c",
+            "user.name=conveyor",
+            "-c",
+            "user.email=conveyor@example.com",
+            "commit",
+            "--quiet",
+            "-m",
+            "initial",
+        ],
+    );
+    git(dir, &["rev-parse", "HEAD"])
+}
+
+/// A request that was made to the fake docker daemon.
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub body: String,
+}
+
+impl Request {
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_str(&self.body).unwrap()
+    }
+}
+
+/// Serves http on a unix socket like the docker daemon does. Every request is recorded and answered
+/// with the status and body the handler returns, sent with chunked transfer encoding like the daemon does.
+#[derive(Debug)]
+pub struct FakeDocker {
+    pub socket: PathBuf,
+    pub requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl FakeDocker {
+    pub fn start<F>(dir: &Path, handler: F) -> Self
+    where
+        F: Fn(&Request) -> (u16, Vec<u8>) + Send + 'static,
+    {
+        let socket = dir.join("docker.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                let request = read_request(&stream);
+                let (status, body) = handler(&request);
+                recorded.lock().unwrap().push(request);
+                write_response(stream, status, &body);
+            }
+        });
+        Self { socket, requests }
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Finds the first request with the method whose path starts with the prefix.
+    pub fn find(&self, method: &str, prefix: &str) -> Option<Request> {
+        self.requests()
+            .into_iter()
+            .find(|r| r.method == method && r.path.starts_with(prefix))
+    }
+}
+
+fn read_request(stream: &UnixStream) -> Request {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap().to_string();
+    let path = parts
+        .next()
+        .unwrap()
+        .trim_start_matches("/v1.41")
+        .to_string();
+
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).unwrap();
+        if header.trim().is_empty() {
+            break;
+        }
+        let header = header.to_ascii_lowercase();
+        if let Some(value) = header.strip_prefix("content-length:") {
+            length = value.trim().parse().unwrap();
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).unwrap();
+    Request {
+        method,
+        path,
+        body: String::from_utf8(body).unwrap(),
+    }
+}
+
+fn write_response(mut stream: UnixStream, status: u16, body: &[u8]) {
+    write!(
+        stream,
+        "HTTP/1.1 {} Fake\r\nContent-Type: application/json\r\nTransfer-Encoding: chunked\r\n\r\n",
+        status
+    )
+    .unwrap();
+    if !body.is_empty() {
+        write!(stream, "{:x}\r\n", body.len()).unwrap();
+        stream.write_all(body).unwrap();
+        write!(stream, "\r\n").unwrap();
+    }
+    write!(stream, "0\r\n\r\n").unwrap();
+}
+
+/// Frames the output like the daemon does for containers without a tty.
+pub fn multiplexed(frames: &[(u8, &str)]) -> Vec<u8> {
+    let mut raw = Vec::new();
+    for (stream, output) in frames {
+        raw.extend_from_slice(&[*stream, 0, 0, 0]);
+        raw.extend_from_slice(&(output.len() as u32).to_be_bytes());
+        raw.extend_from_slice(output.as_bytes());
+    }
+    raw
+}
+
+#[derive(Debug)]
+pub struct NoInfrastructure;
+
+impl DeployInfrastructure for NoInfrastructure {
+    fn start_deployment(
+        &self,
+        _deploy: &Deploy,
+        _secrets: &Secrets,
+        _ctx: &RuntimeContext,
+    ) -> Result<(), DeployPollError> {
+        Ok(())
+    }
+
+    fn check_deployment(
+        &self,
+        _deploy: &Deploy,
+        _ctx: &RuntimeContext,
+    ) -> Result<DeployStatus, DeployPollError> {
+        Ok(DeployStatus::Complete)
+    }
+}
+
+impl TeardownInfrastructure for NoInfrastructure {
+    fn start_teardown(
+        &self,
+        _teardown: &Teardown,
+        _ctx: &RuntimeContext,
+    ) -> Result<(), TeardownPollError> {
+        Ok(())
+    }
+
+    fn check_teardown(
+        &self,
+        _teardown: &Teardown,
+        _ctx: &RuntimeContext,
+    ) -> Result<TeardownStatus, TeardownPollError> {
+        Ok(TeardownStatus::Complete)
+    }
+}
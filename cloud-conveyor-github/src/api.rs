@@ -0,0 +1,204 @@
+//! Defines a minimal client of the REST api of github, for what the webhook payloads do not say.
+use cloud_conveyor_core::reaper::ResolvePullRequests;
+use cloud_conveyor_core::redact::Secret;
+use cloud_conveyor_core::webhook::CompareCommits;
+use failure::{Error, Fail};
+use serde::Deserialize;
+use serde_json::Value;
+
+use std::time::Duration;
+
+/// The url of the api of github.com.
+pub const GITHUB_API_URL: &str = "https://api.github.com";
+
+/// The most items that github returns on one page.
+const PAGE_SIZE: usize = 100;
+
+/// The most files that github lists in a comparison of two commits.
+const COMPARE_FILE_LIMIT: usize = 300;
+
+/// An error of a request to the api of github.
+#[derive(Debug, Fail)]
+pub enum ApiError {
+    /// When github rejects the token, or there is none and the repository is private, this variant
+    /// should be used.
+    #[fail(display = "Github rejected the credentials of the request to {}", path)]
+    Unauthorized {
+        /// The path of the request.
+        path: String,
+    },
+
+    /// Any other failure of a request.
+    #[fail(display = "The request to {} failed: {}", path, info)]
+    Other {
+        /// The path of the request.
+        path: String,
+        /// Why the request failed.
+        info: String,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct PullRequestFile {
+    filename: String,
+    #[serde(default)]
+    previous_filename: Option<String>,
+}
+
+/// A client of the parts of the api of github that cloud conveyor needs.
+#[derive(Debug)]
+pub struct GithubApi {
+    base_url: String,
+    token: Option<Secret<String>>,
+    agent: ureq::Agent,
+}
+
+impl GithubApi {
+    /// Creates an unauthenticated client of the api of github.com, which can only see public repositories.
+    pub fn new() -> Self {
+        Self {
+            base_url: GITHUB_API_URL.to_string(),
+            token: None,
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(30))
+                .build(),
+        }
+    }
+
+    /// Sets the url of the api, such as the one of a github enterprise server.
+    pub fn with_base_url(mut self, base_url: String) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Sets the token that the requests are made with.
+    pub fn with_token(mut self, token: String) -> Self {
+        self.token = Some(Secret::new(token));
+        self
+    }
+
+    fn get(&self, path: &str) -> Result<Value, ApiError> {
+        let mut request = self
+            .agent
+            .get(&format!("{}{}", self.base_url, path))
+            .set("Accept", "application/vnd.github.v3+json")
+            .set("User-Agent", "cloud-conveyor");
+        if let Some(token) = &self.token {
+            request = request.set("Authorization", &format!("token {}", token.expose()));
+        }
+        let other = |info: String| ApiError::Other {
+            path: path.to_string(),
+            info,
+        };
+        match request.call() {
+            Ok(response) => response.into_json().map_err(|e| other(e.to_string())),
+            Err(ureq::Error::Status(401, _)) | Err(ureq::Error::Status(403, _)) => {
+                Err(ApiError::Unauthorized {
+                    path: path.to_string(),
+                })
+            }
+            Err(ureq::Error::Status(status, response)) => {
+                let message = response
+                    .into_json::<Value>()
+                    .ok()
+                    .and_then(|body| body["message"].as_str().map(str::to_string))
+                    .unwrap_or_default();
+                Err(other(format!("status {}: {}", status, message)))
+            }
+            Err(e) => Err(other(e.to_string())),
+        }
+    }
+
+    /// Gets every file that the pull request with the number changes, relative to the root of the repository.
+    /// Renamed files are listed under both their old and new names. The repository is given by its owner and
+    /// name, e.g. `zprobst/cloud-conveyor`.
+    pub fn pull_request_files(&self, repo: &str, number: u32) -> Result<Vec<String>, ApiError> {
+        let mut files = Vec::new();
+        for page in 1.. {
+            let path = format!(
+                "/repos/{}/pulls/{}/files?per_page={}&page={}",
+                repo, number, PAGE_SIZE, page
+            );
+            let page: Vec<PullRequestFile> =
+                serde_json::from_value(self.get(&path)?).map_err(|e| ApiError::Other {
+                    path: path.clone(),
+                    info: e.to_string(),
+                })?;
+            let last = page.len() < PAGE_SIZE;
+            for file in page {
+                files.extend(file.previous_filename);
+                files.push(file.filename);
+            }
+            if last {
+                break;
+            }
+        }
+        Ok(files)
+    }
+
+    /// Gets the sha of the commit that the git ref, such as a tag or a branch, points at. The repository is given
+    /// by its owner and name, e.g. `zprobst/cloud-conveyor`.
+    pub fn commit_sha(&self, repo: &str, git_ref: &str) -> Result<String, ApiError> {
+        let path = format!("/repos/{}/commits/{}", repo, git_ref);
+        match self.get(&path)?["sha"].as_str() {
+            Some(sha) => Ok(sha.to_string()),
+            None => Err(ApiError::Other {
+                path,
+                info: "The commit has no sha".to_string(),
+            }),
+        }
+    }
+}
+
+/// Gets the owner and name of the repository with the git url, e.g. `zprobst/cloud-conveyor` of
+/// `https://github.com/zprobst/cloud-conveyor.git` or `git@github.com:zprobst/cloud-conveyor.git`.
+fn repo_full_name(git_url: &str) -> String {
+    let path = git_url.trim_end_matches('/').trim_end_matches(".git");
+    let mut parts = path.rsplit(['/', ':']);
+    let name = parts.next().unwrap_or_default();
+    let owner = parts.next().unwrap_or_default();
+    format!("{}/{}", owner, name)
+}
+
+impl CompareCommits for GithubApi {
+    fn changed_files(&self, repo: &str, base: &str, head: &str) -> Result<Vec<String>, Error> {
+        let path = format!(
+            "/repos/{}/compare/{}...{}",
+            repo_full_name(repo),
+            base,
+            head
+        );
+        let page: Vec<PullRequestFile> = serde_json::from_value(self.get(&path)?["files"].take())
+            .map_err(|e| ApiError::Other {
+            path: path.clone(),
+            info: e.to_string(),
+        })?;
+        // Github leaves out the files past the limit, which could be the ones that matter.
+        if page.len() >= COMPARE_FILE_LIMIT {
+            return Err(ApiError::Other {
+                path,
+                info: "The commits differ in too many files to list".to_string(),
+            }
+            .into());
+        }
+        let mut files = Vec::new();
+        for file in page {
+            files.extend(file.previous_filename);
+            files.push(file.filename);
+        }
+        Ok(files)
+    }
+}
+
+impl ResolvePullRequests for GithubApi {
+    fn is_open(&self, repo: &str, number: u32) -> Result<bool, Error> {
+        let path = format!("/repos/{}/pulls/{}", repo_full_name(repo), number);
+        Ok(self.get(&path)?["state"] == "open")
+    }
+}
+
+impl Default for GithubApi {
+    fn default() -> Self {
+        Self::new()
+    }
+}
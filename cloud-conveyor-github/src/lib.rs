@@ -8,17 +8,23 @@
     missing_docs,
     rust_2018_idioms,
     missing_debug_implementations,
-    intra_doc_link_resolution_failure
+    rustdoc::broken_intra_doc_links
 )]
+// The derive from failure expands to impls nested in anonymous constants.
+#![allow(non_local_definitions)]
 
-use cloud_conveyor_core::webhook::{InterpretWebhooks, VcsEvent, WebhookRequest};
-use crypto::hmac::Hmac;
-use crypto::mac::Mac;
-use crypto::mac::MacResult;
-use crypto::sha1::Sha1;
+pub mod api;
+
+use crate::api::GithubApi;
+use cloud_conveyor_core::webhook::{
+    is_full_sha, CompareCommits, InterpretWebhooks, PrChange, VcsEvent, WebhookRequest,
+};
 use hex::FromHex;
+use hmac::{Hmac, Mac};
+use log::warn;
 use serde::Deserialize;
 use serde_json::{Error, Value};
+use sha1::Sha1;
 
 #[derive(Debug, Deserialize)]
 struct BranchData {
@@ -27,22 +33,97 @@ struct BranchData {
     git_ref: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct Label {
+    name: String,
+}
+
 #[derive(Debug, Deserialize)]
 struct PullRequest {
     number: u32,
     merged: bool,
     head: BranchData,
     base: BranchData,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    labels: Vec<Label>,
+}
+
+impl PullRequest {
+    fn label_names(&self) -> Vec<String> {
+        self.labels.iter().map(|label| label.name.clone()).collect()
+    }
 }
 
 #[derive(Debug, Deserialize)]
 struct Repository {
     clone_url: String,
+    full_name: String,
 }
 
 #[derive(Debug, Deserialize)]
 struct Release {
     tag_name: String,
+    #[serde(default)]
+    target_commitish: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Commit {
+    #[serde(default)]
+    added: Vec<String>,
+    #[serde(default)]
+    removed: Vec<String>,
+    #[serde(default)]
+    modified: Vec<String>,
+}
+
+/// Stores information about a push webhook payload.
+#[derive(Debug, Deserialize)]
+pub struct PushPayload {
+    repository: Repository,
+    #[serde(rename = "ref")]
+    git_ref: String,
+    after: String,
+    #[serde(default)]
+    deleted: bool,
+    #[serde(default)]
+    commits: Vec<Commit>,
+}
+
+impl PushPayload {
+    /// Gets every file that the commits of the push added, removed or modified, once each. Returns None when
+    /// the push lists no commits, as for new branches and pushes of commits that were already pushed, since
+    /// the files it changes are not known then.
+    fn changed_files(&self) -> Option<Vec<String>> {
+        if self.commits.is_empty() {
+            return None;
+        }
+        let mut files: Vec<String> = Vec::new();
+        for commit in &self.commits {
+            for file in commit
+                .added
+                .iter()
+                .chain(commit.removed.iter())
+                .chain(commit.modified.iter())
+            {
+                if !files.contains(file) {
+                    files.push(file.clone());
+                }
+            }
+        }
+        Some(files)
+    }
+}
+
+/// Stores information about a delete webhook payload.
+#[derive(Debug, Deserialize)]
+pub struct DeletePayload {
+    repository: Repository,
+    #[serde(rename = "ref")]
+    git_ref: String,
+    ref_type: String,
 }
 
 /// Stores information about a pr webhook payload.
@@ -51,6 +132,27 @@ pub struct PullRequestPayload {
     repository: Repository,
     pull_request: PullRequest,
     action: String,
+    #[serde(default)]
+    label: Option<Label>,
+}
+
+impl PullRequestPayload {
+    /// Gets what changed about the pull request when it was updated.
+    fn change(&self) -> PrChange {
+        let label = || {
+            self.label
+                .as_ref()
+                .map(|label| label.name.clone())
+                .unwrap_or_default()
+        };
+        match self.action.as_ref() {
+            "labeled" => PrChange::Labeled(label()),
+            "unlabeled" => PrChange::Unlabeled(label()),
+            "ready_for_review" => PrChange::ReadyForReview,
+            "converted_to_draft" => PrChange::ConvertedToDraft,
+            _ => PrChange::Push,
+        }
+    }
 }
 
 /// Stores information about a release webhook payload.
@@ -69,21 +171,45 @@ pub enum EventType {
     Release(ReleasePayload),
     /// Pr webhook payload documented [here](https://developer.github.com/webhooks/event-payloads/#pull_request)
     Pr(PullRequestPayload),
+    /// Push webhook payload documented [here](https://developer.github.com/webhooks/event-payloads/#push)
+    Push(PushPayload),
+    /// Delete webhook payload documented [here](https://developer.github.com/webhooks/event-payloads/#delete)
+    Delete(DeletePayload),
 }
 
 /// An interface to github that allows for the interpreting of webhooks
 /// with the appropriate security validations in place.
+///
+/// By default, merges are reported when a pull request is merged. Since the payloads of pull requests do not
+/// say which files changed, the [path filters](../cloud_conveyor_core/struct.PathFilter.html) of pull request
+/// and merge triggers only apply when those files are looked up through the api, see [with_api](#method.with_api),
+/// or when merges are reported from pushes instead, see [with_push_merges](#method.with_push_merges).
+/// Every push to a branch is reported as a branch push as well, and the deletion of a branch is reported from
+/// the delete event. Tags are reported when their releases are published, along with the commit they point at,
+/// which is looked up through the api as well. So are the files that a tag changes compared to what a stage
+/// runs, for the path filters of tag triggers.
 #[derive(Debug)]
 pub struct Github {
     webhook_secret: Option<String>,
+    push_merges: bool,
+    api: Option<GithubApi>,
 }
 
 fn parse(body: String) -> Result<Vec<EventType>, Error> {
     let json: Value = serde_json::from_str(&body)?;
     let is_pull_request = json.pointer("/pull_request").is_some();
+    let is_push = json.pointer("/commits").is_some() && json.pointer("/ref").is_some();
+    // The payloads of create events look like those of delete events, but they name the default branch.
+    let is_delete = json.pointer("/ref_type").is_some() && json.pointer("/master_branch").is_none();
     if is_pull_request {
         let pr_data = serde_json::from_value(json)?;
         Ok(vec![EventType::Pr(pr_data)])
+    } else if is_push {
+        let push_data = serde_json::from_value(json)?;
+        Ok(vec![EventType::Push(push_data)])
+    } else if is_delete {
+        let delete_data = serde_json::from_value(json)?;
+        Ok(vec![EventType::Delete(delete_data)])
     } else {
         let push_data = serde_json::from_value(json)?;
         Ok(vec![EventType::Release(push_data)])
@@ -96,12 +222,73 @@ impl Github {
     pub fn unauthenticated() -> Self {
         Self {
             webhook_secret: None,
+            push_merges: false,
+            api: None,
         }
     }
     /// Created an authenticated instance of the webhook interpreter.
     pub fn authenticated(secret: String) -> Self {
         Self {
             webhook_secret: Some(secret),
+            push_merges: false,
+            api: None,
+        }
+    }
+
+    /// Sets whether merges are reported from pushes to branches, along with the files the pushed commits
+    /// changed, rather than from pull requests being merged. The branch that was merged from is not known
+    /// for pushes, so it is reported as empty.
+    pub fn with_push_merges(mut self, push_merges: bool) -> Self {
+        self.push_merges = push_merges;
+        self
+    }
+
+    /// Sets the api that the files that pull requests change are looked up with, so that the
+    /// [path filters](../cloud_conveyor_core/struct.PathFilter.html) of pull request triggers apply.
+    pub fn with_api(mut self, api: GithubApi) -> Self {
+        self.api = Some(api);
+        self
+    }
+
+    /// Looks up the files that the pull request changes. Returns None when there is no api to look them up
+    /// with or the lookup failed, so that the pull request is not filtered out.
+    fn pull_request_files(&self, pr_data: &PullRequestPayload) -> Option<Vec<String>> {
+        let api = self.api.as_ref()?;
+        let number = pr_data.pull_request.number;
+        match api.pull_request_files(&pr_data.repository.full_name, number) {
+            Ok(files) => Some(files),
+            Err(e) => {
+                warn!(
+                    "Could not get the files that PR {} of {} changes: {}",
+                    number, pr_data.repository.full_name, e
+                );
+                None
+            }
+        }
+    }
+
+    /// Finds the sha of the commit that the tag of the release points at. The target of a release is only
+    /// that commit when it is a sha, as it usually names the branch the tag was created from, so the tag is
+    /// looked up through the api otherwise. Returns the tag itself when there is no api to look it up with or
+    /// the lookup failed, which still builds the right commit but can not reuse the builds of its sha.
+    fn release_sha(&self, release_data: &ReleasePayload) -> String {
+        let release = &release_data.release;
+        if is_full_sha(&release.target_commitish) {
+            return release.target_commitish.clone();
+        }
+        let api = match &self.api {
+            Some(api) => api,
+            None => return release.tag_name.clone(),
+        };
+        match api.commit_sha(&release_data.repository.full_name, &release.tag_name) {
+            Ok(sha) => sha,
+            Err(e) => {
+                warn!(
+                    "Could not get the commit of tag {} of {}: {}",
+                    release.tag_name, release_data.repository.full_name, e
+                );
+                release.tag_name.clone()
+            }
         }
     }
 
@@ -111,9 +298,11 @@ impl Github {
             // https://developer.github.com/webhooks/securing/
             match Vec::from_hex(signature) {
                 Ok(signature_bytes) => {
-                    let mut mac = Hmac::new(Sha1::new(), &webhook_secret.as_bytes());
-                    mac.input(&payload.as_bytes());
-                    mac.result() == MacResult::new(&signature_bytes)
+                    let mut mac = Hmac::<Sha1>::new_from_slice(webhook_secret.as_bytes())
+                        .expect("Hmac takes keys of any length");
+                    mac.update(payload.as_bytes());
+                    // This compares in constant time.
+                    mac.verify_slice(&signature_bytes).is_ok()
                 }
                 Err(_) => false,
             }
@@ -138,7 +327,7 @@ impl InterpretWebhooks for Github {
     fn parse_to_intermediary(&self, req: WebhookRequest) -> Vec<Self::Intermediary> {
         // Signature might not be
         let signature_header = &req.headers["X-Hub-Signature"];
-        let sans_prefix = signature_header[5..signature_header.len()].as_bytes();
+        let sans_prefix = &signature_header.as_bytes()[5..];
 
         if self.authenticate(&req.body, sans_prefix) {
             match parse(req.body) {
@@ -160,6 +349,8 @@ impl InterpretWebhooks for Github {
                 // push but does not allow from releases from the command line.
                 "published" => vec![VcsEvent::TagPush {
                     tag: release_data.release.tag_name.clone(),
+                    sha: self.release_sha(release_data),
+                    changed_files: None,
                 }],
                 _ => Vec::with_capacity(0),
             },
@@ -171,15 +362,27 @@ impl InterpretWebhooks for Github {
                     number: pr_data.pull_request.number,
                     sha: pr_data.pull_request.head.sha.clone(),
                     source_branch: pr_data.pull_request.head.git_ref.clone(),
+                    target_branch: Some(pr_data.pull_request.base.git_ref.clone()),
+                    labels: pr_data.pull_request.label_names(),
+                    draft: pr_data.pull_request.draft,
+                    changed_files: self.pull_request_files(pr_data),
                 }],
 
                 // synchronize is used to indicate a change in the branch state or
                 // that there was a push to the source branch. As such, we need  to
-                // tell CC that the was a na update to the PR.
-                "synchronize" => vec![VcsEvent::PullRequestUpdate {
+                // tell CC that the was a na update to the PR. Changes to the labels and
+                // draft state of the PR are updates too, since they decide whether the PR
+                // is deployed.
+                "synchronize" | "labeled" | "unlabeled" | "ready_for_review"
+                | "converted_to_draft" => vec![VcsEvent::PullRequestUpdate {
                     number: pr_data.pull_request.number,
                     sha: pr_data.pull_request.head.sha.clone(),
                     source_branch: pr_data.pull_request.head.git_ref.clone(),
+                    target_branch: Some(pr_data.pull_request.base.git_ref.clone()),
+                    labels: pr_data.pull_request.label_names(),
+                    draft: pr_data.pull_request.draft,
+                    changed_files: self.pull_request_files(pr_data),
+                    change: pr_data.change(),
                 }],
 
                 // When a PR is closed, we will want to start at most two pipelines.
@@ -191,14 +394,16 @@ impl InterpretWebhooks for Github {
                     let pr_closed = VcsEvent::PullRequestComplete {
                         merged: pr_data.pull_request.merged,
                         number: pr_data.pull_request.number,
+                        source_branch: pr_data.pull_request.head.git_ref.clone(),
                     };
-                    if pr_data.pull_request.merged {
+                    if pr_data.pull_request.merged && !self.push_merges {
                         vec![
                             pr_closed,
                             VcsEvent::Merge {
                                 to_branch: pr_data.pull_request.base.git_ref.clone(),
                                 from_branch: pr_data.pull_request.head.git_ref.clone(),
                                 sha: pr_data.pull_request.base.sha.clone(),
+                                changed_files: self.pull_request_files(pr_data),
                             },
                         ]
                     } else {
@@ -207,14 +412,52 @@ impl InterpretWebhooks for Github {
                 }
                 _ => Vec::with_capacity(0),
             },
+            EventType::Push(push_data) => {
+                // Tags are reported when their releases are published and deleted branches
+                // are reported by delete events.
+                let branch = match push_data.git_ref.strip_prefix("refs/heads/") {
+                    Some(branch) if !push_data.deleted => branch,
+                    _ => return Vec::with_capacity(0),
+                };
+                let branch_push = VcsEvent::BranchPush {
+                    branch: branch.to_string(),
+                    sha: push_data.after.clone(),
+                    changed_files: push_data.changed_files(),
+                };
+                if self.push_merges {
+                    vec![
+                        VcsEvent::Merge {
+                            to_branch: branch.to_string(),
+                            from_branch: String::new(),
+                            sha: push_data.after.clone(),
+                            changed_files: push_data.changed_files(),
+                        },
+                        branch_push,
+                    ]
+                } else {
+                    vec![branch_push]
+                }
+            }
+            EventType::Delete(delete_data) => match delete_data.ref_type.as_ref() {
+                "branch" => vec![VcsEvent::BranchDelete {
+                    branch: delete_data.git_ref.clone(),
+                }],
+                _ => Vec::with_capacity(0),
+            },
         }
     }
     fn get_repo<'a>(&self, payload: &'a Self::Intermediary) -> &'a str {
         match payload {
             EventType::Release(push_data) => &push_data.repository.clone_url,
             EventType::Pr(pr_data) => &pr_data.repository.clone_url,
+            EventType::Push(push_data) => &push_data.repository.clone_url,
+            EventType::Delete(delete_data) => &delete_data.repository.clone_url,
         }
     }
+
+    fn commits(&self) -> Option<&dyn CompareCommits> {
+        self.api.as_ref().map(|api| api as &dyn CompareCommits)
+    }
 }
 
 #[cfg(test)]
@@ -224,13 +467,20 @@ mod tests {
     fn invalid_sig() {
         let invalid = "e9bec4a37923d7396bb9c56c1f8eca8b52f49a6c";
         let subject = Github::authenticated("hello".to_owned());
-        assert_eq!(false, subject.authenticate("123456789", invalid.as_bytes()))
+        assert!(!subject.authenticate("123456789", invalid.as_bytes()))
     }
 
     #[test]
     fn valid_sig() {
         let expected = "c6a94f25b8ace8f1c65c9bb6937d32973a4ceb9e";
         let subject = Github::authenticated("hello".to_owned());
-        assert_eq!(true, subject.authenticate("123456789", expected.as_bytes()))
+        assert!(subject.authenticate("123456789", expected.as_bytes()))
+    }
+
+    #[test]
+    fn truncated_sig() {
+        let truncated = "c6a94f25b8ace8f1c65c9bb6937d32973a4c";
+        let subject = Github::authenticated("hello".to_owned());
+        assert!(!subject.authenticate("123456789", truncated.as_bytes()))
     }
 }
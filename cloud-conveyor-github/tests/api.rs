@@ -0,0 +1,167 @@
+mod common;
+
+use cloud_conveyor_core::reaper::ResolvePullRequests;
+use cloud_conveyor_core::webhook::{CompareCommits, InterpretWebhooks, VcsEvent, WebhookRequest};
+use cloud_conveyor_github::api::GithubApi;
+use cloud_conveyor_github::Github;
+use common::MockGithubApi;
+use serde_json::json;
+
+use std::collections::HashMap;
+use std::fs;
+
+const FILES: &str = "/repos/Codertocat/Hello-World/pulls/2/files?per_page=100";
+
+fn changed_files_of_created_pr(api: GithubApi) -> Option<Vec<String>> {
+    let mut headers = HashMap::new();
+    headers.insert("X-Hub-Signature".to_owned(), "hmac=test".to_owned());
+    let request = WebhookRequest {
+        body: fs::read_to_string("tests/data/pr_created.json").unwrap(),
+        headers,
+    };
+    let subject = Github::unauthenticated().with_api(api);
+    let intermediary = subject.parse_to_intermediary(request).pop().unwrap();
+    match subject.get_vcs_event(&intermediary).pop().unwrap() {
+        VcsEvent::PullRequestCreate { changed_files, .. } => changed_files,
+        event => panic!("Unexpected event {:?}", event),
+    }
+}
+
+fn sha_of_release(api: GithubApi, target_commitish: &str) -> String {
+    let mut headers = HashMap::new();
+    headers.insert("X-Hub-Signature".to_owned(), "hmac=test".to_owned());
+    let mut body: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string("tests/data/release.json").unwrap()).unwrap();
+    body["release"]["target_commitish"] = json!(target_commitish);
+    let request = WebhookRequest {
+        body: body.to_string(),
+        headers,
+    };
+    let subject = Github::unauthenticated().with_api(api);
+    let intermediary = subject.parse_to_intermediary(request).pop().unwrap();
+    match subject.get_vcs_event(&intermediary).pop().unwrap() {
+        VcsEvent::TagPush { sha, .. } => sha,
+        event => panic!("Unexpected event {:?}", event),
+    }
+}
+
+#[test]
+fn releases_are_built_from_the_commit_of_their_tag() {
+    let sha = "6113728f27ae82c7b1a177c8d03f9e96e0adf246";
+    let server = MockGithubApi::start();
+    server.respond(
+        "/repos/Codertocat/Hello-World/commits/0.0.1",
+        200,
+        &json!({ "sha": sha }).to_string(),
+    );
+    let api = || GithubApi::new().with_base_url(server.url.clone());
+    assert_eq!(sha_of_release(api(), "master"), sha);
+
+    // Targets that are shas already are not looked up.
+    let target = "ec26c3e57ca3a959ca5aad62de7213c562f8c821";
+    assert_eq!(sha_of_release(api(), target), target);
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn releases_whose_commit_cannot_be_looked_up_are_built_from_their_tag() {
+    let server = MockGithubApi::start();
+    let api = GithubApi::new().with_base_url(server.url.clone());
+    assert_eq!(sha_of_release(api, "master"), "0.0.1");
+}
+
+#[test]
+fn files_of_pull_requests_are_read_from_every_page() {
+    let server = MockGithubApi::start();
+    let first: Vec<_> = (0..100)
+        .map(|i| json!({ "filename": format!("src/{}.rs", i) }))
+        .collect();
+    let second = json!([{ "filename": "docs/new.md", "previous_filename": "docs/old.md" }]);
+    server.respond(&format!("{}&page=1", FILES), 200, &json!(first).to_string());
+    server.respond(&format!("{}&page=2", FILES), 200, &second.to_string());
+
+    let api = GithubApi::new()
+        .with_base_url(server.url.clone())
+        .with_token("secret-token".to_string());
+    let files = changed_files_of_created_pr(api).unwrap();
+    assert_eq!(files.len(), 102);
+    assert_eq!(files[0], "src/0.rs");
+    assert_eq!(files[100..], ["docs/old.md", "docs/new.md"]);
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[0]
+        .headers
+        .iter()
+        .any(|header| header == "Authorization: token secret-token"));
+}
+
+#[test]
+fn files_of_pull_requests_are_unknown_when_they_cannot_be_looked_up() {
+    let server = MockGithubApi::start();
+    server.respond(
+        &format!("{}&page=1", FILES),
+        403,
+        r#"{"message": "Bad credentials"}"#,
+    );
+    let api = GithubApi::new().with_base_url(server.url.clone());
+    assert_eq!(changed_files_of_created_pr(api), None);
+}
+
+#[test]
+fn pull_requests_are_open_until_github_says_otherwise() {
+    let server = MockGithubApi::start();
+    server.respond(
+        "/repos/zprobst/cloud-conveyor/pulls/1",
+        200,
+        r#"{"state": "open"}"#,
+    );
+    server.respond(
+        "/repos/zprobst/cloud-conveyor/pulls/2",
+        200,
+        r#"{"state": "closed"}"#,
+    );
+    let api = GithubApi::new().with_base_url(server.url.clone());
+
+    let https = "https://github.com/zprobst/cloud-conveyor.git";
+    assert!(api.is_open(https, 1).unwrap());
+    assert!(!api.is_open(https, 2).unwrap());
+    assert!(!api
+        .is_open("git@github.com:zprobst/cloud-conveyor.git", 2)
+        .unwrap());
+    assert!(api.is_open(https, 3).is_err());
+}
+
+#[test]
+fn files_that_changed_between_commits_are_compared() {
+    let server = MockGithubApi::start();
+    let files = json!({
+        "files": [
+            { "filename": "services/api/main.rs" },
+            { "filename": "docs/new.md", "previous_filename": "docs/old.md" },
+        ]
+    });
+    server.respond(
+        "/repos/Codertocat/Hello-World/compare/aaa...bbb",
+        200,
+        &files.to_string(),
+    );
+    let too_many: Vec<_> = (0..300)
+        .map(|i| json!({ "filename": format!("src/{}.rs", i) }))
+        .collect();
+    server.respond(
+        "/repos/Codertocat/Hello-World/compare/aaa...ccc",
+        200,
+        &json!({ "files": too_many }).to_string(),
+    );
+
+    let api = GithubApi::new().with_base_url(server.url.clone());
+    let repo = "https://github.com/Codertocat/Hello-World.git";
+    assert_eq!(
+        api.changed_files(repo, "aaa", "bbb").unwrap(),
+        vec!["services/api/main.rs", "docs/old.md", "docs/new.md"]
+    );
+    // Github does not list every file of large comparisons, so they are not known.
+    assert!(api.changed_files(repo, "aaa", "ccc").is_err());
+    assert!(api.changed_files(repo, "aaa", "ddd").is_err());
+}
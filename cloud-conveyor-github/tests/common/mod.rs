@@ -0,0 +1,92 @@
+//! Shared helpers for the integration tests of the github crate.
+#![allow(dead_code)]
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// A request that was made to the mock api.
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub path: String,
+    pub headers: Vec<String>,
+}
+
+/// Stands in for the api of github. Every response is set up front by the path and query of its request,
+/// and requests of anything else are answered with 404.
+#[derive(Debug)]
+pub struct MockGithubApi {
+    pub url: String,
+    pub responses: Arc<Mutex<BTreeMap<String, (u16, String)>>>,
+    pub requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl MockGithubApi {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let responses = Arc::new(Mutex::new(BTreeMap::new()));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let (stored, recorded) = (responses.clone(), requests.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                let request = read_request(&stream);
+                let (status, body) = stored
+                    .lock()
+                    .unwrap()
+                    .get(&request.path)
+                    .cloned()
+                    .unwrap_or((404, r#"{"message": "Not Found"}"#.to_string()));
+                recorded.lock().unwrap().push(request);
+                write_response(stream, status, &body);
+            }
+        });
+        Self {
+            url,
+            responses,
+            requests,
+        }
+    }
+
+    pub fn respond(&self, path: &str, status: u16, body: &str) {
+        self.responses
+            .lock()
+            .unwrap()
+            .insert(path.to_string(), (status, body.to_string()));
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn read_request(stream: &TcpStream) -> Request {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    let path = line.split_whitespace().nth(1).unwrap().to_string();
+    let mut headers = Vec::new();
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).unwrap();
+        let header = header.trim().to_string();
+        if header.is_empty() {
+            break;
+        }
+        headers.push(header);
+    }
+    Request { path, headers }
+}
+
+fn write_response(mut stream: TcpStream, status: u16, body: &str) {
+    write!(
+        stream,
+        "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+    .unwrap();
+}
@@ -1,4 +1,4 @@
-use cloud_conveyor_core::webhook::{InterpretWebhooks, VcsEvent, WebhookRequest};
+use cloud_conveyor_core::webhook::{InterpretWebhooks, PrChange, VcsEvent, WebhookRequest};
 use cloud_conveyor_github::Github;
 
 use std::collections::HashMap;
@@ -13,9 +13,22 @@ fn generate_request_from_file(file_name: &str) -> WebhookRequest {
     }
 }
 
+/// The files that the commits of the push in `push.json` change.
+fn pushed_files() -> Vec<String> {
+    vec![
+        "docs/usage.md".to_owned(),
+        "README.md".to_owned(),
+        "src/old.rs".to_owned(),
+        "src/main.rs".to_owned(),
+    ]
+}
+
 fn compare_payload_to_expected_result(file: &str, results: Vec<VcsEvent>) {
+    compare_payload_to_expected_result_with(Github::unauthenticated(), file, results)
+}
+
+fn compare_payload_to_expected_result_with(subject: Github, file: &str, results: Vec<VcsEvent>) {
     let payload = generate_request_from_file(file);
-    let subject = Github::unauthenticated();
     let mut parse_results = subject.parse_to_intermediary(payload);
     assert_eq!(parse_results.len(), 1);
     let intermediary = parse_results.pop().unwrap();
@@ -34,6 +47,10 @@ fn pr_created() {
             number: 2,
             sha: "ec26c3e57ca3a959ca5aad62de7213c562f8c821".to_owned(),
             source_branch: "changes".to_owned(),
+            target_branch: Some("master".to_owned()),
+            labels: vec![],
+            draft: false,
+            changed_files: None,
         }],
     )
 }
@@ -46,6 +63,10 @@ fn pr_recreated() {
             number: 2,
             sha: "ec26c3e57ca3a959ca5aad62de7213c562f8c821".to_owned(),
             source_branch: "changes".to_owned(),
+            target_branch: Some("master".to_owned()),
+            labels: vec![],
+            draft: false,
+            changed_files: None,
         }],
     )
 }
@@ -58,6 +79,45 @@ fn pr_updated() {
             number: 2,
             sha: "ec26c3e57ca3a959ca5aad62de7213c562f8c821".to_owned(),
             source_branch: "changes".to_owned(),
+            target_branch: Some("master".to_owned()),
+            labels: vec![],
+            draft: false,
+            changed_files: None,
+            change: PrChange::Push,
+        }],
+    )
+}
+
+#[test]
+fn pr_labeled() {
+    compare_payload_to_expected_result(
+        "tests/data/pr_labeled.json",
+        vec![VcsEvent::PullRequestUpdate {
+            number: 2,
+            sha: "ec26c3e57ca3a959ca5aad62de7213c562f8c821".to_owned(),
+            source_branch: "changes".to_owned(),
+            target_branch: Some("master".to_owned()),
+            labels: vec!["deploy-preview".to_owned()],
+            draft: false,
+            changed_files: None,
+            change: PrChange::Labeled("deploy-preview".to_owned()),
+        }],
+    )
+}
+
+#[test]
+fn pr_ready_for_review() {
+    compare_payload_to_expected_result(
+        "tests/data/pr_ready_for_review.json",
+        vec![VcsEvent::PullRequestUpdate {
+            number: 2,
+            sha: "ec26c3e57ca3a959ca5aad62de7213c562f8c821".to_owned(),
+            source_branch: "changes".to_owned(),
+            target_branch: Some("master".to_owned()),
+            labels: vec![],
+            draft: false,
+            changed_files: None,
+            change: PrChange::ReadyForReview,
         }],
     )
 }
@@ -70,11 +130,13 @@ fn pr_merged() {
             VcsEvent::PullRequestComplete {
                 number: 2,
                 merged: true,
+                source_branch: "changes".to_owned(),
             },
             VcsEvent::Merge {
                 to_branch: "master".to_owned(),
                 from_branch: "changes".to_owned(),
                 sha: "f95f852bd8fca8fcc58a9a2d6c842781e32a215e".to_owned(),
+                changed_files: None,
             },
         ],
     )
@@ -87,6 +149,7 @@ fn pr_closed() {
         vec![VcsEvent::PullRequestComplete {
             number: 2,
             merged: false,
+            source_branch: "changes".to_owned(),
         }],
     )
 }
@@ -97,6 +160,86 @@ fn released() {
         "tests/data/release.json",
         vec![VcsEvent::TagPush {
             tag: "0.0.1".to_owned(),
+            // Without an api the commit of the tag is not known, so the tag itself is built.
+            sha: "0.0.1".to_owned(),
+            changed_files: None,
+        }],
+    )
+}
+
+#[test]
+fn pushes_are_branch_pushes_by_default() {
+    compare_payload_to_expected_result(
+        "tests/data/push.json",
+        vec![VcsEvent::BranchPush {
+            branch: "master".to_owned(),
+            sha: "0d1a26e67d8f5eaf1f6ba5c57fc3c7d91ac0fd1c".to_owned(),
+            changed_files: Some(pushed_files()),
+        }],
+    )
+}
+
+#[test]
+fn files_of_pushes_without_commits_are_unknown() {
+    let mut payload = generate_request_from_file("tests/data/push.json");
+    let mut body: serde_json::Value = serde_json::from_str(&payload.body).unwrap();
+    body["commits"] = serde_json::json!([]);
+    payload.body = body.to_string();
+
+    let subject = Github::unauthenticated();
+    let intermediary = subject.parse_to_intermediary(payload).pop().unwrap();
+    assert_eq!(
+        subject.get_vcs_event(&intermediary),
+        vec![VcsEvent::BranchPush {
+            branch: "master".to_owned(),
+            sha: "0d1a26e67d8f5eaf1f6ba5c57fc3c7d91ac0fd1c".to_owned(),
+            changed_files: None,
+        }]
+    );
+}
+
+#[test]
+fn deleted_branches() {
+    compare_payload_to_expected_result(
+        "tests/data/delete.json",
+        vec![VcsEvent::BranchDelete {
+            branch: "feature/login".to_owned(),
+        }],
+    )
+}
+
+#[test]
+fn pushed_merges_carry_changed_files() {
+    let subject = Github::unauthenticated().with_push_merges(true);
+    compare_payload_to_expected_result_with(
+        subject,
+        "tests/data/push.json",
+        vec![
+            VcsEvent::Merge {
+                to_branch: "master".to_owned(),
+                from_branch: "".to_owned(),
+                sha: "0d1a26e67d8f5eaf1f6ba5c57fc3c7d91ac0fd1c".to_owned(),
+                changed_files: Some(pushed_files()),
+            },
+            VcsEvent::BranchPush {
+                branch: "master".to_owned(),
+                sha: "0d1a26e67d8f5eaf1f6ba5c57fc3c7d91ac0fd1c".to_owned(),
+                changed_files: Some(pushed_files()),
+            },
+        ],
+    )
+}
+
+#[test]
+fn pushed_merges_replace_merged_prs() {
+    let subject = Github::unauthenticated().with_push_merges(true);
+    compare_payload_to_expected_result_with(
+        subject,
+        "tests/data/pr_merged.json",
+        vec![VcsEvent::PullRequestComplete {
+            number: 2,
+            merged: true,
+            source_branch: "changes".to_owned(),
         }],
     )
 }
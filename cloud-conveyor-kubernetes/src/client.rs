@@ -0,0 +1,123 @@
+use cloud_conveyor_core::deploy::DeployPollError;
+use cloud_conveyor_core::redact::Secret;
+use cloud_conveyor_core::teardown::TeardownPollError;
+use serde_json::Value;
+
+use std::time::Duration;
+
+/// The name that changes made by cloud conveyor are attributed to by server side apply.
+const FIELD_MANAGER: &str = "cloud-conveyor";
+
+/// An error of a request to the kubernetes api.
+#[derive(Debug)]
+pub(crate) enum ApiError {
+    /// The api rejected the credentials.
+    Unauthorized,
+    /// Any other failure.
+    Other(String),
+}
+
+impl From<ApiError> for DeployPollError {
+    fn from(error: ApiError) -> Self {
+        match error {
+            ApiError::Unauthorized => DeployPollError::Credentials,
+            ApiError::Other(info) => DeployPollError::Other { info },
+        }
+    }
+}
+
+impl From<ApiError> for TeardownPollError {
+    fn from(error: ApiError) -> Self {
+        match error {
+            ApiError::Unauthorized => TeardownPollError::Credentials,
+            ApiError::Other(info) => TeardownPollError::Other { info },
+        }
+    }
+}
+
+/// A minimal client for the parts of the kubernetes api that deployments need.
+#[derive(Debug)]
+pub(crate) struct ApiClient {
+    server: String,
+    token: Option<Secret<String>>,
+    agent: ureq::Agent,
+}
+
+impl ApiClient {
+    pub(crate) fn new(server: String) -> Self {
+        Self {
+            server: server.trim_end_matches('/').to_string(),
+            token: None,
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(30))
+                .build(),
+        }
+    }
+
+    pub(crate) fn with_token(mut self, token: String) -> Self {
+        self.token = Some(Secret::new(token));
+        self
+    }
+
+    fn request(&self, method: &str, path: &str) -> ureq::Request {
+        let request = self
+            .agent
+            .request(method, &format!("{}{}", self.server, path))
+            .set("Accept", "application/json");
+        match &self.token {
+            Some(token) => request.set("Authorization", &format!("Bearer {}", token.expose())),
+            None => request,
+        }
+    }
+
+    fn error(method: &str, path: &str, error: ureq::Error) -> ApiError {
+        match error {
+            ureq::Error::Status(401, _) | ureq::Error::Status(403, _) => ApiError::Unauthorized,
+            ureq::Error::Status(status, response) => {
+                let message = response
+                    .into_json::<Value>()
+                    .ok()
+                    .and_then(|body| body["message"].as_str().map(str::to_string))
+                    .unwrap_or_default();
+                ApiError::Other(format!(
+                    "{} {} failed with status {}: {}",
+                    method, path, status, message
+                ))
+            }
+            error => ApiError::Other(error.to_string()),
+        }
+    }
+
+    fn json(response: ureq::Response) -> Result<Value, ApiError> {
+        response
+            .into_json()
+            .map_err(|e| ApiError::Other(e.to_string()))
+    }
+
+    /// Gets the object at the path. Returns None when it does not exist.
+    pub(crate) fn get(&self, path: &str) -> Result<Option<Value>, ApiError> {
+        match self.request("GET", path).call() {
+            Ok(response) => Self::json(response).map(Some),
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(e) => Err(Self::error("GET", path, e)),
+        }
+    }
+
+    /// Creates or updates the object at the path with server side apply.
+    pub(crate) fn apply(&self, path: &str, object: &Value) -> Result<Value, ApiError> {
+        let path = format!("{}?fieldManager={}&force=true", path, FIELD_MANAGER);
+        self.request("PATCH", &path)
+            .set("Content-Type", "application/apply-patch+yaml")
+            .send_string(&object.to_string())
+            .map_err(|e| Self::error("PATCH", &path, e))
+            .and_then(Self::json)
+    }
+
+    /// Deletes the object at the path. Deleting an object that does not exist is not an error.
+    pub(crate) fn delete(&self, path: &str) -> Result<(), ApiError> {
+        match self.request("DELETE", path).call() {
+            Ok(_) | Err(ureq::Error::Status(404, _)) => Ok(()),
+            Err(e) => Err(Self::error("DELETE", path, e)),
+        }
+    }
+}
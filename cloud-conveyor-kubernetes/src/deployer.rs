@@ -0,0 +1,408 @@
+use crate::client::{ApiClient, ApiError};
+use crate::manifests::{collection_path, load, object_path};
+use crate::rollout::{self, Rollout};
+use cloud_conveyor_core::deploy::{DeployInfrastructure, DeployPollError, DeployStatus};
+use cloud_conveyor_core::pipelining::{Deploy, Teardown};
+use cloud_conveyor_core::runtime::RuntimeContext;
+use cloud_conveyor_core::secrets::Secrets;
+use cloud_conveyor_core::teardown::{TeardownInfrastructure, TeardownPollError, TeardownStatus};
+use cloud_conveyor_core::{qualified_stage_name, short_hash, Application, Stage};
+use log::{info, warn};
+use serde_json::{json, Map, Value};
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+
+/// The longest name kubernetes allows for a namespace.
+const MAX_NAMESPACE_LENGTH: usize = 63;
+
+/// The longest value kubernetes allows for a label.
+const MAX_LABEL_LENGTH: usize = 63;
+
+/// The name of the kubernetes secret that holds the secrets of a stage.
+const SECRETS_NAME: &str = "cloud-conveyor-secrets";
+
+/// The label that marks the objects that cloud conveyor applied, and its value.
+const MANAGED_BY: (&str, &str) = ("app.kubernetes.io/managed-by", "cloud-conveyor");
+
+/// The kinds whose objects are pruned when they are no longer in the manifests, in addition to the kinds
+/// that are in the manifests.
+const PRUNED_KINDS: &[(&str, &str)] = &[
+    ("v1", "ConfigMap"),
+    ("v1", "PersistentVolumeClaim"),
+    ("v1", "Secret"),
+    ("v1", "Service"),
+    ("v1", "ServiceAccount"),
+    ("apps/v1", "DaemonSet"),
+    ("apps/v1", "Deployment"),
+    ("apps/v1", "StatefulSet"),
+    ("batch/v1", "CronJob"),
+    ("batch/v1", "Job"),
+    ("networking.k8s.io/v1", "Ingress"),
+];
+
+/// Gets the name of the namespace of the stage of the application. The name is the
+/// [qualified name](../cloud_conveyor_core/fn.qualified_stage_name.html) of the stage converted into a
+/// valid dns label, so the stages `pr-42` and `api-pr-42` of the application `api` are deployed to the
+/// namespace `api-pr-42`. Names that are too long for a namespace are cut short and end with a
+/// [hash](../cloud_conveyor_core/fn.short_hash.html) of the qualified name, so that stages whose names
+/// only differ past the cut still get namespaces of their own.
+pub fn namespace_name(app: &Application, stage: &Stage) -> String {
+    let qualified = qualified_stage_name(app, stage);
+    let name: String = qualified
+        .to_ascii_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let name = name.trim_matches('-');
+    if name.len() <= MAX_NAMESPACE_LENGTH {
+        return name.to_string();
+    }
+    let hash = short_hash(&qualified);
+    let prefix = name[..MAX_NAMESPACE_LENGTH - hash.len() - 1].trim_end_matches('-');
+    format!("{}-{}", prefix, hash)
+}
+
+/// Converts the text into a valid label value, which only has alphanumerics, `-`, `_` and `.` in it and starts and
+/// ends with an alphanumeric. Like namespaces, values that are too long are cut short and end with a hash of the text.
+fn label_value(text: &str) -> String {
+    let value: String = text
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let value = value.trim_matches(|c: char| !c.is_ascii_alphanumeric());
+    if value.len() <= MAX_LABEL_LENGTH {
+        return value.to_string();
+    }
+    let hash = short_hash(text);
+    let prefix = value[..MAX_LABEL_LENGTH - hash.len() - 1]
+        .trim_end_matches(|c: char| !c.is_ascii_alphanumeric());
+    format!("{}-{}", prefix, hash)
+}
+
+fn text(value: &Value) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
+
+/// Marks the object as applied by cloud conveyor so that it is pruned once it is no longer in the manifests.
+fn label_managed(object: &mut Value) {
+    object["metadata"]["labels"][MANAGED_BY.0] = Value::from(MANAGED_BY.1);
+}
+
+fn other<E: ToString>(e: E) -> DeployPollError {
+    DeployPollError::Other {
+        info: e.to_string(),
+    }
+}
+
+/// Deploys applications to a kubernetes cluster with a namespace for every stage.
+///
+/// The artifacts of the build that is deployed are expected to contain the kubernetes manifests of the application
+/// in the manifest directory, `kubernetes` by default. Every yaml or json file in there is read, in the order of
+/// the file names, after the `${APP}`, `${STAGE}`, `${NAMESPACE}` and `${GIT_REF}` placeholders in it are replaced.
+/// The objects are applied to the namespace of the stage, see [namespace_name](fn.namespace_name.html), with server
+/// side apply, so any namespace set in the manifests is ignored and only namespaced objects are supported. The secrets
+/// of the stage are applied as the `cloud-conveyor-secrets` secret in the namespace.
+///
+/// Every object that is applied is labeled `app.kubernetes.io/managed-by: cloud-conveyor`. Once the manifests are
+/// applied, labeled objects in the namespace that are no longer in them are deleted, much like `kubectl apply --prune`
+/// does. Objects of the kinds that are in the manifests and of common kinds such as config maps, services and
+/// deployments are pruned.
+///
+/// A deployment is complete when the rollout of every deployment and stateful set in the namespace is and fails when
+/// a deployment exceeds its progress deadline. Tearing a stage down deletes its namespace and everything in it.
+///
+/// The api server is reached over http(s) with an optional bearer token. Pointing the deployer at `kubectl proxy`
+/// is the simplest way to reuse the credentials and certificates of a kube config.
+#[derive(Debug)]
+pub struct KubernetesDeployer {
+    client: ApiClient,
+    workspace: PathBuf,
+    manifest_dir: PathBuf,
+}
+
+impl KubernetesDeployer {
+    /// Creates a deployer for the cluster whose api server is at the url. The artifacts of builds are
+    /// downloaded into the workspace directory.
+    pub fn new<P: Into<PathBuf>>(server: String, workspace: P) -> Self {
+        Self {
+            client: ApiClient::new(server),
+            workspace: workspace.into(),
+            manifest_dir: PathBuf::from("kubernetes"),
+        }
+    }
+
+    /// Sets the bearer token that requests to the api server are authenticated with.
+    pub fn with_token(mut self, token: String) -> Self {
+        self.client = self.client.with_token(token);
+        self
+    }
+
+    /// Sets the directory, relative to the root of the build artifacts, that holds the manifests.
+    /// Defaults to `kubernetes`.
+    pub fn with_manifest_dir<P: Into<PathBuf>>(mut self, manifest_dir: P) -> Self {
+        self.manifest_dir = manifest_dir.into();
+        self
+    }
+
+    fn load_manifests(
+        &self,
+        app: &Application,
+        deploy: &Deploy,
+        namespace: &str,
+        ctx: &RuntimeContext,
+    ) -> Result<Vec<Value>, DeployPollError> {
+        let artifacts = self.workspace.join(namespace);
+        if artifacts.exists() {
+            fs::remove_dir_all(&artifacts).map_err(other)?;
+        }
+        fs::create_dir_all(&artifacts).map_err(other)?;
+        ctx.artifact_provider
+            .download(app, &deploy.git_ref, &artifacts)
+            .map_err(other)?;
+
+        let variables = [
+            ("APP", app.app.as_str()),
+            ("STAGE", deploy.stage.name.as_str()),
+            ("NAMESPACE", namespace),
+            ("GIT_REF", deploy.git_ref.as_str()),
+        ];
+        let objects = load(&artifacts.join(&self.manifest_dir), &variables).map_err(other)?;
+        fs::remove_dir_all(&artifacts).map_err(other)?;
+        Ok(objects)
+    }
+
+    fn apply_namespace(
+        &self,
+        app: &Application,
+        stage: &Stage,
+        namespace: &str,
+    ) -> Result<(), ApiError> {
+        let object = json!({
+            "apiVersion": "v1",
+            "kind": "Namespace",
+            "metadata": {
+                "name": namespace,
+                "labels": {
+                    "app.kubernetes.io/managed-by": "cloud-conveyor",
+                    "cloud-conveyor/app": label_value(&app.app),
+                    "cloud-conveyor/stage": label_value(&stage.name),
+                },
+            },
+        });
+        self.client
+            .apply(&format!("/api/v1/namespaces/{}", namespace), &object)
+            .map(|_| ())
+    }
+
+    fn apply_secrets(&self, secrets: &Secrets, namespace: &str) -> Result<(), ApiError> {
+        let values: Map<String, Value> = secrets
+            .iter()
+            .map(|(name, value)| (name.to_string(), Value::from(value)))
+            .collect();
+        let mut object = json!({
+            "apiVersion": "v1",
+            "kind": "Secret",
+            "metadata": { "name": SECRETS_NAME },
+            "type": "Opaque",
+            "stringData": values,
+        });
+        label_managed(&mut object);
+        self.client
+            .apply(
+                &object_path(&object, namespace).map_err(ApiError::Other)?,
+                &object,
+            )
+            .map(|_| ())
+    }
+
+    /// Gets the objects of the kind in the namespace.
+    fn list(
+        &self,
+        group_version: &str,
+        kind: &str,
+        namespace: &str,
+    ) -> Result<Vec<Value>, ApiError> {
+        self.list_path(&collection_path(group_version, kind, namespace))
+    }
+
+    fn list_path(&self, path: &str) -> Result<Vec<Value>, ApiError> {
+        let list = self.client.get(path)?;
+        Ok(list
+            .and_then(|list| list["items"].as_array().cloned())
+            .unwrap_or_default())
+    }
+
+    /// Deletes the objects in the namespace that cloud conveyor applied and that are not among the applied
+    /// objects, which are given as their api versions, kinds and names.
+    fn prune(&self, namespace: &str, applied: &[(String, String, String)]) -> Result<(), ApiError> {
+        let kept: HashSet<(&str, &str)> = applied
+            .iter()
+            .map(|(_, kind, name)| (kind.as_str(), name.as_str()))
+            .collect();
+        let mut kinds: Vec<(&str, &str)> = PRUNED_KINDS.to_vec();
+        kinds.extend(
+            applied
+                .iter()
+                .map(|(api_version, kind, _)| (api_version.as_str(), kind.as_str())),
+        );
+        kinds.sort_unstable();
+        kinds.dedup();
+
+        for (api_version, kind) in kinds {
+            let path = format!(
+                "{}?labelSelector={}%3D{}",
+                collection_path(api_version, kind, namespace),
+                MANAGED_BY.0,
+                MANAGED_BY.1
+            );
+            for object in self.list_path(&path)? {
+                let name = match object["metadata"]["name"].as_str() {
+                    Some(name) => name,
+                    None => continue,
+                };
+                let managed = object["metadata"]["labels"][MANAGED_BY.0] == MANAGED_BY.1;
+                if !managed || kept.contains(&(kind, name)) {
+                    continue;
+                }
+                info!(
+                    "Pruning {} {:?} that is no longer in the manifests of namespace {:?}",
+                    kind, name, namespace
+                );
+                self.client.delete(&format!(
+                    "{}/{}",
+                    collection_path(api_version, kind, namespace),
+                    name
+                ))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl DeployInfrastructure for KubernetesDeployer {
+    fn start_deployment(
+        &self,
+        deploy: &Deploy,
+        secrets: &Secrets,
+        ctx: &RuntimeContext,
+    ) -> Result<(), DeployPollError> {
+        let app = deploy
+            .application(ctx)
+            .ok_or_else(|| DeployPollError::Other {
+                info: format!("No application is configured for repo {}", deploy.repo),
+            })?;
+        let namespace = namespace_name(app, &deploy.stage);
+        let objects = self.load_manifests(app, deploy, &namespace, ctx)?;
+
+        self.apply_namespace(app, &deploy.stage, &namespace)?;
+        let mut applied = Vec::new();
+        if !secrets.is_empty() {
+            self.apply_secrets(secrets, &namespace)?;
+            applied.push((
+                "v1".to_string(),
+                "Secret".to_string(),
+                SECRETS_NAME.to_string(),
+            ));
+        }
+        for mut object in objects {
+            object["metadata"]["namespace"] = Value::from(namespace.as_str());
+            label_managed(&mut object);
+            let path = object_path(&object, &namespace).map_err(other)?;
+            self.client.apply(&path, &object)?;
+            applied.push((
+                text(&object["apiVersion"]),
+                text(&object["kind"]),
+                text(&object["metadata"]["name"]),
+            ));
+        }
+        self.prune(&namespace, &applied)?;
+        info!(
+            "Applied manifests of git_ref {:?} to namespace {:?}",
+            deploy.git_ref, namespace
+        );
+        Ok(())
+    }
+
+    fn check_deployment(
+        &self,
+        deploy: &Deploy,
+        ctx: &RuntimeContext,
+    ) -> Result<DeployStatus, DeployPollError> {
+        let app = deploy
+            .application(ctx)
+            .ok_or_else(|| DeployPollError::Other {
+                info: format!("No application is configured for repo {}", deploy.repo),
+            })?;
+        let namespace = namespace_name(app, &deploy.stage);
+
+        let deployments = self.list("apps/v1", "Deployment", &namespace)?;
+        let stateful_sets = self.list("apps/v1", "StatefulSet", &namespace)?;
+        let rollouts = deployments
+            .iter()
+            .map(rollout::deployment)
+            .chain(stateful_sets.iter().map(rollout::stateful_set));
+
+        let mut status = DeployStatus::Complete;
+        for rollout in rollouts {
+            match rollout {
+                Rollout::Done => {}
+                Rollout::Progressing => status = DeployStatus::Pending,
+                Rollout::Failed(reason) => {
+                    warn!("Rollout to namespace {:?} failed: {}", namespace, reason);
+                    return Ok(DeployStatus::Failed);
+                }
+            }
+        }
+        if status == DeployStatus::Complete {
+            info!("Rollout to namespace {:?} is complete", namespace);
+        }
+        Ok(status)
+    }
+}
+
+impl TeardownInfrastructure for KubernetesDeployer {
+    fn start_teardown(
+        &self,
+        teardown: &Teardown,
+        ctx: &RuntimeContext,
+    ) -> Result<(), TeardownPollError> {
+        let app = teardown
+            .application(ctx)
+            .ok_or_else(|| TeardownPollError::Other {
+                info: format!("No application is configured for repo {}", teardown.repo),
+            })?;
+        let namespace = namespace_name(app, &teardown.stage);
+        self.client
+            .delete(&format!("/api/v1/namespaces/{}", namespace))?;
+        info!("Deleting namespace {:?}", namespace);
+        Ok(())
+    }
+
+    fn check_teardown(
+        &self,
+        teardown: &Teardown,
+        ctx: &RuntimeContext,
+    ) -> Result<TeardownStatus, TeardownPollError> {
+        let app = teardown
+            .application(ctx)
+            .ok_or_else(|| TeardownPollError::Other {
+                info: format!("No application is configured for repo {}", teardown.repo),
+            })?;
+        let namespace = namespace_name(app, &teardown.stage);
+        match self
+            .client
+            .get(&format!("/api/v1/namespaces/{}", namespace))?
+        {
+            Some(_) => Ok(TeardownStatus::Pending),
+            None => Ok(TeardownStatus::Complete),
+        }
+    }
+}
@@ -0,0 +1,19 @@
+//! This crate defines runtime implementations that deploy applications to a kubernetes cluster by applying
+//! their manifests through the kubernetes api. Every stage of an application gets a namespace of its own so
+//! that stages, such as the stages of pull requests, never interfere with each other and can be torn down by
+//! deleting the namespace.
+//!
+//! This crate also uses the standard logging pattern that the core crate uses.
+#![warn(
+    missing_docs,
+    rust_2018_idioms,
+    missing_debug_implementations,
+    rustdoc::broken_intra_doc_links
+)]
+
+mod client;
+mod deployer;
+mod manifests;
+mod rollout;
+
+pub use deployer::{namespace_name, KubernetesDeployer};
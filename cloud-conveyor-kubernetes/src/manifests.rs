@@ -0,0 +1,89 @@
+use serde::Deserialize;
+use serde_json::Value;
+
+use std::fs;
+use std::path::Path;
+
+/// Replaces the `${NAME}` placeholders in the manifest with their values.
+pub(crate) fn render(manifest: &str, variables: &[(&str, &str)]) -> String {
+    variables
+        .iter()
+        .fold(manifest.to_string(), |manifest, (name, value)| {
+            manifest.replace(&format!("${{{}}}", name), value)
+        })
+}
+
+/// Reads the objects of every yaml and json file in the directory in the order of the file names. Files may
+/// hold more than one object as separate yaml documents. Empty documents and `List` objects are flattened.
+pub(crate) fn load(dir: &Path, variables: &[(&str, &str)]) -> Result<Vec<Value>, String> {
+    let mut files: Vec<_> = fs::read_dir(dir)
+        .map_err(|e| format!("Could not read manifests in {:?}: {}", dir, e))?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            matches!(
+                path.extension().and_then(|e| e.to_str()),
+                Some("yaml") | Some("yml") | Some("json")
+            )
+        })
+        .collect();
+    files.sort();
+
+    let mut objects = Vec::new();
+    for file in files {
+        let manifest = fs::read_to_string(&file).map_err(|e| e.to_string())?;
+        let manifest = render(&manifest, variables);
+        for document in serde_yaml::Deserializer::from_str(&manifest) {
+            let object = Value::deserialize(document)
+                .map_err(|e| format!("Invalid manifest {:?}: {}", file, e))?;
+            match object {
+                Value::Null => {}
+                Value::Object(ref o) if o.get("kind").and_then(Value::as_str) == Some("List") => {
+                    objects.extend(o["items"].as_array().cloned().unwrap_or_default());
+                }
+                object => objects.push(object),
+            }
+        }
+    }
+    Ok(objects)
+}
+
+/// Gets the plural resource name of a kind the way kubernetes derives it.
+fn plural(kind: &str) -> String {
+    let kind = kind.to_ascii_lowercase();
+    if kind.ends_with('s') || kind.ends_with('x') {
+        format!("{}es", kind)
+    } else if kind.ends_with('y') && !kind.ends_with("ey") {
+        format!("{}ies", &kind[..kind.len() - 1])
+    } else {
+        format!("{}s", kind)
+    }
+}
+
+/// Gets the api path of a collection of a kind in the namespace. An empty group is the core group.
+pub(crate) fn collection_path(group_version: &str, kind: &str, namespace: &str) -> String {
+    let base = if group_version.contains('/') {
+        format!("/apis/{}", group_version)
+    } else {
+        format!("/api/{}", group_version)
+    };
+    format!("{}/namespaces/{}/{}", base, namespace, plural(kind))
+}
+
+/// Gets the api path of the object in the namespace. Only namespaced objects are supported.
+pub(crate) fn object_path(object: &Value, namespace: &str) -> Result<String, String> {
+    let api_version = object["apiVersion"].as_str();
+    let kind = object["kind"].as_str();
+    let name = object["metadata"]["name"].as_str();
+    match (api_version, kind, name) {
+        (Some(api_version), Some(kind), Some(name)) => Ok(format!(
+            "{}/{}",
+            collection_path(api_version, kind, namespace),
+            name
+        )),
+        _ => Err(format!(
+            "Manifest object is missing its apiVersion, kind or name: {}",
+            object
+        )),
+    }
+}
@@ -0,0 +1,74 @@
+//! Determines the state of the rollout of workloads the same way `kubectl rollout status` does.
+use serde_json::Value;
+
+/// The state of the rollout of a single workload.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Rollout {
+    Done,
+    Progressing,
+    Failed(String),
+}
+
+fn count(value: &Value) -> i64 {
+    value.as_i64().unwrap_or_default()
+}
+
+fn name(object: &Value) -> &str {
+    object["metadata"]["name"].as_str().unwrap_or_default()
+}
+
+/// Determines if the controller has seen the latest spec of the workload.
+fn observed(object: &Value) -> bool {
+    count(&object["status"]["observedGeneration"]) >= count(&object["metadata"]["generation"])
+}
+
+/// Gets the desired number of replicas of the workload, which defaults to one.
+fn desired(object: &Value) -> i64 {
+    object["spec"]["replicas"].as_i64().unwrap_or(1)
+}
+
+pub(crate) fn deployment(object: &Value) -> Rollout {
+    if !observed(object) {
+        return Rollout::Progressing;
+    }
+    let status = &object["status"];
+    let conditions = status["conditions"].as_array().into_iter().flatten();
+    for condition in conditions {
+        if condition["type"] == "Progressing" && condition["reason"] == "ProgressDeadlineExceeded" {
+            return Rollout::Failed(format!(
+                "Deployment {} exceeded its progress deadline",
+                name(object)
+            ));
+        }
+    }
+
+    let updated = count(&status["updatedReplicas"]);
+    if updated < desired(object)
+        || count(&status["replicas"]) > updated
+        || count(&status["availableReplicas"]) < updated
+    {
+        Rollout::Progressing
+    } else {
+        Rollout::Done
+    }
+}
+
+pub(crate) fn stateful_set(object: &Value) -> Rollout {
+    if !observed(object) {
+        return Rollout::Progressing;
+    }
+    let status = &object["status"];
+    // Stateful sets that are updated with OnDelete only roll out when their pods are deleted.
+    if object["spec"]["updateStrategy"]["type"] == "OnDelete" {
+        return Rollout::Done;
+    }
+    let desired = desired(object);
+    if count(&status["readyReplicas"]) < desired
+        || count(&status["updatedReplicas"]) < desired
+        || status["currentRevision"] != status["updateRevision"]
+    {
+        Rollout::Progressing
+    } else {
+        Rollout::Done
+    }
+}
@@ -0,0 +1,226 @@
+//! Shared helpers for the integration tests of the kubernetes runtime.
+#![allow(dead_code)]
+use cloud_conveyor_core::approval::MemoryApprovals;
+use cloud_conveyor_core::audit::MemoryAuditLog;
+use cloud_conveyor_core::credentials::NoCredentials;
+use cloud_conveyor_core::history::MemoryHistory;
+use cloud_conveyor_core::runtime::RuntimeContext;
+use cloud_conveyor_core::secrets::NoSecrets;
+use cloud_conveyor_core::yaml::ConfigFile;
+use cloud_conveyor_core::{Application, Stage};
+use cloud_conveyor_kubernetes::KubernetesDeployer;
+use cloud_conveyor_local::{FilesystemArtifacts, LocalBuilder};
+use serde_json::Value;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+pub const REPO: &str = "https://github.com/zprobst/cloud-conveyor.git";
+
+const CONFIG: &str = r#"
+org: zprobst
+app: cloud-conveyor
+accounts:
+  - name: default
+    id: 123456789
+    regions:
+      - us-east-1
+approvals: {}
+triggers: []
+stages:
+  - name: dev
+"#;
+
+pub fn application() -> Application {
+    let conf: ConfigFile = serde_yaml::from_str(CONFIG).unwrap();
+    conf.into()
+}
+
+pub fn stage(name: &str) -> Stage {
+    let mut stage = application().stages.remove(0);
+    stage.name = name.to_string();
+    stage
+}
+
+pub fn context(dir: &Path, deployer: impl Fn() -> KubernetesDeployer) -> RuntimeContext {
+    RuntimeContext {
+        artifact_provider: Box::new(FilesystemArtifacts::new(dir.join("artifacts"))),
+        builder: Box::new(LocalBuilder::new(dir.join("builds"), "true".to_string())),
+        infrastructure: Box::new(deployer()),
+        teardown: Box::new(deployer()),
+        secrets: Box::new(NoSecrets),
+        credentials: Box::new(NoCredentials),
+        history: Box::new(MemoryHistory::default()),
+        audit: Box::new(MemoryAuditLog::default()),
+        approvals: Box::new(MemoryApprovals::default()),
+        applications: vec![application()],
+    }
+}
+
+/// Stores a build of the sha whose artifacts hold the manifest files.
+pub fn upload_manifests(ctx: &RuntimeContext, dir: &Path, sha: &str, files: &[(&str, &str)]) {
+    let source = dir.join("source").join(sha);
+    fs::create_dir_all(source.join("kubernetes")).unwrap();
+    for (name, content) in files {
+        fs::write(source.join("kubernetes").join(name), content).unwrap();
+    }
+    ctx.artifact_provider
+        .upload(&application(), sha, &source)
+        .unwrap();
+}
+
+/// A request that was made to the mock api server.
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<String>,
+    pub body: String,
+}
+
+/// Stands in for the kubernetes api server. Objects that are applied are stored by their path and can be
+/// read back individually or as a list of their collection. Deleting an object deletes everything underneath
+/// it, like deleting a namespace does.
+#[derive(Debug)]
+pub struct MockApiServer {
+    pub url: String,
+    pub objects: Arc<Mutex<BTreeMap<String, Value>>>,
+    pub requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl MockApiServer {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let objects = Arc::new(Mutex::new(BTreeMap::new()));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let (stored, recorded) = (objects.clone(), requests.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                let request = read_request(&stream);
+                let (status, body) = handle(&mut stored.lock().unwrap(), &request);
+                recorded.lock().unwrap().push(request);
+                write_response(stream, status, &body);
+            }
+        });
+        Self {
+            url,
+            objects,
+            requests,
+        }
+    }
+
+    pub fn object(&self, path: &str) -> Option<Value> {
+        self.objects.lock().unwrap().get(path).cloned()
+    }
+
+    /// Sets the status of a stored object, as its controller would.
+    pub fn set_status(&self, path: &str, status: Value) {
+        let mut objects = self.objects.lock().unwrap();
+        let object = objects.get_mut(path).unwrap();
+        object["status"] = status;
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn handle(objects: &mut BTreeMap<String, Value>, request: &Request) -> (u16, String) {
+    let path = request.path.split('?').next().unwrap().to_string();
+    if path.starts_with("/forbidden") {
+        return (401, r#"{"message": "Unauthorized"}"#.to_string());
+    }
+    match request.method.as_str() {
+        "PATCH" => {
+            let mut object: Value = serde_json::from_str(&request.body).unwrap();
+            if let Some(existing) = objects.get(&path) {
+                object["status"] = existing["status"].clone();
+            }
+            objects.insert(path, object.clone());
+            (200, object.to_string())
+        }
+        "GET" => match objects.get(&path) {
+            Some(object) => (200, object.to_string()),
+            None => {
+                let prefix = format!("{}/", path);
+                let items: Vec<Value> = objects
+                    .iter()
+                    .filter(|(key, _)| {
+                        key.starts_with(&prefix) && !key[prefix.len()..].contains('/')
+                    })
+                    .map(|(_, object)| object.clone())
+                    .collect();
+                let is_namespace =
+                    path.starts_with("/api/v1/namespaces/") && path.matches('/').count() == 4;
+                if items.is_empty() && is_namespace {
+                    (404, r#"{"message": "not found"}"#.to_string())
+                } else {
+                    (200, serde_json::json!({ "items": items }).to_string())
+                }
+            }
+        },
+        "DELETE" => {
+            let namespace = path.rsplit('/').next().unwrap().to_string();
+            let before = objects.len();
+            objects.retain(|key, _| {
+                key != &path && !key.contains(&format!("/namespaces/{}/", namespace))
+            });
+            if objects.len() == before {
+                (404, r#"{"message": "not found"}"#.to_string())
+            } else {
+                (200, "{}".to_string())
+            }
+        }
+        _ => (405, "{}".to_string()),
+    }
+}
+
+fn read_request(stream: &TcpStream) -> Request {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap().to_string();
+    let path = parts.next().unwrap().to_string();
+
+    let mut headers = Vec::new();
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).unwrap();
+        let header = header.trim().to_string();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.to_ascii_lowercase().strip_prefix("content-length:") {
+            length = value.trim().parse().unwrap();
+        }
+        headers.push(header);
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).unwrap();
+    Request {
+        method,
+        path,
+        headers,
+        body: String::from_utf8(body).unwrap(),
+    }
+}
+
+fn write_response(mut stream: TcpStream, status: u16, body: &str) {
+    write!(
+        stream,
+        "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+    .unwrap();
+}
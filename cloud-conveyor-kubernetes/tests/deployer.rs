@@ -0,0 +1,318 @@
+mod common;
+
+use cloud_conveyor_core::deploy::{DeployPollError, DeployStatus};
+use cloud_conveyor_core::pipelining::{Deploy, Teardown};
+use cloud_conveyor_core::secrets::{SecretError, SecretProvider, Secrets};
+use cloud_conveyor_core::teardown::TeardownStatus;
+use cloud_conveyor_core::Application;
+use cloud_conveyor_kubernetes::{namespace_name, KubernetesDeployer};
+use common::{MockApiServer, REPO};
+use serde_json::json;
+
+const DEPLOYMENT: &str = "/apis/apps/v1/namespaces/cloud-conveyor-pr-42/deployments/web";
+const STATEFUL_SET: &str = "/apis/apps/v1/namespaces/cloud-conveyor-pr-42/statefulsets/db";
+
+const WEB: &str = r#"
+apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: web
+  namespace: ignored
+  generation: 1
+spec:
+  replicas: 2
+  template:
+    spec:
+      containers:
+        - name: web
+          image: registry.example.com/web:${GIT_REF}
+          env:
+            - name: STAGE
+              value: ${STAGE}
+---
+apiVersion: v1
+kind: Service
+metadata:
+  name: web
+spec:
+  ports:
+    - port: 80
+"#;
+
+const DB: &str = r#"
+apiVersion: apps/v1
+kind: StatefulSet
+metadata:
+  name: db
+  generation: 1
+spec:
+  replicas: 1
+"#;
+
+#[derive(Debug)]
+struct OneSecret;
+
+impl SecretProvider for OneSecret {
+    fn get_secret(&self, _app: &Application, _name: &str) -> Result<String, SecretError> {
+        Ok("postgres://db".to_string())
+    }
+}
+
+fn deployer(server: &MockApiServer, dir: &std::path::Path) -> KubernetesDeployer {
+    KubernetesDeployer::new(server.url.clone(), dir.join("workspace"))
+}
+
+#[test]
+fn namespace_is_derived_from_the_stage() {
+    let app = common::application();
+    assert_eq!(
+        namespace_name(&app, &common::stage("pr-42")),
+        "cloud-conveyor-pr-42"
+    );
+    assert_eq!(
+        namespace_name(&app, &common::stage("cloud-conveyor-pr-42")),
+        "cloud-conveyor-pr-42"
+    );
+    assert_eq!(
+        namespace_name(&app, &common::stage("Feature/Login_Page")),
+        "cloud-conveyor-feature-login-page"
+    );
+    let long = namespace_name(&app, &common::stage(&"x".repeat(80)));
+    assert_eq!(long.len(), 63);
+    let longer = namespace_name(&app, &common::stage(&"x".repeat(81)));
+    assert_eq!(longer.len(), 63);
+    assert_ne!(long, longer);
+}
+
+#[test]
+fn namespaces_are_labeled_with_valid_label_values() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = MockApiServer::start();
+    let ctx = common::context(dir.path(), || deployer(&server, dir.path()));
+    common::upload_manifests(&ctx, dir.path(), "abc", &[("web.yaml", WEB)]);
+
+    let label = |name: &str| {
+        let stage = common::stage(name);
+        let deploy = Deploy::new(stage.clone(), REPO.to_string(), "abc".to_string());
+        ctx.infrastructure
+            .start_deployment(&deploy, &Secrets::default(), &ctx)
+            .unwrap();
+        let path = format!(
+            "/api/v1/namespaces/{}",
+            namespace_name(&common::application(), &stage)
+        );
+        let namespace = server.object(&path).unwrap();
+        namespace["metadata"]["labels"]["cloud-conveyor/stage"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!(label("Feature/Login_Page"), "Feature-Login_Page");
+    assert_eq!(label("_pr-42."), "pr-42");
+    let long = label(&format!("feature/{}", "x".repeat(80)));
+    assert_eq!(long.len(), 63);
+    let longer = label(&format!("feature/{}", "x".repeat(81)));
+    assert_eq!(longer.len(), 63);
+    assert_ne!(long, longer);
+}
+
+#[test]
+fn manifests_are_applied_to_the_namespace_of_the_stage() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = MockApiServer::start();
+    let ctx = common::context(dir.path(), || deployer(&server, dir.path()));
+    common::upload_manifests(&ctx, dir.path(), "abc", &[("web.yaml", WEB)]);
+
+    let deploy = Deploy::new(common::stage("pr-42"), REPO.to_string(), "abc".to_string());
+    let secrets = Secrets::resolve(
+        &OneSecret,
+        &common::application(),
+        &["DATABASE_URL".to_string()],
+    )
+    .unwrap();
+    ctx.infrastructure
+        .start_deployment(&deploy, &secrets, &ctx)
+        .unwrap();
+
+    let namespace = server
+        .object("/api/v1/namespaces/cloud-conveyor-pr-42")
+        .unwrap();
+    assert_eq!(
+        namespace["metadata"]["labels"]["cloud-conveyor/stage"],
+        "pr-42"
+    );
+    let secret = server
+        .object("/api/v1/namespaces/cloud-conveyor-pr-42/secrets/cloud-conveyor-secrets")
+        .unwrap();
+    assert_eq!(secret["stringData"]["DATABASE_URL"], "postgres://db");
+    let web = server.object(DEPLOYMENT).unwrap();
+    assert_eq!(web["metadata"]["namespace"], "cloud-conveyor-pr-42");
+    let container = &web["spec"]["template"]["spec"]["containers"][0];
+    assert_eq!(container["image"], "registry.example.com/web:abc");
+    assert_eq!(container["env"][0]["value"], "pr-42");
+    assert!(server
+        .object("/api/v1/namespaces/cloud-conveyor-pr-42/services/web")
+        .is_some());
+    assert!(server
+        .requests()
+        .iter()
+        .all(|r| r.method != "PATCH" || r.path.contains("fieldManager=cloud-conveyor&force=true")));
+}
+
+#[test]
+fn objects_that_are_no_longer_in_the_manifests_are_pruned() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = MockApiServer::start();
+    let ctx = common::context(dir.path(), || deployer(&server, dir.path()));
+    common::upload_manifests(
+        &ctx,
+        dir.path(),
+        "abc",
+        &[("web.yaml", WEB), ("db.yaml", DB)],
+    );
+    common::upload_manifests(&ctx, dir.path(), "def", &[("web.yaml", WEB)]);
+    let unmanaged = "/api/v1/namespaces/cloud-conveyor-pr-42/configmaps/settings";
+    server.objects.lock().unwrap().insert(
+        unmanaged.to_string(),
+        json!({"apiVersion": "v1", "kind": "ConfigMap", "metadata": {"name": "settings"}}),
+    );
+
+    let first = Deploy::new(common::stage("pr-42"), REPO.to_string(), "abc".to_string());
+    ctx.infrastructure
+        .start_deployment(&first, &Secrets::default(), &ctx)
+        .unwrap();
+    let web = server.object(DEPLOYMENT).unwrap();
+    assert_eq!(
+        web["metadata"]["labels"]["app.kubernetes.io/managed-by"],
+        "cloud-conveyor"
+    );
+    assert!(server.object(STATEFUL_SET).is_some());
+
+    let second = Deploy::new(common::stage("pr-42"), REPO.to_string(), "def".to_string());
+    ctx.infrastructure
+        .start_deployment(&second, &Secrets::default(), &ctx)
+        .unwrap();
+    assert!(server.object(STATEFUL_SET).is_none());
+    assert!(server.object(DEPLOYMENT).is_some());
+    assert!(server
+        .object("/api/v1/namespaces/cloud-conveyor-pr-42/services/web")
+        .is_some());
+    assert!(server.object(unmanaged).is_some());
+}
+
+#[test]
+fn deployment_is_complete_once_rollouts_are() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = MockApiServer::start();
+    let ctx = common::context(dir.path(), || deployer(&server, dir.path()));
+    common::upload_manifests(
+        &ctx,
+        dir.path(),
+        "abc",
+        &[("web.yaml", WEB), ("db.yaml", DB)],
+    );
+
+    let deploy = Deploy::new(common::stage("pr-42"), REPO.to_string(), "abc".to_string());
+    ctx.infrastructure
+        .start_deployment(&deploy, &Secrets::default(), &ctx)
+        .unwrap();
+    let check = || ctx.infrastructure.check_deployment(&deploy, &ctx).unwrap();
+    assert_eq!(check(), DeployStatus::Pending);
+
+    server.set_status(
+        DEPLOYMENT,
+        json!({"observedGeneration": 1, "replicas": 3, "updatedReplicas": 2, "availableReplicas": 2}),
+    );
+    server.set_status(
+        STATEFUL_SET,
+        json!({"observedGeneration": 1, "readyReplicas": 1, "updatedReplicas": 1,
+               "currentRevision": "db-1", "updateRevision": "db-1"}),
+    );
+    assert_eq!(check(), DeployStatus::Pending);
+
+    server.set_status(
+        DEPLOYMENT,
+        json!({"observedGeneration": 1, "replicas": 2, "updatedReplicas": 2, "availableReplicas": 2}),
+    );
+    assert_eq!(check(), DeployStatus::Complete);
+}
+
+#[test]
+fn deployment_fails_when_progress_deadline_is_exceeded() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = MockApiServer::start();
+    let ctx = common::context(dir.path(), || deployer(&server, dir.path()));
+    common::upload_manifests(&ctx, dir.path(), "abc", &[("web.yaml", WEB)]);
+
+    let deploy = Deploy::new(common::stage("pr-42"), REPO.to_string(), "abc".to_string());
+    ctx.infrastructure
+        .start_deployment(&deploy, &Secrets::default(), &ctx)
+        .unwrap();
+    server.set_status(
+        DEPLOYMENT,
+        json!({
+            "observedGeneration": 1,
+            "replicas": 2,
+            "updatedReplicas": 1,
+            "conditions": [{"type": "Progressing", "status": "False", "reason": "ProgressDeadlineExceeded"}]
+        }),
+    );
+    assert_eq!(
+        ctx.infrastructure.check_deployment(&deploy, &ctx).unwrap(),
+        DeployStatus::Failed
+    );
+}
+
+#[test]
+fn teardown_deletes_the_namespace() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = MockApiServer::start();
+    let ctx = common::context(dir.path(), || deployer(&server, dir.path()));
+    common::upload_manifests(&ctx, dir.path(), "abc", &[("web.yaml", WEB)]);
+    let deploy = Deploy::new(common::stage("pr-42"), REPO.to_string(), "abc".to_string());
+    ctx.infrastructure
+        .start_deployment(&deploy, &Secrets::default(), &ctx)
+        .unwrap();
+
+    let teardown = Teardown::new(common::stage("pr-42"), REPO.to_string());
+    assert_eq!(
+        ctx.teardown.check_teardown(&teardown, &ctx).unwrap(),
+        TeardownStatus::Pending
+    );
+    ctx.teardown.start_teardown(&teardown, &ctx).unwrap();
+    assert_eq!(
+        ctx.teardown.check_teardown(&teardown, &ctx).unwrap(),
+        TeardownStatus::Complete
+    );
+    assert!(server.object(DEPLOYMENT).is_none());
+    assert!(server
+        .requests()
+        .iter()
+        .any(|r| r.method == "DELETE" && r.path == "/api/v1/namespaces/cloud-conveyor-pr-42"));
+}
+
+#[test]
+fn token_is_sent_and_rejections_are_credential_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = MockApiServer::start();
+    let ctx = common::context(dir.path(), || {
+        deployer(&server, dir.path()).with_token("t0ken".to_string())
+    });
+    common::upload_manifests(&ctx, dir.path(), "abc", &[("web.yaml", WEB)]);
+    let deploy = Deploy::new(common::stage("pr-42"), REPO.to_string(), "abc".to_string());
+    ctx.infrastructure
+        .start_deployment(&deploy, &Secrets::default(), &ctx)
+        .unwrap();
+    assert!(server
+        .requests()
+        .iter()
+        .all(|r| r.headers.iter().any(|h| h == "Authorization: Bearer t0ken")));
+
+    let ctx = common::context(dir.path(), || {
+        KubernetesDeployer::new(format!("{}/forbidden", server.url), dir.path())
+    });
+    match ctx.infrastructure.check_deployment(&deploy, &ctx) {
+        Err(DeployPollError::Credentials) => {}
+        result => panic!("unexpected result {:?}", result),
+    }
+}
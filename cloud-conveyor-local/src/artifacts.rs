@@ -0,0 +1,133 @@
+use cloud_conveyor_core::build::{ArtifactManifest, ProvideArtifactLocation};
+use cloud_conveyor_core::Application;
+use failure::Error;
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Copies the directory and everything underneath it to the destination, creating the destination if it
+/// does not exist yet. Files that exist in both are overwritten.
+pub fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Stores artifacts in a directory on the local file system. The bucket of an application is the directory
+/// `<org>/<app>` underneath the root directory and the folder of a build is its sha, relative to the bucket.
+///
+/// The manifest of each recorded build is stored next to its folder as `<sha>.yaml`.
+/// Only recorded builds are returned by [list](../cloud_conveyor_core/build/trait.ProvideArtifactLocation.html#tymethod.list)
+/// and thus considered when [cleaning up](../cloud_conveyor_core/build/trait.ProvideArtifactLocation.html#method.cleanup).
+#[derive(Debug)]
+pub struct FilesystemArtifacts {
+    root: PathBuf,
+}
+
+impl FilesystemArtifacts {
+    /// Creates a provider that stores artifacts underneath the root directory.
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// The root directory the artifacts are stored in.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn app_dir(&self, app: &Application) -> PathBuf {
+        self.root.join(&app.org).join(&app.app)
+    }
+
+    fn build_dir(&self, app: &Application, git_sha: &str) -> PathBuf {
+        self.app_dir(app).join(git_sha)
+    }
+
+    fn manifest_file(&self, app: &Application, git_sha: &str) -> PathBuf {
+        self.app_dir(app).join(format!("{}.yaml", git_sha))
+    }
+}
+
+impl ProvideArtifactLocation for FilesystemArtifacts {
+    fn get_bucket(&self, app: &Application) -> Result<String, Error> {
+        Ok(self.app_dir(app).to_string_lossy().into_owned())
+    }
+
+    fn get_folder(&self, _app: &Application, git_sha: &str) -> Result<String, Error> {
+        Ok(git_sha.to_string())
+    }
+
+    fn find_build(
+        &self,
+        app: &Application,
+        git_sha: &str,
+    ) -> Result<Option<ArtifactManifest>, Error> {
+        match File::open(self.manifest_file(app, git_sha)) {
+            Ok(file) => Ok(Some(serde_yaml::from_reader(file)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn record_build(&self, app: &Application, manifest: &ArtifactManifest) -> Result<(), Error> {
+        fs::create_dir_all(self.app_dir(app))?;
+        let file = File::create(self.manifest_file(app, &manifest.git_sha))?;
+        serde_yaml::to_writer(file, manifest)?;
+        Ok(())
+    }
+
+    fn upload(&self, app: &Application, git_sha: &str, source: &Path) -> Result<(), Error> {
+        // Files of an earlier upload of the sha must not end up among the artifacts of this one.
+        let dir = self.build_dir(app, git_sha);
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        copy_dir(source, &dir)?;
+        Ok(())
+    }
+
+    fn download(&self, app: &Application, git_sha: &str, destination: &Path) -> Result<(), Error> {
+        copy_dir(&self.build_dir(app, git_sha), destination)?;
+        Ok(())
+    }
+
+    fn list(&self, app: &Application) -> Result<Vec<ArtifactManifest>, Error> {
+        let entries = match fs::read_dir(self.app_dir(app)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut builds = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "yaml") {
+                let manifest: ArtifactManifest = serde_yaml::from_reader(File::open(path)?)?;
+                builds.push(manifest);
+            }
+        }
+        builds.sort_by_key(|build| build.built_at);
+        Ok(builds)
+    }
+
+    fn delete(&self, app: &Application, git_sha: &str) -> Result<(), Error> {
+        let dir = self.build_dir(app, git_sha);
+        if dir.exists() {
+            fs::remove_dir_all(dir)?;
+        }
+        let manifest = self.manifest_file(app, git_sha);
+        if manifest.exists() {
+            fs::remove_file(manifest)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+use cloud_conveyor_core::audit::{AuditEntry, AuditLog};
+use cloud_conveyor_core::Application;
+use failure::Error;
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// Stores the audit log in a directory on the local file system. The entries of an application are
+/// appended as lines of json to `<org>/<app>.jsonl` relative to the root directory, so the files are
+/// never rewritten.
+#[derive(Debug)]
+pub struct FilesystemAuditLog {
+    root: PathBuf,
+}
+
+impl FilesystemAuditLog {
+    /// Creates an audit log that is stored underneath the root directory.
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// The root directory the audit log is stored in.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn log_file(&self, app_full_name: &str) -> PathBuf {
+        self.root.join(format!("{}.jsonl", app_full_name))
+    }
+}
+
+impl AuditLog for FilesystemAuditLog {
+    fn append(&self, entry: &AuditEntry) -> Result<(), Error> {
+        let path = self.log_file(&entry.app);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        file.write_all(&line)?;
+        Ok(())
+    }
+
+    fn entries(&self, app: &Application) -> Result<Vec<AuditEntry>, Error> {
+        let file = match File::open(self.log_file(&app.full_name())) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut entries = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                entries.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(entries)
+    }
+}
@@ -0,0 +1,28 @@
+use cloud_conveyor_core::schedule::ResolveBranches;
+use failure::Error;
+
+use std::process::Command;
+
+/// Finds the heads of branches by asking the remote repository with `git ls-remote`, so it works for
+/// any git url that the git installation on the machine can reach.
+#[derive(Debug, Default)]
+pub struct GitBranches;
+
+impl ResolveBranches for GitBranches {
+    fn head(&self, repo: &str, branch: &str) -> Result<String, Error> {
+        let output = Command::new("git")
+            .arg("ls-remote")
+            .arg("--heads")
+            .arg(repo)
+            .arg(format!("refs/heads/{}", branch))
+            .output()?;
+        if !output.status.success() {
+            bail!("{}", String::from_utf8_lossy(&output.stderr));
+        }
+        String::from_utf8_lossy(&output.stdout)
+            .split_whitespace()
+            .next()
+            .map(String::from)
+            .ok_or_else(|| format_err!("The repository {} has no branch {}", repo, branch))
+    }
+}
@@ -0,0 +1,351 @@
+use cloud_conveyor_core::build::{BuildPollError, BuildSource, BuildStatus};
+use cloud_conveyor_core::pipelining::Build;
+use cloud_conveyor_core::runtime::RuntimeContext;
+use cloud_conveyor_core::secrets::Secrets;
+use cloud_conveyor_core::short_hash;
+use cloud_conveyor_core::yaml::{load_build_spec_from_dir, BuildSpec};
+use log::{info, warn};
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::Mutex;
+
+/// Clones the repository into the directory and checks out the git ref.
+pub fn checkout(repo: &str, git_ref: &str, dir: &Path) -> Result<(), BuildPollError> {
+    run_git(
+        Command::new("git")
+            .arg("clone")
+            .arg("--quiet")
+            .arg(repo)
+            .arg(dir),
+    )?;
+    run_git(
+        Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .arg("checkout")
+            .arg("--quiet")
+            .arg(git_ref),
+    )
+}
+
+fn run_git(command: &mut Command) -> Result<(), BuildPollError> {
+    let output = command.output().map_err(other)?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(BuildPollError::Other {
+            info: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    }
+}
+
+fn other(e: io::Error) -> BuildPollError {
+    BuildPollError::Other {
+        info: e.to_string(),
+    }
+}
+
+/// The number of lines at the end of the log of a failed build that are reported with the failure.
+const LOG_EXCERPT_LINES: usize = 20;
+
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+/// Gets a name for the build that is unique to its repository, application and git ref and safe to use
+/// as a file name. Builders use it to name the checkouts and logs of builds. The name ends with a hash of
+/// all three, since different ones can read the same once they are made safe, e.g. `feature/x` and `feature-x`.
+pub fn build_key(build: &Build) -> String {
+    let repo_name = build
+        .repo
+        .trim_end_matches('/')
+        .trim_end_matches(".git")
+        .rsplit(['/', ':'])
+        .next()
+        .unwrap_or_default();
+    // The applications of a monorepo are built from the same git ref, so they need a key each.
+    let app = match (&build.app, &build.path) {
+        (Some(app), Some(_)) => Some(app.as_str()),
+        _ => None,
+    };
+    let hash = short_hash(&format!(
+        "{}\n{}\n{}",
+        build.repo,
+        app.unwrap_or_default(),
+        build.git_ref
+    ));
+    match app {
+        Some(app) => format!(
+            "{}-{}-{}-{}",
+            sanitize(repo_name),
+            sanitize(app),
+            sanitize(&build.git_ref),
+            hash
+        ),
+        None => format!(
+            "{}-{}-{}",
+            sanitize(repo_name),
+            sanitize(&build.git_ref),
+            hash
+        ),
+    }
+}
+
+/// Gets the directory of the application of the build in the checkout, which is the root of the checkout
+/// unless the application lives in a subdirectory of a monorepo.
+pub fn app_dir(build: &Build, checkout: &Path) -> PathBuf {
+    match &build.path {
+        Some(path) => checkout.join(path),
+        None => checkout.to_path_buf(),
+    }
+}
+
+/// Gets the build spec of the build, which is the spec that is set on the build if there is one and the
+/// spec in the directory of the application in the checkout otherwise.
+pub fn build_spec(build: &Build, app_dir: &Path) -> Result<Option<BuildSpec>, BuildPollError> {
+    match &build.spec {
+        Some(spec) => Ok(Some(spec.clone())),
+        None => load_build_spec_from_dir(app_dir).map_err(|e| BuildPollError::Other {
+            info: format!("Invalid build spec in {}: {}", app_dir.display(), e),
+        }),
+    }
+}
+
+/// Gets the shell command that runs the build. This is the commands of the phases of the build spec
+/// if there is one and the given command otherwise.
+pub fn build_command(spec: Option<&BuildSpec>, command: &str) -> String {
+    match spec {
+        Some(spec) => {
+            let commands: Vec<&str> = spec.phases.commands().map(String::as_str).collect();
+            if commands.is_empty() {
+                "true".to_string()
+            } else {
+                commands.join(" && ")
+            }
+        }
+        None => command.to_string(),
+    }
+}
+
+/// Gets the last lines of a build log so that they can be reported with a failed build. Secrets
+/// that the build echoed are scrubbed from this by [Build](../cloud_conveyor_core/pipelining/struct.Build.html)
+/// before it is stored.
+pub fn log_excerpt(log: &Path) -> String {
+    let log = fs::read_to_string(log).unwrap_or_default();
+    let lines: Vec<&str> = log.lines().collect();
+    lines[lines.len().saturating_sub(LOG_EXCERPT_LINES)..].join("\n")
+}
+
+/// Copies the files matching the artifact patterns of the build spec into a directory next to
+/// the checkout. Returns None when there are no patterns.
+fn collect_artifacts(
+    spec: Option<&BuildSpec>,
+    checkout: &Path,
+) -> Result<Option<PathBuf>, BuildPollError> {
+    let patterns = match spec {
+        Some(spec) if !spec.artifacts.is_empty() => &spec.artifacts,
+        _ => return Ok(None),
+    };
+
+    let staging = PathBuf::from(format!("{}.artifacts", checkout.display()));
+    if staging.exists() {
+        fs::remove_dir_all(&staging).map_err(other)?;
+    }
+    fs::create_dir_all(&staging).map_err(other)?;
+
+    for pattern in patterns {
+        let full_pattern = checkout.join(pattern).to_string_lossy().into_owned();
+        let paths = glob::glob(&full_pattern).map_err(|e| BuildPollError::Other {
+            info: format!("Invalid artifact pattern {}: {}", pattern, e),
+        })?;
+        for path in paths.filter_map(Result::ok).filter(|p| p.is_file()) {
+            let relative = path.strip_prefix(checkout).unwrap_or(&path);
+            let target = staging.join(relative);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent).map_err(other)?;
+            }
+            fs::copy(&path, target).map_err(other)?;
+        }
+    }
+    Ok(Some(staging))
+}
+
+/// Uploads the outputs of a successful build in the checkout with the [ProvideArtifactLocation](../cloud_conveyor_core/build/trait.ProvideArtifactLocation.html)
+/// of the runtime context. These are the files matching the artifact patterns of the build spec if it has any and the
+/// contents of the output directory, relative to the checkout, otherwise.
+pub fn upload_outputs(
+    build: &Build,
+    spec: Option<&BuildSpec>,
+    checkout: &Path,
+    output_dir: &Path,
+    ctx: &RuntimeContext,
+) -> Result<(), BuildPollError> {
+    let outputs = match collect_artifacts(spec, checkout)? {
+        Some(staging) => staging,
+        None => checkout.join(output_dir),
+    };
+    if !outputs.is_dir() {
+        info!(
+            "Build of git_ref {:?} produced no outputs in {:?}",
+            build.git_ref, output_dir
+        );
+        return Ok(());
+    }
+
+    let app = build
+        .application(ctx)
+        .ok_or_else(|| BuildPollError::Other {
+            info: format!("No application is configured for repo {}", build.repo),
+        })?;
+    ctx.artifact_provider
+        .upload(app, &build.git_ref, &outputs)
+        .map_err(|e| BuildPollError::Other {
+            info: e.to_string(),
+        })
+}
+
+/// Builds code by checking it out into a scratch directory and running a build command in it as
+/// a child process of cloud conveyor.
+///
+/// The command is run with `sh -c` in the directory of the application in the checkout, which is the root
+/// of the checkout unless the application lives in a subdirectory of a monorepo, with `CONVEYOR_REPO` and `CONVEYOR_GIT_REF`
+/// set. Everything it writes to stdout and stderr ends up in a log file next to the checkout whose path is
+/// reported as the logs of the build. When the command fails, the last lines of the log are reported with the
+/// failure. When the command succeeds, the contents of the output directory of
+/// the checkout are uploaded with the [ProvideArtifactLocation](../cloud_conveyor_core/build/trait.ProvideArtifactLocation.html)
+/// of the runtime context. The secrets of the build are set as environment variables of the command.
+///
+/// When the build has a [BuildSpec](../cloud_conveyor_core/yaml/struct.BuildSpec.html), either set on it or in the
+/// directory of the application in the checkout, the commands of its phases are run in place of the command with the environment variables of the spec set. If the spec names artifacts,
+/// the files matching them are uploaded in place of the output directory. The image and cache paths of the
+/// spec are ignored.
+#[derive(Debug)]
+pub struct LocalBuilder {
+    workspace: PathBuf,
+    command: String,
+    output_dir: PathBuf,
+    processes: Mutex<HashMap<String, Child>>,
+}
+
+impl LocalBuilder {
+    /// Creates a builder that checks out code underneath the workspace directory and
+    /// builds it by running the command.
+    pub fn new<P: Into<PathBuf>>(workspace: P, command: String) -> Self {
+        Self {
+            workspace: workspace.into(),
+            command,
+            output_dir: PathBuf::from("out"),
+            processes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets the directory, relative to the root of the checkout, that the build command writes
+    /// its artifacts to. Defaults to `out`.
+    pub fn with_output_dir<P: Into<PathBuf>>(mut self, output_dir: P) -> Self {
+        self.output_dir = output_dir.into();
+        self
+    }
+
+    fn checkout_dir(&self, build: &Build) -> PathBuf {
+        self.workspace.join(build_key(build))
+    }
+
+    fn log_file(&self, build: &Build) -> PathBuf {
+        self.workspace.join(format!("{}.log", build_key(build)))
+    }
+}
+
+impl BuildSource for LocalBuilder {
+    fn start_build(
+        &self,
+        build: &Build,
+        secrets: &Secrets,
+        _ctx: &RuntimeContext,
+    ) -> Result<(), BuildPollError> {
+        let dir = self.checkout_dir(build);
+        if dir.exists() {
+            fs::remove_dir_all(&dir).map_err(other)?;
+        }
+        fs::create_dir_all(&self.workspace).map_err(other)?;
+        checkout(&build.repo, &build.git_ref, &dir)?;
+
+        let app_dir = app_dir(build, &dir);
+        let spec = build_spec(build, &app_dir)?;
+
+        let log = File::create(self.log_file(build)).map_err(other)?;
+        let mut command = Command::new("sh");
+        if let Some(spec) = &spec {
+            command.envs(&spec.env);
+        }
+        command.envs(secrets.iter());
+        let child = command
+            .arg("-c")
+            .arg(build_command(spec.as_ref(), &self.command))
+            .current_dir(app_dir)
+            .env("CONVEYOR_REPO", &build.repo)
+            .env("CONVEYOR_GIT_REF", &build.git_ref)
+            .stdin(Stdio::null())
+            .stdout(log.try_clone().map_err(other)?)
+            .stderr(log)
+            .spawn()
+            .map_err(other)?;
+
+        info!(
+            "Started local build of git_ref {:?} in {:?}",
+            build.git_ref, dir
+        );
+        self.processes
+            .lock()
+            .unwrap()
+            .insert(build_key(build), child);
+        Ok(())
+    }
+
+    fn check_build(
+        &self,
+        build: &Build,
+        ctx: &RuntimeContext,
+    ) -> Result<BuildStatus, BuildPollError> {
+        let key = build_key(build);
+        let mut processes = self.processes.lock().unwrap();
+        let child = processes
+            .get_mut(&key)
+            .ok_or_else(|| BuildPollError::Other {
+                info: format!("No local build was started for git_ref {}", build.git_ref),
+            })?;
+
+        let status = match child.try_wait().map_err(other)? {
+            Some(status) => status,
+            None => return Ok(BuildStatus::Pending),
+        };
+        processes.remove(&key);
+
+        let logs = self.log_file(build).to_string_lossy().into_owned();
+        if status.success() {
+            let dir = app_dir(build, &self.checkout_dir(build));
+            let spec = build_spec(build, &dir)?;
+            upload_outputs(build, spec.as_ref(), &dir, &self.output_dir, ctx)?;
+            Ok(BuildStatus::Succeeded { logs })
+        } else {
+            warn!(
+                "Local build of git_ref {:?} failed with {}",
+                build.git_ref, status
+            );
+            Ok(BuildStatus::Failed {
+                logs,
+                error: Some(format!(
+                    "Build command exited with {}\n{}",
+                    status,
+                    log_excerpt(&self.log_file(build))
+                )),
+            })
+        }
+    }
+}
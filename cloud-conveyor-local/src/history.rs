@@ -0,0 +1,77 @@
+use cloud_conveyor_core::history::{DeploymentHistory, DeploymentRecord};
+use cloud_conveyor_core::Application;
+use failure::Error;
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Stores the history of the deployments to every stage in a directory on the local file system.
+/// The deployments to a stage are stored as a list in `<app>/<stage>.yaml` relative to the root directory.
+#[derive(Debug)]
+pub struct FilesystemHistory {
+    root: PathBuf,
+}
+
+impl FilesystemHistory {
+    /// Creates a history that is stored underneath the root directory.
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// The root directory the history is stored in.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn app_dir(&self, app: &Application) -> PathBuf {
+        self.root.join(&app.app)
+    }
+
+    fn stage_file(&self, app: &Application, stage: &str) -> PathBuf {
+        self.app_dir(app).join(format!("{}.yaml", stage))
+    }
+}
+
+impl DeploymentHistory for FilesystemHistory {
+    fn record(
+        &self,
+        app: &Application,
+        stage: &str,
+        record: &DeploymentRecord,
+    ) -> Result<(), Error> {
+        let mut records = self.list(app, stage)?;
+        records.push(record.clone());
+        fs::create_dir_all(self.app_dir(app))?;
+        let file = File::create(self.stage_file(app, stage))?;
+        serde_yaml::to_writer(file, &records)?;
+        Ok(())
+    }
+
+    fn list(&self, app: &Application, stage: &str) -> Result<Vec<DeploymentRecord>, Error> {
+        match File::open(self.stage_file(app, stage)) {
+            Ok(file) => Ok(serde_yaml::from_reader(file)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn stages(&self, app: &Application) -> Result<Vec<String>, Error> {
+        let entries = match fs::read_dir(self.app_dir(app)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut stages = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "yaml") {
+                if let Some(stage) = path.file_stem() {
+                    stages.push(stage.to_string_lossy().into_owned());
+                }
+            }
+        }
+        stages.sort();
+        Ok(stages)
+    }
+}
@@ -0,0 +1,31 @@
+//! This crate defines runtime implementations that do all of their work on the machine that cloud
+//! conveyor is running on. They are meant for running the conveyor on a laptop and in tests rather
+//! than for isolated, production grade builds and deployments.
+//!
+//! This crate also uses the standard logging pattern that the core crate uses.
+#![warn(
+    missing_docs,
+    rust_2018_idioms,
+    missing_debug_implementations,
+    rustdoc::broken_intra_doc_links
+)]
+
+#[macro_use]
+extern crate failure;
+
+mod artifacts;
+mod audit;
+mod branches;
+mod builder;
+mod history;
+mod secrets;
+
+pub use artifacts::{copy_dir, FilesystemArtifacts};
+pub use audit::FilesystemAuditLog;
+pub use branches::GitBranches;
+pub use builder::{
+    app_dir, build_command, build_key, build_spec, checkout, log_excerpt, upload_outputs,
+    LocalBuilder,
+};
+pub use history::FilesystemHistory;
+pub use secrets::EncryptedFileSecrets;
@@ -0,0 +1,141 @@
+use aes_gcm::aead::{AeadInPlace, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce, Tag};
+use cloud_conveyor_core::secrets::{SecretError, SecretProvider};
+use cloud_conveyor_core::Application;
+use failure::Error;
+use hex::{FromHex, ToHex};
+use pbkdf2::pbkdf2_hmac;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::fs::File;
+use std::path::Path;
+
+const KEY_ITERATIONS: u32 = 100_000;
+const KEY_LENGTH: usize = 32;
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+const TAG_LENGTH: usize = 16;
+
+/// The on disk format of the file. Every field is hex encoded.
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptedFile {
+    salt: String,
+    nonce: String,
+    tag: String,
+    data: String,
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> Aes256Gcm {
+    let mut key = [0; KEY_LENGTH];
+    pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, KEY_ITERATIONS, &mut key);
+    Aes256Gcm::new(&key.into())
+}
+
+fn from_hex(value: &str) -> Result<Vec<u8>, Error> {
+    Vec::from_hex(value).map_err(|e| format_err!("Secrets file is not valid hex: {:?}", e))
+}
+
+/// Stores secrets in a file that is encrypted with a passphrase. This is meant for running
+/// cloud conveyor locally where there is no secret store of a cloud provider to use.
+///
+/// The file holds the names and values of the secrets as yaml that is encrypted with AES-256-GCM
+/// using a key derived from the passphrase with PBKDF2. The whole file is decrypted when it is opened.
+/// Secrets are looked up as `<org>/<app>/<name>` first so that one file can hold different values for
+/// different applications, and then by their name alone.
+pub struct EncryptedFileSecrets {
+    values: HashMap<String, String>,
+}
+
+impl EncryptedFileSecrets {
+    /// Opens and decrypts the secrets file.
+    pub fn open<P: AsRef<Path>>(path: P, passphrase: &str) -> Result<Self, Error> {
+        let file: EncryptedFile = serde_yaml::from_reader(File::open(path)?)?;
+        let salt = from_hex(&file.salt)?;
+        let nonce = from_hex(&file.nonce)?;
+        let tag = from_hex(&file.tag)?;
+        let mut data = from_hex(&file.data)?;
+        if nonce.len() != NONCE_LENGTH || tag.len() != TAG_LENGTH {
+            bail!("The nonce or tag of the secrets file has the wrong length.");
+        }
+
+        cipher(passphrase, &salt)
+            .decrypt_in_place_detached(
+                Nonce::from_slice(&nonce),
+                &[],
+                &mut data,
+                Tag::from_slice(&tag),
+            )
+            .map_err(|_| {
+                format_err!("The secrets file could not be decrypted. Is the passphrase correct?")
+            })?;
+        let values = serde_yaml::from_slice(&data)?;
+        Ok(Self { values })
+    }
+
+    /// Encrypts the secrets and writes them to the file, replacing what was there.
+    pub fn write<P: AsRef<Path>>(
+        path: P,
+        passphrase: &str,
+        values: &HashMap<String, String>,
+    ) -> Result<(), Error> {
+        let mut salt = [0; SALT_LENGTH];
+        let mut nonce = [0; NONCE_LENGTH];
+        rand::thread_rng().fill_bytes(&mut salt);
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let mut data = serde_yaml::to_vec(values)?;
+        let tag = cipher(passphrase, &salt)
+            .encrypt_in_place_detached(Nonce::from_slice(&nonce), &[], &mut data)
+            .map_err(|_| format_err!("The secrets could not be encrypted."))?;
+
+        let file = EncryptedFile {
+            salt: salt.to_hex(),
+            nonce: nonce.to_hex(),
+            tag: tag.as_slice().to_hex(),
+            data: data.to_hex(),
+        };
+        serde_yaml::to_writer(File::create(path)?, &file)?;
+        Ok(())
+    }
+
+    /// Gets the name that the secret of the application is stored under, which takes precedence over
+    /// a secret of the same name that is shared by every application.
+    pub fn app_key(app: &Application, name: &str) -> String {
+        format!("{}/{}", app.full_name(), name)
+    }
+
+    /// Gets the names of the secrets in the file.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.values.keys().map(String::as_str)
+    }
+
+    /// Gets the names and values of the secrets in the file so that they can be changed and
+    /// written back with [write](#method.write).
+    pub fn into_values(self) -> HashMap<String, String> {
+        self.values
+    }
+}
+
+impl Debug for EncryptedFileSecrets {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptedFileSecrets")
+            .field("names", &self.values.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl SecretProvider for EncryptedFileSecrets {
+    fn get_secret(&self, app: &Application, name: &str) -> Result<String, SecretError> {
+        self.values
+            .get(&Self::app_key(app, name))
+            .or_else(|| self.values.get(name))
+            .cloned()
+            .ok_or_else(|| SecretError::Missing {
+                name: name.to_string(),
+            })
+    }
+}
@@ -0,0 +1,131 @@
+mod common;
+
+use chrono::{TimeZone, Utc};
+use cloud_conveyor_core::build::{ArtifactManifest, ProvideArtifactLocation, RetentionPolicy};
+use cloud_conveyor_local::FilesystemArtifacts;
+
+use std::fs;
+
+fn manifest(git_sha: &str, day: u32) -> ArtifactManifest {
+    ArtifactManifest {
+        git_sha: git_sha.to_string(),
+        bucket: String::new(),
+        folder: git_sha.to_string(),
+        logs: String::new(),
+        branch: Some("master".to_string()),
+        built_at: Utc.with_ymd_and_hms(2020, 6, day, 12, 0, 0).unwrap(),
+    }
+}
+
+#[test]
+fn buckets_are_per_org_and_app_and_folders_per_sha() {
+    let dir = tempfile::tempdir().unwrap();
+    let artifacts = FilesystemArtifacts::new(dir.path());
+    let app = common::application();
+    assert_eq!(
+        artifacts.get_bucket(&app).unwrap(),
+        dir.path().join("zprobst/cloud-conveyor").to_string_lossy()
+    );
+    assert_eq!(artifacts.get_folder(&app, "abc123").unwrap(), "abc123");
+
+    // Apps of the same name in other orgs do not share the builds.
+    let mut other = common::application();
+    other.org = "other".to_string();
+    artifacts
+        .record_build(&app, &manifest("abc123", 1))
+        .unwrap();
+    assert_eq!(artifacts.find_build(&other, "abc123").unwrap(), None);
+}
+
+#[test]
+fn uploaded_artifacts_can_be_downloaded() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    fs::create_dir_all(source.join("nested")).unwrap();
+    fs::write(source.join("nested/app.zip"), "zip").unwrap();
+    let artifacts = FilesystemArtifacts::new(dir.path().join("artifacts"));
+    let app = common::application();
+
+    artifacts.upload(&app, "abc123", &source).unwrap();
+    let destination = dir.path().join("destination");
+    artifacts.download(&app, "abc123", &destination).unwrap();
+    assert_eq!(
+        fs::read_to_string(destination.join("nested/app.zip")).unwrap(),
+        "zip"
+    );
+}
+
+#[test]
+fn uploads_replace_the_artifacts_of_earlier_uploads() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    fs::create_dir_all(&source).unwrap();
+    fs::write(source.join("old.zip"), "old").unwrap();
+    let artifacts = FilesystemArtifacts::new(dir.path().join("artifacts"));
+    let app = common::application();
+    artifacts.upload(&app, "abc123", &source).unwrap();
+
+    fs::remove_file(source.join("old.zip")).unwrap();
+    fs::write(source.join("new.zip"), "new").unwrap();
+    artifacts.upload(&app, "abc123", &source).unwrap();
+
+    let destination = dir.path().join("destination");
+    artifacts.download(&app, "abc123", &destination).unwrap();
+    assert!(destination.join("new.zip").exists());
+    assert!(!destination.join("old.zip").exists());
+}
+
+#[test]
+fn recorded_builds_are_listed_and_deleted() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    fs::create_dir_all(&source).unwrap();
+    fs::write(source.join("app.zip"), "zip").unwrap();
+    let artifacts = FilesystemArtifacts::new(dir.path().join("artifacts"));
+    let app = common::application();
+    assert!(artifacts.list(&app).unwrap().is_empty());
+
+    for (sha, day) in &[("b", 2), ("a", 1)] {
+        artifacts.upload(&app, sha, &source).unwrap();
+        artifacts.record_build(&app, &manifest(sha, *day)).unwrap();
+    }
+    assert_eq!(
+        artifacts.list(&app).unwrap(),
+        vec![manifest("a", 1), manifest("b", 2)]
+    );
+    assert_eq!(
+        artifacts.find_build(&app, "a").unwrap(),
+        Some(manifest("a", 1))
+    );
+
+    artifacts.delete(&app, "a").unwrap();
+    assert_eq!(artifacts.list(&app).unwrap(), vec![manifest("b", 2)]);
+    assert_eq!(artifacts.find_build(&app, "a").unwrap(), None);
+    assert!(!dir
+        .path()
+        .join("artifacts/zprobst/cloud-conveyor/a")
+        .exists());
+}
+
+#[test]
+fn cleanup_enforces_retention() {
+    let dir = tempfile::tempdir().unwrap();
+    let artifacts = FilesystemArtifacts::new(dir.path());
+    let app = common::application();
+    for (sha, day) in &[("a", 1), ("b", 2), ("c", 3)] {
+        artifacts.record_build(&app, &manifest(sha, *day)).unwrap();
+    }
+
+    let policy = RetentionPolicy {
+        builds_per_branch: 1,
+    };
+    let mut deleted = artifacts
+        .cleanup(&app, &policy, &["a".to_string()])
+        .unwrap();
+    deleted.sort();
+    assert_eq!(deleted, vec!["b".to_string()]);
+    assert_eq!(
+        artifacts.list(&app).unwrap(),
+        vec![manifest("a", 1), manifest("c", 3)]
+    );
+}
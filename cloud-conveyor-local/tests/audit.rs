@@ -0,0 +1,46 @@
+mod common;
+
+use cloud_conveyor_core::audit::{ActionDetails, AuditEntry, AuditLog, Origin, Transition};
+use cloud_conveyor_core::pipelining::ActionResult;
+use cloud_conveyor_local::FilesystemAuditLog;
+
+fn deployed(git_ref: &str, stage: &str, result: ActionResult) -> AuditEntry {
+    let details = ActionDetails {
+        git_ref: Some(git_ref.to_string()),
+        stage: Some(stage.to_string()),
+        account: Some("default".to_string()),
+        ..ActionDetails::default()
+    };
+    let origin = Origin::manual("zprobst".to_string());
+    AuditEntry::new(
+        &common::application(),
+        "Deploy",
+        details,
+        Transition::Completed,
+        Some(&origin),
+    )
+    .with_result(result)
+}
+
+#[test]
+fn entries_are_appended_per_application() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = FilesystemAuditLog::new(dir.path());
+    let app = common::application();
+    assert!(log.entries(&app).unwrap().is_empty());
+
+    let entries = vec![
+        deployed("aaa", "stage", ActionResult::Success),
+        deployed("bbb", "stage", ActionResult::Success),
+        deployed("ccc", "stage", ActionResult::Failed),
+    ];
+    for entry in &entries {
+        log.append(entry).unwrap();
+    }
+
+    // A new instance reads what the first one wrote.
+    let log = FilesystemAuditLog::new(dir.path());
+    assert_eq!(log.entries(&app).unwrap(), entries);
+    let file = dir.path().join("zprobst/cloud-conveyor.jsonl");
+    assert_eq!(std::fs::read_to_string(file).unwrap().lines().count(), 3);
+}
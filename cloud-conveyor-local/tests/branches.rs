@@ -0,0 +1,23 @@
+mod common;
+
+use cloud_conveyor_core::schedule::ResolveBranches;
+use cloud_conveyor_local::GitBranches;
+
+use std::process::Command;
+
+#[test]
+fn head_of_the_branch_is_found_in_the_repository() {
+    let dir = tempfile::tempdir().unwrap();
+    let sha = common::create_repo(dir.path(), &[("README.md", "hello")]);
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir.path())
+        .args(["rev-parse", "--abbrev-ref", "HEAD"])
+        .output()
+        .unwrap();
+    let branch = String::from_utf8(output.stdout).unwrap();
+    let repo = dir.path().to_str().unwrap();
+
+    assert_eq!(GitBranches.head(repo, branch.trim()).unwrap(), sha);
+    assert!(GitBranches.head(repo, "missing").is_err());
+}
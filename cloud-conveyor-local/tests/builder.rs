@@ -0,0 +1,289 @@
+mod common;
+
+use cloud_conveyor_core::build::BuildStatus;
+use cloud_conveyor_core::pipelining::Build;
+use cloud_conveyor_core::runtime::RuntimeContext;
+use cloud_conveyor_core::secrets::Secrets;
+use cloud_conveyor_core::yaml::BuildSpec;
+use cloud_conveyor_local::{build_key, EncryptedFileSecrets, FilesystemArtifacts, LocalBuilder};
+
+use std::collections::HashMap;
+use std::fs;
+use std::thread::sleep;
+use std::time::Duration;
+
+fn wait_for_build(build: &Build, ctx: &RuntimeContext) -> BuildStatus {
+    loop {
+        match ctx.builder.check_build(build, ctx).unwrap() {
+            BuildStatus::Pending => sleep(Duration::from_millis(20)),
+            status => return status,
+        }
+    }
+}
+
+#[test]
+fn successful_build_copies_outputs_to_artifacts() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo_dir = dir.path().join("zprobst/cloud-conveyor");
+    let sha = common::create_repo(&repo_dir, &[("README.md", "hello")]);
+    let builder = LocalBuilder::new(
+        dir.path().join("workspace"),
+        "mkdir -p out && cp README.md out/ && echo building $CONVEYOR_GIT_REF".to_string(),
+    );
+    let ctx = common::context(
+        builder,
+        FilesystemArtifacts::new(dir.path().join("artifacts")),
+    );
+
+    let build = Build::new(sha.clone(), format!("file://{}", repo_dir.display()));
+    ctx.builder
+        .start_build(&build, &Secrets::default(), &ctx)
+        .unwrap();
+    let logs = match wait_for_build(&build, &ctx) {
+        BuildStatus::Succeeded { logs } => logs,
+        status => panic!("unexpected build status {:?}", status),
+    };
+
+    let artifact = dir
+        .path()
+        .join("artifacts/zprobst/cloud-conveyor")
+        .join(&sha)
+        .join("README.md");
+    assert_eq!(fs::read_to_string(artifact).unwrap(), "hello");
+    assert!(fs::read_to_string(logs)
+        .unwrap()
+        .contains(&format!("building {}", sha)));
+}
+
+#[test]
+fn applications_in_subdirectories_are_built_there() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo_dir = dir.path().join("zprobst/cloud-conveyor");
+    let sha = common::create_repo(
+        &repo_dir,
+        &[("README.md", "root"), ("services/api/README.md", "api")],
+    );
+    let builder = LocalBuilder::new(
+        dir.path().join("workspace"),
+        "mkdir -p out && cp README.md out/".to_string(),
+    );
+    let ctx = common::context(
+        builder,
+        FilesystemArtifacts::new(dir.path().join("artifacts")),
+    );
+
+    let build = Build::new(sha.clone(), format!("file://{}", repo_dir.display()))
+        .with_app(Some("zprobst/cloud-conveyor".to_string()))
+        .with_path(Some("services/api".to_string()));
+    ctx.builder
+        .start_build(&build, &Secrets::default(), &ctx)
+        .unwrap();
+    match wait_for_build(&build, &ctx) {
+        BuildStatus::Succeeded { .. } => {}
+        status => panic!("unexpected build status {:?}", status),
+    }
+
+    let artifact = dir
+        .path()
+        .join("artifacts/zprobst/cloud-conveyor")
+        .join(&sha)
+        .join("README.md");
+    assert_eq!(fs::read_to_string(artifact).unwrap(), "api");
+}
+
+#[test]
+fn failing_command_fails_build() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo_dir = dir.path().join("zprobst/cloud-conveyor");
+    let sha = common::create_repo(&repo_dir, &[("README.md", "hello")]);
+    let builder = LocalBuilder::new(
+        dir.path().join("workspace"),
+        "echo npm ERR! missing script && exit 3".to_string(),
+    );
+    let ctx = common::context(
+        builder,
+        FilesystemArtifacts::new(dir.path().join("artifacts")),
+    );
+
+    let build = Build::new(sha.clone(), format!("file://{}", repo_dir.display()));
+    ctx.builder
+        .start_build(&build, &Secrets::default(), &ctx)
+        .unwrap();
+    match wait_for_build(&build, &ctx) {
+        BuildStatus::Failed { error, .. } => {
+            let error = error.unwrap();
+            assert!(error.contains('3'));
+            assert!(error.contains("npm ERR! missing script"));
+        }
+        status => panic!("unexpected build status {:?}", status),
+    }
+    assert!(!dir
+        .path()
+        .join("artifacts/zprobst/cloud-conveyor")
+        .join(&sha)
+        .exists());
+}
+
+#[test]
+fn unknown_ref_fails_to_start() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo_dir = dir.path().join("zprobst/cloud-conveyor");
+    common::create_repo(&repo_dir, &[("README.md", "hello")]);
+    let builder = LocalBuilder::new(dir.path().join("workspace"), "true".to_string());
+    let ctx = common::context(
+        builder,
+        FilesystemArtifacts::new(dir.path().join("artifacts")),
+    );
+
+    let build = Build::new(
+        "does-not-exist".to_string(),
+        format!("file://{}", repo_dir.display()),
+    );
+    assert!(ctx
+        .builder
+        .start_build(&build, &Secrets::default(), &ctx)
+        .is_err());
+    assert!(ctx.builder.check_build(&build, &ctx).is_err());
+}
+
+#[test]
+fn build_spec_phases_and_artifacts_are_used() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo_dir = dir.path().join("zprobst/cloud-conveyor");
+    let sha = common::create_repo(&repo_dir, &[("README.md", "hello")]);
+    let spec: BuildSpec = serde_yaml::from_str(
+        r#"
+env:
+  GREETING: hi
+phases:
+  install:
+    - echo install >> phases.txt
+  build:
+    - echo build >> phases.txt
+    - mkdir -p target/release && echo $GREETING > target/release/app
+  package:
+    - echo package >> phases.txt
+artifacts:
+  - target/release/*
+  - phases.txt
+"#,
+    )
+    .unwrap();
+    let builder = LocalBuilder::new(dir.path().join("workspace"), "exit 1".to_string());
+    let ctx = common::context(
+        builder,
+        FilesystemArtifacts::new(dir.path().join("artifacts")),
+    );
+
+    let build =
+        Build::new(sha.clone(), format!("file://{}", repo_dir.display())).with_spec(Some(spec));
+    ctx.builder
+        .start_build(&build, &Secrets::default(), &ctx)
+        .unwrap();
+    match wait_for_build(&build, &ctx) {
+        BuildStatus::Succeeded { .. } => {}
+        status => panic!("unexpected build status {:?}", status),
+    }
+
+    let artifacts = dir
+        .path()
+        .join("artifacts/zprobst/cloud-conveyor")
+        .join(&sha);
+    assert_eq!(
+        fs::read_to_string(artifacts.join("target/release/app")).unwrap(),
+        "hi\n"
+    );
+    assert_eq!(
+        fs::read_to_string(artifacts.join("phases.txt")).unwrap(),
+        "install\nbuild\npackage\n"
+    );
+    assert!(!artifacts.join("README.md").exists());
+}
+
+#[test]
+fn build_spec_of_the_checkout_is_used() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo_dir = dir.path().join("zprobst/cloud-conveyor");
+    let spec = "phases:\n  build:\n    - mkdir -p dist && echo $GREETING > dist/app\nenv:\n  GREETING: hi\nartifacts:\n  - dist/*\n";
+    let sha = common::create_repo(
+        &repo_dir,
+        &[("README.md", "hello"), (".conveyor-build.yaml", spec)],
+    );
+    let builder = LocalBuilder::new(dir.path().join("workspace"), "exit 1".to_string());
+    let ctx = common::context(
+        builder,
+        FilesystemArtifacts::new(dir.path().join("artifacts")),
+    );
+
+    let build = Build::new(sha.clone(), format!("file://{}", repo_dir.display()));
+    ctx.builder
+        .start_build(&build, &Secrets::default(), &ctx)
+        .unwrap();
+    match wait_for_build(&build, &ctx) {
+        BuildStatus::Succeeded { .. } => {}
+        status => panic!("unexpected build status {:?}", status),
+    }
+
+    let artifacts = dir
+        .path()
+        .join("artifacts/zprobst/cloud-conveyor")
+        .join(&sha);
+    assert_eq!(
+        fs::read_to_string(artifacts.join("dist/app")).unwrap(),
+        "hi\n"
+    );
+}
+
+#[test]
+fn secrets_are_available_to_the_command() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo_dir = dir.path().join("zprobst/cloud-conveyor");
+    let sha = common::create_repo(&repo_dir, &[("README.md", "hello")]);
+    let builder = LocalBuilder::new(
+        dir.path().join("workspace"),
+        "mkdir -p out && echo $NPM_TOKEN > out/token".to_string(),
+    );
+    let secrets_file = dir.path().join("secrets.yaml");
+    let mut values = HashMap::new();
+    values.insert("NPM_TOKEN".to_string(), "hunter2".to_string());
+    EncryptedFileSecrets::write(&secrets_file, "passphrase", &values).unwrap();
+    let mut ctx = common::context(
+        builder,
+        FilesystemArtifacts::new(dir.path().join("artifacts")),
+    );
+    ctx.secrets = Box::new(EncryptedFileSecrets::open(&secrets_file, "passphrase").unwrap());
+
+    let build = Build::new(sha.clone(), format!("file://{}", repo_dir.display()));
+    let secrets = Secrets::resolve(
+        &*ctx.secrets,
+        &common::application(),
+        &["NPM_TOKEN".to_string()],
+    )
+    .unwrap();
+    ctx.builder.start_build(&build, &secrets, &ctx).unwrap();
+    match wait_for_build(&build, &ctx) {
+        BuildStatus::Succeeded { .. } => {}
+        status => panic!("unexpected build status {:?}", status),
+    }
+    let token = dir
+        .path()
+        .join("artifacts/zprobst/cloud-conveyor")
+        .join(&sha)
+        .join("token");
+    assert_eq!(fs::read_to_string(token).unwrap(), "hunter2\n");
+}
+
+#[test]
+fn builds_of_refs_that_read_the_same_once_sanitized_have_keys_of_their_own() {
+    let build = |git_ref: &str| {
+        Build::new(
+            git_ref.to_string(),
+            "https://github.com/zprobst/cloud-conveyor.git".to_string(),
+        )
+    };
+    let key = build_key(&build("feature/x"));
+    assert!(key.starts_with("cloud-conveyor-feature-x-"));
+    assert_eq!(key, build_key(&build("feature/x")));
+    assert_ne!(key, build_key(&build("feature-x")));
+    assert_ne!(key, build_key(&build("feature_x")));
+}
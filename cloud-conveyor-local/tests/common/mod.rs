@@ -0,0 +1,130 @@
+//! Shared helpers for the integration tests of the local runtime.
+#![allow(dead_code)]
+use cloud_conveyor_core::approval::MemoryApprovals;
+use cloud_conveyor_core::audit::MemoryAuditLog;
+use cloud_conveyor_core::credentials::NoCredentials;
+use cloud_conveyor_core::deploy::{DeployInfrastructure, DeployPollError, DeployStatus};
+use cloud_conveyor_core::history::MemoryHistory;
+use cloud_conveyor_core::pipelining::{Deploy, Teardown};
+use cloud_conveyor_core::runtime::RuntimeContext;
+use cloud_conveyor_core::secrets::{NoSecrets, Secrets};
+use cloud_conveyor_core::teardown::{TeardownInfrastructure, TeardownPollError, TeardownStatus};
+use cloud_conveyor_core::yaml::ConfigFile;
+use cloud_conveyor_core::Application;
+use cloud_conveyor_local::{FilesystemArtifacts, LocalBuilder};
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+const CONFIG: &str = r#"
+org: zprobst
+app: cloud-conveyor
+accounts:
+  - name: default
+    id: 123456789
+    regions:
+      - us-east-1
+approvals: {}
+triggers: []
+stages:
+  - name: stage
+"#;
+
+pub fn application() -> Application {
+    let conf: ConfigFile = serde_yaml::from_str(CONFIG).unwrap();
+    conf.into()
+}
+
+pub fn context(builder: LocalBuilder, artifacts: FilesystemArtifacts) -> RuntimeContext {
+    RuntimeContext {
+        artifact_provider: Box::new(artifacts),
+        builder: Box::new(builder),
+        infrastructure: Box::new(NoInfrastructure),
+        teardown: Box::new(NoInfrastructure),
+        secrets: Box::new(NoSecrets),
+        credentials: Box::new(NoCredentials),
+        history: Box::new(MemoryHistory::default()),
+        audit: Box::new(MemoryAuditLog::default()),
+        approvals: Box::new(MemoryApprovals::default()),
+        applications: vec![application()],
+    }
+}
+
+fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "git {:?} failed", args);
+    String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
+
+/// Creates a git repository for the test application with a single commit and
+/// returns the sha of that commit.
+pub fn create_repo(dir: &Path, files: &[(&str, &str)]) -> String {
+    fs::create_dir_all(dir).unwrap();
+    git(dir, &["init", "--quiet"]);
+    for (name, content) in files {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+    git(dir, &["add", "."]);
+    git(
+        dir,
+        &[
+            "-c",
+            "user.name=conveyor",
+            "-c",
+            "user.email=conveyor@example.com",
+            "commit",
+            "--quiet",
+            "-m",
+            "initial",
+        ],
+    );
+    git(dir, &["rev-parse", "HEAD"])
+}
+
+#[derive(Debug)]
+pub struct NoInfrastructure;
+
+impl DeployInfrastructure for NoInfrastructure {
+    fn start_deployment(
+        &self,
+        _deploy: &Deploy,
+        _secrets: &Secrets,
+        _ctx: &RuntimeContext,
+    ) -> Result<(), DeployPollError> {
+        Ok(())
+    }
+
+    fn check_deployment(
+        &self,
+        _deploy: &Deploy,
+        _ctx: &RuntimeContext,
+    ) -> Result<DeployStatus, DeployPollError> {
+        Ok(DeployStatus::Complete)
+    }
+}
+
+impl TeardownInfrastructure for NoInfrastructure {
+    fn start_teardown(
+        &self,
+        _teardown: &Teardown,
+        _ctx: &RuntimeContext,
+    ) -> Result<(), TeardownPollError> {
+        Ok(())
+    }
+
+    fn check_teardown(
+        &self,
+        _teardown: &Teardown,
+        _ctx: &RuntimeContext,
+    ) -> Result<TeardownStatus, TeardownPollError> {
+        Ok(TeardownStatus::Complete)
+    }
+}
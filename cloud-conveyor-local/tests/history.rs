@@ -0,0 +1,67 @@
+mod common;
+
+use chrono::{TimeZone, Utc};
+use cloud_conveyor_core::history::{DeploymentHistory, DeploymentOutcome, DeploymentRecord};
+use cloud_conveyor_local::FilesystemHistory;
+
+fn record(git_ref: &str, minute: u32, outcome: DeploymentOutcome) -> DeploymentRecord {
+    DeploymentRecord {
+        git_ref: git_ref.to_string(),
+        deployed_at: Utc.with_ymd_and_hms(2020, 6, 1, 12, minute, 0).unwrap(),
+        outcome,
+        rollback: false,
+        tag: None,
+    }
+}
+
+#[test]
+fn deployments_are_recorded_per_stage() {
+    let dir = tempfile::tempdir().unwrap();
+    let history = FilesystemHistory::new(dir.path());
+    let app = common::application();
+    assert!(history.list(&app, "stage").unwrap().is_empty());
+    assert!(history.last_successful(&app, "stage").unwrap().is_none());
+
+    let records = vec![
+        record("aaa", 1, DeploymentOutcome::Succeeded),
+        record("bbb", 2, DeploymentOutcome::Succeeded),
+        record("ccc", 3, DeploymentOutcome::Failed),
+    ];
+    for record in &records {
+        history.record(&app, "stage", record).unwrap();
+    }
+    history
+        .record(
+            &app,
+            "prod",
+            &record("aaa", 4, DeploymentOutcome::Succeeded),
+        )
+        .unwrap();
+
+    // A new instance reads what the first one wrote.
+    let history = FilesystemHistory::new(dir.path());
+    assert_eq!(history.list(&app, "stage").unwrap(), records);
+    assert_eq!(
+        history.last_successful(&app, "stage").unwrap(),
+        Some(records[1].clone())
+    );
+    assert!(dir.path().join("cloud-conveyor/prod.yaml").is_file());
+    assert_eq!(
+        history.arrival(&app, "aaa", "stage").unwrap(),
+        Some(records[0].clone())
+    );
+    assert!(history.arrival(&app, "ccc", "stage").unwrap().is_none());
+
+    // A stage that was torn down runs nothing, even after a failed deployment.
+    history
+        .record(
+            &app,
+            "stage",
+            &record("bbb", 5, DeploymentOutcome::TornDown),
+        )
+        .unwrap();
+    history
+        .record(&app, "stage", &record("ddd", 6, DeploymentOutcome::Failed))
+        .unwrap();
+    assert!(history.last_successful(&app, "stage").unwrap().is_none());
+}
@@ -0,0 +1,111 @@
+mod common;
+
+use cloud_conveyor_core::secrets::SecretProvider;
+use cloud_conveyor_local::EncryptedFileSecrets;
+
+use std::collections::HashMap;
+use std::fs;
+
+/// A file that the rust-crypto implementation wrote, with the passphrase `correct horse`.
+const RUST_CRYPTO_FILE: &str = r#"
+salt: 703ec4b39eb9026d637bfb9d6fc739ca
+nonce: 8163c5b1f83974da3fac6b12
+tag: 7679f5f44c42e8e0f58039b01a09e71d
+data: 0171dcd65389340bb62080d0d58bf53f345b6e5eb331e0
+"#;
+
+fn values() -> HashMap<String, String> {
+    let mut values = HashMap::new();
+    values.insert("NPM_TOKEN".to_string(), "hunter2".to_string());
+    values.insert(
+        "zprobst/cloud-conveyor/DATABASE_URL".to_string(),
+        "postgres://app".to_string(),
+    );
+    values.insert("DATABASE_URL".to_string(), "postgres://shared".to_string());
+    values
+}
+
+#[test]
+fn written_secrets_can_be_read_back() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("secrets.yaml");
+    EncryptedFileSecrets::write(&path, "correct horse", &values()).unwrap();
+
+    let secrets = EncryptedFileSecrets::open(&path, "correct horse").unwrap();
+    let app = common::application();
+    assert_eq!(secrets.get_secret(&app, "NPM_TOKEN").unwrap(), "hunter2");
+    assert_eq!(
+        secrets.get_secret(&app, "DATABASE_URL").unwrap(),
+        "postgres://app"
+    );
+    assert!(secrets.get_secret(&app, "MISSING").is_err());
+    assert_eq!(secrets.into_values(), values());
+}
+
+#[test]
+fn secrets_are_encrypted_at_rest() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("secrets.yaml");
+    EncryptedFileSecrets::write(&path, "correct horse", &values()).unwrap();
+
+    let contents = fs::read_to_string(&path).unwrap();
+    assert!(!contents.contains("hunter2"));
+    assert!(!contents.contains("NPM_TOKEN"));
+}
+
+#[test]
+fn wrong_passphrase_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("secrets.yaml");
+    EncryptedFileSecrets::write(&path, "correct horse", &values()).unwrap();
+    assert!(EncryptedFileSecrets::open(&path, "battery staple").is_err());
+}
+
+#[test]
+fn debug_does_not_show_values() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("secrets.yaml");
+    EncryptedFileSecrets::write(&path, "correct horse", &values()).unwrap();
+    let secrets = EncryptedFileSecrets::open(&path, "correct horse").unwrap();
+    let printed = format!("{:?}", secrets);
+    assert!(printed.contains("NPM_TOKEN"));
+    assert!(!printed.contains("hunter2"));
+}
+
+#[test]
+fn files_written_with_rust_crypto_can_still_be_read() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("secrets.yaml");
+    fs::write(&path, RUST_CRYPTO_FILE).unwrap();
+
+    let secrets = EncryptedFileSecrets::open(&path, "correct horse").unwrap();
+    let app = common::application();
+    assert_eq!(secrets.get_secret(&app, "NPM_TOKEN").unwrap(), "hunter2");
+    assert!(EncryptedFileSecrets::open(&path, "battery staple").is_err());
+}
+
+#[test]
+fn secrets_of_an_app_are_not_shared_with_apps_of_the_same_name_in_other_orgs() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("secrets.yaml");
+    let app = common::application();
+    let mut values = values();
+    values.insert(
+        EncryptedFileSecrets::app_key(&app, "NPM_TOKEN"),
+        "zprobst-token".to_string(),
+    );
+    EncryptedFileSecrets::write(&path, "correct horse", &values).unwrap();
+
+    let secrets = EncryptedFileSecrets::open(&path, "correct horse").unwrap();
+    let mut fork = common::application();
+    fork.org = "fork".to_string();
+    assert_eq!(
+        secrets.get_secret(&app, "NPM_TOKEN").unwrap(),
+        "zprobst-token"
+    );
+    assert_eq!(secrets.get_secret(&fork, "NPM_TOKEN").unwrap(), "hunter2");
+    assert_eq!(
+        secrets.get_secret(&fork, "DATABASE_URL").unwrap(),
+        "postgres://shared"
+    );
+}
@@ -0,0 +1,17 @@
+//! This crate defines runtime implementations that deploy and tear down infrastructure with
+//! [terraform](https://www.terraform.io/). The terraform cli does all of the work while cloud
+//! conveyor starts it and polls it for completion.
+//!
+//! This crate also uses the standard logging pattern that the core crate uses.
+#![warn(
+    missing_docs,
+    rust_2018_idioms,
+    missing_debug_implementations,
+    rustdoc::broken_intra_doc_links
+)]
+
+mod report;
+mod runner;
+
+pub use report::TerraformReport;
+pub use runner::TerraformRunner;
@@ -0,0 +1,60 @@
+use serde_json::{Map, Value};
+
+/// Summarizes the machine readable output that `terraform apply -json` and `terraform destroy -json`
+/// write. Lines of the output that are not json, such as the output of `terraform init`, are ignored.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TerraformReport {
+    /// The number of resources that were added.
+    pub added: u64,
+    /// The number of resources that were changed.
+    pub changed: u64,
+    /// The number of resources that were removed.
+    pub removed: u64,
+    /// The values of the outputs of the configuration after the apply. Sensitive outputs are left out.
+    pub outputs: Map<String, Value>,
+    /// The summaries and details of the error diagnostics terraform reported.
+    pub errors: Vec<String>,
+}
+
+impl TerraformReport {
+    /// Parses the output of a terraform command.
+    pub fn from_log(log: &str) -> Self {
+        let mut report = Self::default();
+        for message in log
+            .lines()
+            .filter_map(|l| serde_json::from_str::<Value>(l).ok())
+        {
+            match message["type"].as_str() {
+                Some("change_summary") => {
+                    let changes = &message["changes"];
+                    report.added = changes["add"].as_u64().unwrap_or_default();
+                    report.changed = changes["change"].as_u64().unwrap_or_default();
+                    report.removed = changes["remove"].as_u64().unwrap_or_default();
+                }
+                Some("outputs") => {
+                    let outputs = message["outputs"].as_object().into_iter().flatten();
+                    for (name, output) in outputs {
+                        // Planned outputs are reported before the apply without a value.
+                        if output["sensitive"].as_bool() == Some(true) {
+                            continue;
+                        }
+                        if let Some(value) = output.get("value") {
+                            report.outputs.insert(name.clone(), value.clone());
+                        }
+                    }
+                }
+                Some("diagnostic") if message["diagnostic"]["severity"] == "error" => {
+                    let diagnostic = &message["diagnostic"];
+                    let summary = diagnostic["summary"].as_str().unwrap_or_default();
+                    let error = match diagnostic["detail"].as_str() {
+                        Some(detail) if !detail.is_empty() => format!("{}: {}", summary, detail),
+                        _ => summary.to_string(),
+                    };
+                    report.errors.push(error);
+                }
+                _ => {}
+            }
+        }
+        report
+    }
+}
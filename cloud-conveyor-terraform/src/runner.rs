@@ -0,0 +1,458 @@
+use crate::report::TerraformReport;
+use cloud_conveyor_core::credentials::{CredentialError, Credentials};
+use cloud_conveyor_core::deploy::{DeployInfrastructure, DeployPollError, DeployStatus};
+use cloud_conveyor_core::pipelining::{Deploy, Teardown};
+use cloud_conveyor_core::runtime::RuntimeContext;
+use cloud_conveyor_core::secrets::Secrets;
+use cloud_conveyor_core::teardown::{TeardownInfrastructure, TeardownPollError, TeardownStatus};
+use cloud_conveyor_core::{Application, Stage};
+use cloud_conveyor_local::copy_dir;
+use log::{info, warn};
+use serde_json::{Map, Value};
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::Mutex;
+
+/// Selects the workspace of the stage, creating it the first time the stage is deployed.
+const SELECT_WORKSPACE: &str = r#""$TERRAFORM" init -input=false -no-color \
+    && { "$TERRAFORM" workspace select -no-color "$WORKSPACE" || "$TERRAFORM" workspace new -no-color "$WORKSPACE"; }"#;
+
+/// The files of the working directory of a stage that hold the state of terraform and must survive
+/// replacing the configuration with that of a newer build.
+const STATE_FILES: &[&str] = &[
+    ".terraform",
+    ".terraform.lock.hcl",
+    "terraform.tfstate",
+    "terraform.tfstate.d",
+];
+
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+/// Gets the credentials of the account of the stage. Terraform falls back to the credentials of its own
+/// environment when the runtime has none for the account.
+fn account_credentials(
+    ctx: &RuntimeContext,
+    stage: &Stage,
+) -> Result<Option<Credentials>, CredentialError> {
+    match ctx.credentials.credentials(&stage.account) {
+        Ok(credentials) => Ok(Some(credentials)),
+        Err(CredentialError::Missing { .. }) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Gets the name of the terraform workspace of the stage.
+fn workspace_name(stage: &Stage) -> String {
+    format!(
+        "{}-{}",
+        sanitize(&stage.name),
+        sanitize(&stage.account.name)
+    )
+}
+
+/// Deploys and tears down stages by running the terraform cli.
+///
+/// The artifacts of the build that is deployed are expected to contain a terraform module in the module
+/// directory, `terraform` by default. Every stage of an application gets a working directory underneath the
+/// root directory, at `<org>/<app>/<stage>-<account>`, that the module is copied into before it is applied. The state of each stage is kept in its
+/// own terraform workspace named `<stage>-<account>` so that stages never share resources. Since the state
+/// lives in the working directory, the local backend should only be used when the root directory is not
+/// thrown away. Modules can configure a remote backend to avoid that.
+///
+/// Details of the stage are passed as the `app`, `stage`, `account`, `account_id`, `regions` and `git_ref`
+/// variables, where a destroy gets the git ref that was last applied to the stage, and the secrets of the
+/// stage are passed as variables of the same name. Modules only need to declare the variables they use.
+/// When the [CredentialProvider](../cloud_conveyor_core/credentials/trait.CredentialProvider.html) of the
+/// runtime has credentials for the account of the stage, they are passed in the standard `AWS_ACCESS_KEY_ID`,
+/// `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN` environment variables. Everything terraform writes ends up
+/// in a log file next to the working directory. When an apply completes, its outputs are available through
+/// [outputs](#method.outputs).
+#[derive(Debug)]
+pub struct TerraformRunner {
+    root: PathBuf,
+    binary: PathBuf,
+    module_dir: PathBuf,
+    processes: Mutex<HashMap<String, Child>>,
+}
+
+impl TerraformRunner {
+    /// Creates a runner that keeps the working directories of stages underneath the root directory.
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self {
+            root: root.into(),
+            binary: PathBuf::from("terraform"),
+            module_dir: PathBuf::from("terraform"),
+            processes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets the terraform executable to run. Defaults to `terraform` on the path.
+    pub fn with_binary<P: Into<PathBuf>>(mut self, binary: P) -> Self {
+        self.binary = binary.into();
+        self
+    }
+
+    /// Sets the directory, relative to the root of the build artifacts, that holds the terraform
+    /// module. Defaults to `terraform`.
+    pub fn with_module_dir<P: Into<PathBuf>>(mut self, module_dir: P) -> Self {
+        self.module_dir = module_dir.into();
+        self
+    }
+
+    /// Gets the outputs of the last successful apply of the stage.
+    pub fn outputs(&self, app: &Application, stage: &Stage) -> io::Result<Map<String, Value>> {
+        let outputs = File::open(self.stage_file(app, stage, "outputs.json"))?;
+        serde_json::from_reader(outputs).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Gets the directory of the application, keyed by its full name so that applications of the same
+    /// name in different organizations never share state.
+    fn app_dir(&self, app: &Application) -> PathBuf {
+        self.root.join(sanitize(&app.org)).join(sanitize(&app.app))
+    }
+
+    fn working_dir(&self, app: &Application, stage: &Stage) -> PathBuf {
+        self.app_dir(app).join(workspace_name(stage))
+    }
+
+    fn stage_file(&self, app: &Application, stage: &Stage, extension: &str) -> PathBuf {
+        self.app_dir(app)
+            .join(format!("{}.{}", workspace_name(stage), extension))
+    }
+
+    fn process_key(app: &Application, stage: &Stage) -> String {
+        format!("{}/{}", app.full_name(), workspace_name(stage))
+    }
+
+    /// Replaces the configuration in the working directory of the stage with the module of the build.
+    fn prepare_working_dir(
+        &self,
+        app: &Application,
+        deploy: &Deploy,
+        ctx: &RuntimeContext,
+    ) -> Result<PathBuf, String> {
+        let artifacts = self.stage_file(app, &deploy.stage, "artifacts");
+        if artifacts.exists() {
+            fs::remove_dir_all(&artifacts).map_err(|e| e.to_string())?;
+        }
+        fs::create_dir_all(&artifacts).map_err(|e| e.to_string())?;
+        ctx.artifact_provider
+            .download(app, &deploy.git_ref, &artifacts)
+            .map_err(|e| e.to_string())?;
+
+        let module = artifacts.join(&self.module_dir);
+        if !module.is_dir() {
+            return Err(format!(
+                "The artifacts of git_ref {} have no terraform module in {:?}",
+                deploy.git_ref, self.module_dir
+            ));
+        }
+
+        let dir = self.working_dir(app, &deploy.stage);
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        for entry in fs::read_dir(&dir).map_err(|e| e.to_string())? {
+            let entry = entry.map_err(|e| e.to_string())?;
+            if STATE_FILES.iter().any(|f| entry.file_name() == *f) {
+                continue;
+            }
+            let result = if entry.file_type().map_err(|e| e.to_string())?.is_dir() {
+                fs::remove_dir_all(entry.path())
+            } else {
+                fs::remove_file(entry.path())
+            };
+            result.map_err(|e| e.to_string())?;
+        }
+        copy_dir(&module, &dir).map_err(|e| e.to_string())?;
+        Ok(dir)
+    }
+
+    /// Starts terraform in the working directory of the stage with the script.
+    fn spawn(
+        &self,
+        app: &Application,
+        stage: &Stage,
+        git_ref: Option<&str>,
+        secrets: &Secrets,
+        credentials: Option<&Credentials>,
+        script: &str,
+    ) -> io::Result<()> {
+        let log = File::create(self.stage_file(app, stage, "log"))?;
+        let regions = Value::from(stage.account.regions.clone()).to_string();
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(script)
+            .current_dir(self.working_dir(app, stage))
+            .env("TERRAFORM", &self.binary)
+            .env("WORKSPACE", workspace_name(stage))
+            .env("TF_IN_AUTOMATION", "1")
+            .env("TF_VAR_app", &app.app)
+            .env("TF_VAR_stage", &stage.name)
+            .env("TF_VAR_account", &stage.account.name)
+            .env("TF_VAR_account_id", stage.account.id.to_string())
+            .env("TF_VAR_regions", regions);
+        if let Some(git_ref) = git_ref {
+            command.env("TF_VAR_git_ref", git_ref);
+        }
+        for (name, value) in secrets.iter() {
+            command.env(format!("TF_VAR_{}", name), value);
+        }
+        if let Some(credentials) = credentials {
+            command
+                .env("AWS_ACCESS_KEY_ID", &credentials.access_key_id)
+                .env(
+                    "AWS_SECRET_ACCESS_KEY",
+                    credentials.secret_access_key.expose(),
+                );
+            if let Some(token) = &credentials.session_token {
+                command.env("AWS_SESSION_TOKEN", token.expose());
+            }
+        }
+        let child = command
+            .stdin(Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            .spawn()?;
+
+        self.processes
+            .lock()
+            .unwrap()
+            .insert(Self::process_key(app, stage), child);
+        Ok(())
+    }
+
+    /// Checks if terraform is still running for the stage. Once it is done, the output it wrote
+    /// is parsed and returned along side its exit status.
+    fn poll(
+        &self,
+        app: &Application,
+        stage: &Stage,
+    ) -> Result<Option<(ExitStatus, TerraformReport)>, String> {
+        let key = Self::process_key(app, stage);
+        let mut processes = self.processes.lock().unwrap();
+        let child = processes
+            .get_mut(&key)
+            .ok_or_else(|| format!("Terraform was not started for stage {}", stage.name))?;
+        let status = match child.try_wait().map_err(|e| e.to_string())? {
+            Some(status) => status,
+            None => return Ok(None),
+        };
+        processes.remove(&key);
+
+        let log = fs::read_to_string(self.stage_file(app, stage, "log")).unwrap_or_default();
+        let report = TerraformReport::from_log(&log);
+        for error in &report.errors {
+            warn!(
+                "Terraform reported an error for stage {:?}: {}",
+                stage.name, error
+            );
+        }
+        Ok(Some((status, report)))
+    }
+}
+
+impl DeployInfrastructure for TerraformRunner {
+    fn start_deployment(
+        &self,
+        deploy: &Deploy,
+        secrets: &Secrets,
+        ctx: &RuntimeContext,
+    ) -> Result<(), DeployPollError> {
+        let app = deploy
+            .application(ctx)
+            .ok_or_else(|| DeployPollError::Other {
+                info: format!("No application is configured for repo {}", deploy.repo),
+            })?;
+        self.prepare_working_dir(app, deploy, ctx)
+            .map_err(|info| DeployPollError::Other { info })?;
+
+        let script = format!(
+            r#"{} && "$TERRAFORM" apply -auto-approve -input=false -json"#,
+            SELECT_WORKSPACE
+        );
+        let credentials = account_credentials(ctx, &deploy.stage)?;
+        self.spawn(
+            app,
+            &deploy.stage,
+            Some(&deploy.git_ref),
+            secrets,
+            credentials.as_ref(),
+            &script,
+        )
+        .map_err(|e| DeployPollError::Other {
+            info: e.to_string(),
+        })?;
+        info!(
+            "Started terraform apply of git_ref {:?} to workspace {:?}",
+            deploy.git_ref,
+            workspace_name(&deploy.stage)
+        );
+        Ok(())
+    }
+
+    fn check_deployment(
+        &self,
+        deploy: &Deploy,
+        ctx: &RuntimeContext,
+    ) -> Result<DeployStatus, DeployPollError> {
+        let app = deploy
+            .application(ctx)
+            .ok_or_else(|| DeployPollError::Other {
+                info: format!("No application is configured for repo {}", deploy.repo),
+            })?;
+        let (status, report) = match self
+            .poll(app, &deploy.stage)
+            .map_err(|info| DeployPollError::Other { info })?
+        {
+            Some(done) => done,
+            None => return Ok(DeployStatus::Pending),
+        };
+
+        if !status.success() {
+            warn!(
+                "Terraform apply of git_ref {:?} to stage {:?} failed with {}",
+                deploy.git_ref, deploy.stage.name, status
+            );
+            return Ok(DeployStatus::Failed);
+        }
+        info!(
+            "Terraform apply to stage {:?} complete: {} added, {} changed, {} destroyed",
+            deploy.stage.name, report.added, report.changed, report.removed
+        );
+        let outputs =
+            File::create(self.stage_file(app, &deploy.stage, "outputs.json")).map_err(|e| {
+                DeployPollError::Other {
+                    info: e.to_string(),
+                }
+            })?;
+        serde_json::to_writer(outputs, &report.outputs).map_err(|e| DeployPollError::Other {
+            info: e.to_string(),
+        })?;
+        fs::write(
+            self.stage_file(app, &deploy.stage, "git_ref"),
+            &deploy.git_ref,
+        )
+        .map_err(|e| DeployPollError::Other {
+            info: e.to_string(),
+        })?;
+        Ok(DeployStatus::Complete)
+    }
+}
+
+impl TeardownInfrastructure for TerraformRunner {
+    fn start_teardown(
+        &self,
+        teardown: &Teardown,
+        ctx: &RuntimeContext,
+    ) -> Result<(), TeardownPollError> {
+        let app = teardown
+            .application(ctx)
+            .ok_or_else(|| TeardownPollError::Other {
+                info: format!("No application is configured for repo {}", teardown.repo),
+            })?;
+        if !self.working_dir(app, &teardown.stage).is_dir() {
+            return Err(TeardownPollError::Other {
+                info: format!(
+                    "Stage {} was never deployed with terraform",
+                    teardown.stage.name
+                ),
+            });
+        }
+        // Variables without defaults must have values for terraform to plan the destroy.
+        let secrets =
+            Secrets::resolve(&*ctx.secrets, app, &teardown.stage.secrets).map_err(|e| {
+                TeardownPollError::Other {
+                    info: e.to_string(),
+                }
+            })?;
+
+        let script = format!(
+            r#"{} && "$TERRAFORM" destroy -auto-approve -input=false -json"#,
+            SELECT_WORKSPACE
+        );
+        let git_ref = fs::read_to_string(self.stage_file(app, &teardown.stage, "git_ref")).ok();
+        let credentials = account_credentials(ctx, &teardown.stage)?;
+        self.spawn(
+            app,
+            &teardown.stage,
+            git_ref.as_deref(),
+            &secrets,
+            credentials.as_ref(),
+            &script,
+        )
+        .map_err(|e| TeardownPollError::Other {
+            info: e.to_string(),
+        })?;
+        info!(
+            "Started terraform destroy of workspace {:?}",
+            workspace_name(&teardown.stage)
+        );
+        Ok(())
+    }
+
+    fn check_teardown(
+        &self,
+        teardown: &Teardown,
+        ctx: &RuntimeContext,
+    ) -> Result<TeardownStatus, TeardownPollError> {
+        let app = teardown
+            .application(ctx)
+            .ok_or_else(|| TeardownPollError::Other {
+                info: format!("No application is configured for repo {}", teardown.repo),
+            })?;
+        let (status, report) = match self
+            .poll(app, &teardown.stage)
+            .map_err(|info| TeardownPollError::Other { info })?
+        {
+            Some(done) => done,
+            None => return Ok(TeardownStatus::Pending),
+        };
+
+        if !status.success() {
+            warn!(
+                "Terraform destroy of stage {:?} failed with {}",
+                teardown.stage.name, status
+            );
+            return Ok(TeardownStatus::Failed);
+        }
+        info!(
+            "Terraform destroy of stage {:?} complete: {} destroyed",
+            teardown.stage.name, report.removed
+        );
+        // The state of the workspace is empty now, so there is nothing worth keeping.
+        for path in &[
+            self.working_dir(app, &teardown.stage),
+            self.stage_file(app, &teardown.stage, "artifacts"),
+        ] {
+            if path.exists() {
+                fs::remove_dir_all(path).map_err(|e| TeardownPollError::Other {
+                    info: e.to_string(),
+                })?;
+            }
+        }
+        for extension in &["outputs.json", "git_ref"] {
+            let path = self.stage_file(app, &teardown.stage, extension);
+            if path.exists() {
+                fs::remove_file(path).map_err(|e| TeardownPollError::Other {
+                    info: e.to_string(),
+                })?;
+            }
+        }
+        Ok(TeardownStatus::Complete)
+    }
+}
@@ -0,0 +1,102 @@
+//! Shared helpers for the integration tests of the terraform runtime.
+#![allow(dead_code)]
+use cloud_conveyor_core::approval::MemoryApprovals;
+use cloud_conveyor_core::audit::MemoryAuditLog;
+use cloud_conveyor_core::credentials::NoCredentials;
+use cloud_conveyor_core::history::MemoryHistory;
+use cloud_conveyor_core::runtime::RuntimeContext;
+use cloud_conveyor_core::secrets::SecretProvider;
+use cloud_conveyor_core::yaml::ConfigFile;
+use cloud_conveyor_core::{Application, Stage};
+use cloud_conveyor_local::{FilesystemArtifacts, LocalBuilder};
+use cloud_conveyor_terraform::TerraformRunner;
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub const REPO: &str = "https://github.com/zprobst/cloud-conveyor.git";
+
+const CONFIG: &str = r#"
+org: zprobst
+app: cloud-conveyor
+accounts:
+  - name: default
+    id: 123456789
+    regions:
+      - us-east-1
+approvals: {}
+triggers: []
+stages:
+  - name: dev
+  - name: prod
+"#;
+
+pub fn application() -> Application {
+    let conf: ConfigFile = serde_yaml::from_str(CONFIG).unwrap();
+    conf.into()
+}
+
+pub fn stage(name: &str) -> Stage {
+    application()
+        .stages
+        .into_iter()
+        .find(|s| s.name == name)
+        .unwrap()
+}
+
+pub fn context(
+    dir: &Path,
+    runner_for: impl Fn() -> TerraformRunner,
+    secrets: Box<dyn SecretProvider>,
+) -> RuntimeContext {
+    RuntimeContext {
+        artifact_provider: Box::new(FilesystemArtifacts::new(dir.join("artifacts"))),
+        builder: Box::new(LocalBuilder::new(dir.join("builds"), "true".to_string())),
+        infrastructure: Box::new(runner_for()),
+        teardown: Box::new(runner_for()),
+        secrets,
+        credentials: Box::new(NoCredentials),
+        history: Box::new(MemoryHistory::default()),
+        audit: Box::new(MemoryAuditLog::default()),
+        approvals: Box::new(MemoryApprovals::default()),
+        applications: vec![application()],
+    }
+}
+
+/// Stores a build of the sha whose artifacts hold a terraform module with the configuration.
+pub fn upload_module(ctx: &RuntimeContext, dir: &Path, sha: &str, configuration: &str) {
+    let source = dir.join("source").join(sha);
+    fs::create_dir_all(source.join("terraform")).unwrap();
+    fs::write(source.join("terraform/main.tf"), configuration).unwrap();
+    ctx.artifact_provider
+        .upload(&application(), sha, &source)
+        .unwrap();
+}
+
+/// Determines if the terraform cli is installed. Tests that run the real cli are skipped without it.
+pub fn terraform_installed() -> bool {
+    Command::new("terraform")
+        .arg("version")
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+/// Writes an executable that stands in for the terraform cli. It records its arguments to
+/// `calls` next to itself and runs the body of the script.
+pub fn fake_terraform(dir: &Path, body: &str) -> PathBuf {
+    let path = dir.join("fake-terraform");
+    fs::write(
+        &path,
+        format!(
+            "#!/bin/sh\necho \"$@\" >> \"{}\"\n{}\n",
+            dir.join("calls").display(),
+            body
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
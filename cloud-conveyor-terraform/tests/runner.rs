@@ -0,0 +1,244 @@
+mod common;
+
+use cloud_conveyor_core::credentials::{Credentials, StaticCredentials};
+use cloud_conveyor_core::deploy::DeployStatus;
+use cloud_conveyor_core::pipelining::{Deploy, Teardown};
+use cloud_conveyor_core::runtime::RuntimeContext;
+use cloud_conveyor_core::secrets::{NoSecrets, Secrets};
+use cloud_conveyor_core::teardown::TeardownStatus;
+use cloud_conveyor_terraform::{TerraformReport, TerraformRunner};
+use common::REPO;
+
+use std::fs;
+use std::thread::sleep;
+use std::time::Duration;
+
+const CONFIGURATION: &str = r#"
+variable "stage" {}
+variable "account" {}
+variable "git_ref" {}
+
+resource "terraform_data" "marker" {
+  input = "${var.stage}-${var.account}-${var.git_ref}"
+}
+
+output "marker" {
+  value = terraform_data.marker.output
+}
+"#;
+
+fn wait_for_deployment(deploy: &Deploy, ctx: &RuntimeContext) -> DeployStatus {
+    loop {
+        match ctx.infrastructure.check_deployment(deploy, ctx).unwrap() {
+            DeployStatus::Pending => sleep(Duration::from_millis(50)),
+            status => return status,
+        }
+    }
+}
+
+fn wait_for_teardown(teardown: &Teardown, ctx: &RuntimeContext) -> TeardownStatus {
+    loop {
+        match ctx.teardown.check_teardown(teardown, ctx).unwrap() {
+            TeardownStatus::Pending => sleep(Duration::from_millis(50)),
+            status => return status,
+        }
+    }
+}
+
+#[test]
+fn stages_are_applied_to_their_own_workspaces_and_destroyed() {
+    if !common::terraform_installed() {
+        eprintln!("Skipping test since terraform is not installed");
+        return;
+    }
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("terraform");
+    let runner = || TerraformRunner::new(&root);
+    let ctx = common::context(dir.path(), runner, Box::new(NoSecrets));
+    common::upload_module(&ctx, dir.path(), "abc", CONFIGURATION);
+
+    let dev = Deploy::new(common::stage("dev"), REPO.to_string(), "abc".to_string());
+    let prod = Deploy::new(common::stage("prod"), REPO.to_string(), "abc".to_string());
+    for deploy in &[&dev, &prod] {
+        ctx.infrastructure
+            .start_deployment(deploy, &Secrets::default(), &ctx)
+            .unwrap();
+    }
+    for deploy in &[&dev, &prod] {
+        assert_eq!(wait_for_deployment(deploy, &ctx), DeployStatus::Complete);
+    }
+
+    let app = common::application();
+    let outputs = runner().outputs(&app, &dev.stage).unwrap();
+    assert_eq!(outputs["marker"], "dev-default-abc");
+    let outputs = runner().outputs(&app, &prod.stage).unwrap();
+    assert_eq!(outputs["marker"], "prod-default-abc");
+    let dev_dir = root.join("zprobst/cloud-conveyor/dev-default");
+    assert!(dev_dir
+        .join("terraform.tfstate.d/dev-default/terraform.tfstate")
+        .is_file());
+
+    // Applying a newer build keeps the state of the workspace.
+    common::upload_module(&ctx, dir.path(), "def", CONFIGURATION);
+    let update = Deploy::new(common::stage("dev"), REPO.to_string(), "def".to_string());
+    ctx.infrastructure
+        .start_deployment(&update, &Secrets::default(), &ctx)
+        .unwrap();
+    assert_eq!(wait_for_deployment(&update, &ctx), DeployStatus::Complete);
+    let log = fs::read_to_string(root.join("zprobst/cloud-conveyor/dev-default.log")).unwrap();
+    let report = TerraformReport::from_log(&log);
+    assert_eq!((report.added, report.changed, report.removed), (0, 1, 0));
+
+    let teardown = Teardown::new(common::stage("dev"), REPO.to_string());
+    ctx.teardown.start_teardown(&teardown, &ctx).unwrap();
+    assert_eq!(wait_for_teardown(&teardown, &ctx), TeardownStatus::Complete);
+    assert!(!dev_dir.exists());
+    assert!(runner().outputs(&app, &teardown.stage).is_err());
+    assert!(root.join("zprobst/cloud-conveyor/prod-default").is_dir());
+}
+
+#[test]
+fn failed_apply_fails_deployment() {
+    let dir = tempfile::tempdir().unwrap();
+    let binary = common::fake_terraform(
+        dir.path(),
+        r#"case "$1" in
+  apply)
+    echo '{"type":"diagnostic","diagnostic":{"severity":"error","summary":"Invalid provider","detail":"No credentials"}}'
+    exit 1;;
+esac"#,
+    );
+    let runner = || TerraformRunner::new(dir.path().join("terraform")).with_binary(&binary);
+    let ctx = common::context(dir.path(), runner, Box::new(NoSecrets));
+    common::upload_module(&ctx, dir.path(), "abc", CONFIGURATION);
+
+    let deploy = Deploy::new(common::stage("dev"), REPO.to_string(), "abc".to_string());
+    ctx.infrastructure
+        .start_deployment(&deploy, &Secrets::default(), &ctx)
+        .unwrap();
+    assert_eq!(wait_for_deployment(&deploy, &ctx), DeployStatus::Failed);
+
+    let calls = fs::read_to_string(dir.path().join("calls")).unwrap();
+    let calls: Vec<&str> = calls.lines().collect();
+    assert_eq!(calls[0], "init -input=false -no-color");
+    assert_eq!(calls[1], "workspace select -no-color dev-default");
+    assert_eq!(calls[2], "apply -auto-approve -input=false -json");
+    let log = fs::read_to_string(
+        dir.path()
+            .join("terraform/zprobst/cloud-conveyor/dev-default.log"),
+    )
+    .unwrap();
+    assert_eq!(
+        TerraformReport::from_log(&log).errors,
+        vec!["Invalid provider: No credentials".to_string()]
+    );
+}
+
+#[test]
+fn credentials_of_the_account_are_passed_to_terraform() {
+    let dir = tempfile::tempdir().unwrap();
+    let binary = common::fake_terraform(
+        dir.path(),
+        &format!(
+            r#"if [ "$1" = apply ]; then echo "$AWS_ACCESS_KEY_ID $AWS_SESSION_TOKEN" > "{}"; fi"#,
+            dir.path().join("keys").display()
+        ),
+    );
+    let runner = || TerraformRunner::new(dir.path().join("terraform")).with_binary(&binary);
+    let mut ctx = common::context(dir.path(), runner, Box::new(NoSecrets));
+    ctx.credentials = Box::new(
+        StaticCredentials::default().with_account(
+            "default".to_string(),
+            Credentials::new("AKIDEXAMPLE".to_string(), "s3cr3t".to_string())
+                .with_session_token("t0ken".to_string()),
+        ),
+    );
+    common::upload_module(&ctx, dir.path(), "abc", CONFIGURATION);
+
+    let deploy = Deploy::new(common::stage("dev"), REPO.to_string(), "abc".to_string());
+    ctx.infrastructure
+        .start_deployment(&deploy, &Secrets::default(), &ctx)
+        .unwrap();
+    assert_eq!(wait_for_deployment(&deploy, &ctx), DeployStatus::Complete);
+    assert_eq!(
+        fs::read_to_string(dir.path().join("keys")).unwrap(),
+        "AKIDEXAMPLE t0ken\n"
+    );
+}
+
+#[test]
+fn new_workspace_is_created_when_select_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    let binary = common::fake_terraform(
+        dir.path(),
+        r#"if [ "$1 $2" = "workspace select" ]; then exit 1; fi"#,
+    );
+    let runner = || TerraformRunner::new(dir.path().join("terraform")).with_binary(&binary);
+    let ctx = common::context(dir.path(), runner, Box::new(NoSecrets));
+    common::upload_module(&ctx, dir.path(), "abc", CONFIGURATION);
+
+    let deploy = Deploy::new(common::stage("prod"), REPO.to_string(), "abc".to_string());
+    ctx.infrastructure
+        .start_deployment(&deploy, &Secrets::default(), &ctx)
+        .unwrap();
+    assert_eq!(wait_for_deployment(&deploy, &ctx), DeployStatus::Complete);
+
+    let calls = fs::read_to_string(dir.path().join("calls")).unwrap();
+    assert!(calls.contains("workspace new -no-color prod-default"));
+}
+
+#[test]
+fn build_without_module_fails_to_start() {
+    let dir = tempfile::tempdir().unwrap();
+    let runner = || TerraformRunner::new(dir.path().join("terraform")).with_module_dir("infra");
+    let ctx = common::context(dir.path(), runner, Box::new(NoSecrets));
+    common::upload_module(&ctx, dir.path(), "abc", CONFIGURATION);
+
+    let deploy = Deploy::new(common::stage("dev"), REPO.to_string(), "abc".to_string());
+    let error = ctx
+        .infrastructure
+        .start_deployment(&deploy, &Secrets::default(), &ctx)
+        .unwrap_err();
+    assert!(error.to_string().contains("no terraform module"));
+}
+
+#[test]
+fn teardown_of_undeployed_stage_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    let runner = || TerraformRunner::new(dir.path().join("terraform"));
+    let ctx = common::context(dir.path(), runner, Box::new(NoSecrets));
+
+    let teardown = Teardown::new(common::stage("dev"), REPO.to_string());
+    assert!(ctx.teardown.start_teardown(&teardown, &ctx).is_err());
+}
+
+#[test]
+fn applications_of_the_same_name_in_different_orgs_have_their_own_working_directories() {
+    let dir = tempfile::tempdir().unwrap();
+    let binary = common::fake_terraform(dir.path(), "true");
+    let runner = || TerraformRunner::new(dir.path().join("terraform")).with_binary(&binary);
+    let mut ctx = common::context(dir.path(), runner, Box::new(NoSecrets));
+    let mut fork = common::application();
+    fork.org = "fork".to_string();
+    ctx.applications.push(fork.clone());
+    common::upload_module(&ctx, dir.path(), "abc", CONFIGURATION);
+    let source = dir.path().join("source/abc");
+    ctx.artifact_provider.upload(&fork, "abc", &source).unwrap();
+
+    let upstream = Deploy::new(common::stage("dev"), REPO.to_string(), "abc".to_string());
+    let forked = Deploy::new(
+        common::stage("dev"),
+        "https://github.com/fork/cloud-conveyor.git".to_string(),
+        "abc".to_string(),
+    );
+    for deploy in &[&upstream, &forked] {
+        ctx.infrastructure
+            .start_deployment(deploy, &Secrets::default(), &ctx)
+            .unwrap();
+        assert_eq!(wait_for_deployment(deploy, &ctx), DeployStatus::Complete);
+    }
+
+    let root = dir.path().join("terraform");
+    assert!(root.join("zprobst/cloud-conveyor/dev-default").is_dir());
+    assert!(root.join("fork/cloud-conveyor/dev-default").is_dir());
+}